@@ -0,0 +1,12 @@
+use programming_bitcoin::wallet::bip39::Mnemonic;
+
+fn main() {
+    // Write these words down: they are the only backup of the wallet
+    let mnemonic = Mnemonic::generate(12).unwrap();
+    println!("Mnemonic: {}", mnemonic);
+
+    let master = mnemonic.to_master_key("", true).unwrap();
+    let xpub = master.to_extended_public_key();
+    println!("Master xpub: {}", xpub);
+    println!("Address: {}", xpub.public_key().get_address(true, true));
+}
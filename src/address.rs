@@ -0,0 +1,365 @@
+use std::{fmt, str::FromStr};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ecc::point::ECPoint,
+    script::{
+        op::{OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160},
+        template::TemplateError,
+        Command, PushEncoding, Script,
+    },
+    utils::{
+        bech32::{decode_segwit_address, encode_segwit_address},
+        encode_base58::{decode_base58_checksum, encode_base58_checksum},
+        hash160::hash160,
+    },
+};
+
+// Base58 version bytes
+const MAINNET_P2PKH: u8 = 0x00;
+const MAINNET_P2SH: u8 = 0x05;
+const TESTNET_P2PKH: u8 = 0x6f;
+const TESTNET_P2SH: u8 = 0xc4;
+
+// Bech32 human readable parts
+const MAINNET_HRP: &str = "bc";
+const TESTNET_HRP: &str = "tb";
+
+/// What an address pays to
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Payload {
+    /// hash160 of a public key
+    PubkeyHash([u8; 20]),
+    /// hash160 of a redeem script
+    ScriptHash([u8; 20]),
+    /// A segwit output of any version
+    WitnessProgram { version: u8, program: Vec<u8> },
+}
+
+/// The standard address types
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum AddressType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+}
+
+/// A Bitcoin address
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Address {
+    payload: Payload,
+    testnet: bool,
+}
+
+/// Constructors
+impl Address {
+    /// Checks that a witness program has a version from 0 to 16 and is 2
+    /// to 40 bytes, so that the address has a script pubkey
+    pub fn new(payload: Payload, testnet: bool) -> Result<Self, TemplateError> {
+        if let Payload::WitnessProgram { version, program } = &payload {
+            Script::witness_program(*version, program)?;
+        }
+        Ok(Self::from_payload(payload, testnet))
+    }
+
+    /// For payloads that are valid by construction
+    fn from_payload(payload: Payload, testnet: bool) -> Self {
+        Self { payload, testnet }
+    }
+
+    /// Pay to the hash of a compressed public key
+    pub fn p2pkh(pubkey: &ECPoint, testnet: bool) -> Self {
+        Self::from_payload(
+            Payload::PubkeyHash(hash160(&pubkey.to_compressed_sec())),
+            testnet,
+        )
+    }
+
+    /// Pay to the hash of a redeem script
+    pub fn p2sh(redeem_script: &Script, testnet: bool) -> Self {
+        Self::from_payload(
+            Payload::ScriptHash(hash160(&redeem_script.raw_serialize())),
+            testnet,
+        )
+    }
+
+    /// Pay to a P2WPKH output nested in P2SH (BIP49)
+    pub fn p2sh_p2wpkh(pubkey: &ECPoint, testnet: bool) -> Self {
+        let redeem_script = Self::p2wpkh(pubkey, testnet).script_pubkey();
+        Self::p2sh(&redeem_script, testnet)
+    }
+
+    /// Pay to the hash of a compressed public key, segwit version 0
+    pub fn p2wpkh(pubkey: &ECPoint, testnet: bool) -> Self {
+        Self::from_payload(
+            Payload::WitnessProgram {
+                version: 0,
+                program: hash160(&pubkey.to_compressed_sec()).to_vec(),
+            },
+            testnet,
+        )
+    }
+
+    /// Pay to the sha256 of a witness script, segwit version 0
+    pub fn p2wsh(witness_script: &Script, testnet: bool) -> Self {
+        Self::from_payload(
+            Payload::WitnessProgram {
+                version: 0,
+                program: Sha256::digest(witness_script.raw_serialize()).to_vec(),
+            },
+            testnet,
+        )
+    }
+
+    /// Pay to the taproot output key of the internal key and the script tree
+    /// merkle root (BIP341)
+    pub fn p2tr(internal_key: &ECPoint, merkle_root: Option<&[u8; 32]>, testnet: bool) -> Self {
+        // The tweak is a hash, so it's out of range with negligible probability
+        let output_key = internal_key
+            .taproot_tweak(merkle_root)
+            .expect("taproot tweak is out of range");
+        Self::from_payload(
+            Payload::WitnessProgram {
+                version: 1,
+                program: output_key.to_xonly().to_vec(),
+            },
+            testnet,
+        )
+    }
+
+    /// Returns the address paid to by a standard script pubkey
+    pub fn from_script_pubkey(script_pubkey: &Script, testnet: bool) -> Option<Self> {
+        let cmds = script_pubkey.cmds();
+        let payload = match cmds {
+            [Command::Op(OP_DUP), Command::Op(OP_HASH160), Command::Push(hash, PushEncoding::Direct), Command::Op(OP_EQUALVERIFY), Command::Op(OP_CHECKSIG)]
+                if hash.len() == 20 =>
+            {
+                Payload::PubkeyHash(hash.as_slice().try_into().ok()?)
+            }
+            [Command::Op(OP_HASH160), Command::Push(hash, PushEncoding::Direct), Command::Op(OP_EQUAL)]
+                if hash.len() == 20 =>
+            {
+                Payload::ScriptHash(hash.as_slice().try_into().ok()?)
+            }
+            _ => {
+                let (version, program) = script_pubkey.as_witness_program()?;
+                Payload::WitnessProgram {
+                    version,
+                    program: program.to_vec(),
+                }
+            }
+        };
+        Some(Self::from_payload(payload, testnet))
+    }
+}
+
+/// Methods
+impl Address {
+    pub fn payload(&self) -> &Payload {
+        &self.payload
+    }
+
+    pub fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+
+    /// Returns the type of the address, or None for unknown witness programs
+    pub fn address_type(&self) -> Option<AddressType> {
+        match &self.payload {
+            Payload::PubkeyHash(_) => Some(AddressType::P2pkh),
+            Payload::ScriptHash(_) => Some(AddressType::P2sh),
+            Payload::WitnessProgram { version, program } => match (version, program.len()) {
+                (0, 20) => Some(AddressType::P2wpkh),
+                (0, 32) => Some(AddressType::P2wsh),
+                (1, 32) => Some(AddressType::P2tr),
+                _ => None,
+            },
+        }
+    }
+
+    /// Returns the script pubkey that locks outputs to this address
+    pub fn script_pubkey(&self) -> Script {
+        match &self.payload {
+            Payload::PubkeyHash(hash) => Script::p2pkh(hash),
+            Payload::ScriptHash(hash) => Script::p2sh(hash),
+            Payload::WitnessProgram { version, program } => {
+                Script::witness_program(*version, program)
+                    .expect("Address::new checks witness programs")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = match &self.payload {
+            Payload::PubkeyHash(hash) => {
+                let prefix = if self.testnet {
+                    TESTNET_P2PKH
+                } else {
+                    MAINNET_P2PKH
+                };
+                let mut bytes = vec![prefix];
+                bytes.extend_from_slice(hash);
+                encode_base58_checksum(&bytes)
+            }
+            Payload::ScriptHash(hash) => {
+                let prefix = if self.testnet {
+                    TESTNET_P2SH
+                } else {
+                    MAINNET_P2SH
+                };
+                let mut bytes = vec![prefix];
+                bytes.extend_from_slice(hash);
+                encode_base58_checksum(&bytes)
+            }
+            Payload::WitnessProgram { version, program } => {
+                let hrp = if self.testnet {
+                    TESTNET_HRP
+                } else {
+                    MAINNET_HRP
+                };
+                encode_segwit_address(hrp, *version, program)
+            }
+        };
+        write!(f, "{}", encoded)
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        for (hrp, testnet) in [(MAINNET_HRP, false), (TESTNET_HRP, true)] {
+            if lower.starts_with(&format!("{}1", hrp)) {
+                let (version, program) = decode_segwit_address(hrp, s)?;
+                return Ok(Self::from_payload(
+                    Payload::WitnessProgram { version, program },
+                    testnet,
+                ));
+            }
+        }
+
+        let bytes = decode_base58_checksum(s)?;
+        if bytes.len() != 21 {
+            return Err(format!("Invalid address length: {}", bytes.len()));
+        }
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&bytes[1..]);
+        match bytes[0] {
+            MAINNET_P2PKH => Ok(Self::from_payload(Payload::PubkeyHash(hash), false)),
+            TESTNET_P2PKH => Ok(Self::from_payload(Payload::PubkeyHash(hash), true)),
+            MAINNET_P2SH => Ok(Self::from_payload(Payload::ScriptHash(hash), false)),
+            TESTNET_P2SH => Ok(Self::from_payload(Payload::ScriptHash(hash), true)),
+            version => Err(format!("Unknown address version: {}", version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigUint;
+
+    use crate::ecc::secp256k1_params::Secp256k1Params;
+
+    use super::*;
+
+    #[test]
+    fn test_p2pkh_matches_point_address() {
+        let point = Secp256k1Params::g() * BigUint::from(888_u32.pow(3));
+        let address = Address::p2pkh(&point, false);
+        assert_eq!(address.to_string(), point.get_address(true, false));
+        assert_eq!(address.address_type(), Some(AddressType::P2pkh));
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let values = vec![
+            (
+                "1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1",
+                Some(AddressType::P2pkh),
+            ),
+            (
+                "mopVkxp8UhXqRYbCYJsbeE1h1fiF64jcoH",
+                Some(AddressType::P2pkh),
+            ),
+            (
+                "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+                Some(AddressType::P2sh),
+            ),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                Some(AddressType::P2wpkh),
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                Some(AddressType::P2wsh),
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                Some(AddressType::P2tr),
+            ),
+            ("bc1sw50qgdz25j", None),
+        ];
+        for (s, address_type) in values {
+            let address: Address = s.parse().unwrap();
+            assert_eq!(address.to_string(), s);
+            assert_eq!(address.address_type(), address_type);
+            assert_eq!(
+                Address::from_script_pubkey(&address.script_pubkey(), address.is_testnet()),
+                Some(address)
+            );
+        }
+    }
+
+    #[test]
+    fn test_script_pubkey() {
+        let address: Address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            hex::encode(address.script_pubkey().raw_serialize()),
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+        let address: Address = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy".parse().unwrap();
+        assert_eq!(
+            hex::encode(address.script_pubkey().raw_serialize()),
+            "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87"
+        );
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert!("1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF2"
+            .parse::<Address>()
+            .is_err());
+        assert!("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"
+            .parse::<Address>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        let payload = |version, length| Payload::WitnessProgram {
+            version,
+            program: vec![1; length],
+        };
+        let address = Address::new(payload(16, 2), false).unwrap();
+        assert_eq!(
+            hex::encode(address.script_pubkey().raw_serialize()),
+            "60020101"
+        );
+        assert!(Address::new(payload(1, 40), false).is_ok());
+        for (version, length) in [(0, 50), (0, 1), (17, 32)] {
+            assert_eq!(
+                Address::new(payload(version, length), false),
+                Err(TemplateError::InvalidWitnessProgram { version, length })
+            );
+        }
+        assert!(Address::new(Payload::PubkeyHash([0; 20]), true).is_ok());
+    }
+}
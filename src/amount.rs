@@ -0,0 +1,506 @@
+use std::{
+    fmt, io,
+    io::{Read, Write},
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use crate::encode::{Decodable, Encodable};
+
+/// The number of satoshis in a bitcoin
+pub const COIN: u64 = 100_000_000;
+
+/// The units amounts are parsed and displayed in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Denomination {
+    Bitcoin,
+    MilliBitcoin,
+    /// A millionth of a bitcoin, a hundred satoshis
+    Bit,
+    Satoshi,
+}
+
+impl Denomination {
+    /// The number of decimal places of the denomination in satoshis
+    fn decimals(self) -> u32 {
+        match self {
+            Denomination::Bitcoin => 8,
+            Denomination::MilliBitcoin => 5,
+            Denomination::Bit => 2,
+            Denomination::Satoshi => 0,
+        }
+    }
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Denomination::Bitcoin => "BTC",
+            Denomination::MilliBitcoin => "mBTC",
+            Denomination::Bit => "bits",
+            Denomination::Satoshi => "sat",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BTC" | "btc" => Ok(Denomination::Bitcoin),
+            "mBTC" => Ok(Denomination::MilliBitcoin),
+            "bits" | "bit" => Ok(Denomination::Bit),
+            "sat" | "sats" | "satoshi" | "satoshis" => Ok(Denomination::Satoshi),
+            _ => Err(ParseAmountError::UnknownDenomination(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseAmountError {
+    InvalidFormat,
+    /// More decimal places than the denomination has satoshis for
+    TooPrecise,
+    /// More than MAX_MONEY, in either direction
+    OutOfRange,
+    MissingDenomination,
+    UnknownDenomination(String),
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAmountError::InvalidFormat => write!(f, "invalid amount format"),
+            ParseAmountError::TooPrecise => write!(f, "amount has too many decimal places"),
+            ParseAmountError::OutOfRange => write!(f, "amount out of range"),
+            ParseAmountError::MissingDenomination => write!(f, "missing denomination"),
+            ParseAmountError::UnknownDenomination(denomination) => {
+                write!(f, "unknown denomination: {}", denomination)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseAmountError {}
+
+/// Parses an unsigned decimal number of the denomination into satoshis, up
+/// to MAX_MONEY
+fn parse_sats(s: &str, denomination: Denomination) -> Result<u64, ParseAmountError> {
+    let decimals = denomination.decimals() as usize;
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let is_digits = |part: &str| part.bytes().all(|c| c.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !is_digits(integer) || !is_digits(fraction) {
+        return Err(ParseAmountError::InvalidFormat);
+    }
+    // Trailing zeros past the last satoshi are harmless
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals {
+        return Err(ParseAmountError::TooPrecise);
+    }
+
+    let mut sats = 0u64;
+    let digits = integer
+        .bytes()
+        .chain(fraction.bytes())
+        .chain(std::iter::repeat_n(b'0', decimals - fraction.len()));
+    for digit in digits {
+        sats = sats
+            .checked_mul(10)
+            .and_then(|sats| sats.checked_add((digit - b'0') as u64))
+            .filter(|&sats| sats <= Amount::MAX_MONEY.0)
+            .ok_or(ParseAmountError::OutOfRange)?;
+    }
+    Ok(sats)
+}
+
+/// Formats satoshis in the denomination, without trailing zeros
+fn format_sats(sats: u64, denomination: Denomination) -> String {
+    let unit = 10u64.pow(denomination.decimals());
+    let integer = sats / unit;
+    let fraction = sats % unit;
+    if fraction == 0 {
+        return integer.to_string();
+    }
+    let fraction = format!(
+        "{:0width$}",
+        fraction,
+        width = denomination.decimals() as usize
+    );
+    format!("{}.{}", integer, fraction.trim_end_matches('0'))
+}
+
+/// Splits "<value> <denomination>"
+fn split_denomination(s: &str) -> Result<(&str, Denomination), ParseAmountError> {
+    let (value, denomination) = s
+        .trim()
+        .split_once(' ')
+        .ok_or(ParseAmountError::MissingDenomination)?;
+    Ok((value, denomination.trim_start().parse()?))
+}
+
+/// An amount of satoshis. Arithmetic operators panic on overflow; use the
+/// checked methods for amounts from untrusted data.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord, Default)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE_SAT: Amount = Amount(1);
+    pub const ONE_BTC: Amount = Amount(COIN);
+    /// The most bitcoin there will ever be, and the most a transaction may
+    /// spend
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * COIN);
+
+    pub const fn from_sat(sats: u64) -> Self {
+        Amount(sats)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Parses a decimal number of the denomination, such as "0.001" bitcoin
+    pub fn from_str_in(s: &str, denomination: Denomination) -> Result<Self, ParseAmountError> {
+        parse_sats(s, denomination).map(Amount)
+    }
+
+    /// Formats the amount as a decimal number of the denomination, without
+    /// the denomination
+    pub fn to_string_in(self, denomination: Denomination) -> String {
+        format_sats(self.0, denomination)
+    }
+
+    /// Returns true if the amount is at most MAX_MONEY
+    pub fn is_valid(self) -> bool {
+        self <= Amount::MAX_MONEY
+    }
+
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Option<Amount> {
+        self.0.checked_mul(rhs).map(Amount)
+    }
+
+    pub fn saturating_sub(self, rhs: Amount) -> Amount {
+        Amount(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Sums the amounts, or returns None on overflow
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |sum, amount| sum.checked_add(amount))
+    }
+
+    /// Returns the amount as a signed amount, or None above i64::MAX
+    pub fn to_signed(self) -> Option<SignedAmount> {
+        i64::try_from(self.0).ok().map(SignedAmount)
+    }
+}
+
+impl fmt::Display for Amount {
+    /// Formats the amount in bitcoin, such as "0.001 BTC"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.to_string_in(Denomination::Bitcoin),
+            Denomination::Bitcoin
+        )
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    /// Parses an amount followed by its denomination, such as "100 sat"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, denomination) = split_denomination(s)?;
+        Amount::from_str_in(value, denomination)
+    }
+}
+
+/// Serialized as 8 bytes little endian. Decoding does not check the range,
+/// that is up to transaction validation.
+impl Encodable for Amount {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl Decodable for Amount {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        Ok(Amount(u64::consensus_decode(reader)?))
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        self.checked_add(rhs).expect("amount addition overflowed")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        self.checked_sub(rhs)
+            .expect("amount subtraction overflowed")
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Amount) {
+        *self = *self - rhs;
+    }
+}
+
+/// Panics on overflow like Add; use checked_sum for untrusted amounts
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Self {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+/// A signed amount of satoshis, such as a balance change
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord, Default)]
+pub struct SignedAmount(i64);
+
+impl SignedAmount {
+    pub const ZERO: SignedAmount = SignedAmount(0);
+    pub const MAX_MONEY: SignedAmount = SignedAmount(Amount::MAX_MONEY.0 as i64);
+
+    pub const fn from_sat(sats: i64) -> Self {
+        SignedAmount(sats)
+    }
+
+    pub const fn to_sat(self) -> i64 {
+        self.0
+    }
+
+    /// Parses a decimal number of the denomination, with an optional minus
+    /// sign
+    pub fn from_str_in(s: &str, denomination: Denomination) -> Result<Self, ParseAmountError> {
+        match s.strip_prefix('-') {
+            Some(s) => Ok(SignedAmount(-(parse_sats(s, denomination)? as i64))),
+            None => Ok(SignedAmount(parse_sats(s, denomination)? as i64)),
+        }
+    }
+
+    /// Formats the amount as a decimal number of the denomination, without
+    /// the denomination
+    pub fn to_string_in(self, denomination: Denomination) -> String {
+        let value = format_sats(self.0.unsigned_abs(), denomination);
+        if self.0 < 0 {
+            format!("-{}", value)
+        } else {
+            value
+        }
+    }
+
+    /// Returns true if the amount is within MAX_MONEY of zero
+    pub fn is_valid(self) -> bool {
+        self.0.unsigned_abs() <= Amount::MAX_MONEY.0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(self) -> SignedAmount {
+        SignedAmount(self.0.abs())
+    }
+
+    pub fn checked_add(self, rhs: SignedAmount) -> Option<SignedAmount> {
+        self.0.checked_add(rhs.0).map(SignedAmount)
+    }
+
+    pub fn checked_sub(self, rhs: SignedAmount) -> Option<SignedAmount> {
+        self.0.checked_sub(rhs.0).map(SignedAmount)
+    }
+
+    /// Returns the amount as an unsigned amount, or None if negative
+    pub fn to_unsigned(self) -> Option<Amount> {
+        u64::try_from(self.0).ok().map(Amount)
+    }
+}
+
+impl fmt::Display for SignedAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.to_string_in(Denomination::Bitcoin),
+            Denomination::Bitcoin
+        )
+    }
+}
+
+impl FromStr for SignedAmount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, denomination) = split_denomination(s)?;
+        SignedAmount::from_str_in(value, denomination)
+    }
+}
+
+impl Add for SignedAmount {
+    type Output = SignedAmount;
+
+    fn add(self, rhs: SignedAmount) -> SignedAmount {
+        self.checked_add(rhs).expect("amount addition overflowed")
+    }
+}
+
+impl Sub for SignedAmount {
+    type Output = SignedAmount;
+
+    fn sub(self, rhs: SignedAmount) -> SignedAmount {
+        self.checked_sub(rhs)
+            .expect("amount subtraction overflowed")
+    }
+}
+
+impl Neg for SignedAmount {
+    type Output = SignedAmount;
+
+    fn neg(self) -> SignedAmount {
+        SignedAmount(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            ("1 BTC", 100_000_000),
+            ("0.001 BTC", 100_000),
+            ("0.00000001 btc", 1),
+            ("1.50000000000 BTC", 150_000_000),
+            (".5 BTC", 50_000_000),
+            ("21000000 BTC", 2_100_000_000_000_000),
+            ("2.5 mBTC", 250_000),
+            ("1.23 bits", 123),
+            ("100 sat", 100),
+            ("100 sats", 100),
+        ];
+        for (s, sats) in cases {
+            assert_eq!(s.parse::<Amount>(), Ok(Amount::from_sat(sats)), "{}", s);
+        }
+
+        assert_eq!(
+            "0.000000001 BTC".parse::<Amount>(),
+            Err(ParseAmountError::TooPrecise)
+        );
+        assert_eq!(
+            "1.5 sat".parse::<Amount>(),
+            Err(ParseAmountError::TooPrecise)
+        );
+        assert_eq!(
+            "21000000.00000001 BTC".parse::<Amount>(),
+            Err(ParseAmountError::OutOfRange)
+        );
+        assert_eq!(
+            "99999999999999999999 sat".parse::<Amount>(),
+            Err(ParseAmountError::OutOfRange)
+        );
+        assert_eq!(
+            "-1 BTC".parse::<Amount>(),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            ". BTC".parse::<Amount>(),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            "1e3 sat".parse::<Amount>(),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            "100".parse::<Amount>(),
+            Err(ParseAmountError::MissingDenomination)
+        );
+        assert_eq!(
+            "1 XBT".parse::<Amount>(),
+            Err(ParseAmountError::UnknownDenomination("XBT".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let amount = Amount::from_sat(123_456_780);
+        assert_eq!(amount.to_string(), "1.2345678 BTC");
+        assert_eq!(amount.to_string_in(Denomination::MilliBitcoin), "1234.5678");
+        assert_eq!(amount.to_string_in(Denomination::Bit), "1234567.8");
+        assert_eq!(amount.to_string_in(Denomination::Satoshi), "123456780");
+        assert_eq!(Amount::ONE_BTC.to_string(), "1 BTC");
+        assert_eq!(Amount::ZERO.to_string(), "0 BTC");
+        assert_eq!(Amount::from_sat(1).to_string(), "0.00000001 BTC");
+        for denomination in [
+            Denomination::Bitcoin,
+            Denomination::MilliBitcoin,
+            Denomination::Bit,
+            Denomination::Satoshi,
+        ] {
+            let s = amount.to_string_in(denomination);
+            assert_eq!(Amount::from_str_in(&s, denomination), Ok(amount));
+        }
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = Amount::from_sat(u64::MAX);
+        assert_eq!(max.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::ONE_SAT), None);
+        assert_eq!(
+            Amount::ONE_BTC.checked_sub(Amount::ONE_SAT),
+            Some(Amount::from_sat(99_999_999))
+        );
+        assert_eq!(Amount::checked_sum([max, Amount::ONE_SAT]), None);
+        assert_eq!(
+            Amount::checked_sum([Amount::ONE_BTC, Amount::ONE_SAT]),
+            Some(Amount::from_sat(100_000_001))
+        );
+        assert!(Amount::MAX_MONEY.is_valid());
+        assert!(!(Amount::MAX_MONEY + Amount::ONE_SAT).is_valid());
+        assert_eq!(max.to_signed(), None);
+    }
+
+    #[test]
+    fn test_signed_amount() {
+        let amount = "-0.5 BTC".parse::<SignedAmount>().unwrap();
+        assert_eq!(amount.to_sat(), -50_000_000);
+        assert!(amount.is_negative());
+        assert_eq!(amount.to_string(), "-0.5 BTC");
+        assert_eq!(amount.to_unsigned(), None);
+        assert_eq!((-amount).to_unsigned(), Some(Amount::from_sat(50_000_000)));
+        assert_eq!(amount.abs().to_string_in(Denomination::Satoshi), "50000000");
+        assert_eq!(
+            SignedAmount::from_sat(i64::MIN).checked_sub(SignedAmount::from_sat(1)),
+            None
+        );
+        assert!(SignedAmount::MAX_MONEY.is_valid());
+        assert!(!(-SignedAmount::MAX_MONEY - SignedAmount::from_sat(1)).is_valid());
+    }
+}
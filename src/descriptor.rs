@@ -0,0 +1,877 @@
+pub mod checksum;
+pub mod key;
+
+use std::{fmt, str::FromStr};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    address::Address,
+    miniscript::{types::Base, Miniscript, MiniscriptError, ScriptContext},
+    script::{
+        op::{OP_CHECKMULTISIG, OP_CHECKSIG},
+        Command, Script,
+    },
+    utils::{hash160::hash160, tagged_hash::tagged_hash},
+    wallet::bip32::Bip32Error,
+};
+
+use self::{
+    checksum::{descriptor_checksum, verify_checksum},
+    key::{DerivedKey, DescriptorKey},
+};
+
+/// Leaf version of tapscript (BIP342)
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+/// Taproot script trees can't be deeper than this (BIP341)
+const MAX_TAPTREE_DEPTH: usize = 128;
+
+/// OP_CHECKMULTISIG takes at most 20 keys
+const MAX_MULTI_KEYS: usize = 20;
+
+/// A P2SH redeem script can't exceed 520 bytes, which leaves room for 15
+/// compressed keys
+const MAX_SH_MULTI_KEYS: usize = 15;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DescriptorError {
+    /// The checksum doesn't match or is malformed
+    Checksum(String),
+    /// The descriptor isn't well formed
+    Syntax(String),
+    /// A key expression is invalid
+    InvalidKey(String),
+    /// A function or key isn't allowed where it appears, e.g. wpkh() inside
+    /// wsh()
+    InvalidContext(String),
+    /// The multisig threshold is 0 or larger than the number of keys
+    InvalidThreshold { threshold: usize, keys: usize },
+    /// The index of a wildcard is HARDENED or above, out of its range
+    InvalidIndex(u32),
+    /// Deriving a key failed
+    Bip32(Bip32Error),
+    /// combo() expands to several scripts
+    MultipleScripts,
+    /// The script has no address form, e.g. a raw() or bare multisig script
+    NoAddress,
+    /// A miniscript expression is invalid
+    Miniscript(Box<MiniscriptError>),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::Checksum(err) => write!(f, "{}", err),
+            DescriptorError::Syntax(err) => write!(f, "invalid descriptor: {}", err),
+            DescriptorError::InvalidKey(key) => write!(f, "invalid key: {}", key),
+            DescriptorError::InvalidContext(err) => write!(f, "{}", err),
+            DescriptorError::InvalidThreshold { threshold, keys } => {
+                write!(f, "invalid threshold {} for {} keys", threshold, keys)
+            }
+            DescriptorError::InvalidIndex(index) => write!(f, "invalid index {}", index),
+            DescriptorError::Bip32(err) => write!(f, "{}", err),
+            DescriptorError::MultipleScripts => write!(f, "descriptor expands to several scripts"),
+            DescriptorError::NoAddress => write!(f, "descriptor has no address"),
+            DescriptorError::Miniscript(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+impl From<Bip32Error> for DescriptorError {
+    fn from(err: Bip32Error) -> Self {
+        DescriptorError::Bip32(err)
+    }
+}
+
+impl From<MiniscriptError> for DescriptorError {
+    fn from(err: MiniscriptError) -> Self {
+        DescriptorError::Miniscript(Box::new(err))
+    }
+}
+
+/// An output script descriptor (BIP380-386)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Descriptor {
+    /// pk(KEY): <KEY> OP_CHECKSIG
+    Pk(DescriptorKey),
+    /// pkh(KEY): P2PKH
+    Pkh(DescriptorKey),
+    /// wpkh(KEY): P2WPKH
+    Wpkh(DescriptorKey),
+    /// sh(SCRIPT): P2SH of the inner script
+    Sh(Box<Descriptor>),
+    /// wsh(SCRIPT): P2WSH of the inner script
+    Wsh(Box<Descriptor>),
+    /// multi(k,KEY,...) and sortedmulti(k,KEY,...): k-of-n OP_CHECKMULTISIG,
+    /// with sortedmulti ordering the keys by their SEC encoding
+    Multi {
+        threshold: usize,
+        keys: Vec<DescriptorKey>,
+        sorted: bool,
+    },
+    /// tr(KEY) and tr(KEY,TREE): P2TR
+    Tr {
+        internal_key: DescriptorKey,
+        tree: Option<TapTree>,
+    },
+    /// combo(KEY): P2PK, P2PKH and, for compressed keys, P2WPKH and
+    /// P2SH-P2WPKH
+    Combo(DescriptorKey),
+    /// addr(ADDR)
+    Addr(Address),
+    /// raw(HEX)
+    Raw(Script),
+    /// A miniscript expression inside wsh() or a tr() leaf
+    Miniscript(Miniscript),
+}
+
+/// The script tree of a tr() descriptor
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TapTree {
+    Leaf(Box<Descriptor>),
+    Branch(Box<TapTree>, Box<TapTree>),
+}
+
+/// Where a descriptor fragment appears, which decides what it may contain
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Context {
+    Top,
+    Sh,
+    Wsh,
+    Tap,
+}
+
+/// hash_TapLeaf(leaf_version || compact_size(script) || script)
+pub fn tap_leaf_hash(script: &Script) -> [u8; 32] {
+    let mut msg = vec![TAPSCRIPT_LEAF_VERSION];
+    msg.extend(script.serialize());
+    tagged_hash("TapLeaf", &msg)
+}
+
+/// hash_TapBranch of two nodes, in lexicographic order
+pub fn tap_branch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut msg = first.to_vec();
+    msg.extend_from_slice(second);
+    tagged_hash("TapBranch", &msg)
+}
+
+/// Splits "name(args)" into its name and arguments
+pub(crate) fn split_function(s: &str) -> Result<(&str, &str), DescriptorError> {
+    let (name, rest) = s
+        .split_once('(')
+        .ok_or_else(|| DescriptorError::Syntax(format!("expected a function: {}", s)))?;
+    let args = rest
+        .strip_suffix(')')
+        .ok_or_else(|| DescriptorError::Syntax(format!("missing closing parenthesis: {}", s)))?;
+    Ok((name, args))
+}
+
+/// Splits arguments at the commas that aren't nested in (), {} or []
+pub(crate) fn split_args(s: &str) -> Result<Vec<&str>, DescriptorError> {
+    let mut args = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| DescriptorError::Syntax(format!("unbalanced brackets: {}", s)))?
+            }
+            ',' if depth == 0 => {
+                args.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(DescriptorError::Syntax(format!(
+            "unbalanced brackets: {}",
+            s
+        )));
+    }
+    args.push(&s[start..]);
+    Ok(args)
+}
+
+fn single_arg<'a>(name: &str, args: &'a str) -> Result<&'a str, DescriptorError> {
+    match split_args(args)?[..] {
+        [arg] => Ok(arg),
+        _ => Err(DescriptorError::Syntax(format!(
+            "{}() takes a single argument",
+            name
+        ))),
+    }
+}
+
+fn parse_key(s: &str, ctx: Context, segwit: bool) -> Result<DescriptorKey, DescriptorError> {
+    let key: DescriptorKey = s.parse()?;
+    if key.is_xonly() && ctx != Context::Tap {
+        return Err(DescriptorError::InvalidContext(format!(
+            "x-only keys are only allowed in tr(): {}",
+            s
+        )));
+    }
+    if key.is_uncompressed() && (segwit || matches!(ctx, Context::Wsh | Context::Tap)) {
+        return Err(DescriptorError::InvalidContext(format!(
+            "uncompressed keys are not allowed in segwit scripts: {}",
+            s
+        )));
+    }
+    Ok(key)
+}
+
+fn check_context(name: &str, ctx: Context, allowed: &[Context]) -> Result<(), DescriptorError> {
+    if allowed.contains(&ctx) {
+        Ok(())
+    } else {
+        Err(DescriptorError::InvalidContext(format!(
+            "{}() is not allowed here",
+            name
+        )))
+    }
+}
+
+fn parse_descriptor(s: &str, ctx: Context) -> Result<Descriptor, DescriptorError> {
+    use Context::*;
+
+    let (name, args) = split_function(s)?;
+    match name {
+        "pk" => Ok(Descriptor::Pk(parse_key(
+            single_arg(name, args)?,
+            ctx,
+            false,
+        )?)),
+        "pkh" => Ok(Descriptor::Pkh(parse_key(
+            single_arg(name, args)?,
+            ctx,
+            false,
+        )?)),
+        "wpkh" => {
+            check_context(name, ctx, &[Top, Sh])?;
+            Ok(Descriptor::Wpkh(parse_key(
+                single_arg(name, args)?,
+                ctx,
+                true,
+            )?))
+        }
+        "sh" => {
+            check_context(name, ctx, &[Top])?;
+            let inner = parse_descriptor(single_arg(name, args)?, Sh)?;
+            Ok(Descriptor::Sh(Box::new(inner)))
+        }
+        "wsh" => {
+            check_context(name, ctx, &[Top, Sh])?;
+            let inner = parse_descriptor(single_arg(name, args)?, Wsh)?;
+            Ok(Descriptor::Wsh(Box::new(inner)))
+        }
+        "multi" | "sortedmulti" => {
+            // OP_CHECKMULTISIG is disabled in tapscript
+            check_context(name, ctx, &[Top, Sh, Wsh])?;
+            let args = split_args(args)?;
+            let threshold: usize = args[0]
+                .parse()
+                .map_err(|_| DescriptorError::Syntax(format!("invalid threshold: {}", args[0])))?;
+            let keys = args[1..]
+                .iter()
+                .map(|key| parse_key(key, ctx, false))
+                .collect::<Result<Vec<_>, _>>()?;
+            let max_keys = if ctx == Sh {
+                MAX_SH_MULTI_KEYS
+            } else {
+                MAX_MULTI_KEYS
+            };
+            if threshold == 0 || threshold > keys.len() || keys.len() > max_keys {
+                return Err(DescriptorError::InvalidThreshold {
+                    threshold,
+                    keys: keys.len(),
+                });
+            }
+            Ok(Descriptor::Multi {
+                threshold,
+                keys,
+                sorted: name == "sortedmulti",
+            })
+        }
+        "tr" => {
+            check_context(name, ctx, &[Top])?;
+            match split_args(args)?[..] {
+                [key] => Ok(Descriptor::Tr {
+                    internal_key: parse_key(key, Tap, true)?,
+                    tree: None,
+                }),
+                [key, tree] => Ok(Descriptor::Tr {
+                    internal_key: parse_key(key, Tap, true)?,
+                    tree: Some(parse_tap_tree(tree, 0)?),
+                }),
+                _ => Err(DescriptorError::Syntax(
+                    "tr() takes one or two arguments".to_string(),
+                )),
+            }
+        }
+        "combo" => {
+            check_context(name, ctx, &[Top])?;
+            Ok(Descriptor::Combo(parse_key(
+                single_arg(name, args)?,
+                ctx,
+                false,
+            )?))
+        }
+        "addr" => {
+            check_context(name, ctx, &[Top])?;
+            let address = single_arg(name, args)?
+                .parse()
+                .map_err(DescriptorError::Syntax)?;
+            Ok(Descriptor::Addr(address))
+        }
+        "raw" => {
+            check_context(name, ctx, &[Top])?;
+            let arg = single_arg(name, args)?;
+            let bytes = hex::decode(arg)
+                .map_err(|_| DescriptorError::Syntax(format!("invalid hex: {}", arg)))?;
+            let script = Script::from_bytes(&bytes)
+                .map_err(|_| DescriptorError::Syntax(format!("invalid script: {}", arg)))?;
+            Ok(Descriptor::Raw(script))
+        }
+        _ if matches!(ctx, Wsh | Tap) => {
+            let script_ctx = if ctx == Wsh {
+                ScriptContext::Segwitv0
+            } else {
+                ScriptContext::Tapscript
+            };
+            let ms = Miniscript::parse(s, script_ctx)?;
+            if ms.ty().base != Base::B {
+                return Err(MiniscriptError::TypeCheck(format!(
+                    "a script must be of type B: {}",
+                    s
+                ))
+                .into());
+            }
+            Ok(Descriptor::Miniscript(ms))
+        }
+        _ => Err(DescriptorError::Syntax(format!(
+            "unknown function: {}",
+            name
+        ))),
+    }
+}
+
+fn parse_tap_tree(s: &str, depth: usize) -> Result<TapTree, DescriptorError> {
+    if depth > MAX_TAPTREE_DEPTH {
+        return Err(DescriptorError::Syntax(
+            "taproot tree is too deep".to_string(),
+        ));
+    }
+    let Some(inner) = s.strip_prefix('{') else {
+        let leaf = parse_descriptor(s, Context::Tap)?;
+        return Ok(TapTree::Leaf(Box::new(leaf)));
+    };
+    let inner = inner
+        .strip_suffix('}')
+        .ok_or_else(|| DescriptorError::Syntax(format!("missing closing brace: {}", s)))?;
+    match split_args(inner)?[..] {
+        [left, right] => Ok(TapTree::Branch(
+            Box::new(parse_tap_tree(left, depth + 1)?),
+            Box::new(parse_tap_tree(right, depth + 1)?),
+        )),
+        _ => Err(DescriptorError::Syntax(format!(
+            "a taproot branch has two children: {}",
+            s
+        ))),
+    }
+}
+
+fn p2sh_script(redeem_script: &Script) -> Script {
+    Script::p2sh(&hash160(&redeem_script.raw_serialize()))
+}
+
+fn p2wpkh_script(key: &DerivedKey) -> Script {
+    Script::p2wpkh(&hash160(&key.to_sec()))
+}
+
+fn p2wsh_script(witness_script: &Script) -> Script {
+    Script::p2wsh(&Sha256::digest(witness_script.raw_serialize()).into())
+}
+
+/// Parsing
+impl Descriptor {
+    /// Parses a descriptor, verifying its checksum if there is one
+    pub fn parse(s: &str) -> Result<Self, DescriptorError> {
+        let descriptor = verify_checksum(s).map_err(DescriptorError::Checksum)?;
+        parse_descriptor(descriptor, Context::Top)
+    }
+}
+
+/// Expansion
+impl Descriptor {
+    /// Returns true if the descriptor has a key with a wildcard, so that it
+    /// expands to a different script at each index
+    pub fn is_ranged(&self) -> bool {
+        match self {
+            Descriptor::Pk(key)
+            | Descriptor::Pkh(key)
+            | Descriptor::Wpkh(key)
+            | Descriptor::Combo(key) => key.has_wildcard(),
+            Descriptor::Sh(inner) | Descriptor::Wsh(inner) => inner.is_ranged(),
+            Descriptor::Multi { keys, .. } => keys.iter().any(|key| key.has_wildcard()),
+            Descriptor::Tr { internal_key, tree } => {
+                internal_key.has_wildcard() || tree.as_ref().is_some_and(|tree| tree.is_ranged())
+            }
+            Descriptor::Addr(_) | Descriptor::Raw(_) => false,
+            Descriptor::Miniscript(ms) => ms.is_ranged(),
+        }
+    }
+
+    /// Returns the script pubkey at the index. Fails for combo(), which has
+    /// several.
+    pub fn script_pubkey(&self, index: u32) -> Result<Script, DescriptorError> {
+        if let Descriptor::Combo(_) = self {
+            return Err(DescriptorError::MultipleScripts);
+        }
+        self.script(index, Context::Top)
+    }
+
+    /// Returns every script pubkey the descriptor expands to at the index
+    pub fn script_pubkeys(&self, index: u32) -> Result<Vec<Script>, DescriptorError> {
+        let Descriptor::Combo(key) = self else {
+            return Ok(vec![self.script_pubkey(index)?]);
+        };
+        let key = key.derive(index)?;
+        let mut scripts = vec![
+            Script::new(vec![Command::push(key.to_sec()), Command::Op(OP_CHECKSIG)]),
+            Script::p2pkh(&hash160(&key.to_sec())),
+        ];
+        if key.is_compressed() {
+            let p2wpkh = p2wpkh_script(&key);
+            scripts.push(p2sh_script(&p2wpkh));
+            scripts.push(p2wpkh);
+        }
+        Ok(scripts)
+    }
+
+    /// Returns the address at the index
+    pub fn address(&self, index: u32, testnet: bool) -> Result<Address, DescriptorError> {
+        if let Descriptor::Addr(address) = self {
+            return Ok(address.clone());
+        }
+        Address::from_script_pubkey(&self.script_pubkey(index)?, testnet)
+            .ok_or(DescriptorError::NoAddress)
+    }
+
+    /// Returns the redeem script of sh() descriptors at the index
+    pub fn redeem_script(&self, index: u32) -> Result<Option<Script>, DescriptorError> {
+        match self {
+            Descriptor::Sh(inner) => Ok(Some(inner.script(index, Context::Sh)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the witness script of wsh() and sh(wsh()) descriptors at the
+    /// index
+    pub fn witness_script(&self, index: u32) -> Result<Option<Script>, DescriptorError> {
+        match self {
+            Descriptor::Wsh(inner) => Ok(Some(inner.script(index, Context::Wsh)?)),
+            Descriptor::Sh(inner) => inner.witness_script(index),
+            _ => Ok(None),
+        }
+    }
+
+    /// The script of this fragment when it appears in the context
+    fn script(&self, index: u32, ctx: Context) -> Result<Script, DescriptorError> {
+        Ok(match self {
+            Descriptor::Pk(key) => {
+                let key = key.derive(index)?;
+                let key_bytes = if ctx == Context::Tap {
+                    key.to_xonly().to_vec()
+                } else {
+                    key.to_sec()
+                };
+                Script::new(vec![Command::push(key_bytes), Command::Op(OP_CHECKSIG)])
+            }
+            Descriptor::Pkh(key) => {
+                let key = key.derive(index)?;
+                let pubkey_hash = if ctx == Context::Tap {
+                    hash160(&key.to_xonly())
+                } else {
+                    hash160(&key.to_sec())
+                };
+                Script::p2pkh(&pubkey_hash)
+            }
+            Descriptor::Wpkh(key) => p2wpkh_script(&key.derive(index)?),
+            Descriptor::Sh(inner) => p2sh_script(&inner.script(index, Context::Sh)?),
+            Descriptor::Wsh(inner) => p2wsh_script(&inner.script(index, Context::Wsh)?),
+            Descriptor::Multi {
+                threshold,
+                keys,
+                sorted,
+            } => {
+                let mut pubkeys = keys
+                    .iter()
+                    .map(|key| Ok(key.derive(index)?.to_sec()))
+                    .collect::<Result<Vec<_>, DescriptorError>>()?;
+                if *sorted {
+                    pubkeys.sort();
+                }
+                let key_count = pubkeys.len() as i64;
+                let mut cmds = vec![Command::number(*threshold as i64)];
+                cmds.extend(pubkeys.into_iter().map(Command::push));
+                cmds.push(Command::number(key_count));
+                cmds.push(Command::Op(OP_CHECKMULTISIG));
+                Script::new(cmds)
+            }
+            Descriptor::Tr { internal_key, tree } => {
+                let merkle_root = match tree {
+                    Some(tree) => Some(tree.merkle_root(index)?),
+                    None => None,
+                };
+                let output_key = internal_key
+                    .derive(index)?
+                    .point()
+                    .taproot_tweak(merkle_root.as_ref())
+                    .map_err(|err| DescriptorError::InvalidKey(err.to_string()))?;
+                Script::p2tr(&output_key.to_xonly())
+            }
+            Descriptor::Combo(_) => return Err(DescriptorError::MultipleScripts),
+            Descriptor::Addr(address) => address.script_pubkey(),
+            Descriptor::Raw(script) => script.clone(),
+            Descriptor::Miniscript(ms) => ms.encode(index)?,
+        })
+    }
+}
+
+impl TapTree {
+    pub fn is_ranged(&self) -> bool {
+        match self {
+            TapTree::Leaf(leaf) => leaf.is_ranged(),
+            TapTree::Branch(left, right) => left.is_ranged() || right.is_ranged(),
+        }
+    }
+
+    /// Returns the tapscripts of the leaves at the index, with their depth in
+    /// the tree
+    pub fn leaves(&self, index: u32) -> Result<Vec<(usize, Script)>, DescriptorError> {
+        match self {
+            TapTree::Leaf(leaf) => Ok(vec![(0, leaf.script(index, Context::Tap)?)]),
+            TapTree::Branch(left, right) => {
+                let mut leaves = left.leaves(index)?;
+                leaves.extend(right.leaves(index)?);
+                Ok(leaves
+                    .into_iter()
+                    .map(|(depth, script)| (depth + 1, script))
+                    .collect())
+            }
+        }
+    }
+
+    /// Returns the merkle root of the tree at the index (BIP341)
+    pub fn merkle_root(&self, index: u32) -> Result<[u8; 32], DescriptorError> {
+        match self {
+            TapTree::Leaf(leaf) => Ok(tap_leaf_hash(&leaf.script(index, Context::Tap)?)),
+            TapTree::Branch(left, right) => Ok(tap_branch_hash(
+                &left.merkle_root(index)?,
+                &right.merkle_root(index)?,
+            )),
+        }
+    }
+}
+
+/// Writes the descriptor without its checksum
+fn write_descriptor(f: &mut fmt::Formatter<'_>, descriptor: &Descriptor) -> fmt::Result {
+    match descriptor {
+        Descriptor::Pk(key) => write!(f, "pk({})", key),
+        Descriptor::Pkh(key) => write!(f, "pkh({})", key),
+        Descriptor::Wpkh(key) => write!(f, "wpkh({})", key),
+        Descriptor::Sh(inner) => write!(f, "sh({})", DescriptorBody(inner)),
+        Descriptor::Wsh(inner) => write!(f, "wsh({})", DescriptorBody(inner)),
+        Descriptor::Multi {
+            threshold,
+            keys,
+            sorted,
+        } => {
+            let name = if *sorted { "sortedmulti" } else { "multi" };
+            write!(f, "{}({}", name, threshold)?;
+            for key in keys {
+                write!(f, ",{}", key)?;
+            }
+            write!(f, ")")
+        }
+        Descriptor::Tr { internal_key, tree } => match tree {
+            Some(tree) => write!(f, "tr({},{})", internal_key, tree),
+            None => write!(f, "tr({})", internal_key),
+        },
+        Descriptor::Combo(key) => write!(f, "combo({})", key),
+        Descriptor::Addr(address) => write!(f, "addr({})", address),
+        Descriptor::Raw(script) => write!(f, "raw({})", hex::encode(script.raw_serialize())),
+        Descriptor::Miniscript(ms) => write!(f, "{}", ms),
+    }
+}
+
+/// Displays a descriptor without its checksum
+struct DescriptorBody<'a>(&'a Descriptor);
+
+impl fmt::Display for DescriptorBody<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_descriptor(f, self.0)
+    }
+}
+
+impl fmt::Display for TapTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TapTree::Leaf(leaf) => write!(f, "{}", DescriptorBody(leaf)),
+            TapTree::Branch(left, right) => write!(f, "{{{},{}}}", left, right),
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    /// Writes the descriptor followed by its checksum
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = DescriptorBody(self).to_string();
+        let checksum = descriptor_checksum(&body).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wallet::bip39::Mnemonic;
+
+    use super::*;
+
+    fn script_hex(descriptor: &str) -> String {
+        let descriptor: Descriptor = descriptor.parse().unwrap();
+        hex::encode(descriptor.script_pubkey(0).unwrap().raw_serialize())
+    }
+
+    #[test]
+    fn test_script_pubkeys() {
+        let values = vec![
+            (
+                "pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798)",
+                "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac",
+            ),
+            (
+                "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)#8fhd9pwu",
+                "76a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac",
+            ),
+            (
+                "wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+                "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc",
+            ),
+            (
+                "sh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556))",
+                "a914cc6ffbc0bf31af759451068f90ba7a0272b6b33287",
+            ),
+            (
+                "wsh(pkh(02e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13))",
+                "0020fc5acc302aab97f821f9a61e1cc572e7968a603551e95d4ba12b51df6581482f",
+            ),
+            (
+                "sh(wsh(pkh(02e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13)))",
+                "a91455e8d5e8ee4f3604aba23c71c2684fa0a56a3a1287",
+            ),
+            (
+                "multi(1,022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe4,025cbdf0646e5db4eaa398f365f2ea7a0e3d419b7e0330e39ce92bddedcac4f9bc)",
+                "5121022f8bde4d1a07209355b4a7250a5c5128e88b84bddc619ab7cba8d569b240efe421025cbdf0646e5db4eaa398f365f2ea7a0e3d419b7e0330e39ce92bddedcac4f9bc52ae",
+            ),
+            (
+                "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))",
+                "a914a6a8b030a38762f4c1f5cbe387b61a3c5da5cd2687",
+            ),
+            (
+                "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+                "512077aab6e066f8a7419c5ab714c12c67d25007ed55a43cadcacb4d7a970a093f11",
+            ),
+            (
+                "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,pk(669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0))",
+                "512017cf18db381d836d8923b1bdb246cfcd818da1a9f0e6e7907f187f0b2f937754",
+            ),
+            ("raw(deadbeef)#89f8spxm", "deadbeef"),
+            (
+                "addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+        ];
+        for (descriptor, script) in values {
+            assert_eq!(script_hex(descriptor), script, "{}", descriptor);
+        }
+    }
+
+    #[test]
+    fn test_sortedmulti() {
+        let keys = [
+            "03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe",
+            "022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01",
+        ];
+        assert_eq!(
+            script_hex(&format!("sortedmulti(1,{},{})", keys[0], keys[1])),
+            script_hex(&format!("multi(1,{},{})", keys[1], keys[0]))
+        );
+    }
+
+    #[test]
+    fn test_combo() {
+        let descriptor: Descriptor =
+            "combo(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798)"
+                .parse()
+                .unwrap();
+        assert_eq!(descriptor.script_pubkeys(0).unwrap().len(), 4);
+        assert_eq!(
+            descriptor.script_pubkey(0),
+            Err(DescriptorError::MultipleScripts)
+        );
+
+        // Uncompressed keys only have the legacy scripts
+        let descriptor: Descriptor = "combo(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)"
+            .parse()
+            .unwrap();
+        assert_eq!(descriptor.script_pubkeys(0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_ranged_descriptors() {
+        let master = Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap()
+        .to_master_key("", false)
+        .unwrap();
+        let fingerprint = hex::encode(master.fingerprint());
+        assert_eq!(fingerprint, "73c5da0a");
+
+        let values = vec![
+            (
+                "84'/0'/0'",
+                "wpkh",
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+            ),
+            (
+                "86'/0'/0'",
+                "tr",
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            ),
+        ];
+        for (path, function, address) in values {
+            let xpub = master
+                .derive_path(&path.parse().unwrap())
+                .unwrap()
+                .to_extended_public_key();
+            let s = format!("{}([{}/{}]{}/0/*)", function, fingerprint, path, xpub);
+            let descriptor: Descriptor = s.parse().unwrap();
+            assert!(descriptor.is_ranged());
+            assert_eq!(descriptor.address(0, false).unwrap().to_string(), address);
+            assert_eq!(
+                descriptor.to_string(),
+                format!("{}#{}", s, descriptor_checksum(&s).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_scripts() {
+        let descriptor: Descriptor =
+            "sh(wsh(pkh(02e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13)))"
+                .parse()
+                .unwrap();
+        let witness_script = descriptor.witness_script(0).unwrap().unwrap();
+        let redeem_script = descriptor.redeem_script(0).unwrap().unwrap();
+        assert_eq!(redeem_script, p2wsh_script(&witness_script));
+        assert_eq!(
+            descriptor.script_pubkey(0).unwrap(),
+            p2sh_script(&redeem_script)
+        );
+    }
+
+    #[test]
+    fn test_miniscript() {
+        let descriptor: Descriptor = "wsh(and_v(v:pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),older(144)))"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            hex::encode(descriptor.script_pubkey(0).unwrap().raw_serialize()),
+            "0020b401bd478b827e698f59d9296e5192913a0d1bef57614486cf07eb93d3a4b8d0"
+        );
+        // A V expression can't be a whole script
+        assert!(matches!(
+            "wsh(v:pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798))"
+                .parse::<Descriptor>(),
+            Err(DescriptorError::Miniscript(_))
+        ));
+        // Miniscript isn't allowed outside wsh() and tr()
+        assert!("sh(and_v(v:pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),older(144)))"
+            .parse::<Descriptor>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let values = vec![
+            "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))",
+            "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,{pk(669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0),{pk(669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0),pkh(669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0)}})",
+            "addr(3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy)",
+            "raw(deadbeef)",
+            "wsh(or_d(pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),and_v(v:pk(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5),older(144))))",
+            "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,multi_a(1,669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0,a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd))",
+        ];
+        for s in values {
+            let descriptor: Descriptor = s.parse().unwrap();
+            let with_checksum = descriptor.to_string();
+            assert_eq!(with_checksum.split_once('#').unwrap().0, s);
+            assert_eq!(with_checksum.parse::<Descriptor>().unwrap(), descriptor);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let values = vec![
+            // Bad checksum
+            "raw(deadbeef)#89f8spxn",
+            // Unknown function
+            "foo(deadbeef)",
+            // Unbalanced parentheses
+            "sh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)",
+            // Nesting not allowed
+            "wsh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556))",
+            "sh(sh(pk(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)))",
+            "wsh(sh(pk(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)))",
+            "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,multi(1,669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0))",
+            // Uncompressed keys in segwit
+            "wpkh(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)",
+            // X-only keys outside tr()
+            "pk(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+            // Bad thresholds
+            "multi(0,03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)",
+            "multi(2,03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)",
+            // Too many arguments
+            "pkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556,03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556)",
+            "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,{pk(669b8afcec803a0d323e9a17f3ea8e68e8abe5a278020a929adbec52421adbd0)})",
+        ];
+        for s in values {
+            assert!(s.parse::<Descriptor>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_no_address() {
+        let descriptor: Descriptor = "raw(deadbeef)".parse().unwrap();
+        assert_eq!(
+            descriptor.address(0, false),
+            Err(DescriptorError::NoAddress)
+        );
+    }
+}
@@ -0,0 +1,111 @@
+/// Descriptor checksums (BIP380), an 8 character BCH code over the
+/// descriptor string.
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(symbols: &[u64]) -> u64 {
+    const GENERATOR: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+    let mut chk: u64 = 1;
+    for &value in symbols {
+        let top = chk >> 35;
+        chk = (chk & 0x7ffffffff) << 5 ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+/// Maps each character to its position in the input charset, split into 5
+/// low bits and, per group of three characters, one symbol of the high bits
+fn expand(s: &str) -> Result<Vec<u64>, String> {
+    let mut symbols = vec![];
+    let mut groups = vec![];
+    for c in s.chars() {
+        let value = INPUT_CHARSET
+            .find(c)
+            .ok_or_else(|| format!("Invalid character in descriptor: {}", c))?
+            as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [a] => symbols.push(a),
+        [a, b] => symbols.push(a * 3 + b),
+        _ => {}
+    }
+    Ok(symbols)
+}
+
+/// Computes the checksum of a descriptor without its "#checksum" suffix
+pub fn descriptor_checksum(descriptor: &str) -> Result<String, String> {
+    let mut symbols = expand(descriptor)?;
+    symbols.extend_from_slice(&[0; 8]);
+    let checksum = polymod(&symbols) ^ 1;
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET.as_bytes()[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Splits "descriptor#checksum" and verifies the checksum if there is one
+pub fn verify_checksum(s: &str) -> Result<&str, String> {
+    let Some((descriptor, checksum)) = s.split_once('#') else {
+        // Checksums are optional
+        descriptor_checksum(s)?;
+        return Ok(s);
+    };
+    if checksum.len() != 8 {
+        return Err(format!("Invalid checksum length: {}", checksum));
+    }
+    let expected = descriptor_checksum(descriptor)?;
+    if checksum != expected {
+        return Err(format!(
+            "Invalid checksum {}, expected {}",
+            checksum, expected
+        ));
+    }
+    Ok(descriptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            descriptor_checksum(
+                "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)"
+            )
+            .unwrap(),
+            "8fhd9pwu"
+        );
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        assert_eq!(
+            verify_checksum("raw(deadbeef)#89f8spxm"),
+            Ok("raw(deadbeef)")
+        );
+        assert_eq!(verify_checksum("raw(deadbeef)"), Ok("raw(deadbeef)"));
+        assert!(verify_checksum("raw(deadbeef)#89f8spxn").is_err());
+        assert!(verify_checksum("raw(deadbeef)#89f8spx").is_err());
+        assert!(verify_checksum("raw(deadbeef)#").is_err());
+        assert!(verify_checksum("raw(deadbeef)#89f8spxm#89f8spxm").is_err());
+        assert!(verify_checksum("raw(deadbeef\u{e9})").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    ecc::{point::ECPoint, private_key::PrivateKey},
+    wallet::{
+        bip32::{ExtendedPrivateKey, ExtendedPublicKey},
+        derivation_path::{format_child_number, parse_child_number, DerivationPath, HARDENED},
+    },
+};
+
+use super::DescriptorError;
+
+/// The fingerprint of the master key and the path a key was derived along,
+/// written as [d34db33f/44'/0'/0']
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KeyOrigin {
+    pub fingerprint: [u8; 4],
+    pub path: DerivationPath,
+}
+
+/// Whether an extended key expression ends in /* or /*'
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Wildcard {
+    None,
+    Unhardened,
+    Hardened,
+}
+
+/// A key that isn't extended
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SingleKey {
+    /// A hex encoded SEC public key
+    Public { point: ECPoint, compressed: bool },
+    /// A hex encoded BIP340 x-only public key, only valid inside tr()
+    XOnly(ECPoint),
+    /// A WIF private key
+    Private {
+        key: PrivateKey,
+        compressed: bool,
+        testnet: bool,
+    },
+}
+
+/// An xpub or xprv
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExtendedKey {
+    Public(ExtendedPublicKey),
+    Private(ExtendedPrivateKey),
+}
+
+/// A KEY expression of a descriptor (BIP380)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DescriptorKey {
+    Single {
+        origin: Option<KeyOrigin>,
+        key: SingleKey,
+    },
+    Extended {
+        origin: Option<KeyOrigin>,
+        key: ExtendedKey,
+        path: DerivationPath,
+        wildcard: Wildcard,
+    },
+}
+
+/// The public key a key expression resolves to at a derivation index
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DerivedKey {
+    point: ECPoint,
+    compressed: bool,
+}
+
+impl DerivedKey {
+    pub fn point(&self) -> &ECPoint {
+        &self.point
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The SEC encoding, compressed unless the key expression was
+    /// uncompressed
+    pub fn to_sec(&self) -> Vec<u8> {
+        if self.compressed {
+            self.point.to_compressed_sec()
+        } else {
+            self.point.to_uncompressed_sec()
+        }
+    }
+
+    pub fn to_xonly(&self) -> [u8; 32] {
+        self.point.to_xonly()
+    }
+}
+
+impl DescriptorKey {
+    pub fn origin(&self) -> Option<&KeyOrigin> {
+        match self {
+            DescriptorKey::Single { origin, .. } | DescriptorKey::Extended { origin, .. } => {
+                origin.as_ref()
+            }
+        }
+    }
+
+    /// Returns true if the key ends in a wildcard and so differs per index
+    pub fn has_wildcard(&self) -> bool {
+        matches!(self, DescriptorKey::Extended { wildcard, .. } if *wildcard != Wildcard::None)
+    }
+
+    /// Returns true for hex or WIF keys in uncompressed form
+    pub fn is_uncompressed(&self) -> bool {
+        matches!(
+            self,
+            DescriptorKey::Single {
+                key: SingleKey::Public {
+                    compressed: false,
+                    ..
+                } | SingleKey::Private {
+                    compressed: false,
+                    ..
+                },
+                ..
+            }
+        )
+    }
+
+    pub fn is_xonly(&self) -> bool {
+        matches!(
+            self,
+            DescriptorKey::Single {
+                key: SingleKey::XOnly(_),
+                ..
+            }
+        )
+    }
+
+    /// Resolves the key at the index, which must be below HARDENED. The index
+    /// is ignored by keys without a wildcard.
+    pub fn derive(&self, index: u32) -> Result<DerivedKey, DescriptorError> {
+        match self {
+            DescriptorKey::Single { key, .. } => Ok(match key {
+                SingleKey::Public { point, compressed } => DerivedKey {
+                    point: point.clone(),
+                    compressed: *compressed,
+                },
+                SingleKey::XOnly(point) => DerivedKey {
+                    point: point.clone(),
+                    compressed: true,
+                },
+                SingleKey::Private {
+                    key, compressed, ..
+                } => DerivedKey {
+                    point: key.point(),
+                    compressed: *compressed,
+                },
+            }),
+            DescriptorKey::Extended {
+                key,
+                path,
+                wildcard,
+                ..
+            } => {
+                // The hardened bit would silently turn /* into /*'
+                if *wildcard != Wildcard::None && index >= HARDENED {
+                    return Err(DescriptorError::InvalidIndex(index));
+                }
+                let path = match wildcard {
+                    Wildcard::None => path.clone(),
+                    Wildcard::Unhardened => path.child(index),
+                    Wildcard::Hardened => path.hardened_child(index),
+                };
+                let point = match key {
+                    ExtendedKey::Public(xpub) => xpub.derive_path(&path)?.public_key().clone(),
+                    ExtendedKey::Private(xprv) => xprv.derive_path(&path)?.private_key().point(),
+                };
+                Ok(DerivedKey {
+                    point,
+                    compressed: true,
+                })
+            }
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, DescriptorError> {
+    // hex::decode accepts upper case, descriptors don't
+    if s.bytes().any(|b| b.is_ascii_uppercase()) {
+        return Err(DescriptorError::InvalidKey(s.to_string()));
+    }
+    hex::decode(s).map_err(|_| DescriptorError::InvalidKey(s.to_string()))
+}
+
+fn parse_origin(s: &str) -> Result<KeyOrigin, DescriptorError> {
+    let invalid = || DescriptorError::InvalidKey(format!("Invalid key origin: [{}]", s));
+    let mut parts = s.split('/');
+    let fingerprint = parts.next().ok_or_else(invalid)?;
+    if fingerprint.len() != 8 {
+        return Err(invalid());
+    }
+    let fingerprint = parse_hex(fingerprint)
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    let path = parts
+        .map(parse_child_number)
+        .collect::<Result<Vec<u32>, String>>()
+        .map_err(DescriptorError::InvalidKey)?;
+    Ok(KeyOrigin {
+        fingerprint,
+        path: path.into(),
+    })
+}
+
+fn parse_single_key(s: &str) -> Result<SingleKey, DescriptorError> {
+    let invalid = || DescriptorError::InvalidKey(s.to_string());
+    if s.len() == 64 || s.len() == 66 || s.len() == 130 {
+        let bytes = parse_hex(s)?;
+        return match bytes.len() {
+            32 => Ok(SingleKey::XOnly(
+                ECPoint::from_xonly(&bytes).map_err(|_| invalid())?,
+            )),
+            33 if bytes[0] == 2 || bytes[0] == 3 => Ok(SingleKey::Public {
+                point: ECPoint::parse(&bytes).map_err(|_| invalid())?,
+                compressed: true,
+            }),
+            65 if bytes[0] == 4 => Ok(SingleKey::Public {
+                point: ECPoint::parse(&bytes).map_err(|_| invalid())?,
+                compressed: false,
+            }),
+            _ => Err(invalid()),
+        };
+    }
+    let (key, compressed, testnet) = PrivateKey::from_wif(s).map_err(|_| invalid())?;
+    Ok(SingleKey::Private {
+        key,
+        compressed,
+        testnet,
+    })
+}
+
+fn parse_extended_key(s: &str) -> Result<(ExtendedKey, DerivationPath, Wildcard), DescriptorError> {
+    let mut parts = s.split('/');
+    let encoded = parts.next().unwrap_or_default();
+    let key = if let Ok(xpub) = ExtendedPublicKey::from_str(encoded) {
+        ExtendedKey::Public(xpub)
+    } else if let Ok(xprv) = ExtendedPrivateKey::from_str(encoded) {
+        ExtendedKey::Private(xprv)
+    } else {
+        return Err(DescriptorError::InvalidKey(encoded.to_string()));
+    };
+
+    let mut path = vec![];
+    let mut wildcard = Wildcard::None;
+    for part in parts {
+        if wildcard != Wildcard::None {
+            return Err(DescriptorError::InvalidKey(format!(
+                "Wildcard must be the last step: {}",
+                s
+            )));
+        }
+        match part {
+            "*" => wildcard = Wildcard::Unhardened,
+            "*'" | "*h" | "*H" => wildcard = Wildcard::Hardened,
+            _ => path.push(parse_child_number(part).map_err(DescriptorError::InvalidKey)?),
+        }
+    }
+
+    if let ExtendedKey::Public(_) = key {
+        if wildcard == Wildcard::Hardened || path.iter().any(|index| *index >= HARDENED) {
+            return Err(DescriptorError::InvalidKey(format!(
+                "Hardened derivation from an xpub: {}",
+                s
+            )));
+        }
+    }
+
+    Ok((key, path.into(), wildcard))
+}
+
+impl FromStr for DescriptorKey {
+    type Err = DescriptorError;
+
+    /// Parses [origin]KEY where KEY is a hex public key, a WIF private key,
+    /// or an xpub/xprv followed by a path and an optional wildcard
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, key) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (origin, key) = rest.split_once(']').ok_or_else(|| {
+                    DescriptorError::InvalidKey(format!("Unterminated key origin: {}", s))
+                })?;
+                (Some(parse_origin(origin)?), key)
+            }
+            None => (None, s),
+        };
+
+        if key.starts_with("xpub")
+            || key.starts_with("tpub")
+            || key.starts_with("xprv")
+            || key.starts_with("tprv")
+        {
+            let (key, path, wildcard) = parse_extended_key(key)?;
+            return Ok(DescriptorKey::Extended {
+                origin,
+                key,
+                path,
+                wildcard,
+            });
+        }
+
+        Ok(DescriptorKey::Single {
+            origin,
+            key: parse_single_key(key)?,
+        })
+    }
+}
+
+impl fmt::Display for KeyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", hex::encode(self.fingerprint))?;
+        for index in self.path.as_slice() {
+            write!(f, "/{}", format_child_number(*index))?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = self.origin() {
+            write!(f, "{}", origin)?;
+        }
+        match self {
+            DescriptorKey::Single { key, .. } => match key {
+                SingleKey::Public { point, compressed } => {
+                    let sec = if *compressed {
+                        point.to_compressed_sec()
+                    } else {
+                        point.to_uncompressed_sec()
+                    };
+                    write!(f, "{}", hex::encode(sec))
+                }
+                SingleKey::XOnly(point) => write!(f, "{}", hex::encode(point.to_xonly())),
+                SingleKey::Private {
+                    key,
+                    compressed,
+                    testnet,
+                } => write!(f, "{}", key.to_wif(*compressed, *testnet)),
+            },
+            DescriptorKey::Extended {
+                key,
+                path,
+                wildcard,
+                ..
+            } => {
+                match key {
+                    ExtendedKey::Public(xpub) => write!(f, "{}", xpub)?,
+                    ExtendedKey::Private(xprv) => write!(f, "{}", xprv)?,
+                }
+                for index in path.as_slice() {
+                    write!(f, "/{}", format_child_number(*index))?;
+                }
+                match wildcard {
+                    Wildcard::None => Ok(()),
+                    Wildcard::Unhardened => write!(f, "/*"),
+                    Wildcard::Hardened => write!(f, "/*'"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP32 test vector 1
+    const XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    const XPUB_0H: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+
+    #[test]
+    fn test_roundtrip() {
+        let values = vec![
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "[d34db33f/44'/0'/0']0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235",
+            "a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+            "L4rK1yDtCWekvXuE6oXD9jCYfFNV2cWRpVuPLBcCU2z8TrisoyY1",
+        ];
+        for s in values {
+            let key: DescriptorKey = s.parse().unwrap();
+            assert_eq!(key.to_string(), s);
+            assert!(!key.has_wildcard());
+        }
+
+        let s = format!("[deadbeef/0']{}/1/*", XPUB_0H);
+        let key: DescriptorKey = s.parse().unwrap();
+        assert_eq!(key.to_string(), s);
+        assert!(key.has_wildcard());
+        assert_eq!(
+            key.origin().unwrap().path,
+            "m/0'".parse::<DerivationPath>().unwrap()
+        );
+
+        // h is accepted for hardened steps, and printed as '
+        let key: DescriptorKey = format!("{}/0h/*h", XPRV).parse().unwrap();
+        assert_eq!(key.to_string(), format!("{}/0'/*'", XPRV));
+    }
+
+    #[test]
+    fn test_derive() {
+        let xprv: ExtendedPrivateKey = XPRV.parse().unwrap();
+        let expected = xprv
+            .derive_path(&"m/0'/1/2".parse().unwrap())
+            .unwrap()
+            .to_extended_public_key();
+
+        let key: DescriptorKey = format!("{}/1/*", XPUB_0H).parse().unwrap();
+        assert_eq!(key.derive(2).unwrap().point(), expected.public_key());
+        let key: DescriptorKey = format!("{}/0'/1/*", XPRV).parse().unwrap();
+        assert_eq!(key.derive(2).unwrap().point(), expected.public_key());
+        assert_eq!(
+            key.derive(HARDENED + 2),
+            Err(DescriptorError::InvalidIndex(HARDENED + 2))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let values = vec![
+            // Upper case hex
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798".to_string(),
+            // Bad prefix
+            "0579be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+            // Bad origins
+            "[d34db33f/44'0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+                .to_string(),
+            "[d34db3/44']0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+                .to_string(),
+            // Hardened derivation from an xpub
+            format!("{}/1'/*", XPUB_0H),
+            format!("{}/1/*'", XPUB_0H),
+            // Wildcard in the middle of the path
+            format!("{}/*/1", XPUB_0H),
+            "L4rK1yDtCWekvXuE6oXD9jCYfFNV2cWRpVuPLBcCU2z8TrisoyY2".to_string(),
+        ];
+        for s in values {
+            assert!(s.parse::<DescriptorKey>().is_err(), "{}", s);
+        }
+    }
+}
@@ -5,3 +5,47 @@ pub mod point;
 pub mod private_key;
 pub mod secp256k1_params;
 pub mod signature;
+
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EccError {
+    /// The order of a finite field must be prime
+    NonPrimeOrder,
+    /// A field element must be below the order of its field
+    ElementOutOfRange,
+    PointNotOnCurve,
+    /// Not a 33 or 65 byte SEC public key
+    InvalidSec,
+    /// Not a 32 byte x coordinate below the field order
+    InvalidXOnlyKey,
+    InvalidDerSignature,
+    InvalidWif(String),
+    /// A secret must be between 1 and n - 1
+    SecretOutOfRange,
+    /// A taproot tweak at or above the curve order
+    TweakOutOfRange,
+    /// Tweaking gave the point at infinity or a zero secret
+    TweakedToInfinity,
+}
+
+impl fmt::Display for EccError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EccError::NonPrimeOrder => write!(f, "the order of the field must be a prime number"),
+            EccError::ElementOutOfRange => {
+                write!(f, "num must be between 0 and order-1 inclusive")
+            }
+            EccError::PointNotOnCurve => write!(f, "point is not on the curve"),
+            EccError::InvalidSec => write!(f, "invalid SEC format"),
+            EccError::InvalidXOnlyKey => write!(f, "invalid x-only public key"),
+            EccError::InvalidDerSignature => write!(f, "invalid DER signature"),
+            EccError::InvalidWif(reason) => write!(f, "invalid WIF: {}", reason),
+            EccError::SecretOutOfRange => write!(f, "secret is out of range"),
+            EccError::TweakOutOfRange => write!(f, "taproot tweak is out of range"),
+            EccError::TweakedToInfinity => write!(f, "tweaked key is infinity"),
+        }
+    }
+}
+
+impl std::error::Error for EccError {}
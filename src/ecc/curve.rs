@@ -50,39 +50,45 @@ mod tests {
 
     #[test]
     fn test_new() {
-        let field = FiniteField::new(&BigUint::from(17u32));
+        let field = FiniteField::new(&BigUint::from(17u32)).unwrap();
         let curve = EllipticCurve::new(
-            FFElement::new(&BigUint::from(1u32), &field),
-            FFElement::new(&BigUint::from(2u32), &field),
+            FFElement::new(&BigUint::from(1u32), &field).unwrap(),
+            FFElement::new(&BigUint::from(2u32), &field).unwrap(),
+        );
+        assert_eq!(
+            curve.a,
+            FFElement::new(&BigUint::from(1u32), &field).unwrap()
+        );
+        assert_eq!(
+            curve.b,
+            FFElement::new(&BigUint::from(2u32), &field).unwrap()
         );
-        assert_eq!(curve.a, FFElement::new(&BigUint::from(1u32), &field));
-        assert_eq!(curve.b, FFElement::new(&BigUint::from(2u32), &field));
     }
 
     #[test]
     fn test_eq() {
-        let field = FiniteField::new(&BigUint::from(17u32));
+        let field = FiniteField::new(&BigUint::from(17u32)).unwrap();
         let curve1 = EllipticCurve::new(
-            FFElement::new(&BigUint::from(1u32), &field),
-            FFElement::new(&BigUint::from(2u32), &field),
+            FFElement::new(&BigUint::from(1u32), &field).unwrap(),
+            FFElement::new(&BigUint::from(2u32), &field).unwrap(),
         );
         let curve2 = EllipticCurve::new(
-            FFElement::new(&BigUint::from(1u32), &field),
-            FFElement::new(&BigUint::from(2u32), &field),
+            FFElement::new(&BigUint::from(1u32), &field).unwrap(),
+            FFElement::new(&BigUint::from(2u32), &field).unwrap(),
         );
         assert_eq!(curve1, curve2);
     }
 
     #[test]
     fn test_ne() {
-        let field = FiniteField::new(&BigUint::from(17u32));
+        let field = FiniteField::new(&BigUint::from(17u32)).unwrap();
         let curve1 = EllipticCurve::new(
-            FFElement::new(&BigUint::from(1u32), &field),
-            FFElement::new(&BigUint::from(2u32), &field),
+            FFElement::new(&BigUint::from(1u32), &field).unwrap(),
+            FFElement::new(&BigUint::from(2u32), &field).unwrap(),
         );
         let curve2 = EllipticCurve::new(
-            FFElement::new(&BigUint::from(2u32), &field),
-            FFElement::new(&BigUint::from(1u32), &field),
+            FFElement::new(&BigUint::from(2u32), &field).unwrap(),
+            FFElement::new(&BigUint::from(1u32), &field).unwrap(),
         );
         assert_ne!(curve1, curve2);
     }
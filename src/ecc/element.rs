@@ -1,6 +1,6 @@
 use num::BigUint;
 
-use super::finite_field::FiniteField;
+use super::{finite_field::FiniteField, EccError};
 
 /// A finite field element.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -10,13 +10,18 @@ pub struct FFElement {
 }
 
 impl FFElement {
-    pub fn new(num: &BigUint, field: &FiniteField) -> Self {
+    pub fn new(num: &BigUint, field: &FiniteField) -> Result<Self, EccError> {
         // check that num is between 0 and order-1 inclusive
-        if num >= field.order() || num < &BigUint::from(0u32) {
-            panic!("num must be between 0 and order-1 inclusive");
+        if num >= field.order() {
+            return Err(EccError::ElementOutOfRange);
         }
+        Ok(Self::reduced(num.clone(), field))
+    }
+
+    /// Wraps a number already reduced modulo the order
+    fn reduced(num: BigUint, field: &FiniteField) -> Self {
         Self {
-            num: num.clone(),
+            num,
             field: field.clone(),
         }
     }
@@ -32,14 +37,14 @@ impl FFElement {
         let p = self.field.order();
         let exp = BigUint::from(exponent);
         let num = self.num.modpow(&exp, p);
-        Self::new(&num, &self.field)
+        Self::reduced(num, &self.field)
     }
 
     pub fn sqrt(&self) -> Self {
         let p = self.field.order();
         let exp = (p + BigUint::from(1u32)) / BigUint::from(4u32);
         let num = self.num.modpow(&exp, p);
-        Self::new(&num, &self.field)
+        Self::reduced(num, &self.field)
     }
 
     pub fn num(&self) -> &BigUint {
@@ -68,7 +73,7 @@ impl std::ops::Add for FFElement {
         let s = self.num + other.num;
         let mod_sum = s % self.field.order();
 
-        Self::new(&mod_sum, &self.field)
+        Self::reduced(mod_sum, &self.field)
     }
 }
 
@@ -86,7 +91,7 @@ impl std::ops::Sub for FFElement {
         let a = self.num % p;
         let b = other.num % p;
 
-        Self::new(&((a + p - b) % p), &self.field)
+        Self::reduced((a + p - b) % p, &self.field)
     }
 }
 
@@ -101,7 +106,7 @@ impl std::ops::Mul for FFElement {
         let p = self.field.order();
         let m = (self.num * other.num) % p;
 
-        Self::new(&m, &self.field)
+        Self::reduced(m, &self.field)
     }
 }
 
@@ -112,7 +117,7 @@ impl std::ops::Mul<u32> for FFElement {
         let p = self.field.order();
         let m = (self.num * other) % p;
 
-        Self::new(&m, &self.field)
+        Self::reduced(m, &self.field)
     }
 }
 
@@ -134,7 +139,7 @@ impl std::ops::Div for FFElement {
         let two = BigUint::from(2u32);
         let b_inv = b.modpow(&(p - two), p);
 
-        Self::new(&((a * b_inv) % p), &self.field)
+        Self::reduced((a * b_inv) % p, &self.field)
     }
 }
 
@@ -144,88 +149,97 @@ mod tests {
 
     #[test]
     fn test_eq() {
-        let field = FiniteField::new(&BigUint::from(13u32));
-        let a = FFElement::new(&BigUint::from(7u32), &field);
-        let b = FFElement::new(&BigUint::from(6u32), &field);
+        let field = FiniteField::new(&BigUint::from(13u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(7u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(6u32), &field).unwrap();
         assert!(a != b);
         assert!(a == a);
     }
 
     #[test]
     fn test_ne() {
-        let field = FiniteField::new(&BigUint::from(13u32));
-        let a = FFElement::new(&BigUint::from(7u32), &field);
-        let b = FFElement::new(&BigUint::from(6u32), &field);
+        let field = FiniteField::new(&BigUint::from(13u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(7u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(6u32), &field).unwrap();
         assert!(a != b);
         assert!(a == a);
     }
 
+    #[test]
+    fn test_new_out_of_range() {
+        let field = FiniteField::new(&BigUint::from(13u32)).unwrap();
+        assert_eq!(
+            FFElement::new(&BigUint::from(13u32), &field),
+            Err(EccError::ElementOutOfRange)
+        );
+    }
+
     #[test]
     fn test_display() {
-        let field = FiniteField::new(&BigUint::from(13u32));
-        let a = FFElement::new(&BigUint::from(7u32), &field);
+        let field = FiniteField::new(&BigUint::from(13u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         assert_eq!(format!("{}", a), "FieldElement_13(7)");
     }
 
     #[test]
     fn test_add() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(2u32), &field);
-        let b = FFElement::new(&BigUint::from(15u32), &field);
-        let c = FFElement::new(&BigUint::from(17u32), &field);
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(2u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(15u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(17u32), &field).unwrap();
         assert!(a + b == c);
-        let a = FFElement::new(&BigUint::from(17u32), &field);
-        let b = FFElement::new(&BigUint::from(21u32), &field);
-        let c = FFElement::new(&BigUint::from(7u32), &field);
+        let a = FFElement::new(&BigUint::from(17u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(21u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         assert!(a + b == c);
     }
 
     #[test]
     fn test_sub() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(29u32), &field);
-        let b = FFElement::new(&BigUint::from(4u32), &field);
-        let c = FFElement::new(&BigUint::from(25u32), &field);
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(29u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(4u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(25u32), &field).unwrap();
         assert!(a - b == c);
-        let a = FFElement::new(&BigUint::from(15u32), &field);
-        let b = FFElement::new(&BigUint::from(30u32), &field);
-        let c = FFElement::new(&BigUint::from(16u32), &field);
+        let a = FFElement::new(&BigUint::from(15u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(30u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(16u32), &field).unwrap();
         assert!(a - b == c);
     }
 
     #[test]
     fn test_mul() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(24u32), &field);
-        let b = FFElement::new(&BigUint::from(19u32), &field);
-        let c = FFElement::new(&BigUint::from(22u32), &field);
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(24u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(19u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(22u32), &field).unwrap();
         assert!(a * b == c);
     }
 
     #[test]
     fn test_rmul() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(24u32), &field);
-        let b = FFElement::new(&BigUint::from(19u32), &field);
-        let c = FFElement::new(&BigUint::from(22u32), &field);
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(24u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(19u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(22u32), &field).unwrap();
         assert!(b * a == c);
     }
 
     #[test]
     fn test_pow() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(17u32), &field);
-        let b = FFElement::new(&BigUint::from(5u32), &field);
-        let c = FFElement::new(&BigUint::from(18u32), &field);
-        assert!(a.pow(3) == FFElement::new(&BigUint::from(15u32), &field));
-        assert!(b.pow(5) * c == FFElement::new(&BigUint::from(16u32), &field));
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(17u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(5u32), &field).unwrap();
+        let c = FFElement::new(&BigUint::from(18u32), &field).unwrap();
+        assert!(a.pow(3) == FFElement::new(&BigUint::from(15u32), &field).unwrap());
+        assert!(b.pow(5) * c == FFElement::new(&BigUint::from(16u32), &field).unwrap());
     }
 
     #[test]
     fn test_div() {
-        let field = FiniteField::new(&BigUint::from(31u32));
-        let a = FFElement::new(&BigUint::from(3u32), &field);
-        let b = FFElement::new(&BigUint::from(24u32), &field);
-        assert!(a / b == FFElement::new(&BigUint::from(4u32), &field));
+        let field = FiniteField::new(&BigUint::from(31u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(3u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(24u32), &field).unwrap();
+        assert!(a / b == FFElement::new(&BigUint::from(4u32), &field).unwrap());
     }
 }
@@ -2,7 +2,7 @@ use num::BigUint;
 
 use crate::utils::biguint_primality_checker::biguint_primality_checker;
 
-use super::secp256k1_params::Secp256k1Params;
+use super::{secp256k1_params::Secp256k1Params, EccError};
 
 /// A finite field.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -14,18 +14,20 @@ impl FiniteField {
     /// Create a new finite field.  
     /// Arguments:
     /// * `order`: the order of the field must be a prime number.
-    pub fn new(order: &BigUint) -> Self {
+    pub fn new(order: &BigUint) -> Result<Self, EccError> {
         if !biguint_primality_checker(order) {
-            panic!("The order of the field must be a prime number");
+            return Err(EccError::NonPrimeOrder);
         }
-        Self {
+        Ok(Self {
             order: order.clone(),
-        }
+        })
     }
 
+    /// The field of secp256k1 coordinates, whose order is known to be prime
     pub fn new_secp256k1() -> Self {
-        let order = Secp256k1Params::p();
-        Self::new(&order)
+        Self {
+            order: Secp256k1Params::p(),
+        }
     }
 
     /// Get the order of the field.
@@ -49,33 +51,21 @@ mod tests {
     #[test]
     fn test_new() {
         let two = BigUint::from_u32(2).unwrap();
-        let field = FiniteField::new(&two);
+        let field = FiniteField::new(&two).unwrap();
         assert_eq!(field.order, two);
     }
 
     #[test]
-    #[should_panic]
-    fn test_new_panic() {
-        let four = BigUint::from_u32(4).unwrap();
-        let _field = FiniteField::new(&four);
-    }
-
-    #[test]
-    fn test_new_panic_message() {
+    fn test_new_not_prime() {
         let four = BigUint::from_u32(4).unwrap();
-        let result = std::panic::catch_unwind(|| FiniteField::new(&four));
-        assert!(result.is_err());
-        assert_eq!(
-            result.unwrap_err().downcast_ref::<&str>(),
-            Some(&"The order of the field must be a prime number")
-        );
+        assert_eq!(FiniteField::new(&four), Err(EccError::NonPrimeOrder));
     }
 
     #[test]
     fn test_eq() {
         let two = BigUint::from_u32(2).unwrap();
-        let field1 = FiniteField::new(&two);
-        let field2 = FiniteField::new(&two);
+        let field1 = FiniteField::new(&two).unwrap();
+        let field2 = FiniteField::new(&two).unwrap();
         assert_eq!(field1, field2);
     }
 
@@ -83,15 +73,15 @@ mod tests {
     fn test_ne() {
         let two = BigUint::from_u32(2).unwrap();
         let three = BigUint::from_u32(3).unwrap();
-        let field1 = FiniteField::new(&two);
-        let field2 = FiniteField::new(&three);
+        let field1 = FiniteField::new(&two).unwrap();
+        let field2 = FiniteField::new(&three).unwrap();
         assert_ne!(field1, field2);
     }
 
     #[test]
     fn test_display() {
         let two = BigUint::from_u32(2).unwrap();
-        let field = FiniteField::new(&two);
+        let field = FiniteField::new(&two).unwrap();
         assert_eq!(format!("{}", field), "Finite field of order 2");
     }
 }
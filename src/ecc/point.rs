@@ -1,10 +1,12 @@
 use num::{BigUint, Integer};
 
-use crate::utils::{encode_base58::encode_base58_checksum, hash160::hash160};
+use crate::utils::{
+    encode_base58::encode_base58_checksum, hash160::hash160, tagged_hash::tagged_hash,
+};
 
 use super::{
-    curve::EllipticCurve, element::FFElement, secp256k1_params::Secp256k1Params,
-    signature::Signature,
+    curve::EllipticCurve, element::FFElement, private_key::schnorr_challenge,
+    secp256k1_params::Secp256k1Params, signature::Signature, EccError,
 };
 
 /// An elliptic curve point
@@ -31,8 +33,8 @@ impl ECPoint {
     ///     y: y coordinate
     ///     curve: the curve the point is on
     ///
-    /// Note: This function will panic if the point is not on the curve
-    pub fn new(x: &FFElement, y: &FFElement, curve: &EllipticCurve) -> Result<Self, String> {
+    /// Returns an error if the point is not on the curve
+    pub fn new(x: &FFElement, y: &FFElement, curve: &EllipticCurve) -> Result<Self, EccError> {
         let a = curve.a().clone();
         let b = curve.b().clone();
 
@@ -40,7 +42,7 @@ impl ECPoint {
         let x3 = x.pow(3);
 
         if y2 != x3 + a * x.clone() + b {
-            return Err(format!("({}, {}) is not on the curve", *x, *y));
+            return Err(EccError::PointNotOnCurve);
         }
 
         Ok(Self {
@@ -60,7 +62,7 @@ impl ECPoint {
     }
 
     /// Creates a new point on the secp256k1 curve
-    pub fn new_secp256k1(x: &FFElement, y: &FFElement) -> Result<Self, String> {
+    pub fn new_secp256k1(x: &FFElement, y: &FFElement) -> Result<Self, EccError> {
         Self::new(x, y, &EllipticCurve::new_secp256k1())
     }
 
@@ -84,6 +86,10 @@ impl ECPoint {
         // By Fermat's Little Theorem, 1/s = pow(s, N-2, N)
         let n = &Secp256k1Params::n();
         let s = signature.s();
+        let zero = BigUint::from(0u8);
+        if *s == zero || s >= n || *signature.r() == zero || signature.r() >= n {
+            return false;
+        }
         let two = &BigUint::from(2u8);
         let s_inv = s.modpow(&(n - two), n);
 
@@ -98,7 +104,10 @@ impl ECPoint {
         let p = self.clone();
         let total = g * u + p * v;
 
-        total.x.unwrap().num() == signature.r()
+        match &total.x {
+            Some(x) => x.num() == signature.r(),
+            None => false,
+        }
     }
 
     /// Returns the address of the public key
@@ -142,8 +151,8 @@ impl ECPoint {
     /// Uncompressed SEC format
     pub fn to_uncompressed_sec(&self) -> Vec<u8> {
         let mut sec = vec![4u8];
-        sec.extend(self.x().unwrap().num().to_bytes_be());
-        sec.extend(self.y().unwrap().num().to_bytes_be());
+        sec.extend(to_32_bytes(self.x().unwrap().num()));
+        sec.extend(to_32_bytes(self.y().unwrap().num()));
         sec
     }
 
@@ -151,20 +160,25 @@ impl ECPoint {
     pub fn to_compressed_sec(&self) -> Vec<u8> {
         let y_is_even = self.y().unwrap().num().is_even();
         let mut sec = vec![if y_is_even { 2u8 } else { 3u8 }];
-        sec.extend(self.x().unwrap().num().to_bytes_be());
+        sec.extend(to_32_bytes(self.x().unwrap().num()));
         sec
     }
 
     /// Deserialize a point from SEC format
-    pub fn parse(sec_bin: &[u8]) -> Result<Self, String> {
+    pub fn parse(sec_bin: &[u8]) -> Result<Self, EccError> {
+        let p = Secp256k1Params::p();
+
         // The uncompressed SEC format is pretty straightforward.
         if sec_bin.len() == 65 {
             if sec_bin[0] != 4 {
-                return Err("Invalid SEC format".to_string());
+                return Err(EccError::InvalidSec);
             }
 
             let x = BigUint::from_bytes_be(&sec_bin[1..33]);
             let y = BigUint::from_bytes_be(&sec_bin[33..65]);
+            if x >= p || y >= p {
+                return Err(EccError::InvalidSec);
+            }
             return Self::new_secp256k1(
                 &FFElement::new_secp256k1(&x),
                 &FFElement::new_secp256k1(&y),
@@ -173,8 +187,14 @@ impl ECPoint {
 
         if sec_bin.len() == 33 {
             // The evenness of the y coordinate is given in the first byte.
+            if sec_bin[0] != 2 && sec_bin[0] != 3 {
+                return Err(EccError::InvalidSec);
+            }
             let is_even = sec_bin[0] == 2;
             let x = BigUint::from_bytes_be(&sec_bin[1..]);
+            if x >= p {
+                return Err(EccError::InvalidSec);
+            }
             let x = FFElement::new_secp256k1(&x);
 
             // right side of the equation y^2 = x^3 + 7
@@ -202,8 +222,82 @@ impl ECPoint {
             }
         }
 
-        Err("Invalid SEC format".to_string())
+        Err(EccError::InvalidSec)
+    }
+
+    /// BIP340 x-only public key: the 32-byte x coordinate
+    pub fn to_xonly(&self) -> [u8; 32] {
+        to_32_bytes(self.x().unwrap().num())
+    }
+
+    /// Returns the point with the given x coordinate and an even y coordinate
+    pub fn from_xonly(x: &[u8]) -> Result<Self, EccError> {
+        if x.len() != 32 || BigUint::from_bytes_be(x) >= Secp256k1Params::p() {
+            return Err(EccError::InvalidXOnlyKey);
+        }
+        let mut sec = vec![2u8];
+        sec.extend_from_slice(x);
+        Self::parse(&sec).map_err(|_| EccError::InvalidXOnlyKey)
+    }
+
+    /// Returns true if the y coordinate is even
+    pub fn has_even_y(&self) -> bool {
+        self.y().unwrap().num().is_even()
+    }
+
+    /// Verifies a BIP340 Schnorr signature against the x-only form of this
+    /// point
+    pub fn verify_schnorr(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+        if signature.len() != 64 || self.is_infinity() {
+            return false;
+        }
+        let Ok(point) = Self::from_xonly(&self.to_xonly()) else {
+            return false;
+        };
+        let n = Secp256k1Params::n();
+        let r: [u8; 32] = signature[..32].try_into().unwrap();
+        let s = BigUint::from_bytes_be(&signature[32..]);
+        if BigUint::from_bytes_be(&r) >= Secp256k1Params::p() || s >= n {
+            return false;
+        }
+        let e = schnorr_challenge(&r, &point.to_xonly(), message);
+        // R = s*G - e*P = s*G + (n - e)*P
+        let big_r = Secp256k1Params::g() * s + point * ((&n - e) % &n);
+        !big_r.is_infinity() && big_r.has_even_y() && big_r.to_xonly() == r
+    }
+
+    /// BIP341 output key Q = P + t*G, where P is this key with an even y and
+    /// t = hash_TapTweak(x(P) || merkle_root)
+    pub fn taproot_tweak(&self, merkle_root: Option<&[u8; 32]>) -> Result<Self, EccError> {
+        let internal_key = Self::from_xonly(&self.to_xonly())?;
+        let tweak = BigUint::from_bytes_be(&taproot_tweak_hash(&self.to_xonly(), merkle_root));
+        if tweak >= Secp256k1Params::n() {
+            return Err(EccError::TweakOutOfRange);
+        }
+        let output_key = internal_key + Secp256k1Params::g() * tweak;
+        if output_key.is_infinity() {
+            return Err(EccError::TweakedToInfinity);
+        }
+        Ok(output_key)
+    }
+}
+
+/// hash_TapTweak(x(P) || merkle_root), the merkle root being omitted for
+/// key-path-only outputs
+pub fn taproot_tweak_hash(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> [u8; 32] {
+    let mut msg = internal_key.to_vec();
+    if let Some(merkle_root) = merkle_root {
+        msg.extend_from_slice(merkle_root);
     }
+    tagged_hash("TapTweak", &msg)
+}
+
+/// Big-endian bytes of a coordinate, left-padded with zeros to 32 bytes
+fn to_32_bytes(num: &BigUint) -> [u8; 32] {
+    let bytes = num.to_bytes_be();
+    let mut result = [0u8; 32];
+    result[32 - bytes.len()..].copy_from_slice(&bytes);
+    result
 }
 
 impl std::ops::Add for ECPoint {
@@ -368,32 +462,32 @@ mod tests {
 
     #[test]
     fn test_point_on_curve() {
-        let field = FiniteField::new(&BigUint::from(223u32));
-        let a = FFElement::new(&BigUint::from(0u32), &field);
-        let b = FFElement::new(&BigUint::from(7u32), &field);
+        let field = FiniteField::new(&BigUint::from(223u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(0u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         let curve = EllipticCurve::new(a, b);
 
         let valid_points = vec![(192_u32, 105_u32), (17, 56), (1, 193)];
         let invalid_points = vec![(200_u32, 119_u32), (42, 99)];
 
         for (x, y) in valid_points {
-            let x = FFElement::new(&BigUint::from(x), &field);
-            let y = FFElement::new(&BigUint::from(y), &field);
+            let x = FFElement::new(&BigUint::from(x), &field).unwrap();
+            let y = FFElement::new(&BigUint::from(y), &field).unwrap();
             ECPoint::new(&x, &y, &curve).unwrap();
         }
 
         for (x, y) in invalid_points {
-            let x = FFElement::new(&BigUint::from(x), &field);
-            let y = FFElement::new(&BigUint::from(y), &field);
+            let x = FFElement::new(&BigUint::from(x), &field).unwrap();
+            let y = FFElement::new(&BigUint::from(y), &field).unwrap();
             assert!(ECPoint::new(&x, &y, &curve).is_err());
         }
     }
 
     #[test]
     fn test_add() {
-        let field = FiniteField::new(&BigUint::from(223_u32));
-        let a = FFElement::new(&BigUint::from(0u32), &field);
-        let b = FFElement::new(&BigUint::from(7u32), &field);
+        let field = FiniteField::new(&BigUint::from(223_u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(0u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         let curve = EllipticCurve::new(a, b);
 
         let additions: Vec<(u32, u32, u32, u32, u32, u32)> = vec![
@@ -404,16 +498,16 @@ mod tests {
         ];
 
         for (x1_raw, y1_raw, x2_raw, y2_raw, x3_raw, y3_raw) in additions {
-            let x1 = FFElement::new(&BigUint::from(x1_raw), &field);
-            let y1 = FFElement::new(&BigUint::from(y1_raw), &field);
+            let x1 = FFElement::new(&BigUint::from(x1_raw), &field).unwrap();
+            let y1 = FFElement::new(&BigUint::from(y1_raw), &field).unwrap();
             let p1 = ECPoint::new(&x1, &y1, &curve).unwrap();
 
-            let x2 = FFElement::new(&BigUint::from(x2_raw), &field);
-            let y2 = FFElement::new(&BigUint::from(y2_raw), &field);
+            let x2 = FFElement::new(&BigUint::from(x2_raw), &field).unwrap();
+            let y2 = FFElement::new(&BigUint::from(y2_raw), &field).unwrap();
             let p2 = ECPoint::new(&x2, &y2, &curve).unwrap();
 
-            let x3 = FFElement::new(&BigUint::from(x3_raw), &field);
-            let y3 = FFElement::new(&BigUint::from(y3_raw), &field);
+            let x3 = FFElement::new(&BigUint::from(x3_raw), &field).unwrap();
+            let y3 = FFElement::new(&BigUint::from(y3_raw), &field).unwrap();
             let p3 = ECPoint::new(&x3, &y3, &curve).unwrap();
 
             assert_eq!(p1 + p2, p3);
@@ -422,9 +516,9 @@ mod tests {
 
     #[test]
     fn test_rmul() {
-        let field = FiniteField::new(&BigUint::from(223_u32));
-        let a = FFElement::new(&BigUint::from(0u32), &field);
-        let b = FFElement::new(&BigUint::from(7u32), &field);
+        let field = FiniteField::new(&BigUint::from(223_u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(0u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         let curve = EllipticCurve::new(a, b);
 
         let multiplications: Vec<(u32, u32, u32, u32, u32)> = vec![
@@ -438,15 +532,15 @@ mod tests {
         ];
 
         for (s, x1_raw, y1_raw, x2_raw, y2_raw) in multiplications {
-            let x1 = FFElement::new(&BigUint::from(x1_raw), &field);
-            let y1 = FFElement::new(&BigUint::from(y1_raw), &field);
+            let x1 = FFElement::new(&BigUint::from(x1_raw), &field).unwrap();
+            let y1 = FFElement::new(&BigUint::from(y1_raw), &field).unwrap();
             let p1 = ECPoint::new(&x1, &y1, &curve).unwrap();
 
             let p2 = if x2_raw == 0 && y2_raw == 0 {
                 ECPoint::new_infinity(&curve)
             } else {
-                let x2 = FFElement::new(&BigUint::from(x2_raw), &field);
-                let y2 = FFElement::new(&BigUint::from(y2_raw), &field);
+                let x2 = FFElement::new(&BigUint::from(x2_raw), &field).unwrap();
+                let y2 = FFElement::new(&BigUint::from(y2_raw), &field).unwrap();
                 ECPoint::new(&x2, &y2, &curve).unwrap()
             };
 
@@ -456,9 +550,9 @@ mod tests {
 
     #[test]
     fn test_rmul_biguint() {
-        let field = FiniteField::new(&BigUint::from(223_u32));
-        let a = FFElement::new(&BigUint::from(0u32), &field);
-        let b = FFElement::new(&BigUint::from(7u32), &field);
+        let field = FiniteField::new(&BigUint::from(223_u32)).unwrap();
+        let a = FFElement::new(&BigUint::from(0u32), &field).unwrap();
+        let b = FFElement::new(&BigUint::from(7u32), &field).unwrap();
         let curve = EllipticCurve::new(a, b);
 
         let multiplications: Vec<(BigUint, u32, u32, u32, u32)> = vec![
@@ -472,15 +566,15 @@ mod tests {
         ];
 
         for (s, x1_raw, y1_raw, x2_raw, y2_raw) in multiplications {
-            let x1 = FFElement::new(&BigUint::from(x1_raw), &field);
-            let y1 = FFElement::new(&BigUint::from(y1_raw), &field);
+            let x1 = FFElement::new(&BigUint::from(x1_raw), &field).unwrap();
+            let y1 = FFElement::new(&BigUint::from(y1_raw), &field).unwrap();
             let p1 = ECPoint::new(&x1, &y1, &curve).unwrap();
 
             let p2 = if x2_raw == 0 && y2_raw == 0 {
                 ECPoint::new_infinity(&curve)
             } else {
-                let x2 = FFElement::new(&BigUint::from(x2_raw), &field);
-                let y2 = FFElement::new(&BigUint::from(y2_raw), &field);
+                let x2 = FFElement::new(&BigUint::from(x2_raw), &field).unwrap();
+                let y2 = FFElement::new(&BigUint::from(y2_raw), &field).unwrap();
                 ECPoint::new(&x2, &y2, &curve).unwrap()
             };
 
@@ -632,6 +726,25 @@ mod tests {
         assert_eq!(ECPoint::parse(&point.to_compressed_sec()).unwrap(), point);
     }
 
+    #[test]
+    fn test_parse_invalid_sec() {
+        let sec = (Secp256k1Params::g() * BigUint::from(5001u32)).to_compressed_sec();
+        let mut bad_prefix = sec.clone();
+        bad_prefix[0] = 0x04;
+        assert_eq!(ECPoint::parse(&bad_prefix), Err(EccError::InvalidSec));
+        assert_eq!(ECPoint::parse(&sec[..32]), Err(EccError::InvalidSec));
+
+        // x at the field order
+        let mut out_of_range = vec![0x02];
+        out_of_range.extend(to_32_bytes(&Secp256k1Params::p()));
+        assert_eq!(ECPoint::parse(&out_of_range), Err(EccError::InvalidSec));
+
+        // x = 5 has no y, 5^3 + 7 is not a square
+        let mut no_y = vec![0x02; 33];
+        no_y[1..].copy_from_slice(&to_32_bytes(&BigUint::from(5u32)));
+        assert_eq!(ECPoint::parse(&no_y), Err(EccError::PointNotOnCurve));
+    }
+
     #[test]
     fn test_address_exercise_5() {
         let point = Secp256k1Params::g() * BigUint::from(5002u32);
@@ -674,4 +787,36 @@ mod tests {
             assert_eq!(point.get_address(compressed, testnet), address);
         }
     }
+
+    #[test]
+    fn test_taproot_tweak() {
+        // BIP86 test vector for m/86'/0'/0'/0/0
+        let internal_key = ECPoint::from_xonly(
+            &hex::decode("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+                .unwrap(),
+        )
+        .unwrap();
+        let output_key = internal_key.taproot_tweak(None).unwrap();
+        assert_eq!(
+            hex::encode(output_key.to_xonly()),
+            "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+        );
+    }
+
+    #[test]
+    fn test_sec_pads_short_coordinates() {
+        // The x coordinate of (n+1)/2 * G starts with ten zero bytes
+        let x = FFElement::new_secp256k1(
+            &BigUint::from_str_radix(
+                "00000000000000000000003b78ce563f89a0ed9414f5aa28ad0d96d6795f9c63",
+                16,
+            )
+            .unwrap(),
+        );
+        let y = x.pow(3) + FFElement::new_secp256k1(&Secp256k1Params::b());
+        let point = ECPoint::new_secp256k1(&x, &y.sqrt()).unwrap();
+        assert_eq!(point.to_compressed_sec().len(), 33);
+        assert_eq!(point.to_uncompressed_sec().len(), 65);
+        assert_eq!(ECPoint::parse(&point.to_compressed_sec()).unwrap(), point);
+    }
 }
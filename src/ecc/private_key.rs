@@ -3,9 +3,17 @@ use num::BigUint;
 use rfc6979::consts::U32;
 use sha2::{digest::generic_array::GenericArray, Digest, Sha256};
 
-use crate::utils::encode_base58::encode_base58_checksum;
-
-use super::{secp256k1_params::Secp256k1Params, signature::Signature};
+use crate::utils::{
+    encode_base58::{decode_base58_checksum, encode_base58_checksum},
+    tagged_hash::tagged_hash,
+};
+
+use super::{
+    point::{taproot_tweak_hash, ECPoint},
+    secp256k1_params::Secp256k1Params,
+    signature::Signature,
+    EccError,
+};
 
 /// PrivateKey is a wrapper around a secret number.
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -20,6 +28,24 @@ impl PrivateKey {
             secret: secret.clone(),
         }
     }
+
+    /// Returns the secret number.
+    pub fn secret(&self) -> &BigUint {
+        &self.secret
+    }
+
+    /// Returns the secret as 32 big-endian bytes.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let bytes = self.secret.to_bytes_be();
+        let mut result = [0u8; 32];
+        result[32 - bytes.len()..].copy_from_slice(&bytes);
+        result
+    }
+
+    /// Returns the public key, the point secret * G.
+    pub fn point(&self) -> ECPoint {
+        Secp256k1Params::g() * self.secret.clone()
+    }
 }
 
 impl std::fmt::Display for PrivateKey {
@@ -61,13 +87,12 @@ impl PrivateKey {
         let mut p = GenericArray::<u8, U32>::default();
         p.copy_from_slice(p_bytes.as_slice());
 
-        let k_bytes = self.secret.to_bytes_be();
-        let mut k = GenericArray::<u8, U32>::default();
-        k.copy_from_slice(&k_bytes);
+        // Both numbers may have leading zero bytes, so left-pad them to 32 bytes
+        let k = GenericArray::<u8, U32>::from(self.to_bytes());
 
         let z_bytes = z.to_bytes_be();
         let mut z = GenericArray::<u8, U32>::default();
-        z.copy_from_slice(&z_bytes);
+        z[32 - z_bytes.len()..].copy_from_slice(&z_bytes);
 
         let h = Sha256::digest(z);
 
@@ -79,10 +104,7 @@ impl PrivateKey {
     /// Returns the WIF (Wallet Import Format) for the private key.
     pub fn to_wif(&self, compressed: bool, testnet: bool) -> String {
         // create a fixed size array of 32 bytes
-        let mut bytes = self.secret.to_bytes_be();
-        bytes.reverse();
-        bytes.resize(32, 0);
-        bytes.reverse();
+        let bytes = self.to_bytes();
 
         // add the prefix
         let prefix = if testnet { 0xef } else { 0x80 };
@@ -98,6 +120,98 @@ impl PrivateKey {
         // encode the result in base58
         encode_base58_checksum(&wif)
     }
+
+    /// Parses a WIF private key, returning the key and whether it is
+    /// compressed and for testnet.
+    pub fn from_wif(wif: &str) -> Result<(Self, bool, bool), EccError> {
+        let bytes = decode_base58_checksum(wif).map_err(EccError::InvalidWif)?;
+        let testnet = match bytes.first() {
+            Some(0x80) => false,
+            Some(0xef) => true,
+            _ => return Err(EccError::InvalidWif("unknown prefix".to_string())),
+        };
+        let compressed = match bytes.len() {
+            33 => false,
+            34 if bytes[33] == 0x01 => true,
+            _ => return Err(EccError::InvalidWif("invalid length".to_string())),
+        };
+        let secret = BigUint::from_bytes_be(&bytes[1..33]);
+        if secret == BigUint::from(0u32) || secret >= Secp256k1Params::n() {
+            return Err(EccError::SecretOutOfRange);
+        }
+        Ok((Self::new(&secret), compressed, testnet))
+    }
+}
+
+/// BIP340 Schnorr signatures
+impl PrivateKey {
+    /// Signs a 32-byte message, mixing the auxiliary random data into the
+    /// nonce. Returns the 64-byte signature r || s.
+    pub fn sign_schnorr(&self, message: &[u8; 32], aux_rand: &[u8; 32]) -> [u8; 64] {
+        let n = Secp256k1Params::n();
+        let g = Secp256k1Params::g();
+
+        // The signing key must correspond to the point with an even y
+        let point = self.point();
+        let d = if point.has_even_y() {
+            self.secret.clone()
+        } else {
+            &n - &self.secret
+        };
+
+        let mut t = tagged_hash("BIP0340/aux", aux_rand);
+        let d_bytes = PrivateKey::new(&d).to_bytes();
+        for (byte, d_byte) in t.iter_mut().zip(d_bytes) {
+            *byte ^= d_byte;
+        }
+
+        let mut nonce_msg = t.to_vec();
+        nonce_msg.extend_from_slice(&point.to_xonly());
+        nonce_msg.extend_from_slice(message);
+        let k0 = BigUint::from_bytes_be(&tagged_hash("BIP0340/nonce", &nonce_msg)) % &n;
+        // Only possible with negligible probability
+        assert!(k0 != BigUint::from(0u32), "BIP340 nonce is zero");
+
+        let r = g * k0.clone();
+        let k = if r.has_even_y() { k0 } else { &n - k0 };
+
+        let e = schnorr_challenge(&r.to_xonly(), &point.to_xonly(), message);
+        let s = (k + e * d) % &n;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&r.to_xonly());
+        signature[32..].copy_from_slice(&PrivateKey::new(&s).to_bytes());
+        signature
+    }
+
+    /// Returns the key that signs for the BIP341 output key of this internal
+    /// key, see ECPoint::taproot_tweak
+    pub fn taproot_tweak(&self, merkle_root: Option<&[u8; 32]>) -> Result<Self, EccError> {
+        let n = Secp256k1Params::n();
+        let point = self.point();
+        let tweak = BigUint::from_bytes_be(&taproot_tweak_hash(&point.to_xonly(), merkle_root));
+        if tweak >= n {
+            return Err(EccError::TweakOutOfRange);
+        }
+        let d = if point.has_even_y() {
+            self.secret.clone()
+        } else {
+            &n - &self.secret
+        };
+        let secret = (d + tweak) % &n;
+        if secret == BigUint::from(0u32) {
+            return Err(EccError::TweakedToInfinity);
+        }
+        Ok(Self::new(&secret))
+    }
+}
+
+/// hash_BIP0340/challenge(r || P || m) as a number modulo n
+pub(crate) fn schnorr_challenge(r: &[u8; 32], pubkey: &[u8; 32], message: &[u8; 32]) -> BigUint {
+    let mut msg = r.to_vec();
+    msg.extend_from_slice(pubkey);
+    msg.extend_from_slice(message);
+    BigUint::from_bytes_be(&tagged_hash("BIP0340/challenge", &msg)) % Secp256k1Params::n()
 }
 
 #[cfg(test)]
@@ -147,4 +261,45 @@ mod tests {
             "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a"
         );
     }
+
+    #[test]
+    fn test_from_wif() {
+        let values = vec![
+            (5003_u64, true, true),
+            (0x54321deadbeef_u64, true, false),
+            (12345_u64, false, false),
+        ];
+        for (secret, compressed, testnet) in values {
+            let pk = PrivateKey::new(&BigUint::from(secret));
+            let wif = pk.to_wif(compressed, testnet);
+            assert_eq!(
+                PrivateKey::from_wif(&wif).unwrap(),
+                (pk, compressed, testnet)
+            );
+        }
+        assert!(PrivateKey::from_wif("1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1").is_err());
+    }
+
+    #[test]
+    fn test_sign_schnorr() {
+        // BIP340 test vector 0
+        let pk = PrivateKey::new(&BigUint::from(3u32));
+        let sig = pk.sign_schnorr(&[0u8; 32], &[0u8; 32]);
+        assert_eq!(
+            hex::encode(sig),
+            "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0"
+        );
+        assert!(pk.point().verify_schnorr(&[0u8; 32], &sig));
+        assert!(!pk.point().verify_schnorr(&[1u8; 32], &sig));
+    }
+
+    #[test]
+    fn test_taproot_tweak() {
+        let pk = PrivateKey::new(&BigUint::from(3u32));
+        let tweaked = pk.taproot_tweak(None).unwrap();
+        assert_eq!(
+            tweaked.point().to_xonly(),
+            pk.point().taproot_tweak(None).unwrap().to_xonly()
+        );
+    }
 }
@@ -2,6 +2,8 @@ use core::fmt;
 
 use num::BigUint;
 
+use super::EccError;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Signature {
     r: BigUint,
@@ -71,6 +73,90 @@ impl Signature {
 
         der
     }
+
+    /// Parses a DER encoded signature
+    pub fn parse(der: &[u8]) -> Result<Self, EccError> {
+        let invalid = || EccError::InvalidDerSignature;
+        if der.len() < 8 || der[0] != 0x30 || der[1] as usize != der.len() - 2 {
+            return Err(invalid());
+        }
+        let mut rest = &der[2..];
+        let mut numbers = vec![];
+        for _ in 0..2 {
+            if rest.len() < 2 || rest[0] != 0x02 {
+                return Err(invalid());
+            }
+            let length = rest[1] as usize;
+            let number = rest.get(2..2 + length).ok_or_else(invalid)?;
+            numbers.push(BigUint::from_bytes_be(number));
+            rest = &rest[2 + length..];
+        }
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::new(&numbers[0], &numbers[1]))
+    }
+
+    /// Parses a signature as Bitcoin Core did before BIP66, which consensus
+    /// still accepts without DERSIG: lengths may use the long form, integers
+    /// may have extra leading zeros, and bytes after S are ignored. Integers
+    /// over 32 bytes fail.
+    pub fn parse_lax(der: &[u8]) -> Result<Self, EccError> {
+        if der.first() != Some(&0x30) {
+            return Err(EccError::InvalidDerSignature);
+        }
+        // The sequence length isn't checked, only skipped
+        let mut pos = 2;
+        match der.get(1) {
+            Some(&length) if length & 0x80 != 0 => pos += (length & 0x7f) as usize,
+            Some(_) => {}
+            None => return Err(EccError::InvalidDerSignature),
+        }
+        if pos > der.len() {
+            return Err(EccError::InvalidDerSignature);
+        }
+        let (r, pos) = parse_lax_integer(der, pos)?;
+        let (s, _) = parse_lax_integer(der, pos)?;
+        Ok(Self::new(&r, &s))
+    }
+}
+
+/// Parses a DER integer starting at pos, returning it and the position
+/// after it
+fn parse_lax_integer(der: &[u8], mut pos: usize) -> Result<(BigUint, usize), EccError> {
+    let invalid = || EccError::InvalidDerSignature;
+    if der.get(pos) != Some(&0x02) {
+        return Err(invalid());
+    }
+    let length_byte = *der.get(pos + 1).ok_or_else(invalid)?;
+    pos += 2;
+    let length = if length_byte & 0x80 != 0 {
+        let length_bytes = der
+            .get(pos..pos + (length_byte & 0x7f) as usize)
+            .ok_or_else(invalid)?;
+        pos += length_bytes.len();
+        let significant: Vec<u8> = length_bytes
+            .iter()
+            .copied()
+            .skip_while(|&b| b == 0)
+            .collect();
+        if significant.len() >= std::mem::size_of::<usize>() {
+            return Err(invalid());
+        }
+        significant
+            .iter()
+            .fold(0usize, |acc, &byte| (acc << 8) | byte as usize)
+    } else {
+        length_byte as usize
+    };
+    let bytes = der
+        .get(pos..pos.checked_add(length).ok_or_else(invalid)?)
+        .ok_or_else(invalid)?;
+    let first = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    if bytes.len() - first > 32 {
+        return Err(invalid());
+    }
+    Ok((BigUint::from_bytes_be(bytes), pos + length))
 }
 
 impl fmt::Display for Signature {
@@ -108,5 +194,23 @@ mod tests {
             )
             .unwrap()
         );
+        assert_eq!(Signature::parse(&sig.der()).unwrap(), sig);
+        assert!(Signature::parse(&sig.der()[..40]).is_err());
+    }
+
+    #[test]
+    fn test_parse_lax() {
+        let sig = Signature::new(&BigUint::from(0x81u32), &BigUint::from(5u32));
+        assert_eq!(Signature::parse_lax(&sig.der()).unwrap(), sig);
+        // Long form lengths, padded integers and trailing bytes
+        let lax = hex::decode("30810a028103000081020200050101").unwrap();
+        assert_eq!(Signature::parse_lax(&lax).unwrap(), sig);
+        assert!(Signature::parse(&lax).is_err());
+        // Integers must fit in 32 bytes
+        let mut long = vec![0x30, 0x26, 0x02, 0x21, 0x01];
+        long.extend([0; 32]);
+        long.extend([0x02, 0x01, 0x05]);
+        assert!(Signature::parse_lax(&long).is_err());
+        assert!(Signature::parse_lax(&[0x30]).is_err());
     }
 }
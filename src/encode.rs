@@ -0,0 +1,472 @@
+//! Consensus encoding: the byte serialization of the wire types, over any
+//! reader or writer so large inputs like block files can be streamed.
+use std::{
+    fmt,
+    io::{self, Cursor, Read, Write},
+};
+
+/// The largest length prefix accepted when decoding, the same bound as
+/// Bitcoin Core's MAX_SIZE. Larger values can only come from corrupt or
+/// hostile data.
+pub const MAX_SIZE: u64 = 0x0200_0000;
+
+/// What went wrong decoding
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DecodeErrorKind {
+    /// The data ends in the middle of a value
+    UnexpectedEof,
+    /// A length or count prefix above MAX_SIZE
+    OversizedLength(u64),
+    /// A VarInt with a longer encoding than its value needs
+    NonCanonicalCompactSize,
+    /// A zero input count not followed by the segwit flag 0x01
+    InvalidSegwitFlag(u8),
+    /// The segwit flag is set but no input has a witness
+    SuperfluousWitness,
+    /// The pushes of a script run past its length prefix
+    ScriptLengthMismatch,
+    /// Bytes remain after the value
+    TrailingData,
+    /// The reader failed
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErrorKind::UnexpectedEof => write!(f, "unexpected end of data"),
+            DecodeErrorKind::OversizedLength(length) => {
+                write!(f, "length {} exceeds the maximum", length)
+            }
+            DecodeErrorKind::NonCanonicalCompactSize => write!(f, "non-canonical compact size"),
+            DecodeErrorKind::InvalidSegwitFlag(flag) => {
+                write!(f, "invalid segwit flag {:#04x}", flag)
+            }
+            DecodeErrorKind::SuperfluousWitness => write!(f, "superfluous witness record"),
+            DecodeErrorKind::ScriptLengthMismatch => {
+                write!(f, "script pushes run past its length")
+            }
+            DecodeErrorKind::TrailingData => write!(f, "data not consumed entirely"),
+            DecodeErrorKind::Io(kind) => write!(f, "read failed: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for DecodeErrorKind {}
+
+impl From<DecodeErrorKind> for io::Error {
+    /// Carries the kind through the io::Result of the Decodable impls
+    fn from(kind: DecodeErrorKind) -> Self {
+        match kind {
+            DecodeErrorKind::UnexpectedEof => io::ErrorKind::UnexpectedEof.into(),
+            DecodeErrorKind::Io(kind) => kind.into(),
+            kind => io::Error::new(io::ErrorKind::InvalidData, kind),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeErrorKind {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            return DecodeErrorKind::UnexpectedEof;
+        }
+        let kind = err.kind();
+        match err
+            .into_inner()
+            .map(|inner| inner.downcast::<DecodeErrorKind>())
+        {
+            Some(Ok(inner)) => *inner,
+            _ => DecodeErrorKind::Io(kind),
+        }
+    }
+}
+
+/// A decoding failure and the byte offset it was detected at
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodeError {
+    pub offset: u64,
+    pub kind: DecodeErrorKind,
+}
+
+impl DecodeError {
+    pub fn new(offset: u64, kind: impl Into<DecodeErrorKind>) -> Self {
+        DecodeError {
+            offset,
+            kind: kind.into(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A type with a consensus serialization
+pub trait Encodable {
+    /// Writes the serialization, returning the number of bytes written
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize>;
+}
+
+/// A type that can be read from its consensus serialization
+pub trait Decodable: Sized {
+    /// Reads the serialization, consuming only the bytes that belong to it
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self>;
+}
+
+/// Returns the serialization of a value
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut result = Vec::new();
+    value
+        .consensus_encode(&mut result)
+        .expect("writing to a vector does not fail");
+    result
+}
+
+/// Decodes a value from bytes, which must hold nothing else
+pub fn deserialize<T: Decodable>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let (value, consumed) = deserialize_partial(bytes)?;
+    if consumed != bytes.len() {
+        return Err(DecodeError::new(
+            consumed as u64,
+            DecodeErrorKind::TrailingData,
+        ));
+    }
+    Ok(value)
+}
+
+/// Decodes a value from the start of the bytes, returning it along with
+/// the number of bytes read
+pub fn deserialize_partial<T: Decodable>(bytes: &[u8]) -> Result<(T, usize), DecodeError> {
+    let mut cursor = Cursor::new(bytes);
+    let value = decode_cursor(&mut cursor)?;
+    Ok((value, cursor.position() as usize))
+}
+
+/// Decodes a value from a cursor, reporting errors at the cursor position
+pub fn decode_cursor<T: Decodable, B: AsRef<[u8]>>(
+    cursor: &mut Cursor<B>,
+) -> Result<T, DecodeError> {
+    T::consensus_decode(cursor).map_err(|err| DecodeError::new(cursor.position(), err))
+}
+
+/// Decodes a value from a reader, reporting errors at the number of bytes
+/// read from it
+pub fn decode_from<T: Decodable, R: Read>(reader: R) -> Result<T, DecodeError> {
+    let mut reader = CountingReader {
+        inner: reader,
+        count: 0,
+    };
+    T::consensus_decode(&mut reader).map_err(|err| DecodeError::new(reader.count, err))
+}
+
+/// Counts the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// A variable length integer, as used for counts and length prefixes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VarInt(pub u64);
+
+impl VarInt {
+    /// Returns the number of bytes of the encoding
+    pub fn size(&self) -> usize {
+        match self.0 {
+            0..=0xfc => 1,
+            0xfd..=0xffff => 3,
+            0x10000..=0xffff_ffff => 5,
+            _ => 9,
+        }
+    }
+
+    /// Reads a VarInt without checking that it is minimally encoded, as
+    /// read_varint always has
+    pub fn decode_non_canonical<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let value = match u8::consensus_decode(reader)? {
+            0xfd => u64::from(u16::consensus_decode(reader)?),
+            0xfe => u64::from(u32::consensus_decode(reader)?),
+            0xff => u64::consensus_decode(reader)?,
+            i => u64::from(i),
+        };
+        Ok(VarInt(value))
+    }
+}
+
+impl Encodable for VarInt {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        match self.0 {
+            0..=0xfc => (self.0 as u8).consensus_encode(writer),
+            0xfd..=0xffff => {
+                writer.write_all(&[0xfd])?;
+                (self.0 as u16).consensus_encode(writer)?;
+                Ok(3)
+            }
+            0x10000..=0xffff_ffff => {
+                writer.write_all(&[0xfe])?;
+                (self.0 as u32).consensus_encode(writer)?;
+                Ok(5)
+            }
+            _ => {
+                writer.write_all(&[0xff])?;
+                self.0.consensus_encode(writer)?;
+                Ok(9)
+            }
+        }
+    }
+}
+
+impl Decodable for VarInt {
+    /// Rejects values that fit a shorter encoding, like Bitcoin Core
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let (value, min) = match u8::consensus_decode(reader)? {
+            0xfd => (u64::from(u16::consensus_decode(reader)?), 0xfd),
+            0xfe => (u64::from(u32::consensus_decode(reader)?), 0x1_0000),
+            0xff => (u64::consensus_decode(reader)?, 0x1_0000_0000),
+            i => return Ok(VarInt(u64::from(i))),
+        };
+        if value < min {
+            return Err(DecodeErrorKind::NonCanonicalCompactSize.into());
+        }
+        Ok(VarInt(value))
+    }
+}
+
+/// Reads a length or count prefix, rejecting values above MAX_SIZE
+pub fn read_length<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let VarInt(length) = VarInt::consensus_decode(reader)?;
+    if length > MAX_SIZE {
+        return Err(DecodeErrorKind::OversizedLength(length).into());
+    }
+    Ok(length)
+}
+
+/// Reads exactly `length` bytes. The buffer grows as data arrives rather
+/// than being allocated up front, so a bogus length prefix cannot exhaust
+/// memory.
+pub fn read_bytes<R: Read + ?Sized>(reader: &mut R, length: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < length {
+        return Err(DecodeErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+macro_rules! impl_int_encodable {
+    ($($int:ty),*) => {
+        $(
+            impl Encodable for $int {
+                fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                    writer.write_all(&self.to_le_bytes())?;
+                    Ok(std::mem::size_of::<$int>())
+                }
+            }
+
+            impl Decodable for $int {
+                fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+                    let mut buf = [0; std::mem::size_of::<$int>()];
+                    reader.read_exact(&mut buf)?;
+                    Ok(<$int>::from_le_bytes(buf))
+                }
+            }
+        )*
+    };
+}
+
+impl_int_encodable!(u8, u16, u32, u64, i32, i64);
+
+impl<const N: usize> Encodable for [u8; N] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self)?;
+        Ok(N)
+    }
+}
+
+impl<const N: usize> Decodable for [u8; N] {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let mut buf = [0; N];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Byte strings are prefixed with their length
+impl Encodable for [u8] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let len = VarInt(self.len() as u64).consensus_encode(writer)?;
+        writer.write_all(self)?;
+        Ok(len + self.len())
+    }
+}
+
+impl Encodable for Vec<u8> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.as_slice().consensus_encode(writer)
+    }
+}
+
+impl Decodable for Vec<u8> {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let length = read_length(reader)?;
+        read_bytes(reader, length)
+    }
+}
+
+/// Implements encoding for slices and vectors of the types, as a count
+/// followed by the items
+macro_rules! impl_vec_encodable {
+    ($($ty:ty),*) => {
+        $(
+            impl $crate::encode::Encodable for [$ty] {
+                fn consensus_encode<W: std::io::Write + ?Sized>(
+                    &self,
+                    writer: &mut W,
+                ) -> std::io::Result<usize> {
+                    let mut len = $crate::encode::VarInt(self.len() as u64).consensus_encode(writer)?;
+                    for item in self {
+                        len += item.consensus_encode(writer)?;
+                    }
+                    Ok(len)
+                }
+            }
+
+            impl $crate::encode::Encodable for Vec<$ty> {
+                fn consensus_encode<W: std::io::Write + ?Sized>(
+                    &self,
+                    writer: &mut W,
+                ) -> std::io::Result<usize> {
+                    self.as_slice().consensus_encode(writer)
+                }
+            }
+
+            impl $crate::encode::Decodable for Vec<$ty> {
+                fn consensus_decode<R: std::io::Read + ?Sized>(
+                    reader: &mut R,
+                ) -> std::io::Result<Self> {
+                    let count = $crate::encode::read_length(reader)?;
+                    // Not preallocated, the count is untrusted
+                    let mut items = Vec::new();
+                    for _ in 0..count {
+                        items.push(<$ty>::consensus_decode(reader)?);
+                    }
+                    Ok(items)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_vec_encodable;
+
+impl_vec_encodable!(Vec<u8>);
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for (value, len) in [
+            (0, 1),
+            (0xfc, 1),
+            (0xfd, 3),
+            (0xffff, 3),
+            (0x10000, 5),
+            (0xffff_ffff, 5),
+            (0x1_0000_0000, 9),
+            (u64::MAX, 9),
+        ] {
+            let bytes = serialize(&VarInt(value));
+            assert_eq!(bytes.len(), len);
+            assert_eq!(VarInt(value).size(), len);
+            assert_eq!(deserialize::<VarInt>(&bytes).unwrap(), VarInt(value));
+        }
+    }
+
+    #[test]
+    fn test_byte_strings() {
+        let items = vec![vec![], vec![1, 2, 3], vec![0xab; 300]];
+        let bytes = serialize(&items);
+        assert_eq!(bytes[..3], [3, 0, 3]);
+        let mut reader = BufReader::new(bytes.as_slice());
+        assert_eq!(
+            Vec::<Vec<u8>>::consensus_decode(&mut reader).unwrap(),
+            items
+        );
+
+        // Trailing data and truncation
+        let mut bytes = serialize(&vec![1u8, 2]);
+        assert_eq!(deserialize::<Vec<u8>>(&bytes).unwrap(), [1, 2]);
+        bytes.push(0);
+        assert_eq!(
+            deserialize::<Vec<u8>>(&bytes),
+            Err(DecodeError::new(3, DecodeErrorKind::TrailingData))
+        );
+        assert_eq!(deserialize_partial::<Vec<u8>>(&bytes).unwrap().1, 3);
+        assert_eq!(
+            decode_from::<Vec<u8>, _>([5, 1, 2].as_slice()),
+            Err(DecodeError::new(3, DecodeErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_varint() {
+        for value in [0xfc, 0xfd, 0xffff, 0x1_0000, 0xffff_ffff, 0x1_0000_0000] {
+            assert_eq!(
+                deserialize::<VarInt>(&serialize(&VarInt(value))).unwrap().0,
+                value
+            );
+        }
+        let cases: [&[u8]; 4] = [
+            &[0xfd, 0x02, 0x00],
+            &[0xfd, 0xfc, 0x00],
+            &[0xfe, 0xff, 0xff, 0x00, 0x00],
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00],
+        ];
+        for bytes in cases {
+            assert_eq!(
+                deserialize::<VarInt>(bytes),
+                Err(DecodeError::new(
+                    bytes.len() as u64,
+                    DecodeErrorKind::NonCanonicalCompactSize
+                ))
+            );
+            let value = VarInt::decode_non_canonical(&mut &bytes[..]).unwrap();
+            assert!(value.size() < bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_hostile_lengths() {
+        // A length just under the maximum with no data behind it
+        let bytes = serialize(&VarInt(MAX_SIZE));
+        assert_eq!(
+            deserialize::<Vec<u8>>(&bytes),
+            Err(DecodeError::new(5, DecodeErrorKind::UnexpectedEof))
+        );
+
+        let bytes = serialize(&VarInt(MAX_SIZE + 1));
+        assert_eq!(
+            deserialize::<Vec<Vec<u8>>>(&bytes),
+            Err(DecodeError::new(
+                5,
+                DecodeErrorKind::OversizedLength(MAX_SIZE + 1)
+            ))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! The crate-wide error, wrapping the error of each module so callers
+//! mixing several APIs can use `?` throughout.
+use std::fmt;
+
+use crate::{
+    amount::ParseAmountError,
+    descriptor::DescriptorError,
+    ecc::EccError,
+    encode::DecodeError,
+    miniscript::MiniscriptError,
+    psbt::PsbtError,
+    script::{asm::ParseAsmError, interpreter::ScriptError, template::TemplateError},
+    transactions::{
+        builder::BuilderError, check::CheckError, coin_selection::SelectionError,
+        hashes::ParseHashError, input::ParseOutPointError, verify::VerifyError,
+    },
+    utils::{calculate_fee::FeeError, tx_fetcher::FetchError},
+    wallet::{bip32::Bip32Error, bip39::Bip39Error},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Decode(DecodeError),
+    Ecc(EccError),
+    Amount(ParseAmountError),
+    Hash(ParseHashError),
+    Asm(ParseAsmError),
+    Template(TemplateError),
+    Script(ScriptError),
+    OutPoint(ParseOutPointError),
+    Selection(SelectionError),
+    Builder(BuilderError),
+    Verify(VerifyError),
+    Check(CheckError),
+    Psbt(PsbtError),
+    Descriptor(DescriptorError),
+    Miniscript(MiniscriptError),
+    Bip32(Bip32Error),
+    Bip39(Bip39Error),
+    Fetch(FetchError),
+    Fee(FeeError),
+}
+
+/// Implements Display, source and From for the variants, each wrapping the
+/// error of the same name
+macro_rules! impl_error_variants {
+    ($($variant:ident($err:ty)),* $(,)?) => {
+        impl fmt::Display for Error {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(Error::$variant(err) => write!(f, "{}", err),)*
+                }
+            }
+        }
+
+        impl std::error::Error for Error {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                match self {
+                    $(Error::$variant(err) => Some(err),)*
+                }
+            }
+        }
+
+        $(
+            impl From<$err> for Error {
+                fn from(err: $err) -> Self {
+                    Error::$variant(err)
+                }
+            }
+        )*
+    };
+}
+
+impl_error_variants!(
+    Decode(DecodeError),
+    Ecc(EccError),
+    Amount(ParseAmountError),
+    Hash(ParseHashError),
+    Asm(ParseAsmError),
+    Template(TemplateError),
+    Script(ScriptError),
+    OutPoint(ParseOutPointError),
+    Selection(SelectionError),
+    Builder(BuilderError),
+    Verify(VerifyError),
+    Check(CheckError),
+    Psbt(PsbtError),
+    Descriptor(DescriptorError),
+    Miniscript(MiniscriptError),
+    Bip32(Bip32Error),
+    Bip39(Bip39Error),
+    Fetch(FetchError),
+    Fee(FeeError),
+);
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error as _, io::Cursor};
+
+    use crate::{encode::DecodeErrorKind, transactions::tx::Tx};
+
+    use super::*;
+
+    fn parse_tx(hex: &str) -> Result<Tx, Error> {
+        let bytes = hex::decode(hex).unwrap();
+        Ok(Tx::parse(&mut Cursor::new(bytes))?)
+    }
+
+    #[test]
+    fn test_wraps_module_errors() {
+        // Version, then an input count with no inputs behind it
+        let err = parse_tx("0100000001").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Decode(DecodeError {
+                offset: 5,
+                kind: DecodeErrorKind::UnexpectedEof
+            })
+        ));
+        assert_eq!(err.to_string(), "unexpected end of data at byte 5");
+        assert!(err.source().is_some());
+
+        let err: Error = "1 XBT".parse::<crate::amount::Amount>().unwrap_err().into();
+        assert!(matches!(err, Error::Amount(_)));
+    }
+}
@@ -1,4 +1,13 @@
+pub mod address;
+pub mod amount;
+pub mod descriptor;
 pub mod ecc;
+pub mod encode;
+pub mod error;
+pub mod miniscript;
+pub mod policy;
+pub mod psbt;
 pub mod script;
 pub mod transactions;
 pub mod utils;
+pub mod wallet;
@@ -0,0 +1,914 @@
+pub mod policy;
+pub mod satisfy;
+pub mod types;
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    descriptor::{key::DescriptorKey, split_args, split_function, DescriptorError},
+    script::{
+        op::{
+            Opcode, OP_0, OP_0NOTEQUAL, OP_1, OP_ADD, OP_BOOLAND, OP_BOOLOR,
+            OP_CHECKLOCKTIMEVERIFY, OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY,
+            OP_CHECKSEQUENCEVERIFY, OP_CHECKSIG, OP_CHECKSIGADD, OP_CHECKSIGVERIFY, OP_DUP,
+            OP_ELSE, OP_ENDIF, OP_EQUAL, OP_EQUALVERIFY, OP_FROMALTSTACK, OP_HASH160, OP_HASH256,
+            OP_IF, OP_IFDUP, OP_NOTIF, OP_NUMEQUAL, OP_NUMEQUALVERIFY, OP_RIPEMD160, OP_SHA256,
+            OP_SIZE, OP_SWAP, OP_TOALTSTACK, OP_VERIFY,
+        },
+        Command, Script,
+    },
+    utils::hash160::hash160,
+};
+
+use self::types::{Base, Timelocks, Type};
+
+/// OP_CHECKMULTISIG takes at most 20 keys
+const MAX_MULTI_KEYS: usize = 20;
+
+/// multi_a() is limited by the tapscript stack size
+const MAX_MULTI_A_KEYS: usize = 999;
+
+/// Timelocks must be positive and fit in 31 bits
+const MAX_TIMELOCK: u32 = 0x7fff_ffff;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MiniscriptError {
+    /// The expression isn't well formed
+    Syntax(String),
+    /// A fragment's arguments don't have the types it requires
+    TypeCheck(String),
+    /// The threshold is 0 or larger than the number of subexpressions
+    InvalidThreshold { threshold: usize, count: usize },
+    /// older() and after() take a value between 1 and 2^31 - 1
+    InvalidTimelock(u32),
+    /// A fragment or key isn't allowed in the script context
+    InvalidContext(String),
+    /// Parsing or deriving a key failed
+    Descriptor(DescriptorError),
+    /// The satisfier doesn't have what's needed to satisfy the script
+    Unsatisfiable,
+    /// Every satisfaction found could be changed by a third party
+    Malleable,
+    /// The policy has no non-malleable compilation
+    Compile(String),
+}
+
+impl fmt::Display for MiniscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiniscriptError::Syntax(err) => write!(f, "invalid miniscript: {}", err),
+            MiniscriptError::TypeCheck(err) => write!(f, "type check failed: {}", err),
+            MiniscriptError::InvalidThreshold { threshold, count } => {
+                write!(f, "invalid threshold {} of {}", threshold, count)
+            }
+            MiniscriptError::InvalidTimelock(n) => write!(f, "invalid timelock: {}", n),
+            MiniscriptError::InvalidContext(err) => write!(f, "{}", err),
+            MiniscriptError::Descriptor(err) => write!(f, "{}", err),
+            MiniscriptError::Unsatisfiable => write!(f, "cannot satisfy the script"),
+            MiniscriptError::Malleable => write!(f, "only malleable satisfactions exist"),
+            MiniscriptError::Compile(err) => write!(f, "cannot compile policy: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MiniscriptError {}
+
+impl From<DescriptorError> for MiniscriptError {
+    fn from(err: DescriptorError) -> Self {
+        MiniscriptError::Descriptor(err)
+    }
+}
+
+/// The kind of script a miniscript is encoded in, which decides how keys are
+/// serialized and which fragments are available
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ScriptContext {
+    /// P2WSH witness scripts
+    Segwitv0,
+    /// Taproot script path leaves (BIP342)
+    Tapscript,
+}
+
+/// A miniscript fragment, with its subexpressions
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Terminal {
+    /// 0
+    False,
+    /// 1
+    True,
+    /// pk_k(key): <key>
+    PkK(DescriptorKey),
+    /// pk_h(key): OP_DUP OP_HASH160 <HASH160(key)> OP_EQUALVERIFY
+    PkH(DescriptorKey),
+    /// older(n): <n> OP_CHECKSEQUENCEVERIFY
+    Older(u32),
+    /// after(n): <n> OP_CHECKLOCKTIMEVERIFY
+    After(u32),
+    /// sha256(h): OP_SIZE <32> OP_EQUALVERIFY OP_SHA256 <h> OP_EQUAL
+    Sha256([u8; 32]),
+    /// hash256(h): OP_SIZE <32> OP_EQUALVERIFY OP_HASH256 <h> OP_EQUAL
+    Hash256([u8; 32]),
+    /// ripemd160(h): OP_SIZE <32> OP_EQUALVERIFY OP_RIPEMD160 <h> OP_EQUAL
+    Ripemd160([u8; 20]),
+    /// hash160(h): OP_SIZE <32> OP_EQUALVERIFY OP_HASH160 <h> OP_EQUAL
+    Hash160([u8; 20]),
+    /// a:X: OP_TOALTSTACK [X] OP_FROMALTSTACK
+    Alt(Box<Miniscript>),
+    /// s:X: OP_SWAP [X]
+    Swap(Box<Miniscript>),
+    /// c:X: [X] OP_CHECKSIG
+    Check(Box<Miniscript>),
+    /// d:X: OP_DUP OP_IF [X] OP_ENDIF
+    DupIf(Box<Miniscript>),
+    /// v:X: [X] OP_VERIFY, merged into the last opcode when possible
+    Verify(Box<Miniscript>),
+    /// j:X: OP_SIZE OP_0NOTEQUAL OP_IF [X] OP_ENDIF
+    NonZero(Box<Miniscript>),
+    /// n:X: [X] OP_0NOTEQUAL
+    ZeroNotEqual(Box<Miniscript>),
+    /// and_v(X,Y): [X] [Y]
+    AndV(Box<Miniscript>, Box<Miniscript>),
+    /// and_b(X,Y): [X] [Y] OP_BOOLAND
+    AndB(Box<Miniscript>, Box<Miniscript>),
+    /// andor(X,Y,Z): [X] OP_NOTIF [Z] OP_ELSE [Y] OP_ENDIF
+    AndOr(Box<Miniscript>, Box<Miniscript>, Box<Miniscript>),
+    /// or_b(X,Z): [X] [Z] OP_BOOLOR
+    OrB(Box<Miniscript>, Box<Miniscript>),
+    /// or_c(X,Z): [X] OP_NOTIF [Z] OP_ENDIF
+    OrC(Box<Miniscript>, Box<Miniscript>),
+    /// or_d(X,Z): [X] OP_IFDUP OP_NOTIF [Z] OP_ENDIF
+    OrD(Box<Miniscript>, Box<Miniscript>),
+    /// or_i(X,Z): OP_IF [X] OP_ELSE [Z] OP_ENDIF
+    OrI(Box<Miniscript>, Box<Miniscript>),
+    /// thresh(k,X1,...,Xn): [X1] ([Xn] OP_ADD)* <k> OP_EQUAL
+    Thresh(usize, Vec<Miniscript>),
+    /// multi(k,key1,...,keyn): <k> <key1> ... <keyn> <n> OP_CHECKMULTISIG,
+    /// only in segwit v0
+    Multi(usize, Vec<DescriptorKey>),
+    /// multi_a(k,key1,...,keyn): <key1> OP_CHECKSIG (<keyi> OP_CHECKSIGADD)*
+    /// <k> OP_NUMEQUAL, only in tapscript
+    MultiA(usize, Vec<DescriptorKey>),
+}
+
+/// A type checked miniscript expression
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Miniscript {
+    node: Terminal,
+    ty: Type,
+    ctx: ScriptContext,
+}
+
+/// Constructors
+impl Miniscript {
+    /// Type checks the fragment. Its subexpressions must be in the same
+    /// context.
+    pub fn new(node: Terminal, ctx: ScriptContext) -> Result<Self, MiniscriptError> {
+        let ty = type_check(&node, ctx)?;
+        Ok(Self { node, ty, ctx })
+    }
+
+    /// Parses a miniscript expression in the context
+    pub fn parse(s: &str, ctx: ScriptContext) -> Result<Self, MiniscriptError> {
+        // Wrappers are written as letters before a colon, e.g. "vc:pk_k(A)"
+        let (wrappers, base) = match s.split_once(':') {
+            Some((wrappers, base))
+                if !wrappers.is_empty() && wrappers.bytes().all(|c| c.is_ascii_lowercase()) =>
+            {
+                (wrappers, base)
+            }
+            _ => ("", s),
+        };
+        let mut ms = parse_fragment(base, ctx)?;
+        for wrapper in wrappers.chars().rev() {
+            let node = match wrapper {
+                'a' => Terminal::Alt(Box::new(ms)),
+                's' => Terminal::Swap(Box::new(ms)),
+                'c' => Terminal::Check(Box::new(ms)),
+                'd' => Terminal::DupIf(Box::new(ms)),
+                'v' => Terminal::Verify(Box::new(ms)),
+                'j' => Terminal::NonZero(Box::new(ms)),
+                'n' => Terminal::ZeroNotEqual(Box::new(ms)),
+                't' => Terminal::AndV(Box::new(ms), Box::new(Self::new(Terminal::True, ctx)?)),
+                'l' => Terminal::OrI(Box::new(Self::new(Terminal::False, ctx)?), Box::new(ms)),
+                'u' => Terminal::OrI(Box::new(ms), Box::new(Self::new(Terminal::False, ctx)?)),
+                _ => {
+                    return Err(MiniscriptError::Syntax(format!(
+                        "unknown wrapper {}: {}",
+                        wrapper, s
+                    )))
+                }
+            };
+            ms = Self::new(node, ctx)?;
+        }
+        Ok(ms)
+    }
+}
+
+fn parse_key(s: &str, ctx: ScriptContext) -> Result<DescriptorKey, MiniscriptError> {
+    let key: DescriptorKey = s.parse()?;
+    check_key(&key, ctx)?;
+    Ok(key)
+}
+
+fn check_key(key: &DescriptorKey, ctx: ScriptContext) -> Result<(), MiniscriptError> {
+    if key.is_uncompressed() {
+        return Err(MiniscriptError::InvalidContext(format!(
+            "uncompressed keys are not allowed in miniscript: {}",
+            key
+        )));
+    }
+    if key.is_xonly() && ctx == ScriptContext::Segwitv0 {
+        return Err(MiniscriptError::InvalidContext(format!(
+            "x-only keys are only allowed in tapscript: {}",
+            key
+        )));
+    }
+    Ok(())
+}
+
+fn parse_timelock(s: &str) -> Result<u32, MiniscriptError> {
+    let n: u32 = s
+        .parse()
+        .map_err(|_| MiniscriptError::Syntax(format!("invalid timelock: {}", s)))?;
+    if n == 0 || n > MAX_TIMELOCK {
+        return Err(MiniscriptError::InvalidTimelock(n));
+    }
+    Ok(n)
+}
+
+fn parse_hash<const N: usize>(s: &str) -> Result<[u8; N], MiniscriptError> {
+    if s.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(MiniscriptError::Syntax(format!("invalid hash: {}", s)));
+    }
+    hex::decode(s)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| MiniscriptError::Syntax(format!("invalid hash: {}", s)))
+}
+
+fn parse_threshold(s: &str) -> Result<usize, MiniscriptError> {
+    s.parse()
+        .map_err(|_| MiniscriptError::Syntax(format!("invalid threshold: {}", s)))
+}
+
+fn parse_fragment(s: &str, ctx: ScriptContext) -> Result<Miniscript, MiniscriptError> {
+    match s {
+        "0" => return Miniscript::new(Terminal::False, ctx),
+        "1" => return Miniscript::new(Terminal::True, ctx),
+        _ => {}
+    }
+    let (name, args) = split_function(s)?;
+    let args = split_args(args)?;
+    let sub = |i: usize| -> Result<Box<Miniscript>, MiniscriptError> {
+        Ok(Box::new(Miniscript::parse(args[i], ctx)?))
+    };
+    let expected = match name {
+        "pk" | "pk_k" | "pkh" | "pk_h" | "older" | "after" | "sha256" | "hash256" | "ripemd160"
+        | "hash160" => Some(1),
+        "and_v" | "and_b" | "and_n" | "or_b" | "or_c" | "or_d" | "or_i" => Some(2),
+        "andor" => Some(3),
+        _ => None,
+    };
+    if let Some(expected) = expected {
+        if args.len() != expected {
+            return Err(MiniscriptError::Syntax(format!(
+                "{}() takes {} arguments: {}",
+                name, expected, s
+            )));
+        }
+    }
+    let node = match name {
+        // pk(key) = c:pk_k(key) and pkh(key) = c:pk_h(key)
+        "pk" | "pkh" => {
+            let key = parse_key(args[0], ctx)?;
+            let inner = if name == "pk" {
+                Terminal::PkK(key)
+            } else {
+                Terminal::PkH(key)
+            };
+            Terminal::Check(Box::new(Miniscript::new(inner, ctx)?))
+        }
+        "pk_k" => Terminal::PkK(parse_key(args[0], ctx)?),
+        "pk_h" => Terminal::PkH(parse_key(args[0], ctx)?),
+        "older" => Terminal::Older(parse_timelock(args[0])?),
+        "after" => Terminal::After(parse_timelock(args[0])?),
+        "sha256" => Terminal::Sha256(parse_hash(args[0])?),
+        "hash256" => Terminal::Hash256(parse_hash(args[0])?),
+        "ripemd160" => Terminal::Ripemd160(parse_hash(args[0])?),
+        "hash160" => Terminal::Hash160(parse_hash(args[0])?),
+        "and_v" => Terminal::AndV(sub(0)?, sub(1)?),
+        "and_b" => Terminal::AndB(sub(0)?, sub(1)?),
+        // and_n(X,Y) = andor(X,Y,0)
+        "and_n" => Terminal::AndOr(
+            sub(0)?,
+            sub(1)?,
+            Box::new(Miniscript::new(Terminal::False, ctx)?),
+        ),
+        "andor" => Terminal::AndOr(sub(0)?, sub(1)?, sub(2)?),
+        "or_b" => Terminal::OrB(sub(0)?, sub(1)?),
+        "or_c" => Terminal::OrC(sub(0)?, sub(1)?),
+        "or_d" => Terminal::OrD(sub(0)?, sub(1)?),
+        "or_i" => Terminal::OrI(sub(0)?, sub(1)?),
+        "thresh" => {
+            let subs = args[1..]
+                .iter()
+                .map(|arg| Miniscript::parse(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            Terminal::Thresh(parse_threshold(args[0])?, subs)
+        }
+        "multi" | "multi_a" => {
+            let keys = args[1..]
+                .iter()
+                .map(|arg| parse_key(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            let threshold = parse_threshold(args[0])?;
+            if name == "multi" {
+                Terminal::Multi(threshold, keys)
+            } else {
+                Terminal::MultiA(threshold, keys)
+            }
+        }
+        _ => {
+            return Err(MiniscriptError::Syntax(format!(
+                "unknown fragment: {}",
+                name
+            )))
+        }
+    };
+    Miniscript::new(node, ctx)
+}
+
+fn check_multi(
+    name: &str,
+    k: usize,
+    keys: &[DescriptorKey],
+    max_keys: usize,
+    ctx: ScriptContext,
+    allowed: ScriptContext,
+) -> Result<(), MiniscriptError> {
+    if ctx != allowed {
+        return Err(MiniscriptError::InvalidContext(format!(
+            "{}() is not allowed in {:?}",
+            name, ctx
+        )));
+    }
+    if k == 0 || k > keys.len() || keys.len() > max_keys {
+        return Err(MiniscriptError::InvalidThreshold {
+            threshold: k,
+            count: keys.len(),
+        });
+    }
+    keys.iter().try_for_each(|key| check_key(key, ctx))
+}
+
+fn type_check(node: &Terminal, ctx: ScriptContext) -> Result<Type, MiniscriptError> {
+    Ok(match node {
+        Terminal::False => Type::false_(),
+        Terminal::True => Type::true_(),
+        Terminal::PkK(key) => {
+            check_key(key, ctx)?;
+            Type::pk_k()
+        }
+        Terminal::PkH(key) => {
+            check_key(key, ctx)?;
+            Type::pk_h()
+        }
+        Terminal::Older(n) | Terminal::After(n) if *n == 0 || *n > MAX_TIMELOCK => {
+            return Err(MiniscriptError::InvalidTimelock(*n))
+        }
+        Terminal::Older(n) => Type::time(Timelocks::older(*n)),
+        Terminal::After(n) => Type::time(Timelocks::after(*n)),
+        Terminal::Sha256(_)
+        | Terminal::Hash256(_)
+        | Terminal::Ripemd160(_)
+        | Terminal::Hash160(_) => Type::hash(),
+        Terminal::Alt(x) => Type::alt(x.ty)?,
+        Terminal::Swap(x) => Type::swap(x.ty)?,
+        Terminal::Check(x) => Type::check(x.ty)?,
+        Terminal::DupIf(x) => Type::dup_if(x.ty, ctx == ScriptContext::Tapscript)?,
+        Terminal::Verify(x) => Type::verify(x.ty)?,
+        Terminal::NonZero(x) => Type::non_zero(x.ty)?,
+        Terminal::ZeroNotEqual(x) => Type::zero_not_equal(x.ty)?,
+        Terminal::AndV(x, y) => Type::and_v(x.ty, y.ty)?,
+        Terminal::AndB(x, y) => Type::and_b(x.ty, y.ty)?,
+        Terminal::AndOr(x, y, z) => Type::and_or(x.ty, y.ty, z.ty)?,
+        Terminal::OrB(x, z) => Type::or_b(x.ty, z.ty)?,
+        Terminal::OrC(x, z) => Type::or_c(x.ty, z.ty)?,
+        Terminal::OrD(x, z) => Type::or_d(x.ty, z.ty)?,
+        Terminal::OrI(x, z) => Type::or_i(x.ty, z.ty)?,
+        Terminal::Thresh(k, subs) => {
+            Type::thresh(*k, &subs.iter().map(|sub| sub.ty).collect::<Vec<_>>())?
+        }
+        Terminal::Multi(k, keys) => {
+            check_multi(
+                "multi",
+                *k,
+                keys,
+                MAX_MULTI_KEYS,
+                ctx,
+                ScriptContext::Segwitv0,
+            )?;
+            Type::multi()
+        }
+        Terminal::MultiA(k, keys) => {
+            check_multi(
+                "multi_a",
+                *k,
+                keys,
+                MAX_MULTI_A_KEYS,
+                ctx,
+                ScriptContext::Tapscript,
+            )?;
+            Type::multi()
+        }
+    })
+}
+
+/// Methods
+impl Miniscript {
+    pub fn node(&self) -> &Terminal {
+        &self.node
+    }
+
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
+
+    pub fn context(&self) -> ScriptContext {
+        self.ctx
+    }
+
+    /// Returns the subexpressions of the fragment
+    pub fn children(&self) -> Vec<&Miniscript> {
+        match &self.node {
+            Terminal::Alt(x)
+            | Terminal::Swap(x)
+            | Terminal::Check(x)
+            | Terminal::DupIf(x)
+            | Terminal::Verify(x)
+            | Terminal::NonZero(x)
+            | Terminal::ZeroNotEqual(x) => vec![x],
+            Terminal::AndV(x, y)
+            | Terminal::AndB(x, y)
+            | Terminal::OrB(x, y)
+            | Terminal::OrC(x, y)
+            | Terminal::OrD(x, y)
+            | Terminal::OrI(x, y) => vec![x, y],
+            Terminal::AndOr(x, y, z) => vec![x, y, z],
+            Terminal::Thresh(_, subs) => subs.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns every key in the expression
+    pub fn keys(&self) -> Vec<&DescriptorKey> {
+        match &self.node {
+            Terminal::PkK(key) | Terminal::PkH(key) => vec![key],
+            Terminal::Multi(_, keys) | Terminal::MultiA(_, keys) => keys.iter().collect(),
+            _ => self
+                .children()
+                .into_iter()
+                .flat_map(|child| child.keys())
+                .collect(),
+        }
+    }
+
+    /// Returns true if a key has a wildcard
+    pub fn is_ranged(&self) -> bool {
+        self.keys().iter().any(|key| key.has_wildcard())
+    }
+
+    /// Returns true if a non-malleable satisfaction always exists
+    pub fn is_non_malleable(&self) -> bool {
+        self.ty.malleability.m
+    }
+
+    /// Returns true if every satisfaction requires a signature
+    pub fn requires_signature(&self) -> bool {
+        self.ty.malleability.s
+    }
+
+    /// Returns true if the expression can be used as a whole script: it's of
+    /// type B, non-malleable, needs a signature to be spent and never needs
+    /// both a height and a time lock of one kind
+    pub fn is_sane(&self) -> bool {
+        self.ty.base == Base::B
+            && self.is_non_malleable()
+            && self.requires_signature()
+            && !self.ty.has_mixed_timelocks()
+    }
+
+    /// Returns the script of the expression with its keys derived at the
+    /// index
+    pub fn encode(&self, index: u32) -> Result<Script, MiniscriptError> {
+        let mut cmds = vec![];
+        self.encode_with(&|key| self.key_bytes(key, index), &mut cmds)?;
+        Ok(Script::new(cmds))
+    }
+
+    /// Serializes the key derived at the index as it appears in the script
+    fn key_bytes(&self, key: &DescriptorKey, index: u32) -> Result<Vec<u8>, DescriptorError> {
+        let key = key.derive(index)?;
+        Ok(match self.ctx {
+            ScriptContext::Segwitv0 => key.to_sec(),
+            ScriptContext::Tapscript => key.to_xonly().to_vec(),
+        })
+    }
+
+    /// Returns the size of the script in bytes
+    pub fn script_size(&self) -> usize {
+        let key_size = match self.ctx {
+            ScriptContext::Segwitv0 => 33,
+            ScriptContext::Tapscript => 32,
+        };
+        let mut cmds = vec![];
+        self.encode_with(&|_: &DescriptorKey| Ok(vec![0; key_size]), &mut cmds)
+            .expect("placeholder keys can't fail");
+        Script::new(cmds).raw_serialize().len()
+    }
+
+    /// Appends the script of the expression, serializing keys with key_bytes
+    fn encode_with(
+        &self,
+        key_bytes: &dyn Fn(&DescriptorKey) -> Result<Vec<u8>, DescriptorError>,
+        cmds: &mut Vec<Command>,
+    ) -> Result<(), MiniscriptError> {
+        match &self.node {
+            Terminal::False => cmds.push(Command::Op(OP_0)),
+            Terminal::True => cmds.push(Command::Op(OP_1)),
+            Terminal::PkK(key) => cmds.push(Command::push(key_bytes(key)?)),
+            Terminal::PkH(key) => cmds.extend([
+                Command::Op(OP_DUP),
+                Command::Op(OP_HASH160),
+                Command::push(hash160(&key_bytes(key)?).to_vec()),
+                Command::Op(OP_EQUALVERIFY),
+            ]),
+            Terminal::Older(n) => cmds.extend([
+                Command::number(*n as i64),
+                Command::Op(OP_CHECKSEQUENCEVERIFY),
+            ]),
+            Terminal::After(n) => cmds.extend([
+                Command::number(*n as i64),
+                Command::Op(OP_CHECKLOCKTIMEVERIFY),
+            ]),
+            Terminal::Sha256(hash) => encode_hash(OP_SHA256, hash, cmds),
+            Terminal::Hash256(hash) => encode_hash(OP_HASH256, hash, cmds),
+            Terminal::Ripemd160(hash) => encode_hash(OP_RIPEMD160, hash, cmds),
+            Terminal::Hash160(hash) => encode_hash(OP_HASH160, hash, cmds),
+            Terminal::Alt(x) => {
+                cmds.push(Command::Op(OP_TOALTSTACK));
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_FROMALTSTACK));
+            }
+            Terminal::Swap(x) => {
+                cmds.push(Command::Op(OP_SWAP));
+                x.encode_with(key_bytes, cmds)?;
+            }
+            Terminal::Check(x) => {
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_CHECKSIG));
+            }
+            Terminal::DupIf(x) => {
+                cmds.extend([Command::Op(OP_DUP), Command::Op(OP_IF)]);
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::Verify(x) => {
+                x.encode_with(key_bytes, cmds)?;
+                // Opcodes with a VERIFY form absorb the OP_VERIFY
+                let merged = match cmds.last() {
+                    Some(Command::Op(OP_EQUAL)) => Some(OP_EQUALVERIFY),
+                    Some(Command::Op(OP_CHECKSIG)) => Some(OP_CHECKSIGVERIFY),
+                    Some(Command::Op(OP_CHECKMULTISIG)) => Some(OP_CHECKMULTISIGVERIFY),
+                    Some(Command::Op(OP_NUMEQUAL)) => Some(OP_NUMEQUALVERIFY),
+                    _ => None,
+                };
+                match merged {
+                    Some(op) => *cmds.last_mut().unwrap() = Command::Op(op),
+                    None => cmds.push(Command::Op(OP_VERIFY)),
+                }
+            }
+            Terminal::NonZero(x) => {
+                cmds.extend([
+                    Command::Op(OP_SIZE),
+                    Command::Op(OP_0NOTEQUAL),
+                    Command::Op(OP_IF),
+                ]);
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::ZeroNotEqual(x) => {
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_0NOTEQUAL));
+            }
+            Terminal::AndV(x, y) => {
+                x.encode_with(key_bytes, cmds)?;
+                y.encode_with(key_bytes, cmds)?;
+            }
+            Terminal::AndB(x, y) => {
+                x.encode_with(key_bytes, cmds)?;
+                y.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_BOOLAND));
+            }
+            Terminal::AndOr(x, y, z) => {
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_NOTIF));
+                z.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ELSE));
+                y.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::OrB(x, z) => {
+                x.encode_with(key_bytes, cmds)?;
+                z.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_BOOLOR));
+            }
+            Terminal::OrC(x, z) => {
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_NOTIF));
+                z.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::OrD(x, z) => {
+                x.encode_with(key_bytes, cmds)?;
+                cmds.extend([Command::Op(OP_IFDUP), Command::Op(OP_NOTIF)]);
+                z.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::OrI(x, z) => {
+                cmds.push(Command::Op(OP_IF));
+                x.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ELSE));
+                z.encode_with(key_bytes, cmds)?;
+                cmds.push(Command::Op(OP_ENDIF));
+            }
+            Terminal::Thresh(k, subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    sub.encode_with(key_bytes, cmds)?;
+                    if i > 0 {
+                        cmds.push(Command::Op(OP_ADD));
+                    }
+                }
+                cmds.extend([Command::number(*k as i64), Command::Op(OP_EQUAL)]);
+            }
+            Terminal::Multi(k, keys) => {
+                cmds.push(Command::number(*k as i64));
+                for key in keys {
+                    cmds.push(Command::push(key_bytes(key)?));
+                }
+                cmds.extend([
+                    Command::number(keys.len() as i64),
+                    Command::Op(OP_CHECKMULTISIG),
+                ]);
+            }
+            Terminal::MultiA(k, keys) => {
+                for (i, key) in keys.iter().enumerate() {
+                    cmds.push(Command::push(key_bytes(key)?));
+                    cmds.push(Command::Op(if i == 0 {
+                        OP_CHECKSIG
+                    } else {
+                        OP_CHECKSIGADD
+                    }));
+                }
+                cmds.extend([Command::number(*k as i64), Command::Op(OP_NUMEQUAL)]);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_hash(op: Opcode, hash: &[u8], cmds: &mut Vec<Command>) {
+    cmds.extend([
+        Command::Op(OP_SIZE),
+        Command::number(32),
+        Command::Op(OP_EQUALVERIFY),
+        Command::Op(op),
+        Command::push(hash.to_vec()),
+        Command::Op(OP_EQUAL),
+    ]);
+}
+
+impl fmt::Display for Miniscript {
+    /// Writes the expression in its shortest form, using the pk(), pkh(),
+    /// and_n() and t:, l:, u: aliases
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut wrappers = String::new();
+        let mut ms = self;
+        loop {
+            let (wrapper, inner) = match &ms.node {
+                Terminal::Alt(x) => ('a', x),
+                Terminal::Swap(x) => ('s', x),
+                Terminal::Check(x) if !matches!(x.node, Terminal::PkK(_) | Terminal::PkH(_)) => {
+                    ('c', x)
+                }
+                Terminal::DupIf(x) => ('d', x),
+                Terminal::Verify(x) => ('v', x),
+                Terminal::NonZero(x) => ('j', x),
+                Terminal::ZeroNotEqual(x) => ('n', x),
+                Terminal::AndV(x, y) if y.node == Terminal::True => ('t', x),
+                Terminal::OrI(x, z) if x.node == Terminal::False => ('l', z),
+                Terminal::OrI(x, z) if z.node == Terminal::False => ('u', x),
+                _ => break,
+            };
+            wrappers.push(wrapper);
+            ms = inner;
+        }
+        if !wrappers.is_empty() {
+            write!(f, "{}:", wrappers)?;
+        }
+        match &ms.node {
+            Terminal::False => write!(f, "0"),
+            Terminal::True => write!(f, "1"),
+            Terminal::PkK(key) => write!(f, "pk_k({})", key),
+            Terminal::PkH(key) => write!(f, "pk_h({})", key),
+            Terminal::Older(n) => write!(f, "older({})", n),
+            Terminal::After(n) => write!(f, "after({})", n),
+            Terminal::Sha256(hash) => write!(f, "sha256({})", hex::encode(hash)),
+            Terminal::Hash256(hash) => write!(f, "hash256({})", hex::encode(hash)),
+            Terminal::Ripemd160(hash) => write!(f, "ripemd160({})", hex::encode(hash)),
+            Terminal::Hash160(hash) => write!(f, "hash160({})", hex::encode(hash)),
+            Terminal::Check(x) => match &x.node {
+                Terminal::PkK(key) => write!(f, "pk({})", key),
+                Terminal::PkH(key) => write!(f, "pkh({})", key),
+                _ => unreachable!("other c: fragments are written as wrappers"),
+            },
+            Terminal::AndV(x, y) => write!(f, "and_v({},{})", x, y),
+            Terminal::AndB(x, y) => write!(f, "and_b({},{})", x, y),
+            Terminal::AndOr(x, y, z) if z.node == Terminal::False => {
+                write!(f, "and_n({},{})", x, y)
+            }
+            Terminal::AndOr(x, y, z) => write!(f, "andor({},{},{})", x, y, z),
+            Terminal::OrB(x, z) => write!(f, "or_b({},{})", x, z),
+            Terminal::OrC(x, z) => write!(f, "or_c({},{})", x, z),
+            Terminal::OrD(x, z) => write!(f, "or_d({},{})", x, z),
+            Terminal::OrI(x, z) => write!(f, "or_i({},{})", x, z),
+            Terminal::Thresh(k, subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ")")
+            }
+            Terminal::Multi(k, keys) | Terminal::MultiA(k, keys) => {
+                let name = if let Terminal::Multi(..) = ms.node {
+                    "multi"
+                } else {
+                    "multi_a"
+                };
+                write!(f, "{}({}", name, k)?;
+                for key in keys {
+                    write!(f, ",{}", key)?;
+                }
+                write!(f, ")")
+            }
+            _ => unreachable!("wrappers are written above"),
+        }
+    }
+}
+
+impl FromStr for Miniscript {
+    type Err = MiniscriptError;
+
+    /// Parses a segwit v0 miniscript
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, ScriptContext::Segwitv0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) const A: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    pub(crate) const B: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    pub(crate) const C: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    fn script_hex(s: &str, ctx: ScriptContext) -> String {
+        let ms = Miniscript::parse(s, ctx).unwrap();
+        hex::encode(ms.encode(0).unwrap().raw_serialize())
+    }
+
+    #[test]
+    fn test_encode() {
+        let segwit = ScriptContext::Segwitv0;
+        assert_eq!(
+            script_hex(&format!("pk({})", A), segwit),
+            format!("21{}ac", A)
+        );
+        // and_v(v:pk(A),older(144)): <A> OP_CHECKSIGVERIFY <144> OP_CSV
+        assert_eq!(
+            script_hex(&format!("and_v(v:pk({}),older(144))", A), segwit),
+            format!("21{}ad029000b2", A)
+        );
+        // The <32> of hash fragments is a one byte push
+        let hash = "9267d3dbed802941483f1afa2a6bc68de5f653128aca9bf1461c5d0a3ad36ed2";
+        assert_eq!(
+            script_hex(&format!("sha256({})", hash), segwit),
+            format!("82012088a820{}87", hash)
+        );
+        // or_d(pk(A),pkh(B))
+        assert_eq!(
+            script_hex(&format!("or_d(pk({}),pkh({}))", A, B), segwit),
+            format!(
+                "21{}ac736476a914{}88ac68",
+                A,
+                hex::encode(hash160(&hex::decode(B).unwrap()))
+            )
+        );
+        // multi_a(2,A,B) serializes x-only keys
+        assert_eq!(
+            script_hex(&format!("multi_a(2,{},{})", A, B), ScriptContext::Tapscript),
+            format!("20{}ac20{}ba529c", &A[2..], &B[2..])
+        );
+    }
+
+    #[test]
+    fn test_script_size() {
+        for s in [
+            format!("and_v(v:pk({}),older(144))", A),
+            format!("thresh(2,pk({}),s:pk({}),s:pk({}))", A, B, C),
+            format!("andor(pk({}),after(500000001),pkh({}))", A, B),
+        ] {
+            let ms: Miniscript = s.parse().unwrap();
+            assert_eq!(
+                ms.script_size(),
+                ms.encode(0).unwrap().raw_serialize().len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for s in [
+            format!("pk({})", A),
+            format!("and_v(v:pk({}),older(144))", A),
+            format!("or_d(pk({}),and_v(v:pkh({}),older(1000)))", A, B),
+            format!("thresh(2,pk({}),s:pk({}),sln:older(12960))", A, B),
+            format!("and_n(pk({}),after(100))", A),
+            format!("t:or_c(pk({}),v:pk({}))", A, B),
+            format!("multi(2,{},{},{})", A, B, C),
+        ] {
+            let ms: Miniscript = s.parse().unwrap();
+            assert_eq!(ms.to_string(), s);
+        }
+        // Aliases are written in their short form
+        let ms: Miniscript = format!("c:pk_k({})", A).parse().unwrap();
+        assert_eq!(ms.to_string(), format!("pk({})", A));
+        let ms: Miniscript = format!("andor(pk({}),older(9),0)", A).parse().unwrap();
+        assert_eq!(ms.to_string(), format!("and_n(pk({}),older(9))", A));
+    }
+
+    #[test]
+    fn test_types() {
+        let ms: Miniscript = format!("or_d(pk({}),and_v(v:pkh({}),older(1000)))", A, B)
+            .parse()
+            .unwrap();
+        assert!(ms.is_sane());
+        assert_eq!(ms.keys().len(), 2);
+
+        // Only a timelock, anyone can spend
+        let ms: Miniscript = "older(144)".parse().unwrap();
+        assert!(!ms.is_sane());
+
+        // Both a height and a time lock
+        let ms: Miniscript = format!("and_v(v:after(100),and_v(v:after(500000001),pk({})))", A)
+            .parse()
+            .unwrap();
+        assert!(ms.ty().has_mixed_timelocks());
+        assert!(!ms.is_sane());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let segwit = ScriptContext::Segwitv0;
+        let tap = ScriptContext::Tapscript;
+        // pk_k is K, and_v wants a V first
+        assert!(matches!(
+            Miniscript::parse(&format!("and_v(pk_k({}),1)", A), segwit),
+            Err(MiniscriptError::TypeCheck(_))
+        ));
+        assert!(matches!(
+            Miniscript::parse("older(0)", segwit),
+            Err(MiniscriptError::InvalidTimelock(0))
+        ));
+        assert!(matches!(
+            Miniscript::parse(&format!("multi(2,{},{})", A, B), tap),
+            Err(MiniscriptError::InvalidContext(_))
+        ));
+        assert!(matches!(
+            Miniscript::parse(&format!("multi_a(1,{})", A), segwit),
+            Err(MiniscriptError::InvalidContext(_))
+        ));
+        assert!(matches!(
+            Miniscript::parse(&format!("pk({})", &A[2..]), segwit),
+            Err(MiniscriptError::InvalidContext(_))
+        ));
+        assert!(Miniscript::parse(&format!("pk({})", &A[2..]), tap).is_ok());
+        assert!(matches!(
+            Miniscript::parse(&format!("x:pk({})", A), segwit),
+            Err(MiniscriptError::Syntax(_))
+        ));
+        assert!(Miniscript::parse("sha256(00)", segwit).is_err());
+        assert!(Miniscript::parse(&format!("thresh(3,pk({}),s:pk({}))", A, B), segwit).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use crate::descriptor::{key::DescriptorKey, split_args, split_function};
+
+use super::{
+    check_key, parse_hash, parse_threshold, parse_timelock,
+    types::{Base, Correctness, Malleability},
+    Miniscript, MiniscriptError, ScriptContext, Terminal,
+};
+
+/// A spending policy: the conditions under which coins can be spent, without
+/// the details of the script that enforces them
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Policy {
+    /// pk(key): a signature for the key
+    Key(DescriptorKey),
+    /// after(n): the transaction's locktime is at least n
+    After(u32),
+    /// older(n): the input's relative locktime is at least n
+    Older(u32),
+    Sha256([u8; 32]),
+    Hash256([u8; 32]),
+    Ripemd160([u8; 20]),
+    Hash160([u8; 20]),
+    /// and(X,Y): both policies
+    And(Box<Policy>, Box<Policy>),
+    /// or(X,Y): either policy, weighted by how likely each is to be used, as
+    /// in or(9@X,1@Y)
+    Or((usize, Box<Policy>), (usize, Box<Policy>)),
+    /// thresh(k,X1,...,Xn): k of the policies
+    Thresh(usize, Vec<Policy>),
+}
+
+/// Parsing
+impl Policy {
+    fn parse_weighted(s: &str) -> Result<(usize, Box<Policy>), MiniscriptError> {
+        match s.split_once('@') {
+            Some((weight, policy)) if !weight.contains('(') => {
+                let weight = weight
+                    .parse()
+                    .ok()
+                    .filter(|weight| *weight > 0)
+                    .ok_or_else(|| MiniscriptError::Syntax(format!("invalid weight: {}", s)))?;
+                Ok((weight, Box::new(policy.parse()?)))
+            }
+            _ => Ok((1, Box::new(s.parse()?))),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = MiniscriptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = split_function(s)?;
+        let args = split_args(args)?;
+        let expected = match name {
+            "pk" | "after" | "older" | "sha256" | "hash256" | "ripemd160" | "hash160" => 1,
+            "and" | "or" => 2,
+            _ => args.len(),
+        };
+        if args.len() != expected {
+            return Err(MiniscriptError::Syntax(format!(
+                "{}() takes {} arguments: {}",
+                name, expected, s
+            )));
+        }
+        Ok(match name {
+            "pk" => Policy::Key(args[0].parse()?),
+            "after" => Policy::After(parse_timelock(args[0])?),
+            "older" => Policy::Older(parse_timelock(args[0])?),
+            "sha256" => Policy::Sha256(parse_hash(args[0])?),
+            "hash256" => Policy::Hash256(parse_hash(args[0])?),
+            "ripemd160" => Policy::Ripemd160(parse_hash(args[0])?),
+            "hash160" => Policy::Hash160(parse_hash(args[0])?),
+            "and" => Policy::And(Box::new(args[0].parse()?), Box::new(args[1].parse()?)),
+            "or" => Policy::Or(
+                Policy::parse_weighted(args[0])?,
+                Policy::parse_weighted(args[1])?,
+            ),
+            "thresh" => {
+                let threshold = parse_threshold(args[0])?;
+                let subs = args[1..]
+                    .iter()
+                    .map(|arg| arg.parse())
+                    .collect::<Result<Vec<_>, _>>()?;
+                if threshold == 0 || threshold > subs.len() {
+                    return Err(MiniscriptError::InvalidThreshold {
+                        threshold,
+                        count: subs.len(),
+                    });
+                }
+                Policy::Thresh(threshold, subs)
+            }
+            _ => return Err(MiniscriptError::Syntax(format!("unknown policy: {}", name))),
+        })
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Key(key) => write!(f, "pk({})", key),
+            Policy::After(n) => write!(f, "after({})", n),
+            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::Sha256(hash) => write!(f, "sha256({})", hex::encode(hash)),
+            Policy::Hash256(hash) => write!(f, "hash256({})", hex::encode(hash)),
+            Policy::Ripemd160(hash) => write!(f, "ripemd160({})", hex::encode(hash)),
+            Policy::Hash160(hash) => write!(f, "hash160({})", hex::encode(hash)),
+            Policy::And(x, y) => write!(f, "and({},{})", x, y),
+            Policy::Or((wx, x), (wy, y)) => {
+                write!(f, "or(")?;
+                if *wx != 1 {
+                    write!(f, "{}@", wx)?;
+                }
+                write!(f, "{},", x)?;
+                if *wy != 1 {
+                    write!(f, "{}@", wy)?;
+                }
+                write!(f, "{})", y)
+            }
+            Policy::Thresh(k, subs) => {
+                write!(f, "thresh({}", k)?;
+                for sub in subs {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Compilation
+impl Policy {
+    /// Returns every key in the policy
+    pub fn keys(&self) -> Vec<&DescriptorKey> {
+        match self {
+            Policy::Key(key) => vec![key],
+            Policy::And(x, y) | Policy::Or((_, x), (_, y)) => {
+                let mut keys = x.keys();
+                keys.extend(y.keys());
+                keys
+            }
+            Policy::Thresh(_, subs) => subs.iter().flat_map(|sub| sub.keys()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Compiles the policy to the non-malleable miniscript with the smallest
+    /// expected spending cost: its script size plus the size of the witness,
+    /// weighted by how likely each branch is to be used
+    pub fn compile(&self, ctx: ScriptContext) -> Result<Miniscript, MiniscriptError> {
+        for key in self.keys() {
+            check_key(key, ctx)?;
+        }
+        let mut compiler = Compiler {
+            ctx,
+            cache: HashMap::new(),
+        };
+        compiler
+            .compile(self, 1.0, 0.0)
+            .into_iter()
+            .filter(|candidate| candidate.ms.ty().base == Base::B)
+            .min_by(|a, b| a.cost.total_cmp(&b.cost))
+            .map(|candidate| candidate.ms)
+            .ok_or_else(|| {
+                MiniscriptError::Compile(format!("no non-malleable miniscript for {}", self))
+            })
+    }
+}
+
+/// Candidates that behave the same when combined with other fragments
+type TypeClass = (Base, Correctness, Malleability);
+
+/// A compiled fragment, with the expected sizes of its satisfaction and
+/// dissatisfaction witnesses in bytes
+#[derive(Debug, Clone)]
+struct Candidate {
+    ms: Miniscript,
+    sat: Option<f64>,
+    dissat: Option<f64>,
+    cost: f64,
+}
+
+/// A fragment to consider, with its expected witness sizes
+type Fragment = (Terminal, Option<f64>, Option<f64>);
+
+/// Finds the cheapest miniscript of every type for each policy, given the
+/// odds that it gets satisfied or dissatisfied
+struct Compiler {
+    ctx: ScriptContext,
+    cache: HashMap<(String, u64, u64), Vec<Candidate>>,
+}
+
+impl Compiler {
+    fn compile(&mut self, policy: &Policy, p_sat: f64, p_dissat: f64) -> Vec<Candidate> {
+        let key = (policy.to_string(), p_sat.to_bits(), p_dissat.to_bits());
+        if let Some(candidates) = self.cache.get(&key) {
+            return candidates.clone();
+        }
+        let mut best = BTreeMap::new();
+        for fragment in self.fragments(policy, p_sat, p_dissat) {
+            self.insert(&mut best, fragment, p_sat, p_dissat);
+        }
+        self.wrap(&mut best, p_sat, p_dissat);
+        let candidates: Vec<Candidate> = best.into_values().collect();
+        self.cache.insert(key, candidates.clone());
+        candidates
+    }
+
+    /// Keeps the fragment if it's the cheapest of its type so far
+    fn insert(
+        &self,
+        best: &mut BTreeMap<TypeClass, Candidate>,
+        (node, sat, dissat): Fragment,
+        p_sat: f64,
+        p_dissat: f64,
+    ) -> bool {
+        let Ok(ms) = Miniscript::new(node, self.ctx) else {
+            return false;
+        };
+        let ty = ms.ty();
+        if !ty.malleability.m || ty.has_mixed_timelocks() {
+            return false;
+        }
+        let mut cost = ms.script_size() as f64;
+        for (p, size) in [(p_sat, sat), (p_dissat, dissat)] {
+            if p > 0.0 {
+                match size {
+                    Some(size) => cost += p * size,
+                    None => return false,
+                }
+            }
+        }
+        let class = (ty.base, ty.correctness, ty.malleability);
+        if best
+            .get(&class)
+            .is_some_and(|candidate| candidate.cost <= cost)
+        {
+            return false;
+        }
+        best.insert(
+            class,
+            Candidate {
+                ms,
+                sat,
+                dissat,
+                cost,
+            },
+        );
+        true
+    }
+
+    /// Adds the wrapped forms of the candidates until none gets cheaper
+    fn wrap(&self, best: &mut BTreeMap<TypeClass, Candidate>, p_sat: f64, p_dissat: f64) {
+        let zero = || Box::new(Miniscript::new(Terminal::False, self.ctx).unwrap());
+        let one = || Box::new(Miniscript::new(Terminal::True, self.ctx).unwrap());
+        loop {
+            let mut changed = false;
+            let candidates: Vec<Candidate> = best.values().cloned().collect();
+            for Candidate {
+                ms, sat, dissat, ..
+            } in candidates
+            {
+                let x = || Box::new(ms.clone());
+                for fragment in [
+                    (Terminal::Alt(x()), sat, dissat),
+                    (Terminal::Swap(x()), sat, dissat),
+                    (Terminal::Check(x()), sat, dissat),
+                    (Terminal::DupIf(x()), add(sat, ONE), EMPTY),
+                    (Terminal::Verify(x()), sat, None),
+                    (Terminal::NonZero(x()), sat, EMPTY),
+                    (Terminal::ZeroNotEqual(x()), sat, dissat),
+                    (Terminal::AndV(x(), one()), sat, None),
+                    (
+                        Terminal::OrI(zero(), x()),
+                        add(sat, EMPTY),
+                        min(ONE, add(dissat, EMPTY)),
+                    ),
+                    (
+                        Terminal::OrI(x(), zero()),
+                        add(sat, ONE),
+                        min(add(dissat, ONE), EMPTY),
+                    ),
+                ] {
+                    changed |= self.insert(best, fragment, p_sat, p_dissat);
+                }
+            }
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// The fragments that can implement the policy, from the candidates of
+    /// its subpolicies
+    fn fragments(&mut self, policy: &Policy, p_sat: f64, p_dissat: f64) -> Vec<Fragment> {
+        let (sig, key) = match self.ctx {
+            ScriptContext::Segwitv0 => (73.0, 34.0),
+            ScriptContext::Tapscript => (66.0, 33.0),
+        };
+        let hash = (Some(33.0), Some(33.0));
+        let mut fragments = vec![];
+        match policy {
+            Policy::Key(k) => {
+                fragments.push((Terminal::PkK(k.clone()), Some(sig), EMPTY));
+                fragments.push((Terminal::PkH(k.clone()), Some(sig + key), Some(1.0 + key)));
+            }
+            Policy::After(n) => fragments.push((Terminal::After(*n), Some(0.0), None)),
+            Policy::Older(n) => fragments.push((Terminal::Older(*n), Some(0.0), None)),
+            Policy::Sha256(h) => fragments.push((Terminal::Sha256(*h), hash.0, hash.1)),
+            Policy::Hash256(h) => fragments.push((Terminal::Hash256(*h), hash.0, hash.1)),
+            Policy::Ripemd160(h) => fragments.push((Terminal::Ripemd160(*h), hash.0, hash.1)),
+            Policy::Hash160(h) => fragments.push((Terminal::Hash160(*h), hash.0, hash.1)),
+            Policy::And(a, b) => {
+                for (x, y) in [(a, b), (b, a)] {
+                    let xs = self.compile(x, p_sat, p_dissat);
+                    let ys = self.compile(y, p_sat, p_dissat);
+                    let xs_v = self.compile(x, p_sat, 0.0);
+                    let ys_v = self.compile(y, p_sat, 0.0);
+                    for (x, y) in pairs(&xs_v, &ys) {
+                        fragments.push((
+                            Terminal::AndV(boxed(x), boxed(y)),
+                            add(x.sat, y.sat),
+                            None,
+                        ));
+                    }
+                    for (x, y) in pairs(&xs, &ys) {
+                        fragments.push((
+                            Terminal::AndB(boxed(x), boxed(y)),
+                            add(x.sat, y.sat),
+                            add(x.dissat, y.dissat),
+                        ));
+                    }
+                    // and_n(X,Y) = andor(X,Y,0)
+                    for (x, y) in pairs(&xs, &ys_v) {
+                        let zero = Miniscript::new(Terminal::False, self.ctx).unwrap();
+                        fragments.push((
+                            Terminal::AndOr(boxed(x), boxed(y), Box::new(zero)),
+                            add(x.sat, y.sat),
+                            x.dissat,
+                        ));
+                    }
+                }
+            }
+            Policy::Or((wa, a), (wb, b)) => {
+                let total = (wa + wb) as f64;
+                let (pa, pb) = (*wa as f64 / total, *wb as f64 / total);
+                for ((px, x), (pz, z)) in [((pa, a), (pb, b)), ((pb, b), (pa, a))] {
+                    let (sx, sz) = (px * p_sat, pz * p_sat);
+                    let xs = self.compile(x, sx, p_dissat + sz);
+                    let zs = self.compile(z, sz, p_dissat);
+                    let zs_b = self.compile(z, sz, p_dissat + sx);
+                    for (x, z) in pairs(&xs, &zs_b) {
+                        fragments.push((
+                            Terminal::OrB(boxed(x), boxed(z)),
+                            mix(px, add(x.sat, z.dissat), add(x.dissat, z.sat)),
+                            add(x.dissat, z.dissat),
+                        ));
+                    }
+                    for (x, z) in pairs(&xs, &zs) {
+                        fragments.push((
+                            Terminal::OrD(boxed(x), boxed(z)),
+                            mix(px, x.sat, add(x.dissat, z.sat)),
+                            add(x.dissat, z.dissat),
+                        ));
+                    }
+                    if p_dissat == 0.0 {
+                        let xs_c = self.compile(x, sx, sz);
+                        let zs_c = self.compile(z, sz, 0.0);
+                        for (x, z) in pairs(&xs_c, &zs_c) {
+                            fragments.push((
+                                Terminal::OrC(boxed(x), boxed(z)),
+                                mix(px, x.sat, add(x.dissat, z.sat)),
+                                None,
+                            ));
+                        }
+                    }
+                    let xs_i = self.compile(x, sx, p_dissat / 2.0);
+                    let zs_i = self.compile(z, sz, p_dissat / 2.0);
+                    for (x, z) in pairs(&xs_i, &zs_i) {
+                        fragments.push((
+                            Terminal::OrI(boxed(x), boxed(z)),
+                            mix(px, add(x.sat, ONE), add(z.sat, EMPTY)),
+                            min(add(x.dissat, ONE), add(z.dissat, EMPTY)),
+                        ));
+                    }
+                    // or(and(X,Y),Z) = andor(X,Y,Z)
+                    if let Policy::And(a, b) = x.as_ref() {
+                        for (x, y) in [(a, b), (b, a)] {
+                            let xs = self.compile(x, sx, sz + p_dissat);
+                            let ys = self.compile(y, sx, 0.0);
+                            for (x, y) in pairs(&xs, &ys) {
+                                for z in &zs {
+                                    fragments.push((
+                                        Terminal::AndOr(boxed(x), boxed(y), boxed(z)),
+                                        mix(px, add(x.sat, y.sat), add(x.dissat, z.sat)),
+                                        add(x.dissat, z.dissat),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Policy::Thresh(k, subs) => {
+                let (k, n) = (*k, subs.len());
+                if n == 1 {
+                    return self.fragments(&subs[0], p_sat, p_dissat);
+                }
+                if k == n {
+                    let rest = Policy::Thresh(n - 1, subs[1..].to_vec());
+                    let and = Policy::And(Box::new(subs[0].clone()), Box::new(rest));
+                    fragments.extend(self.fragments(&and, p_sat, p_dissat));
+                } else if k == 1 {
+                    let rest = Policy::Thresh(1, subs[1..].to_vec());
+                    let or = Policy::Or((1, Box::new(subs[0].clone())), (n - 1, Box::new(rest)));
+                    fragments.extend(self.fragments(&or, p_sat, p_dissat));
+                }
+                let keys: Option<Vec<DescriptorKey>> = subs
+                    .iter()
+                    .map(|sub| match sub {
+                        Policy::Key(key) => Some(key.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(keys) = keys {
+                    fragments.push(match self.ctx {
+                        ScriptContext::Segwitv0 => (
+                            Terminal::Multi(k, keys),
+                            Some(1.0 + k as f64 * sig),
+                            Some(1.0 + k as f64),
+                        ),
+                        ScriptContext::Tapscript => (
+                            Terminal::MultiA(k, keys),
+                            Some(k as f64 * sig + (n - k) as f64),
+                            Some(n as f64),
+                        ),
+                    });
+                }
+                // Each subexpression is satisfied with odds k/n
+                let p_sub = k as f64 / n as f64;
+                let sub_sat = p_sat * p_sub;
+                let sub_dissat = p_sat * (1.0 - p_sub) + p_dissat;
+                let mut args = vec![];
+                let (mut sat, mut dissat) = (Some(0.0), Some(0.0));
+                for (i, sub) in subs.iter().enumerate() {
+                    let base = if i == 0 { Base::B } else { Base::W };
+                    let arg = self
+                        .compile(sub, sub_sat, sub_dissat)
+                        .into_iter()
+                        .filter(|candidate| {
+                            let ty = candidate.ms.ty();
+                            ty.base == base && ty.correctness.d && ty.correctness.u
+                        })
+                        .min_by(|a, b| a.cost.total_cmp(&b.cost));
+                    let Some(arg) = arg else {
+                        return fragments;
+                    };
+                    sat = add(sat, mix(p_sub, arg.sat, arg.dissat));
+                    dissat = add(dissat, arg.dissat);
+                    args.push(arg.ms);
+                }
+                fragments.push((Terminal::Thresh(k, args), sat, dissat));
+            }
+        }
+        fragments
+    }
+}
+
+/// The size of an empty push and of a push of 1, as used by the branch
+/// selectors of OP_IF
+const EMPTY: Option<f64> = Some(1.0);
+const ONE: Option<f64> = Some(2.0);
+
+/// Every combination of the candidates
+fn pairs<'a>(xs: &'a [Candidate], ys: &'a [Candidate]) -> Vec<(&'a Candidate, &'a Candidate)> {
+    xs.iter()
+        .flat_map(|x| ys.iter().map(move |y| (x, y)))
+        .collect()
+}
+
+fn boxed(candidate: &Candidate) -> Box<Miniscript> {
+    Box::new(candidate.ms.clone())
+}
+
+fn add(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    Some(a? + b?)
+}
+
+/// The expected size of a witness that is `a` with probability p, and `b`
+/// otherwise
+fn mix(p: f64, a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    if p == 0.0 {
+        b
+    } else if p == 1.0 {
+        a
+    } else {
+        Some(p * a? + (1.0 - p) * b?)
+    }
+}
+
+/// The smaller of the witnesses that are possible
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miniscript::{
+        satisfy::Assets,
+        tests::{A, B, C},
+    };
+
+    #[test]
+    fn test_parse_policy() {
+        for s in [
+            format!("pk({})", A),
+            format!("or(99@pk({}),and(pk({}),older(144)))", A, B),
+            format!("thresh(2,pk({}),pk({}),after(100))", A, B),
+        ] {
+            let policy: Policy = s.parse().unwrap();
+            assert_eq!(policy.to_string(), s);
+        }
+        assert!("or(pk(A))".parse::<Policy>().is_err());
+        assert!(format!("or(0@pk({}),pk({}))", A, B)
+            .parse::<Policy>()
+            .is_err());
+        assert!(format!("thresh(3,pk({}),pk({}))", A, B)
+            .parse::<Policy>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_compile() {
+        let compile = |s: &str| {
+            s.parse::<Policy>()
+                .unwrap()
+                .compile(ScriptContext::Segwitv0)
+                .unwrap()
+        };
+        assert_eq!(
+            compile(&format!("pk({})", A)).to_string(),
+            format!("pk({})", A)
+        );
+
+        // A vault: the hot key most of the time, the recovery key after a
+        // delay. The recovery branch is rarely used, so the smaller script
+        // of pkh() is worth its larger witness.
+        let ms = compile(&format!("or(99@pk({}),1@and(pk({}),older(144)))", A, B));
+        assert!(ms.is_sane());
+        assert_eq!(
+            ms.to_string(),
+            format!("or_d(pk({}),and_v(v:pkh({}),older(144)))", A, B)
+        );
+
+        let ms = compile(&format!("thresh(2,pk({}),pk({}),pk({}))", A, B, C));
+        assert_eq!(ms.to_string(), format!("multi(2,{},{},{})", A, B, C));
+
+        let ms = compile(&format!("and(pk({}),or(pk({}),after(100)))", A, B));
+        assert!(ms.is_sane());
+        let mut assets = Assets {
+            lock_time: Some(100),
+            ..Default::default()
+        };
+        assets
+            .signatures
+            .insert(hex::decode(A).unwrap(), vec![1; 72]);
+        assert!(ms.satisfy(&assets, 0).is_ok());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let policy: Policy = "and(after(100),after(500000001))".parse().unwrap();
+        assert!(matches!(
+            policy.compile(ScriptContext::Segwitv0),
+            Err(MiniscriptError::Compile(_))
+        ));
+        let policy: Policy = format!("pk({})", &A[2..]).parse().unwrap();
+        assert!(matches!(
+            policy.compile(ScriptContext::Segwitv0),
+            Err(MiniscriptError::InvalidContext(_))
+        ));
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::utils::{hash160::hash160, hash256::hash256};
+
+use super::{
+    types::{LOCKTIME_THRESHOLD, SEQUENCE_LOCKTIME_TYPE_FLAG},
+    Miniscript, MiniscriptError, Terminal,
+};
+
+/// Sequence numbers with this bit set have no relative locktime (BIP68)
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// The bits of a sequence number holding the relative locktime (BIP68)
+const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// Provides the signatures, preimages and timelocks needed to satisfy a
+/// miniscript. Everything is unavailable by default.
+pub trait Satisfier {
+    /// Returns the signature for the public key, serialized as it appears in
+    /// the script
+    fn lookup_signature(&self, _pubkey: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn lookup_sha256(&self, _hash: &[u8; 32]) -> Option<[u8; 32]> {
+        None
+    }
+
+    fn lookup_hash256(&self, _hash: &[u8; 32]) -> Option<[u8; 32]> {
+        None
+    }
+
+    fn lookup_ripemd160(&self, _hash: &[u8; 20]) -> Option<[u8; 32]> {
+        None
+    }
+
+    fn lookup_hash160(&self, _hash: &[u8; 20]) -> Option<[u8; 32]> {
+        None
+    }
+
+    /// Returns true if older(n) is satisfied by the spending input
+    fn check_older(&self, _n: u32) -> bool {
+        false
+    }
+
+    /// Returns true if after(n) is satisfied by the spending transaction
+    fn check_after(&self, _n: u32) -> bool {
+        false
+    }
+}
+
+/// A satisfier from known signatures and preimages, and the sequence and
+/// locktime of the spending transaction
+#[derive(Debug, Default, Clone)]
+pub struct Assets {
+    /// Signatures by serialized public key
+    pub signatures: HashMap<Vec<u8>, Vec<u8>>,
+    pub preimages: Vec<[u8; 32]>,
+    /// The sequence of the input being signed
+    pub sequence: Option<u32>,
+    /// The locktime of the spending transaction
+    pub lock_time: Option<u32>,
+}
+
+impl Assets {
+    fn find_preimage(&self, matches: impl Fn(&[u8; 32]) -> bool) -> Option<[u8; 32]> {
+        self.preimages
+            .iter()
+            .find(|preimage| matches(preimage))
+            .copied()
+    }
+}
+
+impl Satisfier for Assets {
+    fn lookup_signature(&self, pubkey: &[u8]) -> Option<Vec<u8>> {
+        self.signatures.get(pubkey).cloned()
+    }
+
+    fn lookup_sha256(&self, hash: &[u8; 32]) -> Option<[u8; 32]> {
+        self.find_preimage(|preimage| Sha256::digest(preimage)[..] == hash[..])
+    }
+
+    fn lookup_hash256(&self, hash: &[u8; 32]) -> Option<[u8; 32]> {
+        self.find_preimage(|preimage| hash256(preimage) == *hash)
+    }
+
+    fn lookup_ripemd160(&self, hash: &[u8; 20]) -> Option<[u8; 32]> {
+        self.find_preimage(|preimage| Ripemd160::digest(preimage)[..] == hash[..])
+    }
+
+    fn lookup_hash160(&self, hash: &[u8; 20]) -> Option<[u8; 32]> {
+        self.find_preimage(|preimage| hash160(preimage) == *hash)
+    }
+
+    fn check_older(&self, n: u32) -> bool {
+        let Some(sequence) = self.sequence else {
+            return false;
+        };
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+        // Heights and times can't be compared
+        if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != n & SEQUENCE_LOCKTIME_TYPE_FLAG {
+            return false;
+        }
+        sequence & SEQUENCE_LOCKTIME_MASK >= n & SEQUENCE_LOCKTIME_MASK
+    }
+
+    fn check_after(&self, n: u32) -> bool {
+        let Some(lock_time) = self.lock_time else {
+            return false;
+        };
+        (lock_time < LOCKTIME_THRESHOLD) == (n < LOCKTIME_THRESHOLD) && lock_time >= n
+    }
+}
+
+/// A witness stack, bottom first, and what it takes to produce it
+#[derive(Debug, Clone)]
+struct Witness {
+    stack: Vec<Vec<u8>>,
+    /// Producing it takes a signature
+    has_sig: bool,
+    /// A third party could replace it with another valid witness
+    malleable: bool,
+}
+
+impl Witness {
+    fn new(stack: Vec<Vec<u8>>) -> Self {
+        Self {
+            stack,
+            has_sig: false,
+            malleable: false,
+        }
+    }
+
+    fn empty() -> Option<Self> {
+        Some(Self::new(vec![]))
+    }
+
+    fn push(item: Vec<u8>) -> Option<Self> {
+        Some(Self::new(vec![item]))
+    }
+
+    fn signature(signature: Vec<u8>) -> Self {
+        Self {
+            has_sig: true,
+            ..Self::new(vec![signature])
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.stack.iter().map(|item| item.len() + 1).sum()
+    }
+}
+
+/// Both witnesses, with `deeper` below `top`
+fn concat(deeper: &Option<Witness>, top: &Option<Witness>) -> Option<Witness> {
+    let (deeper, top) = (deeper.as_ref()?, top.as_ref()?);
+    let mut stack = deeper.stack.clone();
+    stack.extend(top.stack.iter().cloned());
+    Some(Witness {
+        stack,
+        has_sig: deeper.has_sig || top.has_sig,
+        malleable: deeper.malleable || top.malleable,
+    })
+}
+
+/// Picks the witness an honest signer should use when either would do
+fn choose(a: Option<Witness>, b: Option<Witness>) -> Option<Witness> {
+    let (mut a, mut b) = match (a, b) {
+        (None, b) => return b,
+        (a, None) => return a,
+        (Some(a), Some(b)) => (a, b),
+    };
+    // A witness without a signature can always be used by a third party, so
+    // it must be the one we pick
+    match (a.has_sig, b.has_sig) {
+        (false, true) => return Some(a),
+        (true, false) => return Some(b),
+        (false, false) => {
+            a.malleable = true;
+            b.malleable = true;
+        }
+        (true, true) => {
+            if a.malleable != b.malleable {
+                return Some(if a.malleable { b } else { a });
+            }
+        }
+    }
+    Some(if b.size() < a.size() { b } else { a })
+}
+
+/// The best satisfaction and dissatisfaction of an expression
+struct Satisfaction {
+    sat: Option<Witness>,
+    dissat: Option<Witness>,
+}
+
+/// Satisfaction
+impl Miniscript {
+    /// Returns the witness stack, bottom first, that satisfies the script
+    /// with the keys derived at the index. Fails if the satisfier is missing
+    /// something, or if the only witnesses it can build are malleable.
+    pub fn satisfy(
+        &self,
+        satisfier: &dyn Satisfier,
+        index: u32,
+    ) -> Result<Vec<Vec<u8>>, MiniscriptError> {
+        let witness = self
+            .satisfaction(satisfier, index)?
+            .sat
+            .ok_or(MiniscriptError::Unsatisfiable)?;
+        if witness.malleable {
+            return Err(MiniscriptError::Malleable);
+        }
+        Ok(witness.stack)
+    }
+
+    fn satisfaction(
+        &self,
+        satisfier: &dyn Satisfier,
+        index: u32,
+    ) -> Result<Satisfaction, MiniscriptError> {
+        let empty = || Witness::push(vec![]);
+        let one = || Witness::push(vec![1]);
+        // Hash fragments can be dissatisfied by any 32 bytes that aren't a
+        // preimage, so the dissatisfaction is malleable
+        let hash_dissat = || {
+            Some(Witness {
+                malleable: true,
+                ..Witness::new(vec![vec![0; 32]])
+            })
+        };
+        let preimage =
+            |preimage: Option<[u8; 32]>| preimage.map(|p| Witness::new(vec![p.to_vec()]));
+
+        Ok(match &self.node {
+            Terminal::False => Satisfaction {
+                sat: None,
+                dissat: Witness::empty(),
+            },
+            Terminal::True => Satisfaction {
+                sat: Witness::empty(),
+                dissat: None,
+            },
+            Terminal::PkK(key) => {
+                let key = self.key_bytes(key, index)?;
+                Satisfaction {
+                    sat: satisfier.lookup_signature(&key).map(Witness::signature),
+                    dissat: empty(),
+                }
+            }
+            Terminal::PkH(key) => {
+                let key = self.key_bytes(key, index)?;
+                let sig = satisfier.lookup_signature(&key).map(Witness::signature);
+                let key = Witness::push(key);
+                Satisfaction {
+                    sat: concat(&sig, &key),
+                    dissat: concat(&empty(), &key),
+                }
+            }
+            Terminal::Older(n) => Satisfaction {
+                sat: satisfier.check_older(*n).then(|| Witness::new(vec![])),
+                dissat: None,
+            },
+            Terminal::After(n) => Satisfaction {
+                sat: satisfier.check_after(*n).then(|| Witness::new(vec![])),
+                dissat: None,
+            },
+            Terminal::Sha256(hash) => Satisfaction {
+                sat: preimage(satisfier.lookup_sha256(hash)),
+                dissat: hash_dissat(),
+            },
+            Terminal::Hash256(hash) => Satisfaction {
+                sat: preimage(satisfier.lookup_hash256(hash)),
+                dissat: hash_dissat(),
+            },
+            Terminal::Ripemd160(hash) => Satisfaction {
+                sat: preimage(satisfier.lookup_ripemd160(hash)),
+                dissat: hash_dissat(),
+            },
+            Terminal::Hash160(hash) => Satisfaction {
+                sat: preimage(satisfier.lookup_hash160(hash)),
+                dissat: hash_dissat(),
+            },
+            Terminal::Alt(x)
+            | Terminal::Swap(x)
+            | Terminal::Check(x)
+            | Terminal::ZeroNotEqual(x) => x.satisfaction(satisfier, index)?,
+            Terminal::DupIf(x) => Satisfaction {
+                sat: concat(&x.satisfaction(satisfier, index)?.sat, &one()),
+                dissat: empty(),
+            },
+            Terminal::Verify(x) => Satisfaction {
+                sat: x.satisfaction(satisfier, index)?.sat,
+                dissat: None,
+            },
+            Terminal::NonZero(x) => Satisfaction {
+                sat: x.satisfaction(satisfier, index)?.sat,
+                dissat: empty(),
+            },
+            // X runs first, so its inputs go on top
+            Terminal::AndV(x, y) => {
+                let (x, y) = (
+                    x.satisfaction(satisfier, index)?,
+                    y.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: concat(&y.sat, &x.sat),
+                    dissat: None,
+                }
+            }
+            Terminal::AndB(x, y) => {
+                let (x, y) = (
+                    x.satisfaction(satisfier, index)?,
+                    y.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: concat(&y.sat, &x.sat),
+                    dissat: concat(&y.dissat, &x.dissat),
+                }
+            }
+            Terminal::AndOr(x, y, z) => {
+                let x = x.satisfaction(satisfier, index)?;
+                let y = y.satisfaction(satisfier, index)?;
+                let z = z.satisfaction(satisfier, index)?;
+                Satisfaction {
+                    sat: choose(concat(&y.sat, &x.sat), concat(&z.sat, &x.dissat)),
+                    dissat: concat(&z.dissat, &x.dissat),
+                }
+            }
+            Terminal::OrB(x, z) => {
+                let (x, z) = (
+                    x.satisfaction(satisfier, index)?,
+                    z.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: choose(concat(&z.dissat, &x.sat), concat(&z.sat, &x.dissat)),
+                    dissat: concat(&z.dissat, &x.dissat),
+                }
+            }
+            Terminal::OrC(x, z) => {
+                let (x, z) = (
+                    x.satisfaction(satisfier, index)?,
+                    z.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: choose(x.sat, concat(&z.sat, &x.dissat)),
+                    dissat: None,
+                }
+            }
+            Terminal::OrD(x, z) => {
+                let (x, z) = (
+                    x.satisfaction(satisfier, index)?,
+                    z.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: choose(x.sat.clone(), concat(&z.sat, &x.dissat)),
+                    dissat: concat(&z.dissat, &x.dissat),
+                }
+            }
+            // OP_IF takes the branch selector from the top of the stack
+            Terminal::OrI(x, z) => {
+                let (x, z) = (
+                    x.satisfaction(satisfier, index)?,
+                    z.satisfaction(satisfier, index)?,
+                );
+                Satisfaction {
+                    sat: choose(concat(&x.sat, &one()), concat(&z.sat, &empty())),
+                    dissat: choose(concat(&x.dissat, &one()), concat(&z.dissat, &empty())),
+                }
+            }
+            Terminal::Thresh(k, subs) => {
+                // best[j] is the best witness satisfying exactly j of the
+                // subexpressions seen so far. Later ones go deeper.
+                let mut best = vec![Witness::empty()];
+                for sub in subs {
+                    let sub = sub.satisfaction(satisfier, index)?;
+                    let mut next = vec![None; best.len() + 1];
+                    for (j, witness) in best.iter().enumerate() {
+                        next[j] = choose(next[j].take(), concat(&sub.dissat, witness));
+                        next[j + 1] = choose(next[j + 1].take(), concat(&sub.sat, witness));
+                    }
+                    best = next;
+                }
+                Satisfaction {
+                    sat: best[*k].clone(),
+                    dissat: best[0].clone(),
+                }
+            }
+            Terminal::Multi(k, keys) => {
+                // Signatures in the order of their keys, after the extra
+                // element OP_CHECKMULTISIG consumes
+                let mut best = vec![empty()];
+                for key in keys {
+                    let sig = satisfier
+                        .lookup_signature(&self.key_bytes(key, index)?)
+                        .map(Witness::signature);
+                    let mut next = best.clone();
+                    next.push(None);
+                    for (j, witness) in best.iter().enumerate() {
+                        next[j + 1] = choose(next[j + 1].take(), concat(witness, &sig));
+                    }
+                    best = next;
+                }
+                Satisfaction {
+                    sat: best[*k].clone(),
+                    dissat: Some(Witness::new(vec![vec![]; k + 1])),
+                }
+            }
+            Terminal::MultiA(k, keys) => {
+                // One element per key, the first key's on top
+                let mut best = vec![Witness::empty()];
+                for key in keys {
+                    let sig = satisfier
+                        .lookup_signature(&self.key_bytes(key, index)?)
+                        .map(Witness::signature);
+                    let mut next = vec![None; best.len() + 1];
+                    for (j, witness) in best.iter().enumerate() {
+                        next[j] = choose(next[j].take(), concat(&empty(), witness));
+                        next[j + 1] = choose(next[j + 1].take(), concat(&sig, witness));
+                    }
+                    best = next;
+                }
+                Satisfaction {
+                    sat: best[*k].clone(),
+                    dissat: best[0].clone(),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miniscript::{
+        tests::{A, B, C},
+        ScriptContext,
+    };
+
+    fn sig(byte: u8) -> Vec<u8> {
+        vec![byte; 72]
+    }
+
+    fn assets(keys: &[(&str, u8)]) -> Assets {
+        let mut assets = Assets::default();
+        for (key, byte) in keys {
+            assets
+                .signatures
+                .insert(hex::decode(key).unwrap(), sig(*byte));
+        }
+        assets
+    }
+
+    #[test]
+    fn test_satisfy_and_or() {
+        let ms: Miniscript = format!("or_d(pk({}),and_v(v:pk({}),older(144)))", A, B)
+            .parse()
+            .unwrap();
+
+        // With A's signature the first branch is used
+        let witness = ms.satisfy(&assets(&[(A, 1)]), 0).unwrap();
+        assert_eq!(witness, vec![sig(1)]);
+
+        // Otherwise B must sign and the input must be 144 blocks old
+        let mut b = assets(&[(B, 2)]);
+        assert_eq!(ms.satisfy(&b, 0), Err(MiniscriptError::Unsatisfiable));
+        b.sequence = Some(144);
+        assert_eq!(ms.satisfy(&b, 0).unwrap(), vec![sig(2), vec![]]);
+
+        // A time based sequence doesn't satisfy a height based older()
+        b.sequence = Some(SEQUENCE_LOCKTIME_TYPE_FLAG | 144);
+        assert!(ms.satisfy(&b, 0).is_err());
+    }
+
+    #[test]
+    fn test_satisfy_hash() {
+        let preimage = [7u8; 32];
+        let hash = hex::encode(Sha256::digest(preimage));
+        let ms: Miniscript = format!("and_v(v:pk({}),sha256({}))", A, hash)
+            .parse()
+            .unwrap();
+        let mut assets = assets(&[(A, 1)]);
+        assert!(ms.satisfy(&assets, 0).is_err());
+        assets.preimages.push(preimage);
+        assert_eq!(
+            ms.satisfy(&assets, 0).unwrap(),
+            vec![preimage.to_vec(), sig(1)]
+        );
+    }
+
+    #[test]
+    fn test_satisfy_thresholds() {
+        let ms: Miniscript = format!("multi(2,{},{},{})", A, B, C).parse().unwrap();
+        let witness = ms.satisfy(&assets(&[(A, 1), (C, 3)]), 0).unwrap();
+        assert_eq!(witness, vec![vec![], sig(1), sig(3)]);
+
+        let ms: Miniscript = format!("thresh(2,pk({}),s:pk({}),s:pk({}))", A, B, C)
+            .parse()
+            .unwrap();
+        let witness = ms.satisfy(&assets(&[(A, 1), (C, 3)]), 0).unwrap();
+        assert_eq!(witness, vec![sig(3), vec![], sig(1)]);
+        assert!(ms.satisfy(&assets(&[(B, 2)]), 0).is_err());
+
+        let ms = Miniscript::parse(
+            &format!("multi_a(2,{},{},{})", &A[2..], &B[2..], &C[2..]),
+            ScriptContext::Tapscript,
+        )
+        .unwrap();
+        let witness = ms
+            .satisfy(&assets(&[(&A[2..], 1), (&B[2..], 2)]), 0)
+            .unwrap();
+        assert_eq!(witness, vec![vec![], sig(2), sig(1)]);
+    }
+
+    #[test]
+    fn test_malleable_satisfaction() {
+        // Either timelock can be used, and nothing binds the choice
+        let ms: Miniscript = "or_i(older(144),after(100))".parse().unwrap();
+        let assets = Assets {
+            sequence: Some(144),
+            lock_time: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(ms.satisfy(&assets, 0), Err(MiniscriptError::Malleable));
+    }
+}
@@ -0,0 +1,735 @@
+use std::fmt;
+
+use super::MiniscriptError;
+
+/// The basic type of a miniscript expression
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub enum Base {
+    /// Consumes its inputs and pushes a nonzero value on satisfaction, zero
+    /// on dissatisfaction
+    B,
+    /// Consumes its inputs and continues, or aborts the script
+    V,
+    /// Pushes a key that a signature check will consume
+    K,
+    /// Like B, but takes its inputs from under the top stack element
+    W,
+}
+
+/// Correctness properties (z, o, n, d, u)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default, PartialOrd, Ord)]
+pub struct Correctness {
+    /// Consumes no stack elements
+    pub z: bool,
+    /// Consumes exactly one stack element
+    pub o: bool,
+    /// The top input is never zero when satisfied
+    pub n: bool,
+    /// Has a dissatisfaction
+    pub d: bool,
+    /// Pushes exactly 1 on satisfaction
+    pub u: bool,
+}
+
+/// Malleability properties (s, f, e) and whether the expression is
+/// non-malleable (m)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default, PartialOrd, Ord)]
+pub struct Malleability {
+    /// Every satisfaction requires a signature
+    pub s: bool,
+    /// Every dissatisfaction requires a signature, or there are none
+    pub f: bool,
+    /// There is a unique dissatisfaction that requires no signature
+    pub e: bool,
+    /// A non-malleable satisfaction always exists
+    pub m: bool,
+}
+
+/// The kinds of timelocks an expression uses, to catch expressions that
+/// require both a height and a time lock of the same kind, which no
+/// transaction can satisfy
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct Timelocks {
+    pub csv_height: bool,
+    pub csv_time: bool,
+    pub cltv_height: bool,
+    pub cltv_time: bool,
+    /// Some satisfaction needs both a height and a time lock of one kind
+    pub mixed: bool,
+}
+
+/// The full type of a miniscript expression
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Type {
+    pub base: Base,
+    pub correctness: Correctness,
+    pub malleability: Malleability,
+    pub timelocks: Timelocks,
+}
+
+/// Relative locktimes with this bit set are in units of 512 seconds
+pub(crate) const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Absolute locktimes at or above this value are UNIX timestamps
+pub(crate) const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+impl Timelocks {
+    pub fn older(n: u32) -> Self {
+        let time = n & SEQUENCE_LOCKTIME_TYPE_FLAG != 0;
+        Self {
+            csv_time: time,
+            csv_height: !time,
+            ..Default::default()
+        }
+    }
+
+    pub fn after(n: u32) -> Self {
+        let time = n >= LOCKTIME_THRESHOLD;
+        Self {
+            cltv_time: time,
+            cltv_height: !time,
+            ..Default::default()
+        }
+    }
+
+    /// Timelocks of one branch or the other
+    fn or(self, other: Self) -> Self {
+        Self {
+            csv_height: self.csv_height || other.csv_height,
+            csv_time: self.csv_time || other.csv_time,
+            cltv_height: self.cltv_height || other.cltv_height,
+            cltv_time: self.cltv_time || other.cltv_time,
+            mixed: self.mixed || other.mixed,
+        }
+    }
+
+    /// Timelocks that must all be satisfied together
+    fn and(self, other: Self) -> Self {
+        let mixed = (self.csv_height && other.csv_time)
+            || (self.csv_time && other.csv_height)
+            || (self.cltv_height && other.cltv_time)
+            || (self.cltv_time && other.cltv_height);
+        Self {
+            mixed: self.or(other).mixed || mixed,
+            ..self.or(other)
+        }
+    }
+}
+
+fn type_error(fragment: &str, requirement: &str) -> MiniscriptError {
+    MiniscriptError::TypeCheck(format!("{} requires {}", fragment, requirement))
+}
+
+impl Type {
+    /// 0
+    pub fn false_() -> Self {
+        Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: true,
+                u: true,
+                d: true,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                s: true,
+                e: true,
+                m: true,
+                ..Default::default()
+            },
+            timelocks: Timelocks::default(),
+        }
+    }
+
+    /// 1
+    pub fn true_() -> Self {
+        Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: true,
+                u: true,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                f: true,
+                m: true,
+                ..Default::default()
+            },
+            timelocks: Timelocks::default(),
+        }
+    }
+
+    /// pk_k(key)
+    pub fn pk_k() -> Self {
+        Self {
+            base: Base::K,
+            correctness: Correctness {
+                o: true,
+                n: true,
+                d: true,
+                u: true,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                s: true,
+                e: true,
+                m: true,
+                ..Default::default()
+            },
+            timelocks: Timelocks::default(),
+        }
+    }
+
+    /// pk_h(key)
+    pub fn pk_h() -> Self {
+        Self {
+            correctness: Correctness {
+                n: true,
+                d: true,
+                u: true,
+                ..Default::default()
+            },
+            ..Self::pk_k()
+        }
+    }
+
+    /// multi(k, ...) and multi_a(k, ...)
+    pub fn multi() -> Self {
+        Self {
+            base: Base::B,
+            correctness: Correctness {
+                n: true,
+                d: true,
+                u: true,
+                ..Default::default()
+            },
+            ..Self::pk_k()
+        }
+    }
+
+    /// older(n) and after(n)
+    pub fn time(timelocks: Timelocks) -> Self {
+        Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: true,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                f: true,
+                m: true,
+                ..Default::default()
+            },
+            timelocks,
+        }
+    }
+
+    /// sha256(h), hash256(h), ripemd160(h) and hash160(h)
+    pub fn hash() -> Self {
+        Self {
+            base: Base::B,
+            correctness: Correctness {
+                o: true,
+                n: true,
+                d: true,
+                u: true,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                m: true,
+                ..Default::default()
+            },
+            timelocks: Timelocks::default(),
+        }
+    }
+
+    /// a:X
+    pub fn alt(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B {
+            return Err(type_error("a:X", "X to be B"));
+        }
+        Ok(Self {
+            base: Base::W,
+            correctness: Correctness {
+                d: x.correctness.d,
+                u: x.correctness.u,
+                ..Default::default()
+            },
+            ..x
+        })
+    }
+
+    /// s:X
+    pub fn swap(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B || !x.correctness.o {
+            return Err(type_error("s:X", "X to be Bo"));
+        }
+        Self::alt(x)
+    }
+
+    /// c:X
+    pub fn check(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::K {
+            return Err(type_error("c:X", "X to be K"));
+        }
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                u: true,
+                ..x.correctness
+            },
+            malleability: Malleability {
+                s: true,
+                ..x.malleability
+            },
+            ..x
+        })
+    }
+
+    /// d:X. In tapscript MINIMALIF makes the result a unit.
+    pub fn dup_if(x: Self, tapscript: bool) -> Result<Self, MiniscriptError> {
+        if x.base != Base::V || !x.correctness.z {
+            return Err(type_error("d:X", "X to be Vz"));
+        }
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                o: true,
+                n: true,
+                d: true,
+                u: tapscript,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                s: x.malleability.s,
+                e: true,
+                f: false,
+                m: x.malleability.m,
+            },
+            ..x
+        })
+    }
+
+    /// v:X
+    pub fn verify(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B {
+            return Err(type_error("v:X", "X to be B"));
+        }
+        Ok(Self {
+            base: Base::V,
+            correctness: Correctness {
+                d: false,
+                u: false,
+                ..x.correctness
+            },
+            malleability: Malleability {
+                s: x.malleability.s,
+                f: true,
+                e: false,
+                m: x.malleability.m,
+            },
+            ..x
+        })
+    }
+
+    /// j:X
+    pub fn non_zero(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B || !x.correctness.n {
+            return Err(type_error("j:X", "X to be Bn"));
+        }
+        Ok(Self {
+            correctness: Correctness {
+                z: false,
+                o: x.correctness.o,
+                n: true,
+                d: true,
+                u: x.correctness.u,
+            },
+            malleability: Malleability {
+                s: x.malleability.s,
+                f: false,
+                e: x.malleability.f,
+                m: x.malleability.m,
+            },
+            ..x
+        })
+    }
+
+    /// n:X
+    pub fn zero_not_equal(x: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B {
+            return Err(type_error("n:X", "X to be B"));
+        }
+        Ok(Self {
+            correctness: Correctness {
+                u: true,
+                ..x.correctness
+            },
+            ..x
+        })
+    }
+
+    /// and_v(X,Y)
+    pub fn and_v(x: Self, y: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::V || y.base == Base::W {
+            return Err(type_error("and_v(X,Y)", "X to be V and Y to be B, K or V"));
+        }
+        let (cx, cy) = (x.correctness, y.correctness);
+        let (mx, my) = (x.malleability, y.malleability);
+        Ok(Self {
+            base: y.base,
+            correctness: Correctness {
+                z: cx.z && cy.z,
+                o: (cx.z && cy.o) || (cy.z && cx.o),
+                n: cx.n || (cx.z && cy.n),
+                d: false,
+                u: cy.u,
+            },
+            malleability: Malleability {
+                s: mx.s || my.s,
+                f: mx.s || my.f,
+                e: false,
+                m: mx.m && my.m,
+            },
+            timelocks: x.timelocks.and(y.timelocks),
+        })
+    }
+
+    /// and_b(X,Y)
+    pub fn and_b(x: Self, y: Self) -> Result<Self, MiniscriptError> {
+        if x.base != Base::B || y.base != Base::W {
+            return Err(type_error("and_b(X,Y)", "X to be B and Y to be W"));
+        }
+        let (cx, cy) = (x.correctness, y.correctness);
+        let (mx, my) = (x.malleability, y.malleability);
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: cx.z && cy.z,
+                o: (cx.z && cy.o) || (cy.z && cx.o),
+                n: cx.n || (cx.z && cy.n),
+                d: cx.d && cy.d,
+                u: true,
+            },
+            malleability: Malleability {
+                s: mx.s || my.s,
+                f: (mx.f && (my.f || mx.s)) || (my.s && my.f),
+                e: mx.e && my.e && mx.s && my.s,
+                m: mx.m && my.m,
+            },
+            timelocks: x.timelocks.and(y.timelocks),
+        })
+    }
+
+    /// andor(X,Y,Z)
+    pub fn and_or(x: Self, y: Self, z: Self) -> Result<Self, MiniscriptError> {
+        let (cx, cy, cz) = (x.correctness, y.correctness, z.correctness);
+        if x.base != Base::B || !cx.d || !cx.u || y.base != z.base || y.base == Base::W {
+            return Err(type_error(
+                "andor(X,Y,Z)",
+                "X to be Bdu and Y and Z to be both B, K or V",
+            ));
+        }
+        let (mx, my, mz) = (x.malleability, y.malleability, z.malleability);
+        Ok(Self {
+            base: y.base,
+            correctness: Correctness {
+                z: cx.z && cy.z && cz.z,
+                o: (cx.z && cy.o && cz.o) || (cx.o && cy.z && cz.z),
+                n: false,
+                d: cz.d,
+                u: cy.u && cz.u,
+            },
+            malleability: Malleability {
+                s: mz.s && (mx.s || my.s),
+                f: mz.f && (mx.s || my.f),
+                e: mz.e && (mx.s || my.f),
+                m: mx.m && my.m && mz.m && mx.e && (mx.s || my.s || mz.s),
+            },
+            timelocks: x.timelocks.and(y.timelocks).or(z.timelocks),
+        })
+    }
+
+    /// or_b(X,Z)
+    pub fn or_b(x: Self, z: Self) -> Result<Self, MiniscriptError> {
+        let (cx, cz) = (x.correctness, z.correctness);
+        if x.base != Base::B || !cx.d || z.base != Base::W || !cz.d {
+            return Err(type_error("or_b(X,Z)", "X to be Bd and Z to be Wd"));
+        }
+        let (mx, mz) = (x.malleability, z.malleability);
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: cx.z && cz.z,
+                o: (cx.z && cz.o) || (cz.z && cx.o),
+                n: false,
+                d: true,
+                u: true,
+            },
+            malleability: Malleability {
+                s: mx.s && mz.s,
+                f: false,
+                e: true,
+                m: mx.m && mz.m && mx.e && mz.e && (mx.s || mz.s),
+            },
+            timelocks: x.timelocks.or(z.timelocks),
+        })
+    }
+
+    /// or_c(X,Z)
+    pub fn or_c(x: Self, z: Self) -> Result<Self, MiniscriptError> {
+        let (cx, cz) = (x.correctness, z.correctness);
+        if x.base != Base::B || !cx.d || !cx.u || z.base != Base::V {
+            return Err(type_error("or_c(X,Z)", "X to be Bdu and Z to be V"));
+        }
+        let (mx, mz) = (x.malleability, z.malleability);
+        Ok(Self {
+            base: Base::V,
+            correctness: Correctness {
+                z: cx.z && cz.z,
+                o: cx.o && cz.z,
+                ..Default::default()
+            },
+            malleability: Malleability {
+                s: mx.s && mz.s,
+                f: true,
+                e: false,
+                m: mx.m && mz.m && mx.e && (mx.s || mz.s),
+            },
+            timelocks: x.timelocks.or(z.timelocks),
+        })
+    }
+
+    /// or_d(X,Z)
+    pub fn or_d(x: Self, z: Self) -> Result<Self, MiniscriptError> {
+        let (cx, cz) = (x.correctness, z.correctness);
+        if x.base != Base::B || !cx.d || !cx.u || z.base != Base::B {
+            return Err(type_error("or_d(X,Z)", "X to be Bdu and Z to be B"));
+        }
+        let (mx, mz) = (x.malleability, z.malleability);
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: cx.z && cz.z,
+                o: cx.o && cz.z,
+                n: false,
+                d: cz.d,
+                u: cz.u,
+            },
+            malleability: Malleability {
+                s: mx.s && mz.s,
+                f: mz.f,
+                e: mz.e,
+                m: mx.m && mz.m && mx.e && (mx.s || mz.s),
+            },
+            timelocks: x.timelocks.or(z.timelocks),
+        })
+    }
+
+    /// or_i(X,Z)
+    pub fn or_i(x: Self, z: Self) -> Result<Self, MiniscriptError> {
+        if x.base != z.base || x.base == Base::W {
+            return Err(type_error("or_i(X,Z)", "X and Z to be both B, K or V"));
+        }
+        let (cx, cz) = (x.correctness, z.correctness);
+        let (mx, mz) = (x.malleability, z.malleability);
+        Ok(Self {
+            base: x.base,
+            correctness: Correctness {
+                z: false,
+                o: cx.z && cz.z,
+                n: false,
+                d: cx.d || cz.d,
+                u: cx.u && cz.u,
+            },
+            malleability: Malleability {
+                s: mx.s && mz.s,
+                f: mx.f && mz.f,
+                e: (mx.e && mz.f) || (mz.e && mx.f),
+                m: mx.m && mz.m && (mx.s || mz.s),
+            },
+            timelocks: x.timelocks.or(z.timelocks),
+        })
+    }
+
+    /// thresh(k,X1,...,Xn)
+    pub fn thresh(k: usize, subs: &[Self]) -> Result<Self, MiniscriptError> {
+        if k == 0 || k > subs.len() {
+            return Err(MiniscriptError::InvalidThreshold {
+                threshold: k,
+                count: subs.len(),
+            });
+        }
+        for (i, sub) in subs.iter().enumerate() {
+            let expected = if i == 0 { Base::B } else { Base::W };
+            if sub.base != expected || !sub.correctness.d || !sub.correctness.u {
+                return Err(type_error(
+                    "thresh(k,X1,...,Xn)",
+                    "X1 to be Bdu and the others to be Wdu",
+                ));
+            }
+        }
+        let zero_args = subs.iter().filter(|sub| sub.correctness.z).count();
+        let one_args = subs.iter().filter(|sub| sub.correctness.o).count();
+        // Subexpressions without signatures, which third parties can satisfy
+        let non_safe = subs.iter().filter(|sub| !sub.malleability.s).count();
+        let all_e = subs.iter().all(|sub| sub.malleability.e);
+        let all_m = subs.iter().all(|sub| sub.malleability.m);
+
+        let mut timelocks = Timelocks::default();
+        for sub in subs {
+            // With k > 1 several subexpressions are satisfied together
+            timelocks = if k > 1 {
+                timelocks.and(sub.timelocks)
+            } else {
+                timelocks.or(sub.timelocks)
+            };
+        }
+
+        Ok(Self {
+            base: Base::B,
+            correctness: Correctness {
+                z: zero_args == subs.len(),
+                o: zero_args == subs.len() - 1 && one_args == 1,
+                n: false,
+                d: true,
+                u: true,
+            },
+            malleability: Malleability {
+                s: non_safe < k,
+                f: false,
+                e: all_e && non_safe == 0,
+                // The satisfier picks which k to satisfy; it is only unique
+                // if at most k can be satisfied without a signature
+                m: all_m && all_e && non_safe <= k,
+            },
+            timelocks,
+        })
+    }
+
+    /// Returns true if some satisfaction needs both a height and a time lock
+    /// of one kind
+    pub fn has_mixed_timelocks(&self) -> bool {
+        self.timelocks.mixed
+    }
+}
+
+impl fmt::Display for Type {
+    /// Writes the type like "Bondu", base type followed by its properties
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.base)?;
+        let c = self.correctness;
+        let m = self.malleability;
+        for (set, letter) in [
+            (c.z, 'z'),
+            (c.o, 'o'),
+            (c.n, 'n'),
+            (c.d, 'd'),
+            (c.u, 'u'),
+            (m.e, 'e'),
+            (m.f, 'f'),
+            (m.s, 's'),
+            (m.m, 'm'),
+        ] {
+            if set {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrappers() {
+        // pk(A) = c:pk_k(A)
+        let pk = Type::check(Type::pk_k()).unwrap();
+        assert_eq!(pk.to_string(), "Bonduesm");
+        assert_eq!(Type::verify(pk).unwrap().to_string(), "Vonfsm");
+        assert_eq!(Type::swap(pk).unwrap().to_string(), "Wduesm");
+        assert!(Type::check(pk).is_err());
+
+        // d:v:older(144)
+        let older = Type::time(Timelocks::older(144));
+        let dv = Type::dup_if(Type::verify(older).unwrap(), false).unwrap();
+        assert_eq!(dv.to_string(), "Bondem");
+        assert!(Type::swap(older).is_err());
+    }
+
+    #[test]
+    fn test_combinators() {
+        let pk = Type::check(Type::pk_k()).unwrap();
+        let older = Type::time(Timelocks::older(144));
+
+        // and_v(v:pk(A),older(144)) needs a signature and a timelock
+        let and_v = Type::and_v(Type::verify(pk).unwrap(), older).unwrap();
+        assert_eq!(and_v.base, Base::B);
+        assert!(and_v.malleability.s && and_v.malleability.m);
+
+        // or_d(pk(A),older(144)) can be satisfied without a signature,
+        // but is still non-malleable because pk(A) is e and s
+        let or_d = Type::or_d(pk, older).unwrap();
+        assert!(!or_d.malleability.s && or_d.malleability.m);
+
+        // or_i(older(144),older(288)) has no signature in either branch, so
+        // a third party could switch branches
+        let or_i = Type::or_i(older, Type::time(Timelocks::older(288))).unwrap();
+        assert!(!or_i.malleability.m);
+
+        assert!(Type::and_b(pk, pk).is_err());
+        assert!(Type::or_d(older, pk).is_err());
+    }
+
+    #[test]
+    fn test_mixed_timelocks() {
+        let height = Type::time(Timelocks::after(100_000));
+        let time = Type::time(Timelocks::after(1_600_000_000));
+        let pk = Type::check(Type::pk_k()).unwrap();
+
+        let and_v = Type::and_v(Type::verify(height).unwrap(), time).unwrap();
+        assert!(and_v.has_mixed_timelocks());
+        let or_i = Type::or_i(
+            Type::and_v(Type::verify(pk).unwrap(), height).unwrap(),
+            Type::and_v(Type::verify(pk).unwrap(), time).unwrap(),
+        )
+        .unwrap();
+        assert!(!or_i.has_mixed_timelocks());
+    }
+
+    #[test]
+    fn test_thresh() {
+        let pk = Type::check(Type::pk_k()).unwrap();
+        let w = Type::swap(pk).unwrap();
+        let thresh = Type::thresh(2, &[pk, w, w]).unwrap();
+        assert_eq!(thresh.to_string(), "Bduesm");
+        assert!(Type::thresh(0, &[pk, w]).is_err());
+        assert!(Type::thresh(3, &[pk, w]).is_err());
+        assert!(Type::thresh(1, &[pk, pk]).is_err());
+
+        // thresh(1,or_i(pk(A),pk(B)),s:pk(C)): or_i has two
+        // dissatisfactions, so the thresh has more than one too
+        let or_i = Type::or_i(pk, pk).unwrap();
+        assert!(!or_i.malleability.e);
+        assert_eq!(Type::thresh(1, &[or_i, w]).unwrap().to_string(), "Bdus");
+
+        // a:n:d:v:older(n) needs no signature
+        let older = |n| {
+            let dv = Type::dup_if(
+                Type::verify(Type::time(Timelocks::older(n))).unwrap(),
+                false,
+            );
+            Type::zero_not_equal(dv.unwrap()).unwrap()
+        };
+        let w_older = |n| Type::alt(older(n)).unwrap();
+        // With k = 1 either timelock may be satisfied instead of pk(A)
+        let thresh = Type::thresh(1, &[pk, w_older(1), w_older(2)]).unwrap();
+        assert_eq!(thresh.to_string(), "Bdu");
+        // With k = 2 no more than k subexpressions can be satisfied
+        // without a signature
+        let thresh = Type::thresh(2, &[older(1), w_older(2), w]).unwrap();
+        assert_eq!(thresh.to_string(), "Bdum");
+    }
+}
@@ -0,0 +1,309 @@
+//! The standardness rules Bitcoin Core applies to the transactions it
+//! relays (IsStandardTx). Transactions breaking them are valid but won't
+//! propagate through the network.
+use std::fmt;
+
+use crate::{
+    script::template::ScriptTemplate,
+    transactions::{builder::dust_threshold, tx::Tx},
+};
+
+/// The lowest and highest transaction versions relayed, version 3 being
+/// the topologically restricted transactions of BIP431
+pub const MIN_STANDARD_TX_VERSION: u32 = 1;
+pub const MAX_STANDARD_TX_VERSION: u32 = 3;
+
+/// Transactions heavier than this aren't relayed, a tenth of the block
+/// weight limit
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// Large enough for a P2SH 15-of-15 multisig spend with compressed keys
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1_650;
+
+/// The largest OP_RETURN script pubkey relayed, OP_RETURN included
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// Bare multisig outputs with more keys than this aren't standard
+pub const MAX_STANDARD_MULTISIG_KEYS: usize = 3;
+
+/// A standardness rule a transaction breaks
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Violation {
+    Version(u32),
+    /// The weight is over MAX_STANDARD_TX_WEIGHT
+    Weight(usize),
+    ScriptSigSize {
+        input: usize,
+        size: usize,
+    },
+    ScriptSigNotPushOnly(usize),
+    /// The output pays to a script of no standard template
+    NonStandardScriptPubKey(usize),
+    BareMultisig(usize),
+    Dust(usize),
+    /// More than one output is OP_RETURN
+    MultiOpReturn,
+}
+
+impl Violation {
+    /// The reason Bitcoin Core gives when rejecting the transaction
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Violation::Version(_) => "version",
+            Violation::Weight(_) => "tx-size",
+            Violation::ScriptSigSize { .. } => "scriptsig-size",
+            Violation::ScriptSigNotPushOnly(_) => "scriptsig-not-pushonly",
+            Violation::NonStandardScriptPubKey(_) => "scriptpubkey",
+            Violation::BareMultisig(_) => "bare-multisig",
+            Violation::Dust(_) => "dust",
+            Violation::MultiOpReturn => "multi-op-return",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Version(version) => write!(f, "version {} isn't standard", version),
+            Violation::Weight(weight) => write!(f, "weight {} is too large", weight),
+            Violation::ScriptSigSize { input, size } => {
+                write!(f, "script sig of input {} is {} bytes", input, size)
+            }
+            Violation::ScriptSigNotPushOnly(input) => {
+                write!(f, "script sig of input {} isn't push only", input)
+            }
+            Violation::NonStandardScriptPubKey(output) => {
+                write!(f, "script pubkey of output {} isn't standard", output)
+            }
+            Violation::BareMultisig(output) => write!(f, "output {} is bare multisig", output),
+            Violation::Dust(output) => write!(f, "output {} is dust", output),
+            Violation::MultiOpReturn => write!(f, "more than one OP_RETURN output"),
+        }
+    }
+}
+
+/// The configurable standardness rules, with Bitcoin Core's defaults
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Policy {
+    /// The largest OP_RETURN script pubkey relayed, None to relay none
+    max_datacarrier_bytes: Option<usize>,
+    permit_bare_multisig: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_datacarrier_bytes: Some(MAX_OP_RETURN_RELAY),
+            permit_bare_multisig: true,
+        }
+    }
+}
+
+impl Policy {
+    /// Sets the largest OP_RETURN script pubkey relayed, None to make
+    /// OP_RETURN outputs non standard
+    pub fn max_datacarrier_bytes(mut self, max_datacarrier_bytes: Option<usize>) -> Self {
+        self.max_datacarrier_bytes = max_datacarrier_bytes;
+        self
+    }
+
+    pub fn permit_bare_multisig(mut self, permit_bare_multisig: bool) -> Self {
+        self.permit_bare_multisig = permit_bare_multisig;
+        self
+    }
+
+    /// Returns the standardness rules the transaction breaks, none if
+    /// nodes would relay it
+    pub fn check(&self, tx: &Tx) -> Vec<Violation> {
+        let mut violations = vec![];
+        let version = tx.get_version();
+        if !(MIN_STANDARD_TX_VERSION..=MAX_STANDARD_TX_VERSION).contains(&version) {
+            violations.push(Violation::Version(version));
+        }
+        let weight = tx.weight();
+        if weight > MAX_STANDARD_TX_WEIGHT {
+            violations.push(Violation::Weight(weight));
+        }
+
+        for (index, input) in tx.get_inputs().iter().enumerate() {
+            let script_sig = input.get_script_sig();
+            let size = script_sig.raw_serialize().len();
+            if size > MAX_STANDARD_SCRIPTSIG_SIZE {
+                violations.push(Violation::ScriptSigSize { input: index, size });
+            }
+            // Script sigs that run code can be changed by third parties
+            if !script_sig.is_push_only() {
+                violations.push(Violation::ScriptSigNotPushOnly(index));
+            }
+        }
+
+        let mut op_returns = 0;
+        for (index, output) in tx.get_outputs().iter().enumerate() {
+            let script_pubkey = output.get_script_pubkey();
+            let template = script_pubkey.classify();
+            if !self.is_standard(&template, script_pubkey.raw_serialize().len()) {
+                violations.push(Violation::NonStandardScriptPubKey(index));
+                continue;
+            }
+            match template {
+                ScriptTemplate::NullData(_) => op_returns += 1,
+                ScriptTemplate::Multisig { .. } if !self.permit_bare_multisig => {
+                    violations.push(Violation::BareMultisig(index));
+                }
+                _ if output.get_amount() < dust_threshold(&script_pubkey) => {
+                    violations.push(Violation::Dust(index));
+                }
+                _ => {}
+            }
+        }
+        if op_returns > 1 {
+            violations.push(Violation::MultiOpReturn);
+        }
+        violations
+    }
+
+    fn is_standard(&self, template: &ScriptTemplate, size: usize) -> bool {
+        match template {
+            ScriptTemplate::NonStandard => false,
+            ScriptTemplate::Multisig { m, keys } => {
+                (1..=MAX_STANDARD_MULTISIG_KEYS).contains(&keys.len())
+                    && (1..=keys.len()).contains(m)
+            }
+            ScriptTemplate::NullData(_) => {
+                self.max_datacarrier_bytes.is_some_and(|max| size <= max)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Returns the standardness rules the transaction breaks under Bitcoin
+/// Core's default policy
+pub fn check_standard(tx: &Tx) -> Vec<Violation> {
+    Policy::default().check(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        amount::Amount,
+        script::{opcode::Opcode, Command, Script},
+        transactions::{
+            hashes::Txid,
+            input::{OutPoint, TxInput},
+            output::TxOutput,
+        },
+    };
+
+    use super::*;
+
+    fn input(script_sig: Script) -> TxInput {
+        TxInput::new(OutPoint::new(Txid::from_bytes([1; 32]), 0), script_sig, 0)
+    }
+
+    fn output(sats: u64, script_pubkey: Script) -> TxOutput {
+        TxOutput::new(Amount::from_sat(sats), script_pubkey)
+    }
+
+    fn standard_tx() -> Tx {
+        let script_sig = Script::new(vec![
+            Command::push(vec![0x30; 72]),
+            Command::push(vec![2; 33]),
+        ]);
+        let outputs = vec![
+            output(546, Script::p2pkh(&[0; 20])),
+            output(294, Script::p2wpkh(&[0; 20])),
+            output(330, Script::p2tr(&[0; 32])),
+            output(0, Script::op_return(&[0; 80])),
+        ];
+        Tx::new(2, vec![input(script_sig)], outputs, 0)
+    }
+
+    #[test]
+    fn test_standard() {
+        assert_eq!(check_standard(&standard_tx()), vec![]);
+        let multisig = Script::multisig(1, &[vec![2; 33], vec![3; 33], vec![4; 65]]).unwrap();
+        let tx = Tx::new(3, vec![], vec![output(1000, multisig)], 0);
+        assert_eq!(check_standard(&tx), vec![]);
+        assert_eq!(
+            Policy::default().permit_bare_multisig(false).check(&tx),
+            vec![Violation::BareMultisig(0)]
+        );
+    }
+
+    #[test]
+    fn test_version_and_weight() {
+        let mut tx = standard_tx();
+        tx = Tx::new(4, tx.get_inputs(), tx.get_outputs(), 0);
+        assert_eq!(check_standard(&tx), vec![Violation::Version(4)]);
+        let outputs = vec![
+            output(
+                1000,
+                Script::new(vec![
+                    Command::push(vec![0; 520]),
+                    Command::Op(Opcode::OP_DROP)
+                ])
+            );
+            200
+        ];
+        let tx = Tx::new(0, vec![], outputs, 0);
+        let violations = check_standard(&tx);
+        assert_eq!(violations[0], Violation::Version(0));
+        assert_eq!(violations[1], Violation::Weight(tx.weight()));
+        assert_eq!(violations[2].reason(), "scriptpubkey");
+    }
+
+    #[test]
+    fn test_script_sig() {
+        let big = Script::new(vec![Command::push(vec![0; 520]); 4]);
+        let code = Script::new(vec![Command::Op(Opcode::OP_1), Command::Op(Opcode::OP_DUP)]);
+        let tx = Tx::new(1, vec![input(big), input(code)], vec![], 0);
+        assert_eq!(
+            check_standard(&tx),
+            vec![
+                Violation::ScriptSigSize {
+                    input: 0,
+                    size: 4 * 523
+                },
+                Violation::ScriptSigNotPushOnly(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outputs() {
+        let outputs = vec![
+            output(545, Script::p2pkh(&[0; 20])),
+            output(293, Script::p2wpkh(&[0; 20])),
+            output(0, Script::op_return(&[0; 81])),
+            output(0, Script::op_return(&[])),
+            output(1000, Script::multisig(1, &vec![vec![2; 33]; 4]).unwrap()),
+            output(1000, Script::new(vec![Command::Op(Opcode::OP_1)])),
+        ];
+        let tx = Tx::new(2, vec![], outputs, 0);
+        let violations = check_standard(&tx);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::Dust(0),
+                Violation::Dust(1),
+                Violation::NonStandardScriptPubKey(2),
+                Violation::NonStandardScriptPubKey(4),
+                Violation::NonStandardScriptPubKey(5),
+            ]
+        );
+        assert_eq!(violations[0].to_string(), "output 0 is dust");
+
+        let outputs = vec![output(0, Script::op_return(&[])); 2];
+        let tx = Tx::new(2, vec![], outputs, 0);
+        assert_eq!(check_standard(&tx), vec![Violation::MultiOpReturn]);
+        let policy = Policy::default().max_datacarrier_bytes(None);
+        assert_eq!(
+            policy.check(&tx),
+            vec![
+                Violation::NonStandardScriptPubKey(0),
+                Violation::NonStandardScriptPubKey(1)
+            ]
+        );
+    }
+}
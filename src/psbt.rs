@@ -0,0 +1,1087 @@
+mod finalize;
+pub mod input;
+pub mod output;
+mod raw;
+mod sign;
+
+use std::{collections::BTreeMap, fmt, io::Cursor, str::FromStr};
+
+use crate::{
+    amount::Amount,
+    descriptor::key::KeyOrigin,
+    ecc::point::ECPoint,
+    script::Script,
+    transactions::{
+        input::{OutPoint, TxInput},
+        output::TxOutput,
+        tx::Tx,
+    },
+    utils::{
+        base64::{decode_base64, encode_base64},
+        varint::encode_varint,
+    },
+};
+
+use self::{
+    input::PsbtInput,
+    output::PsbtOutput,
+    raw::{parse_array, parse_key_origin, parse_tx, parse_u32, parse_varint, read_map},
+    raw::{serialize_key_origin, write_pair, write_raw_pair},
+};
+
+/// "psbt" followed by 0xff
+const PSBT_MAGIC: [u8; 5] = [0x70, 0x73, 0x62, 0x74, 0xff];
+
+const PSBT_GLOBAL_UNSIGNED_TX: u64 = 0x00;
+const PSBT_GLOBAL_XPUB: u64 = 0x01;
+const PSBT_GLOBAL_TX_VERSION: u64 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u64 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u64 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u64 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u64 = 0x06;
+const PSBT_GLOBAL_VERSION: u64 = 0xfb;
+/// The key type of proprietary entries in every map
+const PSBT_PROPRIETARY: u64 = 0xfc;
+
+/// Bits of PSBT_GLOBAL_TX_MODIFIABLE
+pub const TX_MODIFIABLE_INPUTS: u8 = 0x01;
+pub const TX_MODIFIABLE_OUTPUTS: u8 = 0x02;
+pub const TX_MODIFIABLE_SIGHASH_SINGLE: u8 = 0x04;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PsbtError {
+    /// The data doesn't start with the PSBT magic bytes
+    InvalidMagic,
+    /// The data ends in the middle of a map
+    UnexpectedEof,
+    /// There are bytes after the last output map
+    TrailingData,
+    InvalidBase64(String),
+    /// Only versions 0 and 2 exist
+    UnsupportedVersion(u32),
+    /// A key appears twice in a map
+    DuplicateKey(Vec<u8>),
+    /// A key has data it shouldn't, or of the wrong length
+    InvalidKey(Vec<u8>),
+    /// The value of a key type is malformed
+    InvalidValue(u64),
+    /// A field required by the PSBT version is absent
+    MissingField(&'static str),
+    /// A key type isn't allowed in the PSBT version
+    UnexpectedField(u64),
+    /// The unsigned transaction of a version 0 PSBT has script sigs or
+    /// witnesses
+    UnsignedTxNotEmpty,
+    /// The index isn't that of an input or output
+    IndexOutOfRange(usize),
+    /// The UTXO given for an input isn't the one it spends
+    UtxoMismatch(usize),
+    /// An input has neither a witness nor a non-witness UTXO
+    MissingUtxo(usize),
+    /// The redeem or witness script of an input doesn't hash to the script
+    /// it spends
+    ScriptMismatch(usize),
+    /// The inputs need both a height and a time locktime
+    LocktimeConflict,
+    /// Combined PSBTs are for different transactions
+    CombineMismatch,
+    /// The PSBT doesn't allow adding inputs or outputs
+    NotModifiable,
+    /// Computing a signature hash failed
+    Sighash(String),
+    /// An input can't be finalized with the data available
+    Finalize {
+        input: usize,
+        reason: String,
+    },
+    /// The transaction can't be extracted before every input is finalized
+    NotFinalized(usize),
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsbtError::InvalidMagic => write!(f, "invalid PSBT magic bytes"),
+            PsbtError::UnexpectedEof => write!(f, "unexpected end of PSBT data"),
+            PsbtError::TrailingData => write!(f, "trailing data after PSBT"),
+            PsbtError::InvalidBase64(err) => write!(f, "{}", err),
+            PsbtError::UnsupportedVersion(version) => {
+                write!(f, "unsupported PSBT version {}", version)
+            }
+            PsbtError::DuplicateKey(key) => write!(f, "duplicate key {}", hex::encode(key)),
+            PsbtError::InvalidKey(key) => write!(f, "invalid key {}", hex::encode(key)),
+            PsbtError::InvalidValue(key_type) => {
+                write!(f, "invalid value for key type {:#04x}", key_type)
+            }
+            PsbtError::MissingField(field) => write!(f, "missing {}", field),
+            PsbtError::UnexpectedField(key_type) => {
+                write!(f, "key type {:#04x} not allowed in this version", key_type)
+            }
+            PsbtError::UnsignedTxNotEmpty => {
+                write!(f, "unsigned transaction has script sigs or witnesses")
+            }
+            PsbtError::IndexOutOfRange(index) => write!(f, "index {} out of range", index),
+            PsbtError::UtxoMismatch(index) => {
+                write!(f, "UTXO doesn't match the outpoint of input {}", index)
+            }
+            PsbtError::MissingUtxo(index) => write!(f, "input {} has no UTXO", index),
+            PsbtError::ScriptMismatch(index) => {
+                write!(f, "script doesn't match the UTXO of input {}", index)
+            }
+            PsbtError::LocktimeConflict => {
+                write!(f, "inputs require both height and time locktimes")
+            }
+            PsbtError::CombineMismatch => write!(f, "PSBTs are for different transactions"),
+            PsbtError::NotModifiable => write!(f, "PSBT is not modifiable"),
+            PsbtError::Sighash(err) => write!(f, "{}", err),
+            PsbtError::Finalize { input, reason } => {
+                write!(f, "can't finalize input {}: {}", input, reason)
+            }
+            PsbtError::NotFinalized(index) => write!(f, "input {} is not finalized", index),
+        }
+    }
+}
+
+impl std::error::Error for PsbtError {}
+
+/// A partially signed bitcoin transaction, version 0 (BIP174) or 2 (BIP370)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Psbt {
+    pub version: u32,
+    /// Version 0: the transaction with empty script sigs and witnesses
+    pub unsigned_tx: Option<Tx>,
+    /// Extended public keys by their 78-byte serialization, with their
+    /// origin
+    pub xpubs: BTreeMap<Vec<u8>, KeyOrigin>,
+    /// Version 2: the transaction version
+    pub tx_version: Option<u32>,
+    /// Version 2: the locktime when no input requires one
+    pub fallback_locktime: Option<u32>,
+    /// Version 2: TX_MODIFIABLE_* flags
+    pub tx_modifiable: Option<u8>,
+    /// Proprietary and unknown entries by their serialized key
+    pub proprietary: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+}
+
+/// Returns true if any input of the transaction has a script sig or witness
+fn has_signatures(tx: &Tx) -> bool {
+    tx.get_inputs()
+        .iter()
+        .any(|input| !input.get_script_sig().cmds().is_empty() || !input.get_witness().is_empty())
+}
+
+/// Creator
+impl Psbt {
+    /// Creates a version 0 PSBT for an unsigned transaction
+    pub fn from_unsigned_tx(tx: Tx) -> Result<Self, PsbtError> {
+        if has_signatures(&tx) {
+            return Err(PsbtError::UnsignedTxNotEmpty);
+        }
+        Ok(Psbt {
+            version: 0,
+            inputs: vec![PsbtInput::default(); tx.get_inputs().len()],
+            outputs: vec![PsbtOutput::default(); tx.get_outputs().len()],
+            unsigned_tx: Some(tx),
+            xpubs: BTreeMap::new(),
+            tx_version: None,
+            fallback_locktime: None,
+            tx_modifiable: None,
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    /// Creates an empty version 2 PSBT to which inputs and outputs can be
+    /// added
+    pub fn new_v2(tx_version: u32, fallback_locktime: Option<u32>) -> Self {
+        Psbt {
+            version: 2,
+            unsigned_tx: None,
+            xpubs: BTreeMap::new(),
+            tx_version: Some(tx_version),
+            fallback_locktime,
+            tx_modifiable: Some(TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS),
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+}
+
+/// Constructor (version 2)
+impl Psbt {
+    /// Adds an input spending the previous output
+    pub fn add_input(
+        &mut self,
+        previous_output: OutPoint,
+        sequence: Option<u32>,
+    ) -> Result<&mut PsbtInput, PsbtError> {
+        if self.version != 2 || self.tx_modifiable.unwrap_or(0) & TX_MODIFIABLE_INPUTS == 0 {
+            return Err(PsbtError::NotModifiable);
+        }
+        self.inputs.push(PsbtInput {
+            previous_txid: Some(previous_output.txid),
+            output_index: Some(previous_output.vout),
+            sequence,
+            ..PsbtInput::default()
+        });
+        Ok(self.inputs.last_mut().unwrap())
+    }
+
+    /// Adds an output paying the amount to the script pubkey
+    pub fn add_output(
+        &mut self,
+        amount: Amount,
+        script_pubkey: Script,
+    ) -> Result<&mut PsbtOutput, PsbtError> {
+        if self.version != 2 || self.tx_modifiable.unwrap_or(0) & TX_MODIFIABLE_OUTPUTS == 0 {
+            return Err(PsbtError::NotModifiable);
+        }
+        self.outputs.push(PsbtOutput {
+            amount: Some(amount),
+            script: Some(script_pubkey),
+            ..PsbtOutput::default()
+        });
+        Ok(self.outputs.last_mut().unwrap())
+    }
+}
+
+/// Updater
+impl Psbt {
+    fn input_mut(&mut self, index: usize) -> Result<&mut PsbtInput, PsbtError> {
+        self.inputs
+            .get_mut(index)
+            .ok_or(PsbtError::IndexOutOfRange(index))
+    }
+
+    /// Attaches the transaction whose output the input spends, checking it
+    /// against the outpoint
+    pub fn add_non_witness_utxo(&mut self, index: usize, tx: Tx) -> Result<(), PsbtError> {
+        let previous_output = self.previous_output(index)?;
+        non_witness_output(&tx, &previous_output, index)?;
+        self.input_mut(index)?.non_witness_utxo = Some(tx);
+        Ok(())
+    }
+
+    /// Attaches the output a segwit input spends
+    pub fn add_witness_utxo(&mut self, index: usize, utxo: TxOutput) -> Result<(), PsbtError> {
+        self.input_mut(index)?.witness_utxo = Some(utxo);
+        Ok(())
+    }
+
+    pub fn add_redeem_script(&mut self, index: usize, script: Script) -> Result<(), PsbtError> {
+        self.input_mut(index)?.redeem_script = Some(script);
+        Ok(())
+    }
+
+    pub fn add_witness_script(&mut self, index: usize, script: Script) -> Result<(), PsbtError> {
+        self.input_mut(index)?.witness_script = Some(script);
+        Ok(())
+    }
+
+    /// Records where a key signing for the input was derived from, so that
+    /// signers holding the master key can find it
+    pub fn add_bip32_derivation(
+        &mut self,
+        index: usize,
+        pubkey: &ECPoint,
+        origin: KeyOrigin,
+    ) -> Result<(), PsbtError> {
+        self.input_mut(index)?
+            .bip32_derivation
+            .insert(pubkey.to_compressed_sec(), origin);
+        Ok(())
+    }
+
+    /// Returns the output an input spends, from its witness or non-witness
+    /// UTXO
+    pub fn spent_output(&self, index: usize) -> Result<TxOutput, PsbtError> {
+        let input = self
+            .inputs
+            .get(index)
+            .ok_or(PsbtError::IndexOutOfRange(index))?;
+        if let Some(utxo) = &input.witness_utxo {
+            return Ok(utxo.clone());
+        }
+        let tx = input
+            .non_witness_utxo
+            .as_ref()
+            .ok_or(PsbtError::MissingUtxo(index))?;
+        non_witness_output(tx, &self.previous_output(index)?, index)
+    }
+
+    /// The outpoint an input spends, from the unsigned transaction or the
+    /// version 2 input fields
+    fn previous_output(&self, index: usize) -> Result<OutPoint, PsbtError> {
+        if let Some(tx) = &self.unsigned_tx {
+            let input = tx
+                .get_inputs()
+                .get(index)
+                .cloned()
+                .ok_or(PsbtError::IndexOutOfRange(index))?;
+            return Ok(OutPoint::new(input.get_prev_tx(), input.get_prev_index()));
+        }
+        let input = self
+            .inputs
+            .get(index)
+            .ok_or(PsbtError::IndexOutOfRange(index))?;
+        match (input.previous_txid, input.output_index) {
+            (Some(txid), Some(vout)) => Ok(OutPoint::new(txid, vout)),
+            _ => Err(PsbtError::MissingField(
+                "input previous txid and output index",
+            )),
+        }
+    }
+}
+
+/// The output of a non-witness UTXO an input spends, checking that it is
+/// the transaction the outpoint refers to
+fn non_witness_output(
+    tx: &Tx,
+    previous_output: &OutPoint,
+    index: usize,
+) -> Result<TxOutput, PsbtError> {
+    if tx.id() != previous_output.txid {
+        return Err(PsbtError::UtxoMismatch(index));
+    }
+    tx.get_outputs()
+        .get(previous_output.vout as usize)
+        .cloned()
+        .ok_or(PsbtError::UtxoMismatch(index))
+}
+
+/// Combiner
+impl Psbt {
+    /// Merges the entries of another PSBT for the same transaction into this
+    /// one
+    pub fn combine(&mut self, other: Psbt) -> Result<(), PsbtError> {
+        if self.version != other.version
+            || self.unsigned_tx()? != other.unsigned_tx()?
+            || self.inputs.len() != other.inputs.len()
+            || self.outputs.len() != other.outputs.len()
+        {
+            return Err(PsbtError::CombineMismatch);
+        }
+        for (key, origin) in other.xpubs {
+            self.xpubs.entry(key).or_insert(origin);
+        }
+        for (key, value) in other.proprietary {
+            self.proprietary.entry(key).or_insert(value);
+        }
+        for (key, value) in other.unknown {
+            self.unknown.entry(key).or_insert(value);
+        }
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other);
+        }
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.combine(other);
+        }
+        Ok(())
+    }
+}
+
+/// Extractor
+impl Psbt {
+    /// Returns the transaction being signed, without signatures
+    pub fn unsigned_tx(&self) -> Result<Tx, PsbtError> {
+        if let Some(tx) = &self.unsigned_tx {
+            return Ok(tx.clone());
+        }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let prev_tx = input
+                    .previous_txid
+                    .ok_or(PsbtError::MissingField("input previous txid"))?;
+                let output_index = input
+                    .output_index
+                    .ok_or(PsbtError::MissingField("input output index"))?;
+                Ok(TxInput::new(
+                    OutPoint::new(prev_tx, output_index),
+                    Script::new(vec![]),
+                    input.sequence.unwrap_or(0xffffffff),
+                ))
+            })
+            .collect::<Result<Vec<_>, PsbtError>>()?;
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|output| match (output.amount, &output.script) {
+                (Some(amount), Some(script)) => Ok(TxOutput::new(amount, script.clone())),
+                _ => Err(PsbtError::MissingField("output amount and script")),
+            })
+            .collect::<Result<Vec<_>, PsbtError>>()?;
+        let tx_version = self
+            .tx_version
+            .ok_or(PsbtError::MissingField("transaction version"))?;
+        Ok(Tx::new(tx_version, inputs, outputs, self.locktime()?))
+    }
+
+    /// The locktime of a version 2 PSBT (BIP370): the largest required
+    /// locktime of the kind every input with a requirement supports,
+    /// preferring heights, or the fallback locktime
+    fn locktime(&self) -> Result<u32, PsbtError> {
+        let constrained: Vec<&PsbtInput> = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                input.required_time_locktime.is_some() || input.required_height_locktime.is_some()
+            })
+            .collect();
+        if constrained.is_empty() {
+            return Ok(self.fallback_locktime.unwrap_or(0));
+        }
+        if constrained
+            .iter()
+            .all(|input| input.required_height_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_height_locktime)
+                .max()
+                .unwrap());
+        }
+        if constrained
+            .iter()
+            .all(|input| input.required_time_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_time_locktime)
+                .max()
+                .unwrap());
+        }
+        Err(PsbtError::LocktimeConflict)
+    }
+
+    /// Builds the signed transaction once every input is finalized
+    pub fn extract_tx(&self) -> Result<Tx, PsbtError> {
+        let mut tx = self.unsigned_tx()?;
+        for (index, (tx_input, input)) in tx.inputs_mut().iter_mut().zip(&self.inputs).enumerate() {
+            if !input.is_finalized() {
+                return Err(PsbtError::NotFinalized(index));
+            }
+            if let Some(script_sig) = &input.final_script_sig {
+                tx_input.set_script_sig(script_sig.clone());
+            }
+            if let Some(witness) = &input.final_script_witness {
+                tx_input.set_witness(witness.clone());
+            }
+        }
+        Ok(tx)
+    }
+}
+
+/// Parsing
+impl Psbt {
+    /// Parses a binary PSBT
+    pub fn parse(bytes: &[u8]) -> Result<Self, PsbtError> {
+        if !bytes.starts_with(&PSBT_MAGIC) {
+            return Err(PsbtError::InvalidMagic);
+        }
+        let mut cursor = Cursor::new(bytes.to_vec());
+        cursor.set_position(PSBT_MAGIC.len() as u64);
+
+        let mut psbt = Psbt {
+            version: 0,
+            unsigned_tx: None,
+            xpubs: BTreeMap::new(),
+            tx_version: None,
+            fallback_locktime: None,
+            tx_modifiable: None,
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+        };
+        let mut input_count = None;
+        let mut output_count = None;
+        let mut v2_fields = vec![];
+        for pair in read_map(&mut cursor)? {
+            match pair.key_type {
+                PSBT_GLOBAL_UNSIGNED_TX => {
+                    pair.expect_empty_key()?;
+                    psbt.unsigned_tx = Some(parse_tx(&pair)?);
+                }
+                PSBT_GLOBAL_XPUB => {
+                    let xpub = pair.key_data_of_len(&[78])?;
+                    let origin =
+                        parse_key_origin(&pair.value).ok_or_else(|| pair.invalid_value())?;
+                    psbt.xpubs.insert(xpub, origin);
+                }
+                PSBT_GLOBAL_TX_VERSION => {
+                    pair.expect_empty_key()?;
+                    psbt.tx_version = Some(parse_u32(&pair)?);
+                    v2_fields.push(pair.key_type);
+                }
+                PSBT_GLOBAL_FALLBACK_LOCKTIME => {
+                    pair.expect_empty_key()?;
+                    psbt.fallback_locktime = Some(parse_u32(&pair)?);
+                    v2_fields.push(pair.key_type);
+                }
+                PSBT_GLOBAL_INPUT_COUNT => {
+                    pair.expect_empty_key()?;
+                    input_count = Some(parse_varint(&pair)?);
+                    v2_fields.push(pair.key_type);
+                }
+                PSBT_GLOBAL_OUTPUT_COUNT => {
+                    pair.expect_empty_key()?;
+                    output_count = Some(parse_varint(&pair)?);
+                    v2_fields.push(pair.key_type);
+                }
+                PSBT_GLOBAL_TX_MODIFIABLE => {
+                    pair.expect_empty_key()?;
+                    psbt.tx_modifiable = Some(parse_array::<1>(&pair)?[0]);
+                    v2_fields.push(pair.key_type);
+                }
+                PSBT_GLOBAL_VERSION => {
+                    pair.expect_empty_key()?;
+                    psbt.version = parse_u32(&pair)?;
+                }
+                PSBT_PROPRIETARY => {
+                    psbt.proprietary.insert(pair.key(), pair.value);
+                }
+                _ => {
+                    psbt.unknown.insert(pair.key(), pair.value);
+                }
+            }
+        }
+
+        let (input_count, output_count) = match psbt.version {
+            0 => {
+                if let Some(key_type) = v2_fields.first() {
+                    return Err(PsbtError::UnexpectedField(*key_type));
+                }
+                let tx = psbt
+                    .unsigned_tx
+                    .as_ref()
+                    .ok_or(PsbtError::MissingField("unsigned transaction"))?;
+                if has_signatures(tx) {
+                    return Err(PsbtError::UnsignedTxNotEmpty);
+                }
+                (tx.get_inputs().len(), tx.get_outputs().len())
+            }
+            2 => {
+                if psbt.unsigned_tx.is_some() {
+                    return Err(PsbtError::UnexpectedField(PSBT_GLOBAL_UNSIGNED_TX));
+                }
+                if psbt.tx_version.is_none() {
+                    return Err(PsbtError::MissingField("transaction version"));
+                }
+                match (input_count, output_count) {
+                    (Some(inputs), Some(outputs)) => (inputs as usize, outputs as usize),
+                    _ => return Err(PsbtError::MissingField("input and output counts")),
+                }
+            }
+            version => return Err(PsbtError::UnsupportedVersion(version)),
+        };
+
+        for _ in 0..input_count {
+            psbt.inputs
+                .push(PsbtInput::parse(&mut cursor, psbt.version)?);
+        }
+        for _ in 0..output_count {
+            psbt.outputs
+                .push(PsbtOutput::parse(&mut cursor, psbt.version)?);
+        }
+        if cursor.position() as usize != bytes.len() {
+            return Err(PsbtError::TrailingData);
+        }
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            if let Some(tx) = &input.non_witness_utxo {
+                non_witness_output(tx, &psbt.previous_output(index)?, index)?;
+            }
+        }
+        Ok(psbt)
+    }
+
+    /// Returns the binary serialization
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = PSBT_MAGIC.to_vec();
+        if let Some(tx) = &self.unsigned_tx {
+            // The unsigned transaction is always in the legacy serialization
+            write_pair(
+                &mut result,
+                PSBT_GLOBAL_UNSIGNED_TX,
+                &[],
+                &tx.serialize_legacy(),
+            );
+        }
+        for (xpub, origin) in &self.xpubs {
+            write_pair(
+                &mut result,
+                PSBT_GLOBAL_XPUB,
+                xpub,
+                &serialize_key_origin(origin),
+            );
+        }
+        if self.version == 2 {
+            if let Some(tx_version) = self.tx_version {
+                write_pair(
+                    &mut result,
+                    PSBT_GLOBAL_TX_VERSION,
+                    &[],
+                    &tx_version.to_le_bytes(),
+                );
+            }
+            if let Some(locktime) = self.fallback_locktime {
+                write_pair(
+                    &mut result,
+                    PSBT_GLOBAL_FALLBACK_LOCKTIME,
+                    &[],
+                    &locktime.to_le_bytes(),
+                );
+            }
+            let input_count = encode_varint(self.inputs.len() as u64).unwrap();
+            write_pair(&mut result, PSBT_GLOBAL_INPUT_COUNT, &[], &input_count);
+            let output_count = encode_varint(self.outputs.len() as u64).unwrap();
+            write_pair(&mut result, PSBT_GLOBAL_OUTPUT_COUNT, &[], &output_count);
+            if let Some(flags) = self.tx_modifiable {
+                write_pair(&mut result, PSBT_GLOBAL_TX_MODIFIABLE, &[], &[flags]);
+            }
+        }
+        if self.version != 0 {
+            write_pair(
+                &mut result,
+                PSBT_GLOBAL_VERSION,
+                &[],
+                &self.version.to_le_bytes(),
+            );
+        }
+        for (key, value) in self.proprietary.iter().chain(&self.unknown) {
+            write_raw_pair(&mut result, key, value);
+        }
+        result.push(0x00);
+        for input in &self.inputs {
+            result.extend(input.serialize());
+        }
+        for output in &self.outputs {
+            result.extend(output.serialize());
+        }
+        result
+    }
+
+    /// Parses a base64 PSBT, the usual text form
+    pub fn from_base64(s: &str) -> Result<Self, PsbtError> {
+        Self::parse(&decode_base64(s.trim()).map_err(PsbtError::InvalidBase64)?)
+    }
+
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.serialize())
+    }
+}
+
+impl fmt::Display for Psbt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_base64())
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = PsbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_base64(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigUint;
+
+    use crate::{
+        address::Address,
+        ecc::{private_key::PrivateKey, signature::Signature},
+        script::{
+            op::{OP_1, OP_CHECKMULTISIG, OP_CHECKSIG, OP_DROP},
+            Command,
+        },
+        transactions::{hashes::Txid, sighash::SIGHASH_ALL},
+        utils::hash160::hash160,
+        wallet::derivation_path::DerivationPath,
+    };
+
+    use super::*;
+
+    fn key(secret: u32) -> PrivateKey {
+        PrivateKey::new(&BigUint::from(secret))
+    }
+
+    fn unsigned_tx(prev_txs: &[[u8; 32]]) -> Tx {
+        let inputs = prev_txs
+            .iter()
+            .map(|prev_tx| {
+                let outpoint = OutPoint::new(Txid::from_bytes(*prev_tx), 0);
+                TxInput::new(outpoint, Script::new(vec![]), 0xfffffffd)
+            })
+            .collect();
+        let output = TxOutput::new(
+            Amount::from_sat(90_000),
+            Address::p2wpkh(&key(9).point(), false).script_pubkey(),
+        );
+        Tx::new(2, inputs, vec![output], 800_000)
+    }
+
+    fn verify_ecdsa(z: [u8; 32], sig: &[u8], pubkey: &[u8]) -> bool {
+        let sig = Signature::parse(&sig[..sig.len() - 1]).unwrap();
+        ECPoint::parse(pubkey)
+            .unwrap()
+            .verify(&BigUint::from_bytes_be(&z), &sig)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx(&[[1; 32], [2; 32]])).unwrap();
+        let spk = Address::p2wpkh(&key(1).point(), false).script_pubkey();
+        psbt.add_witness_utxo(0, TxOutput::new(Amount::from_sat(50_000), spk))
+            .unwrap();
+        psbt.add_redeem_script(1, Script::new(vec![Command::Op(OP_1)]))
+            .unwrap();
+        let origin = KeyOrigin {
+            fingerprint: [0xd3, 0x4d, 0xb3, 0x3f],
+            path: DerivationPath::new(vec![0x80000054, 0x80000000, 0x80000000, 0, 1]),
+        };
+        psbt.add_bip32_derivation(0, &key(1).point(), origin)
+            .unwrap();
+        psbt.inputs[1]
+            .sha256_preimages
+            .insert([7; 32], vec![1, 2, 3]);
+        psbt.outputs[0].tap_internal_key = Some(key(2).point().to_xonly());
+        psbt.unknown.insert(vec![0x0f, 0xaa], vec![0xbb]);
+        psbt.proprietary
+            .insert(vec![0xfc, 0x01, 0x61, 0x00], vec![]);
+
+        let serialized = psbt.serialize();
+        assert!(serialized.starts_with(b"psbt\xff"));
+        assert_eq!(Psbt::parse(&serialized).unwrap(), psbt);
+        let base64 = psbt.to_string();
+        assert_eq!(base64.parse::<Psbt>().unwrap(), psbt);
+        assert_eq!(Psbt::from_base64(&base64).unwrap().serialize(), serialized);
+    }
+
+    #[test]
+    fn test_bip174_vector() {
+        // A valid PSBT from BIP174 with a non-witness UTXO and partial
+        // signatures in the UTXO's witness
+        let base64 = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+        let psbt = Psbt::from_base64(base64).unwrap();
+        assert_eq!(psbt.version, 0);
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.outputs.len(), 2);
+        let utxo = psbt.inputs[0].non_witness_utxo.as_ref().unwrap();
+        assert_eq!(
+            utxo.id(),
+            psbt.unsigned_tx().unwrap().get_inputs()[0].get_prev_tx()
+        );
+        assert_eq!(psbt.to_base64(), base64);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let psbt = Psbt::from_unsigned_tx(unsigned_tx(&[[1; 32]])).unwrap();
+        let serialized = psbt.serialize();
+
+        let mut bad_magic = serialized.clone();
+        bad_magic[4] = 0x00;
+        assert_eq!(Psbt::parse(&bad_magic), Err(PsbtError::InvalidMagic));
+
+        assert_eq!(
+            Psbt::parse(&serialized[..serialized.len() - 1]),
+            Err(PsbtError::UnexpectedEof)
+        );
+
+        let mut trailing = serialized.clone();
+        trailing.push(0x00);
+        assert_eq!(Psbt::parse(&trailing), Err(PsbtError::TrailingData));
+
+        // The unsigned transaction appears twice in the global map
+        let tx = psbt.unsigned_tx.as_ref().unwrap().serialize();
+        let mut duplicate = PSBT_MAGIC.to_vec();
+        write_pair(&mut duplicate, PSBT_GLOBAL_UNSIGNED_TX, &[], &tx);
+        write_pair(&mut duplicate, PSBT_GLOBAL_UNSIGNED_TX, &[], &tx);
+        duplicate.extend([0x00, 0x00, 0x00]);
+        assert_eq!(
+            Psbt::parse(&duplicate),
+            Err(PsbtError::DuplicateKey(vec![0x00]))
+        );
+
+        // A version 2 field in a version 0 PSBT
+        let mut v2_field = PSBT_MAGIC.to_vec();
+        write_pair(&mut v2_field, PSBT_GLOBAL_UNSIGNED_TX, &[], &tx);
+        write_pair(
+            &mut v2_field,
+            PSBT_GLOBAL_TX_VERSION,
+            &[],
+            &2u32.to_le_bytes(),
+        );
+        v2_field.extend([0x00, 0x00, 0x00]);
+        assert_eq!(
+            Psbt::parse(&v2_field),
+            Err(PsbtError::UnexpectedField(PSBT_GLOBAL_TX_VERSION))
+        );
+
+        let mut signed = unsigned_tx(&[[1; 32]]);
+        signed.inputs_mut()[0].set_witness(vec![vec![1]]);
+        assert_eq!(
+            Psbt::from_unsigned_tx(signed),
+            Err(PsbtError::UnsignedTxNotEmpty)
+        );
+
+        assert!(Psbt::from_base64("cHNidP8=!").is_err());
+    }
+
+    #[test]
+    fn test_v2() {
+        let mut psbt = Psbt::new_v2(2, Some(100));
+        let spk = Address::p2wpkh(&key(1).point(), false).script_pubkey();
+        psbt.add_input(OutPoint::new(Txid::from_bytes([1; 32]), 0), None)
+            .unwrap();
+        psbt.add_output(Amount::from_sat(1_000), spk.clone())
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx().unwrap().get_locktime().value(), 100);
+
+        let input = psbt
+            .add_input(
+                OutPoint::new(Txid::from_bytes([2; 32]), 1),
+                Some(0xfffffffe),
+            )
+            .unwrap();
+        input.required_height_locktime = Some(800_000);
+        let input = psbt
+            .add_input(OutPoint::new(Txid::from_bytes([3; 32]), 2), None)
+            .unwrap();
+        input.required_height_locktime = Some(800_001);
+        input.required_time_locktime = Some(1_700_000_000);
+        let tx = psbt.unsigned_tx().unwrap();
+        assert_eq!(tx.get_locktime().value(), 800_001);
+        assert_eq!(tx.get_inputs()[1].get_sequence(), 0xfffffffe);
+        assert_eq!(tx.get_inputs()[2].get_prev_index(), 2);
+
+        let serialized = psbt.serialize();
+        assert_eq!(Psbt::parse(&serialized).unwrap(), psbt);
+
+        psbt.inputs[1].required_height_locktime = None;
+        psbt.inputs[1].required_time_locktime = Some(1_700_000_000);
+        assert_eq!(
+            psbt.unsigned_tx().unwrap().get_locktime().value(),
+            1_700_000_000
+        );
+        psbt.inputs[2].required_time_locktime = None;
+        assert_eq!(psbt.unsigned_tx(), Err(PsbtError::LocktimeConflict));
+
+        psbt.tx_modifiable = Some(TX_MODIFIABLE_OUTPUTS);
+        assert!(matches!(
+            psbt.add_input(OutPoint::new(Txid::from_bytes([4; 32]), 0), None),
+            Err(PsbtError::NotModifiable)
+        ));
+    }
+
+    #[test]
+    fn test_sign_segwit_multisig() {
+        let (alice, bob) = (key(11), key(12));
+        let witness_script = Script::new(vec![
+            Command::number(2),
+            Command::push(alice.point().to_compressed_sec()),
+            Command::push(bob.point().to_compressed_sec()),
+            Command::number(2),
+            Command::Op(OP_CHECKMULTISIG),
+        ]);
+        let redeem_script = Address::p2wsh(&witness_script, false).script_pubkey();
+        let multisig_spk = Address::p2sh(&redeem_script, false).script_pubkey();
+        let wpkh_spk = Address::p2wpkh(&alice.point(), false).script_pubkey();
+
+        // The creator and updater
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx(&[[1; 32], [2; 32]])).unwrap();
+        psbt.add_witness_utxo(0, TxOutput::new(Amount::from_sat(40_000), wpkh_spk))
+            .unwrap();
+        psbt.add_witness_utxo(1, TxOutput::new(Amount::from_sat(60_000), multisig_spk))
+            .unwrap();
+        psbt.add_redeem_script(1, redeem_script).unwrap();
+        psbt.add_witness_script(1, witness_script.clone()).unwrap();
+        assert_eq!(
+            psbt.finalize_input(1),
+            Err(PsbtError::Finalize {
+                input: 1,
+                reason: "missing signatures".to_string()
+            })
+        );
+
+        // Each co-signer signs a copy, then the copies are combined
+        let mut alice_psbt = Psbt::from_base64(&psbt.to_base64()).unwrap();
+        assert_eq!(alice_psbt.sign(&alice).unwrap(), 2);
+        let mut bob_psbt = psbt.clone();
+        assert_eq!(bob_psbt.sign(&bob).unwrap(), 1);
+        psbt.combine(alice_psbt).unwrap();
+        psbt.combine(bob_psbt).unwrap();
+        assert_eq!(psbt.inputs[1].partial_sigs.len(), 2);
+        assert_eq!(psbt.extract_tx(), Err(PsbtError::NotFinalized(0)));
+
+        psbt.finalize().unwrap();
+        assert!(psbt.inputs[1].partial_sigs.is_empty());
+        assert!(psbt.inputs[1].witness_script.is_none());
+        let tx = psbt.extract_tx().unwrap();
+        let parsed = Tx::parse(&mut Cursor::new(tx.serialize())).unwrap();
+        assert_eq!(parsed, tx);
+
+        let inputs = tx.get_inputs();
+        let witness = inputs[0].get_witness();
+        let script_code = sign::p2pkh_script(&hash160(&witness[1]));
+        let z = tx
+            .sig_hash_segwit_v0(0, &script_code, Amount::from_sat(40_000), SIGHASH_ALL)
+            .unwrap();
+        assert!(verify_ecdsa(z, &witness[0], &witness[1]));
+
+        let witness = inputs[1].get_witness();
+        assert_eq!(witness.len(), 4);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[3], witness_script.raw_serialize());
+        assert_eq!(inputs[1].get_script_sig().cmds().len(), 1);
+        let z = tx
+            .sig_hash_segwit_v0(1, &witness_script, Amount::from_sat(60_000), SIGHASH_ALL)
+            .unwrap();
+        assert!(verify_ecdsa(
+            z,
+            &witness[1],
+            &alice.point().to_compressed_sec()
+        ));
+        assert!(verify_ecdsa(
+            z,
+            &witness[2],
+            &bob.point().to_compressed_sec()
+        ));
+    }
+
+    #[test]
+    fn test_sign_legacy() {
+        let alice = key(21);
+        let spk = Address::p2pkh(&alice.point(), false).script_pubkey();
+        let prev_tx = Tx::new(
+            1,
+            vec![TxInput::new(
+                OutPoint::new(Txid::from_bytes([5; 32]), 0),
+                Script::new(vec![]),
+                0xffffffff,
+            )],
+            vec![TxOutput::new(Amount::from_sat(100_000), spk.clone())],
+            0,
+        );
+        let prev_hash = prev_tx.id().to_bytes();
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx(&[prev_hash])).unwrap();
+
+        assert_eq!(
+            psbt.add_non_witness_utxo(0, unsigned_tx(&[[1; 32]])),
+            Err(PsbtError::UtxoMismatch(0))
+        );
+        // A UTXO that isn't the one spent, set without the updater's check
+        let mut tampered = psbt.clone();
+        tampered.inputs[0].non_witness_utxo = Some(unsigned_tx(&[[1; 32]]));
+        assert_eq!(tampered.spent_output(0), Err(PsbtError::UtxoMismatch(0)));
+        assert_eq!(tampered.sign(&alice), Err(PsbtError::UtxoMismatch(0)));
+        assert_eq!(
+            Psbt::parse(&tampered.serialize()),
+            Err(PsbtError::UtxoMismatch(0))
+        );
+        // The output index is past the outputs of the UTXO
+        let mut v2 = Psbt::new_v2(2, None);
+        v2.add_input(OutPoint::new(prev_tx.id(), 1), None)
+            .unwrap()
+            .non_witness_utxo = Some(prev_tx.clone());
+        assert_eq!(v2.spent_output(0), Err(PsbtError::UtxoMismatch(0)));
+        assert_eq!(
+            Psbt::parse(&v2.serialize()),
+            Err(PsbtError::UtxoMismatch(0))
+        );
+
+        psbt.add_non_witness_utxo(0, prev_tx).unwrap();
+        assert_eq!(psbt.sign(&key(22)).unwrap(), 0);
+        assert_eq!(psbt.sign(&alice).unwrap(), 1);
+        psbt.finalize().unwrap();
+        let tx = psbt.extract_tx().unwrap();
+
+        let script_sig = tx.get_inputs()[0].get_script_sig();
+        let [Command::Push(sig, _), Command::Push(pubkey, _)] = script_sig.cmds() else {
+            panic!("unexpected script sig");
+        };
+        let z = tx.sig_hash(0, &spk, SIGHASH_ALL).unwrap();
+        assert!(verify_ecdsa(z, sig, pubkey));
+    }
+
+    #[test]
+    fn test_sign_script_mismatch() {
+        let alice = key(41);
+        let script = |n| {
+            Script::new(vec![
+                Command::number(n),
+                Command::Op(OP_DROP),
+                Command::push(alice.point().to_compressed_sec()),
+                Command::Op(OP_CHECKSIG),
+            ])
+        };
+        let sign = |spk: Script, redeem_script: Option<Script>, witness_script: Option<Script>| {
+            let mut psbt = Psbt::from_unsigned_tx(unsigned_tx(&[[1; 32]])).unwrap();
+            psbt.add_witness_utxo(0, TxOutput::new(Amount::from_sat(50_000), spk))
+                .unwrap();
+            psbt.inputs[0].redeem_script = redeem_script;
+            psbt.inputs[0].witness_script = witness_script;
+            psbt.sign(&alice)
+        };
+
+        let p2wsh = Address::p2wsh(&script(1), false).script_pubkey();
+        assert_eq!(sign(p2wsh.clone(), None, Some(script(1))), Ok(1));
+        assert_eq!(
+            sign(p2wsh.clone(), None, Some(script(2))),
+            Err(PsbtError::ScriptMismatch(0))
+        );
+
+        let p2sh = Address::p2sh(&script(1), false).script_pubkey();
+        assert_eq!(sign(p2sh.clone(), Some(script(1)), None), Ok(1));
+        assert_eq!(
+            sign(p2sh, Some(script(2)), None),
+            Err(PsbtError::ScriptMismatch(0))
+        );
+
+        // P2SH-P2WSH: the witness script must hash to the nested program
+        let nested = Address::p2sh(&p2wsh, false).script_pubkey();
+        assert_eq!(
+            sign(nested.clone(), Some(p2wsh.clone()), Some(script(1))),
+            Ok(1)
+        );
+        assert_eq!(
+            sign(nested, Some(p2wsh), Some(script(2))),
+            Err(PsbtError::ScriptMismatch(0))
+        );
+    }
+
+    #[test]
+    fn test_sign_taproot_key_path() {
+        let internal_key = key(31);
+        let spk = Address::p2tr(&internal_key.point(), None, false).script_pubkey();
+        let mut psbt = Psbt::new_v2(2, None);
+        psbt.add_input(OutPoint::new(Txid::from_bytes([1; 32]), 0), None)
+            .unwrap()
+            .witness_utxo = Some(TxOutput::new(Amount::from_sat(70_000), spk.clone()));
+        psbt.inputs[0].tap_internal_key = Some(internal_key.point().to_xonly());
+        psbt.add_output(Amount::from_sat(60_000), spk.clone())
+            .unwrap();
+
+        assert_eq!(psbt.sign(&internal_key).unwrap(), 1);
+        psbt.finalize().unwrap();
+        assert!(psbt.inputs[0].tap_internal_key.is_none());
+        let tx = psbt.extract_tx().unwrap();
+
+        let inputs = tx.get_inputs();
+        let witness = inputs[0].get_witness();
+        assert_eq!(witness.len(), 1);
+        assert_eq!(witness[0].len(), 64);
+        let prevouts = [TxOutput::new(Amount::from_sat(70_000), spk)];
+        let msg = tx.sig_hash_taproot(0, &prevouts, 0, None).unwrap();
+        let output_key = internal_key.point().taproot_tweak(None).unwrap();
+        assert!(output_key.verify_schnorr(&msg, &witness[0]));
+    }
+}
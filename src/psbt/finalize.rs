@@ -0,0 +1,201 @@
+use crate::{
+    address::AddressType,
+    script::{
+        op::{OP_0, OP_CHECKMULTISIG, OP_CHECKSIG},
+        Command, Script,
+    },
+    utils::hash160::hash160,
+};
+
+use super::{
+    input::PsbtInput,
+    sign::{classify, tap_leaf_hash},
+    Psbt, PsbtError,
+};
+
+/// The number pushed by OP_1 to OP_16
+fn small_number(cmd: &Command) -> Option<usize> {
+    match cmd {
+        Command::Op(op) => match op.small_int() {
+            Some(n @ 1..=16) => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the partial signature and key that satisfy a key hash
+fn pubkey_hash_sig(input: &PsbtInput, hash: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    input
+        .partial_sigs
+        .iter()
+        .find(|(pubkey, _)| hash160(pubkey).as_slice() == hash)
+        .map(|(pubkey, sig)| (sig.clone(), pubkey.clone()))
+}
+
+/// The stack satisfying a legacy or segwit v0 script from the partial
+/// signatures: <KEY> OP_CHECKSIG, P2PKH scripts, and k-of-n
+/// OP_CHECKMULTISIG
+fn satisfy(input: &PsbtInput, script: &Script) -> Option<Vec<Vec<u8>>> {
+    match script.cmds() {
+        [Command::Push(pubkey, _), Command::Op(OP_CHECKSIG)] => {
+            Some(vec![input.partial_sigs.get(pubkey)?.clone()])
+        }
+        [first, keys @ .., count, Command::Op(OP_CHECKMULTISIG)] => {
+            let threshold = small_number(first)?;
+            if small_number(count)? != keys.len() {
+                return None;
+            }
+            // Signatures must be in the order of the keys; the extra empty
+            // item is consumed by the OP_CHECKMULTISIG off-by-one bug
+            let mut stack = vec![vec![]];
+            for key in keys {
+                let Command::Push(pubkey, _) = key else {
+                    return None;
+                };
+                if stack.len() > threshold {
+                    break;
+                }
+                if let Some(sig) = input.partial_sigs.get(pubkey) {
+                    stack.push(sig.clone());
+                }
+            }
+            (stack.len() > threshold).then_some(stack)
+        }
+        _ => {
+            let (address_type, hash) = classify(script)?;
+            if address_type != AddressType::P2pkh {
+                return None;
+            }
+            let (sig, pubkey) = pubkey_hash_sig(input, &hash)?;
+            Some(vec![sig, pubkey])
+        }
+    }
+}
+
+/// A script sig pushing the stack items
+fn push_only(stack: Vec<Vec<u8>>) -> Script {
+    Script::new(
+        stack
+            .into_iter()
+            .map(|item| {
+                if item.is_empty() {
+                    Command::Op(OP_0)
+                } else {
+                    Command::push(item)
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The witness of a taproot input: the key path signature, or else the
+/// first leaf of the form <KEY> OP_CHECKSIG with a signature
+fn satisfy_taproot(input: &PsbtInput) -> Option<Vec<Vec<u8>>> {
+    if let Some(sig) = &input.tap_key_sig {
+        return Some(vec![sig.clone()]);
+    }
+    input
+        .tap_leaf_scripts
+        .iter()
+        .find_map(|(control_block, (script, leaf_version))| {
+            let [Command::Push(xonly, _), Command::Op(OP_CHECKSIG)] = script.cmds() else {
+                return None;
+            };
+            let xonly: [u8; 32] = xonly.as_slice().try_into().ok()?;
+            let leaf_hash = tap_leaf_hash(script, *leaf_version);
+            let sig = input.tap_script_sigs.get(&(xonly, leaf_hash))?;
+            Some(vec![
+                sig.clone(),
+                script.raw_serialize(),
+                control_block.clone(),
+            ])
+        })
+}
+
+/// Finalizer
+impl Psbt {
+    /// Finalizes every input, failing on the first that can't be
+    pub fn finalize(&mut self) -> Result<(), PsbtError> {
+        for index in 0..self.inputs.len() {
+            self.finalize_input(index)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the final script sig and witness of an input from its partial
+    /// signatures and scripts, then clears the fields only signers need
+    pub fn finalize_input(&mut self, index: usize) -> Result<(), PsbtError> {
+        let input = self
+            .inputs
+            .get(index)
+            .ok_or(PsbtError::IndexOutOfRange(index))?;
+        if input.is_finalized() {
+            return Ok(());
+        }
+        let fail = |reason: &str| PsbtError::Finalize {
+            input: index,
+            reason: reason.to_string(),
+        };
+        let missing_sigs = || fail("missing signatures");
+
+        let spent = self.spent_output(index)?;
+        let (address_type, data) =
+            classify(&spent.get_script_pubkey()).ok_or_else(|| fail("unknown script pubkey"))?;
+        let (script_sig, witness) = match address_type {
+            AddressType::P2pkh => {
+                let (sig, pubkey) = pubkey_hash_sig(input, &data).ok_or_else(missing_sigs)?;
+                (Some(push_only(vec![sig, pubkey])), None)
+            }
+            AddressType::P2wpkh => {
+                let (sig, pubkey) = pubkey_hash_sig(input, &data).ok_or_else(missing_sigs)?;
+                (None, Some(vec![sig, pubkey]))
+            }
+            AddressType::P2wsh => {
+                let witness_script = input
+                    .witness_script
+                    .as_ref()
+                    .ok_or_else(|| fail("missing witness script"))?;
+                let mut witness = satisfy(input, witness_script).ok_or_else(missing_sigs)?;
+                witness.push(witness_script.raw_serialize());
+                (None, Some(witness))
+            }
+            AddressType::P2sh => {
+                let redeem_script = input
+                    .redeem_script
+                    .as_ref()
+                    .ok_or_else(|| fail("missing redeem script"))?;
+                let script_sig = Script::new(vec![Command::push(redeem_script.raw_serialize())]);
+                match classify(redeem_script) {
+                    Some((AddressType::P2wpkh, program)) => {
+                        let (sig, pubkey) =
+                            pubkey_hash_sig(input, &program).ok_or_else(missing_sigs)?;
+                        (Some(script_sig), Some(vec![sig, pubkey]))
+                    }
+                    Some((AddressType::P2wsh, _)) => {
+                        let witness_script = input
+                            .witness_script
+                            .as_ref()
+                            .ok_or_else(|| fail("missing witness script"))?;
+                        let mut witness =
+                            satisfy(input, witness_script).ok_or_else(missing_sigs)?;
+                        witness.push(witness_script.raw_serialize());
+                        (Some(script_sig), Some(witness))
+                    }
+                    _ => {
+                        let mut stack = satisfy(input, redeem_script).ok_or_else(missing_sigs)?;
+                        stack.push(redeem_script.raw_serialize());
+                        (Some(push_only(stack)), None)
+                    }
+                }
+            }
+            AddressType::P2tr => (None, Some(satisfy_taproot(input).ok_or_else(missing_sigs)?)),
+        };
+
+        let input = &mut self.inputs[index];
+        input.final_script_sig = script_sig;
+        input.final_script_witness = witness;
+        input.clear_after_finalizing();
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use crate::{
+    descriptor::key::KeyOrigin,
+    script::Script,
+    transactions::{hashes::Txid, output::TxOutput, tx::Tx},
+};
+
+use super::{
+    raw::{
+        parse_array, parse_key_origin, parse_script, parse_tap_key_origin, parse_tx,
+        parse_tx_output, parse_u32, parse_witness, read_map, script_from_bytes,
+        serialize_key_origin, serialize_tap_key_origin, serialize_witness, write_pair,
+        write_raw_pair,
+    },
+    PsbtError, PSBT_PROPRIETARY,
+};
+
+const PSBT_IN_NON_WITNESS_UTXO: u64 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u64 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u64 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u64 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u64 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u64 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u64 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u64 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u64 = 0x08;
+const PSBT_IN_POR_COMMITMENT: u64 = 0x09;
+const PSBT_IN_RIPEMD160: u64 = 0x0a;
+const PSBT_IN_SHA256: u64 = 0x0b;
+const PSBT_IN_HASH160: u64 = 0x0c;
+const PSBT_IN_HASH256: u64 = 0x0d;
+const PSBT_IN_PREVIOUS_TXID: u64 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u64 = 0x0f;
+const PSBT_IN_SEQUENCE: u64 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u64 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u64 = 0x12;
+const PSBT_IN_TAP_KEY_SIG: u64 = 0x13;
+const PSBT_IN_TAP_SCRIPT_SIG: u64 = 0x14;
+const PSBT_IN_TAP_LEAF_SCRIPT: u64 = 0x15;
+const PSBT_IN_TAP_BIP32_DERIVATION: u64 = 0x16;
+const PSBT_IN_TAP_INTERNAL_KEY: u64 = 0x17;
+const PSBT_IN_TAP_MERKLE_ROOT: u64 = 0x18;
+
+/// The per-input map of a PSBT
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PsbtInput {
+    /// The whole transaction whose output is spent, needed for non-segwit
+    /// inputs
+    pub non_witness_utxo: Option<Tx>,
+    /// The output spent, enough for segwit inputs
+    pub witness_utxo: Option<TxOutput>,
+    /// Signatures with the sighash byte, by SEC public key
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    /// Key origins by SEC public key
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeyOrigin>,
+    pub final_script_sig: Option<Script>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Proof of reserves commitment
+    pub por_commitment: Option<Vec<u8>>,
+    /// Hash preimages by hash
+    pub ripemd160_preimages: BTreeMap<[u8; 20], Vec<u8>>,
+    pub sha256_preimages: BTreeMap<[u8; 32], Vec<u8>>,
+    pub hash160_preimages: BTreeMap<[u8; 20], Vec<u8>>,
+    pub hash256_preimages: BTreeMap<[u8; 32], Vec<u8>>,
+    /// PSBTv2: the hash of the transaction spent, in serialization order
+    pub previous_txid: Option<Txid>,
+    /// PSBTv2: the index of the output spent
+    pub output_index: Option<u32>,
+    /// PSBTv2: the sequence number, 0xffffffff if absent
+    pub sequence: Option<u32>,
+    /// PSBTv2: the minimum unix timestamp locktime the input needs
+    pub required_time_locktime: Option<u32>,
+    /// PSBTv2: the minimum block height locktime the input needs
+    pub required_height_locktime: Option<u32>,
+    /// The BIP340 signature of a taproot key path spend
+    pub tap_key_sig: Option<Vec<u8>>,
+    /// BIP340 signatures by x-only key and leaf hash
+    pub tap_script_sigs: BTreeMap<([u8; 32], [u8; 32]), Vec<u8>>,
+    /// Leaf scripts and their leaf versions by control block
+    pub tap_leaf_scripts: BTreeMap<Vec<u8>, (Script, u8)>,
+    /// The leaf hashes an x-only key appears in and its origin
+    pub tap_bip32_derivation: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeyOrigin)>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_merkle_root: Option<[u8; 32]>,
+    /// Proprietary and unknown entries by their serialized key
+    pub proprietary: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Parsing
+impl PsbtInput {
+    pub(super) fn parse(cursor: &mut Cursor<Vec<u8>>, version: u32) -> Result<Self, PsbtError> {
+        let mut input = PsbtInput::default();
+        for pair in read_map(cursor)? {
+            let v2_only = matches!(
+                pair.key_type,
+                PSBT_IN_PREVIOUS_TXID
+                    | PSBT_IN_OUTPUT_INDEX
+                    | PSBT_IN_SEQUENCE
+                    | PSBT_IN_REQUIRED_TIME_LOCKTIME
+                    | PSBT_IN_REQUIRED_HEIGHT_LOCKTIME
+            );
+            if v2_only && version == 0 {
+                return Err(PsbtError::UnexpectedField(pair.key_type));
+            }
+            match pair.key_type {
+                PSBT_IN_NON_WITNESS_UTXO => {
+                    pair.expect_empty_key()?;
+                    input.non_witness_utxo = Some(parse_tx(&pair)?);
+                }
+                PSBT_IN_WITNESS_UTXO => {
+                    pair.expect_empty_key()?;
+                    input.witness_utxo = Some(parse_tx_output(&pair)?);
+                }
+                PSBT_IN_PARTIAL_SIG => {
+                    let pubkey = pair.key_data_of_len(&[33, 65])?;
+                    input.partial_sigs.insert(pubkey, pair.value);
+                }
+                PSBT_IN_SIGHASH_TYPE => {
+                    pair.expect_empty_key()?;
+                    input.sighash_type = Some(parse_u32(&pair)?);
+                }
+                PSBT_IN_REDEEM_SCRIPT => {
+                    pair.expect_empty_key()?;
+                    input.redeem_script = Some(parse_script(&pair)?);
+                }
+                PSBT_IN_WITNESS_SCRIPT => {
+                    pair.expect_empty_key()?;
+                    input.witness_script = Some(parse_script(&pair)?);
+                }
+                PSBT_IN_BIP32_DERIVATION => {
+                    let pubkey = pair.key_data_of_len(&[33, 65])?;
+                    let origin =
+                        parse_key_origin(&pair.value).ok_or_else(|| pair.invalid_value())?;
+                    input.bip32_derivation.insert(pubkey, origin);
+                }
+                PSBT_IN_FINAL_SCRIPTSIG => {
+                    pair.expect_empty_key()?;
+                    input.final_script_sig = Some(parse_script(&pair)?);
+                }
+                PSBT_IN_FINAL_SCRIPTWITNESS => {
+                    pair.expect_empty_key()?;
+                    input.final_script_witness = Some(parse_witness(&pair)?);
+                }
+                PSBT_IN_POR_COMMITMENT => {
+                    pair.expect_empty_key()?;
+                    input.por_commitment = Some(pair.value);
+                }
+                PSBT_IN_RIPEMD160 => {
+                    let hash = pair.key_data_of_len(&[20])?.try_into().unwrap();
+                    input.ripemd160_preimages.insert(hash, pair.value);
+                }
+                PSBT_IN_SHA256 => {
+                    let hash = pair.key_data_of_len(&[32])?.try_into().unwrap();
+                    input.sha256_preimages.insert(hash, pair.value);
+                }
+                PSBT_IN_HASH160 => {
+                    let hash = pair.key_data_of_len(&[20])?.try_into().unwrap();
+                    input.hash160_preimages.insert(hash, pair.value);
+                }
+                PSBT_IN_HASH256 => {
+                    let hash = pair.key_data_of_len(&[32])?.try_into().unwrap();
+                    input.hash256_preimages.insert(hash, pair.value);
+                }
+                PSBT_IN_PREVIOUS_TXID => {
+                    pair.expect_empty_key()?;
+                    input.previous_txid = Some(Txid::from_bytes(parse_array(&pair)?));
+                }
+                PSBT_IN_OUTPUT_INDEX => {
+                    pair.expect_empty_key()?;
+                    input.output_index = Some(parse_u32(&pair)?);
+                }
+                PSBT_IN_SEQUENCE => {
+                    pair.expect_empty_key()?;
+                    input.sequence = Some(parse_u32(&pair)?);
+                }
+                PSBT_IN_REQUIRED_TIME_LOCKTIME => {
+                    pair.expect_empty_key()?;
+                    let locktime = parse_u32(&pair)?;
+                    if locktime < 500_000_000 {
+                        return Err(pair.invalid_value());
+                    }
+                    input.required_time_locktime = Some(locktime);
+                }
+                PSBT_IN_REQUIRED_HEIGHT_LOCKTIME => {
+                    pair.expect_empty_key()?;
+                    let locktime = parse_u32(&pair)?;
+                    if locktime == 0 || locktime >= 500_000_000 {
+                        return Err(pair.invalid_value());
+                    }
+                    input.required_height_locktime = Some(locktime);
+                }
+                PSBT_IN_TAP_KEY_SIG => {
+                    pair.expect_empty_key()?;
+                    if !matches!(pair.value.len(), 64 | 65) {
+                        return Err(pair.invalid_value());
+                    }
+                    input.tap_key_sig = Some(pair.value);
+                }
+                PSBT_IN_TAP_SCRIPT_SIG => {
+                    let key_data = pair.key_data_of_len(&[64])?;
+                    if !matches!(pair.value.len(), 64 | 65) {
+                        return Err(pair.invalid_value());
+                    }
+                    let xonly = key_data[..32].try_into().unwrap();
+                    let leaf_hash = key_data[32..].try_into().unwrap();
+                    input.tap_script_sigs.insert((xonly, leaf_hash), pair.value);
+                }
+                PSBT_IN_TAP_LEAF_SCRIPT => {
+                    // The control block is the leaf version and parity byte,
+                    // the internal key and up to 128 merkle path hashes
+                    let control_block = &pair.key_data;
+                    if control_block.len() < 33 || (control_block.len() - 33) % 32 != 0 {
+                        return Err(PsbtError::InvalidKey(pair.key()));
+                    }
+                    let (leaf_version, script) = pair
+                        .value
+                        .split_last()
+                        .ok_or_else(|| pair.invalid_value())?;
+                    let script = script_from_bytes(script).ok_or_else(|| pair.invalid_value())?;
+                    input
+                        .tap_leaf_scripts
+                        .insert(control_block.clone(), (script, *leaf_version));
+                }
+                PSBT_IN_TAP_BIP32_DERIVATION => {
+                    let xonly = pair.key_data_of_len(&[32])?.try_into().unwrap();
+                    let origin = parse_tap_key_origin(&pair)?;
+                    input.tap_bip32_derivation.insert(xonly, origin);
+                }
+                PSBT_IN_TAP_INTERNAL_KEY => {
+                    pair.expect_empty_key()?;
+                    input.tap_internal_key = Some(parse_array(&pair)?);
+                }
+                PSBT_IN_TAP_MERKLE_ROOT => {
+                    pair.expect_empty_key()?;
+                    input.tap_merkle_root = Some(parse_array(&pair)?);
+                }
+                PSBT_PROPRIETARY => {
+                    input.proprietary.insert(pair.key(), pair.value);
+                }
+                _ => {
+                    input.unknown.insert(pair.key(), pair.value);
+                }
+            }
+        }
+        if version == 2 && (input.previous_txid.is_none() || input.output_index.is_none()) {
+            return Err(PsbtError::MissingField(
+                "input previous txid and output index",
+            ));
+        }
+        Ok(input)
+    }
+
+    /// Serializes the map, separator included
+    pub(super) fn serialize(&self) -> Vec<u8> {
+        let mut result = vec![];
+        if let Some(tx) = &self.non_witness_utxo {
+            write_pair(&mut result, PSBT_IN_NON_WITNESS_UTXO, &[], &tx.serialize());
+        }
+        if let Some(output) = &self.witness_utxo {
+            write_pair(&mut result, PSBT_IN_WITNESS_UTXO, &[], &output.serialize());
+        }
+        for (pubkey, sig) in &self.partial_sigs {
+            write_pair(&mut result, PSBT_IN_PARTIAL_SIG, pubkey, sig);
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            write_pair(
+                &mut result,
+                PSBT_IN_SIGHASH_TYPE,
+                &[],
+                &sighash_type.to_le_bytes(),
+            );
+        }
+        if let Some(script) = &self.redeem_script {
+            write_pair(
+                &mut result,
+                PSBT_IN_REDEEM_SCRIPT,
+                &[],
+                &script.raw_serialize(),
+            );
+        }
+        if let Some(script) = &self.witness_script {
+            write_pair(
+                &mut result,
+                PSBT_IN_WITNESS_SCRIPT,
+                &[],
+                &script.raw_serialize(),
+            );
+        }
+        for (pubkey, origin) in &self.bip32_derivation {
+            write_pair(
+                &mut result,
+                PSBT_IN_BIP32_DERIVATION,
+                pubkey,
+                &serialize_key_origin(origin),
+            );
+        }
+        if let Some(script) = &self.final_script_sig {
+            write_pair(
+                &mut result,
+                PSBT_IN_FINAL_SCRIPTSIG,
+                &[],
+                &script.raw_serialize(),
+            );
+        }
+        if let Some(witness) = &self.final_script_witness {
+            write_pair(
+                &mut result,
+                PSBT_IN_FINAL_SCRIPTWITNESS,
+                &[],
+                &serialize_witness(witness),
+            );
+        }
+        if let Some(commitment) = &self.por_commitment {
+            write_pair(&mut result, PSBT_IN_POR_COMMITMENT, &[], commitment);
+        }
+        for (hash, preimage) in &self.ripemd160_preimages {
+            write_pair(&mut result, PSBT_IN_RIPEMD160, hash, preimage);
+        }
+        for (hash, preimage) in &self.sha256_preimages {
+            write_pair(&mut result, PSBT_IN_SHA256, hash, preimage);
+        }
+        for (hash, preimage) in &self.hash160_preimages {
+            write_pair(&mut result, PSBT_IN_HASH160, hash, preimage);
+        }
+        for (hash, preimage) in &self.hash256_preimages {
+            write_pair(&mut result, PSBT_IN_HASH256, hash, preimage);
+        }
+        if let Some(txid) = &self.previous_txid {
+            write_pair(&mut result, PSBT_IN_PREVIOUS_TXID, &[], txid.as_bytes());
+        }
+        if let Some(index) = self.output_index {
+            write_pair(&mut result, PSBT_IN_OUTPUT_INDEX, &[], &index.to_le_bytes());
+        }
+        if let Some(sequence) = self.sequence {
+            write_pair(&mut result, PSBT_IN_SEQUENCE, &[], &sequence.to_le_bytes());
+        }
+        if let Some(locktime) = self.required_time_locktime {
+            write_pair(
+                &mut result,
+                PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                &[],
+                &locktime.to_le_bytes(),
+            );
+        }
+        if let Some(locktime) = self.required_height_locktime {
+            write_pair(
+                &mut result,
+                PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                &[],
+                &locktime.to_le_bytes(),
+            );
+        }
+        if let Some(sig) = &self.tap_key_sig {
+            write_pair(&mut result, PSBT_IN_TAP_KEY_SIG, &[], sig);
+        }
+        for ((xonly, leaf_hash), sig) in &self.tap_script_sigs {
+            let mut key_data = xonly.to_vec();
+            key_data.extend_from_slice(leaf_hash);
+            write_pair(&mut result, PSBT_IN_TAP_SCRIPT_SIG, &key_data, sig);
+        }
+        for (control_block, (script, leaf_version)) in &self.tap_leaf_scripts {
+            let mut value = script.raw_serialize();
+            value.push(*leaf_version);
+            write_pair(&mut result, PSBT_IN_TAP_LEAF_SCRIPT, control_block, &value);
+        }
+        for (xonly, (leaf_hashes, origin)) in &self.tap_bip32_derivation {
+            write_pair(
+                &mut result,
+                PSBT_IN_TAP_BIP32_DERIVATION,
+                xonly,
+                &serialize_tap_key_origin(leaf_hashes, origin),
+            );
+        }
+        if let Some(key) = &self.tap_internal_key {
+            write_pair(&mut result, PSBT_IN_TAP_INTERNAL_KEY, &[], key);
+        }
+        if let Some(root) = &self.tap_merkle_root {
+            write_pair(&mut result, PSBT_IN_TAP_MERKLE_ROOT, &[], root);
+        }
+        for (key, value) in self.proprietary.iter().chain(&self.unknown) {
+            write_raw_pair(&mut result, key, value);
+        }
+        result.push(0x00);
+        result
+    }
+}
+
+/// Methods
+impl PsbtInput {
+    /// Returns true once the Finalizer has built the final script sig or
+    /// witness
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    /// Adds the entries of other that this input lacks
+    pub(super) fn combine(&mut self, other: PsbtInput) {
+        merge_option(&mut self.non_witness_utxo, other.non_witness_utxo);
+        merge_option(&mut self.witness_utxo, other.witness_utxo);
+        merge_map(&mut self.partial_sigs, other.partial_sigs);
+        merge_option(&mut self.sighash_type, other.sighash_type);
+        merge_option(&mut self.redeem_script, other.redeem_script);
+        merge_option(&mut self.witness_script, other.witness_script);
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation);
+        merge_option(&mut self.final_script_sig, other.final_script_sig);
+        merge_option(&mut self.final_script_witness, other.final_script_witness);
+        merge_option(&mut self.por_commitment, other.por_commitment);
+        merge_map(&mut self.ripemd160_preimages, other.ripemd160_preimages);
+        merge_map(&mut self.sha256_preimages, other.sha256_preimages);
+        merge_map(&mut self.hash160_preimages, other.hash160_preimages);
+        merge_map(&mut self.hash256_preimages, other.hash256_preimages);
+        merge_option(
+            &mut self.required_time_locktime,
+            other.required_time_locktime,
+        );
+        merge_option(
+            &mut self.required_height_locktime,
+            other.required_height_locktime,
+        );
+        merge_option(&mut self.tap_key_sig, other.tap_key_sig);
+        merge_map(&mut self.tap_script_sigs, other.tap_script_sigs);
+        merge_map(&mut self.tap_leaf_scripts, other.tap_leaf_scripts);
+        merge_map(&mut self.tap_bip32_derivation, other.tap_bip32_derivation);
+        merge_option(&mut self.tap_internal_key, other.tap_internal_key);
+        merge_option(&mut self.tap_merkle_root, other.tap_merkle_root);
+        merge_map(&mut self.proprietary, other.proprietary);
+        merge_map(&mut self.unknown, other.unknown);
+    }
+
+    /// Clears everything but the UTXOs, the final scripts, the fields
+    /// describing the transaction and the proprietary and unknown entries,
+    /// as the Finalizer must
+    pub(super) fn clear_after_finalizing(&mut self) {
+        *self = PsbtInput {
+            non_witness_utxo: self.non_witness_utxo.take(),
+            witness_utxo: self.witness_utxo.take(),
+            final_script_sig: self.final_script_sig.take(),
+            final_script_witness: self.final_script_witness.take(),
+            previous_txid: self.previous_txid,
+            output_index: self.output_index,
+            sequence: self.sequence,
+            required_time_locktime: self.required_time_locktime,
+            required_height_locktime: self.required_height_locktime,
+            proprietary: std::mem::take(&mut self.proprietary),
+            unknown: std::mem::take(&mut self.unknown),
+            ..PsbtInput::default()
+        };
+    }
+}
+
+pub(super) fn merge_option<T>(ours: &mut Option<T>, theirs: Option<T>) {
+    if ours.is_none() {
+        *ours = theirs;
+    }
+}
+
+pub(super) fn merge_map<K: Ord, V>(ours: &mut BTreeMap<K, V>, theirs: BTreeMap<K, V>) {
+    for (key, value) in theirs {
+        ours.entry(key).or_insert(value);
+    }
+}
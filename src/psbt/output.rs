@@ -0,0 +1,272 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use crate::{
+    amount::Amount, descriptor::key::KeyOrigin, script::Script, utils::varint::read_varint,
+};
+
+use super::{
+    input::{merge_map, merge_option},
+    raw::{
+        parse_array, parse_key_origin, parse_script, parse_tap_key_origin, read_map,
+        script_from_bytes, serialize_key_origin, serialize_tap_key_origin, write_pair,
+        write_raw_pair, Pair,
+    },
+    PsbtError, PSBT_PROPRIETARY,
+};
+
+const PSBT_OUT_REDEEM_SCRIPT: u64 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u64 = 0x01;
+const PSBT_OUT_BIP32_DERIVATION: u64 = 0x02;
+const PSBT_OUT_AMOUNT: u64 = 0x03;
+const PSBT_OUT_SCRIPT: u64 = 0x04;
+const PSBT_OUT_TAP_INTERNAL_KEY: u64 = 0x05;
+const PSBT_OUT_TAP_TREE: u64 = 0x06;
+const PSBT_OUT_TAP_BIP32_DERIVATION: u64 = 0x07;
+
+/// A leaf of a taproot output's script tree, in depth-first order
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TapTreeLeaf {
+    pub depth: u8,
+    pub leaf_version: u8,
+    pub script: Script,
+}
+
+/// The per-output map of a PSBT
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PsbtOutput {
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    /// Key origins by SEC public key
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeyOrigin>,
+    /// PSBTv2: the amount
+    pub amount: Option<Amount>,
+    /// PSBTv2: the script pubkey
+    pub script: Option<Script>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_tree: Option<Vec<TapTreeLeaf>>,
+    /// The leaf hashes an x-only key appears in and its origin
+    pub tap_bip32_derivation: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeyOrigin)>,
+    /// Proprietary and unknown entries by their serialized key
+    pub proprietary: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Parsing
+impl PsbtOutput {
+    pub(super) fn parse(cursor: &mut Cursor<Vec<u8>>, version: u32) -> Result<Self, PsbtError> {
+        let mut output = PsbtOutput::default();
+        for pair in read_map(cursor)? {
+            let v2_only = matches!(pair.key_type, PSBT_OUT_AMOUNT | PSBT_OUT_SCRIPT);
+            if v2_only && version == 0 {
+                return Err(PsbtError::UnexpectedField(pair.key_type));
+            }
+            match pair.key_type {
+                PSBT_OUT_REDEEM_SCRIPT => {
+                    pair.expect_empty_key()?;
+                    output.redeem_script = Some(parse_script(&pair)?);
+                }
+                PSBT_OUT_WITNESS_SCRIPT => {
+                    pair.expect_empty_key()?;
+                    output.witness_script = Some(parse_script(&pair)?);
+                }
+                PSBT_OUT_BIP32_DERIVATION => {
+                    let pubkey = pair.key_data_of_len(&[33, 65])?;
+                    let origin =
+                        parse_key_origin(&pair.value).ok_or_else(|| pair.invalid_value())?;
+                    output.bip32_derivation.insert(pubkey, origin);
+                }
+                PSBT_OUT_AMOUNT => {
+                    pair.expect_empty_key()?;
+                    let amount = i64::from_le_bytes(parse_array(&pair)?);
+                    let amount = u64::try_from(amount).map_err(|_| pair.invalid_value())?;
+                    output.amount = Some(Amount::from_sat(amount));
+                }
+                PSBT_OUT_SCRIPT => {
+                    pair.expect_empty_key()?;
+                    output.script = Some(parse_script(&pair)?);
+                }
+                PSBT_OUT_TAP_INTERNAL_KEY => {
+                    pair.expect_empty_key()?;
+                    output.tap_internal_key = Some(parse_array(&pair)?);
+                }
+                PSBT_OUT_TAP_TREE => {
+                    pair.expect_empty_key()?;
+                    output.tap_tree = Some(parse_tap_tree(&pair)?);
+                }
+                PSBT_OUT_TAP_BIP32_DERIVATION => {
+                    let xonly = pair.key_data_of_len(&[32])?.try_into().unwrap();
+                    let origin = parse_tap_key_origin(&pair)?;
+                    output.tap_bip32_derivation.insert(xonly, origin);
+                }
+                PSBT_PROPRIETARY => {
+                    output.proprietary.insert(pair.key(), pair.value);
+                }
+                _ => {
+                    output.unknown.insert(pair.key(), pair.value);
+                }
+            }
+        }
+        if version == 2 && (output.amount.is_none() || output.script.is_none()) {
+            return Err(PsbtError::MissingField("output amount and script"));
+        }
+        Ok(output)
+    }
+
+    /// Serializes the map, separator included
+    pub(super) fn serialize(&self) -> Vec<u8> {
+        let mut result = vec![];
+        if let Some(script) = &self.redeem_script {
+            write_pair(
+                &mut result,
+                PSBT_OUT_REDEEM_SCRIPT,
+                &[],
+                &script.raw_serialize(),
+            );
+        }
+        if let Some(script) = &self.witness_script {
+            write_pair(
+                &mut result,
+                PSBT_OUT_WITNESS_SCRIPT,
+                &[],
+                &script.raw_serialize(),
+            );
+        }
+        for (pubkey, origin) in &self.bip32_derivation {
+            write_pair(
+                &mut result,
+                PSBT_OUT_BIP32_DERIVATION,
+                pubkey,
+                &serialize_key_origin(origin),
+            );
+        }
+        if let Some(amount) = self.amount {
+            write_pair(
+                &mut result,
+                PSBT_OUT_AMOUNT,
+                &[],
+                &amount.to_sat().to_le_bytes(),
+            );
+        }
+        if let Some(script) = &self.script {
+            write_pair(&mut result, PSBT_OUT_SCRIPT, &[], &script.raw_serialize());
+        }
+        if let Some(key) = &self.tap_internal_key {
+            write_pair(&mut result, PSBT_OUT_TAP_INTERNAL_KEY, &[], key);
+        }
+        if let Some(leaves) = &self.tap_tree {
+            let mut value = vec![];
+            for leaf in leaves {
+                value.push(leaf.depth);
+                value.push(leaf.leaf_version);
+                value.extend(leaf.script.serialize());
+            }
+            write_pair(&mut result, PSBT_OUT_TAP_TREE, &[], &value);
+        }
+        for (xonly, (leaf_hashes, origin)) in &self.tap_bip32_derivation {
+            write_pair(
+                &mut result,
+                PSBT_OUT_TAP_BIP32_DERIVATION,
+                xonly,
+                &serialize_tap_key_origin(leaf_hashes, origin),
+            );
+        }
+        for (key, value) in self.proprietary.iter().chain(&self.unknown) {
+            write_raw_pair(&mut result, key, value);
+        }
+        result.push(0x00);
+        result
+    }
+}
+
+/// Methods
+impl PsbtOutput {
+    /// Adds the entries of other that this output lacks
+    pub(super) fn combine(&mut self, other: PsbtOutput) {
+        merge_option(&mut self.redeem_script, other.redeem_script);
+        merge_option(&mut self.witness_script, other.witness_script);
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation);
+        merge_option(&mut self.tap_internal_key, other.tap_internal_key);
+        merge_option(&mut self.tap_tree, other.tap_tree);
+        merge_map(&mut self.tap_bip32_derivation, other.tap_bip32_derivation);
+        merge_map(&mut self.proprietary, other.proprietary);
+        merge_map(&mut self.unknown, other.unknown);
+    }
+}
+
+/// The leaves of a tap tree: depth, leaf version and length-prefixed script
+/// each
+fn parse_tap_tree(pair: &Pair) -> Result<Vec<TapTreeLeaf>, PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let mut leaves = vec![];
+    while (cursor.position() as usize) < pair.value.len() {
+        let position = cursor.position() as usize;
+        let (depth, leaf_version) = match pair.value.get(position..position + 2) {
+            Some([depth, leaf_version]) => (*depth, *leaf_version),
+            _ => return Err(pair.invalid_value()),
+        };
+        if depth > 128 {
+            return Err(pair.invalid_value());
+        }
+        cursor.set_position(position as u64 + 2);
+        let length = read_varint(&mut cursor).map_err(|_| pair.invalid_value())? as usize;
+        let start = cursor.position() as usize;
+        let script = pair
+            .value
+            .get(start..start + length)
+            .and_then(script_from_bytes)
+            .ok_or_else(|| pair.invalid_value())?;
+        cursor.set_position((start + length) as u64);
+        leaves.push(TapTreeLeaf {
+            depth,
+            leaf_version,
+            script,
+        });
+    }
+    if leaves.is_empty() {
+        return Err(pair.invalid_value());
+    }
+    Ok(leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::varint::encode_varint;
+
+    use super::*;
+
+    #[test]
+    fn test_tap_tree_roundtrip() {
+        let script = script_from_bytes(
+            &hex::decode("20f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9ac")
+                .unwrap(),
+        )
+        .unwrap();
+        let output = PsbtOutput {
+            tap_tree: Some(vec![
+                TapTreeLeaf {
+                    depth: 1,
+                    leaf_version: 0xc0,
+                    script: script.clone(),
+                },
+                TapTreeLeaf {
+                    depth: 1,
+                    leaf_version: 0xc0,
+                    script,
+                },
+            ]),
+            ..PsbtOutput::default()
+        };
+        let serialized = output.serialize();
+        let parsed = PsbtOutput::parse(&mut Cursor::new(serialized), 0).unwrap();
+        assert_eq!(parsed, output);
+
+        let mut value = encode_varint(1).unwrap();
+        value.push(0xc0);
+        let truncated = Pair {
+            key_type: PSBT_OUT_TAP_TREE,
+            key_data: vec![],
+            value,
+        };
+        assert!(parse_tap_tree(&truncated).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::BTreeSet,
+    io::{Cursor, Read},
+};
+
+use crate::{
+    descriptor::key::KeyOrigin,
+    script::Script,
+    transactions::{output::TxOutput, tx::Tx},
+    utils::varint::{encode_varint, read_varint},
+    wallet::derivation_path::DerivationPath,
+};
+
+use super::PsbtError;
+
+/// A key-value pair of a PSBT map, with the key split into its type and
+/// data
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(super) struct Pair {
+    pub key_type: u64,
+    pub key_data: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl Pair {
+    /// The serialized key, type included, used for unknown and proprietary
+    /// entries
+    pub fn key(&self) -> Vec<u8> {
+        let mut key = encode_varint(self.key_type).unwrap();
+        key.extend(&self.key_data);
+        key
+    }
+
+    /// Fails if the key has data, for types that may appear only once
+    pub fn expect_empty_key(&self) -> Result<(), PsbtError> {
+        if self.key_data.is_empty() {
+            Ok(())
+        } else {
+            Err(PsbtError::InvalidKey(self.key()))
+        }
+    }
+
+    /// Returns the key data, failing unless it is one of the lengths
+    pub fn key_data_of_len(&self, lengths: &[usize]) -> Result<Vec<u8>, PsbtError> {
+        if lengths.contains(&self.key_data.len()) {
+            Ok(self.key_data.clone())
+        } else {
+            Err(PsbtError::InvalidKey(self.key()))
+        }
+    }
+
+    pub fn invalid_value(&self) -> PsbtError {
+        PsbtError::InvalidValue(self.key_type)
+    }
+}
+
+fn read_bytes(cursor: &mut Cursor<Vec<u8>>, length: u64) -> Result<Vec<u8>, PsbtError> {
+    let remaining = cursor.get_ref().len() as u64 - cursor.position();
+    if length > remaining {
+        return Err(PsbtError::UnexpectedEof);
+    }
+    let mut bytes = vec![0; length as usize];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| PsbtError::UnexpectedEof)?;
+    Ok(bytes)
+}
+
+/// Reads a map up to and including its 0x00 separator, rejecting duplicate
+/// keys
+pub(super) fn read_map(cursor: &mut Cursor<Vec<u8>>) -> Result<Vec<Pair>, PsbtError> {
+    let mut pairs = vec![];
+    let mut keys = BTreeSet::new();
+    loop {
+        let key_len = read_varint(cursor).map_err(|_| PsbtError::UnexpectedEof)?;
+        if key_len == 0 {
+            return Ok(pairs);
+        }
+        let key = read_bytes(cursor, key_len)?;
+        let mut key_cursor = Cursor::new(key.clone());
+        let key_type =
+            read_varint(&mut key_cursor).map_err(|_| PsbtError::InvalidKey(key.clone()))?;
+        let key_data = key[key_cursor.position() as usize..].to_vec();
+        let value_len = read_varint(cursor).map_err(|_| PsbtError::UnexpectedEof)?;
+        let value = read_bytes(cursor, value_len)?;
+        if !keys.insert(key.clone()) {
+            return Err(PsbtError::DuplicateKey(key));
+        }
+        pairs.push(Pair {
+            key_type,
+            key_data,
+            value,
+        });
+    }
+}
+
+/// Writes a key-value pair
+pub(super) fn write_pair(result: &mut Vec<u8>, key_type: u64, key_data: &[u8], value: &[u8]) {
+    let mut key = encode_varint(key_type).unwrap();
+    key.extend_from_slice(key_data);
+    write_raw_pair(result, &key, value);
+}
+
+/// Writes a key-value pair whose key is already serialized
+pub(super) fn write_raw_pair(result: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    result.extend(encode_varint(key.len() as u64).unwrap());
+    result.extend_from_slice(key);
+    result.extend(encode_varint(value.len() as u64).unwrap());
+    result.extend_from_slice(value);
+}
+
+pub(super) fn parse_u32(pair: &Pair) -> Result<u32, PsbtError> {
+    let bytes: [u8; 4] = pair
+        .value
+        .as_slice()
+        .try_into()
+        .map_err(|_| pair.invalid_value())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(super) fn parse_array<const N: usize>(pair: &Pair) -> Result<[u8; N], PsbtError> {
+    pair.value
+        .as_slice()
+        .try_into()
+        .map_err(|_| pair.invalid_value())
+}
+
+/// Parses a compact size that must make up the whole value
+pub(super) fn parse_varint(pair: &Pair) -> Result<u64, PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let value = read_varint(&mut cursor).map_err(|_| pair.invalid_value())?;
+    if cursor.position() as usize != pair.value.len() {
+        return Err(pair.invalid_value());
+    }
+    Ok(value)
+}
+
+/// Parses a script given without its length prefix
+pub(crate) fn script_from_bytes(bytes: &[u8]) -> Option<Script> {
+    Script::from_bytes(bytes).ok()
+}
+
+pub(super) fn parse_script(pair: &Pair) -> Result<Script, PsbtError> {
+    script_from_bytes(&pair.value).ok_or_else(|| pair.invalid_value())
+}
+
+pub(super) fn parse_tx(pair: &Pair) -> Result<Tx, PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let tx = Tx::parse(&mut cursor).map_err(|_| pair.invalid_value())?;
+    if cursor.position() as usize != pair.value.len() {
+        return Err(pair.invalid_value());
+    }
+    Ok(tx)
+}
+
+pub(super) fn parse_tx_output(pair: &Pair) -> Result<TxOutput, PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let output = TxOutput::parse(&mut cursor).map_err(|_| pair.invalid_value())?;
+    if cursor.position() as usize != pair.value.len() {
+        return Err(pair.invalid_value());
+    }
+    Ok(output)
+}
+
+/// A witness stack: the number of items followed by each length-prefixed
+/// item
+pub(super) fn parse_witness(pair: &Pair) -> Result<Vec<Vec<u8>>, PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let num_items = read_varint(&mut cursor).map_err(|_| pair.invalid_value())?;
+    let mut witness = vec![];
+    for _ in 0..num_items {
+        let length = read_varint(&mut cursor).map_err(|_| pair.invalid_value())?;
+        witness.push(read_bytes(&mut cursor, length).map_err(|_| pair.invalid_value())?);
+    }
+    if cursor.position() as usize != pair.value.len() {
+        return Err(pair.invalid_value());
+    }
+    Ok(witness)
+}
+
+pub(super) fn serialize_witness(witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = encode_varint(witness.len() as u64).unwrap();
+    for item in witness {
+        result.extend(encode_varint(item.len() as u64).unwrap());
+        result.extend(item);
+    }
+    result
+}
+
+/// The master key fingerprint followed by the derivation path as little
+/// endian 32-bit numbers
+pub(super) fn parse_key_origin(bytes: &[u8]) -> Option<KeyOrigin> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(4) {
+        return None;
+    }
+    let fingerprint = bytes[..4].try_into().ok()?;
+    let path = bytes[4..]
+        .chunks(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    Some(KeyOrigin {
+        fingerprint,
+        path: DerivationPath::new(path),
+    })
+}
+
+pub(super) fn serialize_key_origin(origin: &KeyOrigin) -> Vec<u8> {
+    let mut result = origin.fingerprint.to_vec();
+    for index in origin.path.as_slice() {
+        result.extend(index.to_le_bytes());
+    }
+    result
+}
+
+/// A taproot key origin: the hashes of the leaves the key appears in, then
+/// the key origin
+pub(super) fn parse_tap_key_origin(pair: &Pair) -> Result<(Vec<[u8; 32]>, KeyOrigin), PsbtError> {
+    let mut cursor = Cursor::new(pair.value.clone());
+    let num_hashes = read_varint(&mut cursor).map_err(|_| pair.invalid_value())?;
+    let mut leaf_hashes = vec![];
+    for _ in 0..num_hashes {
+        let hash = read_bytes(&mut cursor, 32).map_err(|_| pair.invalid_value())?;
+        leaf_hashes.push(hash.try_into().unwrap());
+    }
+    let origin = parse_key_origin(&pair.value[cursor.position() as usize..])
+        .ok_or_else(|| pair.invalid_value())?;
+    Ok((leaf_hashes, origin))
+}
+
+pub(super) fn serialize_tap_key_origin(leaf_hashes: &[[u8; 32]], origin: &KeyOrigin) -> Vec<u8> {
+    let mut result = encode_varint(leaf_hashes.len() as u64).unwrap();
+    for hash in leaf_hashes {
+        result.extend_from_slice(hash);
+    }
+    result.extend(serialize_key_origin(origin));
+    result
+}
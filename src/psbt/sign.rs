@@ -0,0 +1,214 @@
+use num::BigUint;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    address::{Address, AddressType, Payload},
+    ecc::private_key::PrivateKey,
+    script::{
+        op::{OP_CHECKSIG, OP_DUP, OP_EQUALVERIFY, OP_HASH160},
+        Command, Script,
+    },
+    transactions::{
+        output::TxOutput,
+        sighash::{SIGHASH_ALL, SIGHASH_DEFAULT},
+        tx::Tx,
+    },
+    utils::{hash160::hash160, tagged_hash::tagged_hash},
+};
+
+use super::{Psbt, PsbtError};
+
+/// hash_TapLeaf(leaf_version || compact_size(script) || script)
+pub(super) fn tap_leaf_hash(script: &Script, leaf_version: u8) -> [u8; 32] {
+    let mut msg = vec![leaf_version];
+    msg.extend(script.serialize());
+    tagged_hash("TapLeaf", &msg)
+}
+
+pub(super) fn p2pkh_script(pubkey_hash: &[u8]) -> Script {
+    Script::new(vec![
+        Command::Op(OP_DUP),
+        Command::Op(OP_HASH160),
+        Command::push(pubkey_hash.to_vec()),
+        Command::Op(OP_EQUALVERIFY),
+        Command::Op(OP_CHECKSIG),
+    ])
+}
+
+/// The witness program of a segwit script, or the hash of a P2PKH or P2SH
+/// script, with the type of the script
+pub(super) fn classify(script: &Script) -> Option<(AddressType, Vec<u8>)> {
+    let address = Address::from_script_pubkey(script, false)?;
+    let data = match address.payload() {
+        Payload::PubkeyHash(hash) | Payload::ScriptHash(hash) => hash.to_vec(),
+        Payload::WitnessProgram { program, .. } => program.clone(),
+    };
+    Some((address.address_type()?, data))
+}
+
+/// Signer
+impl Psbt {
+    /// Adds signatures by the key to every input it can sign: P2PKH, P2WPKH,
+    /// P2SH and P2WSH scripts that contain the key or its hash, or list it
+    /// in their BIP32 derivations, taproot key path spends whose internal
+    /// key it is, and taproot leaves that contain it. Returns the number of
+    /// signatures added.
+    pub fn sign(&mut self, key: &PrivateKey) -> Result<usize, PsbtError> {
+        let tx = self.unsigned_tx()?;
+        let mut prevouts: Option<Vec<TxOutput>> = None;
+        let mut count = 0;
+        for index in 0..self.inputs.len() {
+            if self.inputs[index].is_finalized() {
+                continue;
+            }
+            let spent = match self.spent_output(index) {
+                Ok(spent) => spent,
+                Err(PsbtError::MissingUtxo(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            let Some((address_type, data)) = classify(&spent.get_script_pubkey()) else {
+                continue;
+            };
+            if address_type == AddressType::P2tr {
+                if prevouts.is_none() {
+                    prevouts = Some(
+                        (0..self.inputs.len())
+                            .map(|i| self.spent_output(i))
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                count += self.sign_taproot(&tx, index, &data, prevouts.as_ref().unwrap(), key)?;
+            } else {
+                count += self.sign_ecdsa(&tx, index, address_type, &data, &spent, key)?;
+            }
+        }
+        Ok(count)
+    }
+
+    fn sign_ecdsa(
+        &mut self,
+        tx: &Tx,
+        index: usize,
+        address_type: AddressType,
+        data: &[u8],
+        spent: &TxOutput,
+        key: &PrivateKey,
+    ) -> Result<usize, PsbtError> {
+        let input = &self.inputs[index];
+        // The witness script, if it hashes to the P2WSH program
+        let witness_script = |program: &[u8]| match &input.witness_script {
+            Some(witness_script) => {
+                if Sha256::digest(witness_script.raw_serialize()).as_slice() != program {
+                    return Err(PsbtError::ScriptMismatch(index));
+                }
+                Ok(Some(witness_script.clone()))
+            }
+            None => Ok(None),
+        };
+        // The script code and whether BIP143 applies
+        let (script_code, segwit) = match address_type {
+            AddressType::P2pkh => (spent.get_script_pubkey(), false),
+            AddressType::P2wpkh => (p2pkh_script(data), true),
+            AddressType::P2wsh => match witness_script(data)? {
+                Some(witness_script) => (witness_script, true),
+                None => return Ok(0),
+            },
+            AddressType::P2sh => {
+                let Some(redeem_script) = &input.redeem_script else {
+                    return Ok(0);
+                };
+                if hash160(&redeem_script.raw_serialize()) != data {
+                    return Err(PsbtError::ScriptMismatch(index));
+                }
+                match classify(redeem_script) {
+                    Some((AddressType::P2wpkh, program)) => (p2pkh_script(&program), true),
+                    Some((AddressType::P2wsh, program)) => match witness_script(&program)? {
+                        Some(witness_script) => (witness_script, true),
+                        None => return Ok(0),
+                    },
+                    _ => (redeem_script.clone(), false),
+                }
+            }
+            AddressType::P2tr => return Ok(0),
+        };
+
+        let point = key.point();
+        let candidates = [point.to_compressed_sec(), point.to_uncompressed_sec()];
+        let Some(pubkey) = candidates.into_iter().find(|sec| {
+            let hash = hash160(sec).to_vec();
+            input.bip32_derivation.contains_key(sec)
+                || script_code.cmds().iter().any(
+                    |cmd| matches!(cmd, Command::Push(data, _) if *data == *sec || *data == hash),
+                )
+        }) else {
+            return Ok(0);
+        };
+
+        let sighash_type = input.sighash_type.unwrap_or(SIGHASH_ALL);
+        let hash = if segwit {
+            tx.sig_hash_segwit_v0(index, &script_code, spent.get_amount(), sighash_type)
+        } else {
+            tx.sig_hash(index, &script_code, sighash_type)
+        }
+        .map_err(PsbtError::Sighash)?;
+        let mut sig = key.sign(&BigUint::from_bytes_be(&hash)).der();
+        sig.push(sighash_type as u8);
+        self.inputs[index].partial_sigs.insert(pubkey, sig);
+        Ok(1)
+    }
+
+    fn sign_taproot(
+        &mut self,
+        tx: &Tx,
+        index: usize,
+        output_key: &[u8],
+        prevouts: &[TxOutput],
+        key: &PrivateKey,
+    ) -> Result<usize, PsbtError> {
+        let input = &self.inputs[index];
+        let sighash_type = input.sighash_type.unwrap_or(SIGHASH_DEFAULT);
+        let sign = |leaf_hash: Option<&[u8; 32]>, key: &PrivateKey| {
+            let hash = tx
+                .sig_hash_taproot(index, prevouts, sighash_type, leaf_hash)
+                .map_err(PsbtError::Sighash)?;
+            let mut sig = key.sign_schnorr(&hash, &rand::random()).to_vec();
+            if sighash_type != SIGHASH_DEFAULT {
+                sig.push(sighash_type as u8);
+            }
+            Ok::<_, PsbtError>(sig)
+        };
+
+        let xonly = key.point().to_xonly();
+        let mut key_sig = None;
+        if input.tap_internal_key == Some(xonly) {
+            let tweaked = key
+                .taproot_tweak(input.tap_merkle_root.as_ref())
+                .map_err(|err| PsbtError::Sighash(err.to_string()))?;
+            if tweaked.point().to_xonly().as_slice() == output_key {
+                key_sig = Some(sign(None, &tweaked)?);
+            }
+        }
+
+        let mut script_sigs = vec![];
+        for (script, leaf_version) in input.tap_leaf_scripts.values() {
+            let has_key = script
+                .cmds()
+                .iter()
+                .any(|cmd| matches!(cmd, Command::Push(data, _) if data.as_slice() == xonly));
+            if has_key {
+                let leaf_hash = tap_leaf_hash(script, *leaf_version);
+                script_sigs.push((leaf_hash, sign(Some(&leaf_hash), key)?));
+            }
+        }
+
+        let input = &mut self.inputs[index];
+        let count = usize::from(key_sig.is_some()) + script_sigs.len();
+        if key_sig.is_some() {
+            input.tap_key_sig = key_sig;
+        }
+        for (leaf_hash, sig) in script_sigs {
+            input.tap_script_sigs.insert((xonly, leaf_hash), sig);
+        }
+        Ok(count)
+    }
+}
@@ -1,120 +1,278 @@
+pub mod asm;
+pub mod interpreter;
+pub mod num;
 pub mod op;
+pub mod opcode;
+pub mod sigops;
+pub mod template;
 
 use core::fmt;
-use std::io::{Cursor, Error, Read};
+use std::io::{Cursor, Error, Read, Write};
 
-use crate::{script::op::create_op_code_names, utils::varint::read_varint};
+use crate::{
+    encode::{self, Decodable, DecodeError, DecodeErrorKind, Encodable},
+    script::{
+        op::{encode_num, OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4},
+        opcode::Opcode,
+    },
+};
+
+/// How the length of pushed data is encoded: in the opcode itself for up
+/// to 75 bytes, or after OP_PUSHDATA1, OP_PUSHDATA2 or OP_PUSHDATA4
+#[derive(Debug, Eq, PartialEq, Clone, Copy, PartialOrd, Ord)]
+pub enum PushEncoding {
+    Direct,
+    PushData1,
+    PushData2,
+    PushData4,
+}
+
+impl PushEncoding {
+    /// The shortest encoding for data of the length
+    pub fn minimal(length: usize) -> Self {
+        match length {
+            0..=75 => PushEncoding::Direct,
+            76..=0xff => PushEncoding::PushData1,
+            0x100..=0xffff => PushEncoding::PushData2,
+            _ => PushEncoding::PushData4,
+        }
+    }
+}
+
+/// A script element: an opcode, or data to push on the stack along with
+/// how its length was encoded, so that parsed scripts serialize back to
+/// the same bytes
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Command {
+    Op(Opcode),
+    Push(Vec<u8>, PushEncoding),
+}
+
+impl Command {
+    /// Pushes the data with the shortest length encoding
+    pub fn push(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        let encoding = PushEncoding::minimal(data.len());
+        Command::Push(data, encoding)
+    }
+
+    /// Pushes a number, using the small integer opcodes when possible
+    pub fn number(num: i64) -> Self {
+        match num {
+            -1 => Command::Op(Opcode::OP_1NEGATE),
+            0..=16 => Command::Op(Opcode::from_small_int(num as u8).unwrap()),
+            _ => Command::push(encode_num(num)),
+        }
+    }
+
+    /// Returns the data of a push, None for other opcodes
+    pub fn push_data(&self) -> Option<&[u8]> {
+        match self {
+            Command::Push(data, _) => Some(data),
+            Command::Op(_) => None,
+        }
+    }
+
+    /// Returns false for pushes that have a shorter form (BIP62 rule 3,
+    /// enforced under MINIMALDATA): the empty push and single bytes 1 to 16
+    /// and 0x81 have dedicated opcodes, and others must use the shortest
+    /// length encoding
+    pub fn is_minimal_push(&self) -> bool {
+        match self {
+            Command::Op(_) => true,
+            Command::Push(data, encoding) => match data.as_slice() {
+                // A direct empty push is the OP_0 byte itself
+                [] => *encoding == PushEncoding::Direct,
+                [1..=16] | [0x81] => false,
+                _ => *encoding == PushEncoding::minimal(data.len()),
+            },
+        }
+    }
+
+    /// Writes the command in script bytes. A push whose encoding can't hold
+    /// its length uses the next encoding that can.
+    fn serialize_into(&self, result: &mut Vec<u8>) {
+        match self {
+            Command::Op(op_code) => result.push(*op_code as u8),
+            Command::Push(data, encoding) => {
+                let length = data.len();
+                match (*encoding).max(PushEncoding::minimal(length)) {
+                    PushEncoding::Direct => result.push(length as u8),
+                    PushEncoding::PushData1 => {
+                        result.push(OP_PUSHDATA1 as u8);
+                        result.push(length as u8);
+                    }
+                    PushEncoding::PushData2 => {
+                        result.push(OP_PUSHDATA2 as u8);
+                        result.extend_from_slice(&(length as u16).to_le_bytes());
+                    }
+                    PushEncoding::PushData4 => {
+                        result.push(OP_PUSHDATA4 as u8);
+                        result.extend_from_slice(&(length as u32).to_le_bytes());
+                    }
+                }
+                result.extend_from_slice(data);
+            }
+        }
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Script {
-    cmds: Vec<Vec<u8>>,
+    cmds: Vec<Command>,
+    /// A push running past the end of the script, from its opcode. Such
+    /// bytes are valid in transactions and only fail when run.
+    truncated: Vec<u8>,
 }
 
 impl Script {
+    /// Creates a script from its commands
+    pub fn new(cmds: Vec<Command>) -> Self {
+        Script {
+            cmds,
+            truncated: vec![],
+        }
+    }
+
+    /// Returns the commands of the script, up to any truncated push
+    pub fn cmds(&self) -> &[Command] {
+        &self.cmds
+    }
+
+    /// Returns the bytes of the push that runs past the end of the script,
+    /// from its opcode, None if every push is complete
+    pub fn truncated_push(&self) -> Option<&[u8]> {
+        (!self.truncated.is_empty()).then_some(self.truncated.as_slice())
+    }
+
     /// Parses a script from a byte vector
-    pub fn parse(reader: &mut Cursor<Vec<u8>>) -> Result<Script, Error> {
+    pub fn parse(reader: &mut Cursor<Vec<u8>>) -> Result<Script, DecodeError> {
+        encode::decode_cursor(reader)
+    }
+
+    /// Parses script bytes given without the length prefix. Fails if a
+    /// push runs past the end, the offset being where the push starts.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Script, DecodeError> {
+        let script = Script::from_bytes_lossless(bytes);
+        match script.truncated_push() {
+            Some(push) => Err(DecodeError::new(
+                (bytes.len() - push.len()) as u64,
+                DecodeErrorKind::ScriptLengthMismatch,
+            )),
+            None => Ok(script),
+        }
+    }
+
+    /// Parses script bytes given without the length prefix, keeping a push
+    /// that runs past the end as its raw bytes, so that any bytes serialize
+    /// back unchanged. Such scripts fail with BadOpcode when run.
+    pub fn from_bytes_lossless(bytes: &[u8]) -> Script {
         let mut cmds = vec![];
-        let mut count = 0;
-        let length = read_varint(reader)?;
-        while count < length {
-            let mut current = [0u8; 1];
-            reader.read_exact(&mut current)?;
-            count += 1;
-            let current_byte = current[0];
-            if (1..=75).contains(&current_byte) {
-                let n = current_byte;
-                let mut cmd = vec![0u8; n as usize];
-                reader.read_exact(&mut cmd)?;
-                cmds.push(cmd);
-                count += n as u64;
-            } else if current_byte == 76 {
-                let data_length = read_varint(reader)?;
-                let mut cmd = vec![0u8; data_length as usize];
-                reader.read_exact(&mut cmd)?;
-                cmds.push(cmd);
-                count += data_length + 1;
-            } else if current_byte == 77 {
-                let data_length = read_varint(reader)?;
-                let mut cmd = vec![0u8; data_length as usize];
-                reader.read_exact(&mut cmd)?;
-                cmds.push(cmd);
-                count += data_length + 2;
+        let mut i = 0;
+        while i < bytes.len() {
+            let start = i;
+            let op_code = bytes[i];
+            i += 1;
+            let (size, encoding) = match Opcode::from(op_code) {
+                opcode if opcode.is_push_bytes() => (0, PushEncoding::Direct),
+                OP_PUSHDATA1 => (1, PushEncoding::PushData1),
+                OP_PUSHDATA2 => (2, PushEncoding::PushData2),
+                OP_PUSHDATA4 => (4, PushEncoding::PushData4),
+                opcode => {
+                    cmds.push(Command::Op(opcode));
+                    continue;
+                }
+            };
+            let length = if size == 0 {
+                Some(op_code as usize)
             } else {
-                let op_code = current_byte;
-                cmds.push(vec![op_code]);
-            }
-        }
-        if count != length {
-            return Err(Error::new(
-                std::io::ErrorKind::InvalidData,
-                "parsing script failed",
-            ));
+                read_le(bytes, &mut i, size)
+            };
+            let Some(data) = length.and_then(|length| bytes.get(i..i.saturating_add(length)))
+            else {
+                return Script {
+                    cmds,
+                    truncated: bytes[start..].to_vec(),
+                };
+            };
+            cmds.push(Command::Push(data.to_vec(), encoding));
+            i += data.len();
         }
-        Ok(Script { cmds })
+        Script::new(cmds)
     }
 
-    fn raw_serialize(&self) -> Vec<u8> {
+    /// Returns true if every push uses its shortest form
+    pub fn has_minimal_pushes(&self) -> bool {
+        self.cmds.iter().all(Command::is_minimal_push)
+    }
+
+    /// Serializes the script without the length prefix
+    pub fn raw_serialize(&self) -> Vec<u8> {
         let mut result = vec![];
         for cmd in &self.cmds {
-            if cmd.len() == 1 {
-                let op_code = cmd[0];
-                result.push(op_code);
-            } else {
-                let length = cmd.len();
-                if length < 76 {
-                    result.push(length as u8);
-                } else if length <= 0xff {
-                    result.push(76);
-                    result.push(length as u8);
-                } else if length <= 520 {
-                    result.push(77);
-                    result.extend_from_slice(&length.to_le_bytes()[..2]);
-                } else {
-                    panic!("too long a cmd");
-                }
-                result.extend_from_slice(cmd);
-            }
+            cmd.serialize_into(&mut result);
         }
+        result.extend_from_slice(&self.truncated);
         result
     }
 
     /// Serializes the script into a byte vector
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result = self.raw_serialize();
-        let total = result.len();
-        let mut length_bytes = vec![];
-        if total < 0xfd {
-            length_bytes.push(total as u8);
-        } else if total <= 0xffff {
-            length_bytes.push(0xfd);
-            length_bytes.extend_from_slice(&(total as u16).to_le_bytes());
-        } else {
-            length_bytes.push(0xfe);
-            length_bytes.extend_from_slice(&(total as u32).to_le_bytes());
-        }
-        length_bytes.append(&mut result);
-        length_bytes
+        encode::serialize(self)
+    }
+}
+
+/// Reads the little endian length of a OP_PUSHDATA opcode, None if the
+/// script ends first
+fn read_le(bytes: &[u8], i: &mut usize, size: usize) -> Option<usize> {
+    let length = bytes.get(*i..*i + size)?;
+    *i += size;
+    Some(
+        length
+            .iter()
+            .rev()
+            .fold(0, |acc, &byte| (acc << 8) | byte as usize),
+    )
+}
+
+/// A script is serialized with a length prefix
+impl Encodable for Script {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.raw_serialize().consensus_encode(writer)
+    }
+}
+
+/// Decoding is lossless: consensus only rejects truncated pushes when they
+/// run, so transactions may carry them in any script
+impl Decodable for Script {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let bytes = Vec::<u8>::consensus_decode(reader)?;
+        Ok(Script::from_bytes_lossless(&bytes))
     }
 }
 
 impl fmt::Display for Script {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let op_code_names = create_op_code_names();
         let mut result = String::new();
 
         for cmd in &self.cmds {
-            if cmd.len() == 1 {
-                let op_code = cmd[0];
-                result.push_str(op_code_names[&op_code]);
-            } else {
-                result.push_str(
-                    &cmd.iter()
-                        .map(|byte| format!("{:02x}", byte))
-                        .collect::<String>(),
-                );
+            match cmd {
+                Command::Op(op_code) => result.push_str(op_code.name()),
+                Command::Push(cmd, _) => {
+                    let hex = hex::encode(cmd);
+                    // Hex made only of digits would read back as a number
+                    if hex.bytes().all(|b| b.is_ascii_digit()) {
+                        result.push_str("0x");
+                    }
+                    result.push_str(&hex);
+                }
             }
             result.push(' ');
         }
+        // As Bitcoin Core shows a truncated push
+        if !self.truncated.is_empty() {
+            result.push_str("[error] ");
+        }
 
         write!(f, "{}", result)
     }
@@ -131,12 +289,12 @@ mod tests {
         let mut script_pubkey = Cursor::new(script_pubkey);
         let script = Script::parse(&mut script_pubkey).unwrap();
         let want = hex::decode("304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a71601").unwrap();
-        assert_eq!(script.cmds[0], want);
+        assert_eq!(script.cmds[0], Command::push(want));
 
         let want =
             hex::decode("035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937")
                 .unwrap();
-        assert_eq!(script.cmds[1], want);
+        assert_eq!(script.cmds[1], Command::push(want));
     }
 
     #[test]
@@ -147,4 +305,107 @@ mod tests {
         let script = Script::parse(&mut script_pubkey).unwrap();
         assert_eq!(hex::encode(script.serialize()), want);
     }
+
+    #[test]
+    fn test_serialize_long_push() {
+        // Beyond the 520 byte stack limit, but still serializable
+        let script = Script::new(vec![Command::push(vec![0xab; 0x10000])]);
+        let raw = script.raw_serialize();
+        assert_eq!(raw[..5], [0x4e, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(raw.len(), 5 + 0x10000);
+
+        let script = Script::new(vec![Command::push(vec![0xab; 600])]);
+        assert_eq!(script.raw_serialize()[..3], [0x4d, 0x58, 0x02]);
+    }
+
+    #[test]
+    fn test_pushdata() {
+        // OP_PUSHDATA1 and OP_PUSHDATA2 lengths are fixed-size, not varints
+        let raw = hex::decode("4c03aabbcc4d0200ddee4e01000000ff").unwrap();
+        let script = Script::from_bytes(&raw).unwrap();
+        assert_eq!(
+            script.cmds(),
+            [
+                Command::Push(vec![0xaa, 0xbb, 0xcc], PushEncoding::PushData1),
+                Command::Push(vec![0xdd, 0xee], PushEncoding::PushData2),
+                Command::Push(vec![0xff], PushEncoding::PushData4),
+            ]
+        );
+        // Non-minimal pushes keep their bytes
+        assert_eq!(script.raw_serialize(), raw);
+
+        let long = Command::push(vec![0xab; 0xfd]);
+        assert_eq!(
+            long,
+            Command::Push(vec![0xab; 0xfd], PushEncoding::PushData1)
+        );
+        let mut raw = vec![0x4c, 0xfd];
+        raw.extend([0xab; 0xfd]);
+        assert_eq!(Script::new(vec![long]).raw_serialize(), raw);
+    }
+
+    #[test]
+    fn test_minimal_push() {
+        assert!(Command::push(vec![0x02; 20]).is_minimal_push());
+        assert!(Command::push(vec![0x11]).is_minimal_push());
+        assert!(Command::Op(Opcode::OP_0).is_minimal_push());
+        assert!(!Command::push(vec![0x10]).is_minimal_push());
+        assert!(!Command::push(vec![0x81]).is_minimal_push());
+        assert!(!Command::Push(vec![], PushEncoding::PushData1).is_minimal_push());
+        assert!(!Command::Push(vec![0x02; 20], PushEncoding::PushData1).is_minimal_push());
+        assert!(!Command::Push(vec![0x02; 76], PushEncoding::PushData2).is_minimal_push());
+
+        let script = Script::from_bytes(&hex::decode("0111").unwrap()).unwrap();
+        assert!(script.has_minimal_pushes());
+        let script = Script::from_bytes(&hex::decode("4c0102").unwrap()).unwrap();
+        assert!(!script.has_minimal_pushes());
+    }
+
+    #[test]
+    fn test_display() {
+        let script = Script::from_bytes(&hex::decode("7eb0ba0211227ebbff").unwrap()).unwrap();
+        assert_eq!(
+            script.to_string(),
+            "OP_CAT OP_NOP1 OP_CHECKSIGADD 0x1122 OP_CAT OP_RETURN_187 OP_INVALIDOPCODE "
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // The push runs past the script length
+        for (raw, offset) in [("0205", 0), ("000001", 2), ("004d01", 1), ("4e0100", 0)] {
+            assert_eq!(
+                Script::from_bytes(&hex::decode(raw).unwrap()),
+                Err(DecodeError::new(
+                    offset,
+                    DecodeErrorKind::ScriptLengthMismatch
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncated_push() {
+        let raw = hex::decode("76a94c").unwrap();
+        let script = Script::from_bytes_lossless(&raw);
+        assert_eq!(
+            script.cmds(),
+            [Command::Op(Opcode::OP_DUP), Command::Op(Opcode::OP_HASH160)]
+        );
+        assert_eq!(script.truncated_push(), Some([0x4c].as_slice()));
+        assert_eq!(script.raw_serialize(), raw);
+        assert_eq!(script.to_string(), "OP_DUP OP_HASH160 [error] ");
+        assert!(!script.is_push_only());
+        assert!(Script::from_bytes_lossless(&[0x51])
+            .truncated_push()
+            .is_none());
+
+        // The length prefix bounds the script, the push can't read past it,
+        // and decoding keeps its bytes
+        let mut cursor = Cursor::new(vec![0x02, 0x05, 0x01, 0x02, 0x03, 0x04]);
+        let script = Script::parse(&mut cursor).unwrap();
+        assert_eq!(cursor.position(), 3);
+        assert_eq!(script.truncated_push(), Some([0x05, 0x01].as_slice()));
+        assert_eq!(script.serialize(), [0x02, 0x05, 0x01]);
+    }
 }
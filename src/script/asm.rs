@@ -0,0 +1,172 @@
+use std::{fmt, str::FromStr};
+
+use super::{opcode::Opcode, Command, Script};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseAsmError {
+    /// Neither an opcode name, a number, hex nor a quoted string
+    UnknownToken(String),
+    InvalidHex(String),
+    /// Script numbers are limited to 4 bytes, -2^31 excluded
+    NumberOutOfRange(String),
+    UnterminatedString,
+    /// OP_PUSHBYTES_n and OP_PUSHDATA1/2/4 are written as the pushed data
+    BarePushOpcode(Opcode),
+}
+
+impl fmt::Display for ParseAsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAsmError::UnknownToken(token) => write!(f, "unknown token {}", token),
+            ParseAsmError::InvalidHex(token) => write!(f, "invalid hex {}", token),
+            ParseAsmError::NumberOutOfRange(token) => {
+                write!(f, "number out of range: {}", token)
+            }
+            ParseAsmError::UnterminatedString => write!(f, "unterminated string"),
+            ParseAsmError::BarePushOpcode(opcode) => {
+                write!(f, "{} without its data", opcode)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseAsmError {}
+
+impl Script {
+    /// Parses the assembly written by Display. Tokens are separated by
+    /// whitespace and are one of:
+    /// * an opcode name, with or without the OP_ prefix, as in OP_DUP or DUP
+    /// * a decimal number, pushed as by Command::number
+    /// * hex data to push, optionally prefixed with 0x. Hex made only of
+    ///   digits needs the prefix, as it reads as a number otherwise.
+    /// * a string in single or double quotes, whose bytes are pushed
+    ///
+    /// Pushes use the shortest length encoding.
+    pub fn from_asm(asm: &str) -> Result<Script, ParseAsmError> {
+        let mut cmds = vec![];
+        let mut chars = asm.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            if c == '\'' || c == '"' {
+                chars.next();
+                let end = chars
+                    .find(|&(_, next)| next == c)
+                    .map(|(end, _)| end)
+                    .ok_or(ParseAsmError::UnterminatedString)?;
+                cmds.push(Command::push(&asm.as_bytes()[start + 1..end]));
+                continue;
+            }
+            let mut end = asm.len();
+            while let Some(&(i, next)) = chars.peek() {
+                if next.is_whitespace() {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            cmds.push(parse_token(&asm[start..end])?);
+        }
+        Ok(Script::new(cmds))
+    }
+}
+
+fn parse_token(token: &str) -> Result<Command, ParseAsmError> {
+    if let Some(hex) = token.strip_prefix("0x") {
+        let data = hex::decode(hex).map_err(|_| ParseAsmError::InvalidHex(token.to_string()))?;
+        return Ok(Command::push(data));
+    }
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return match token.parse::<i32>() {
+            Ok(num) if num != i32::MIN => Ok(Command::number(num.into())),
+            _ => Err(ParseAsmError::NumberOutOfRange(token.to_string())),
+        };
+    }
+    if let Ok(opcode) = token.parse::<Opcode>() {
+        if opcode.is_push_bytes()
+            || matches!(
+                opcode,
+                Opcode::OP_PUSHDATA1 | Opcode::OP_PUSHDATA2 | Opcode::OP_PUSHDATA4
+            )
+        {
+            return Err(ParseAsmError::BarePushOpcode(opcode));
+        }
+        return Ok(Command::Op(opcode));
+    }
+    match hex::decode(token) {
+        Ok(data) => Ok(Command::push(data)),
+        Err(_) => Err(ParseAsmError::UnknownToken(token.to_string())),
+    }
+}
+
+impl FromStr for Script {
+    type Err = ParseAsmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Script::from_asm(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::op::*;
+
+    use super::*;
+
+    #[test]
+    fn test_from_asm() {
+        let expected = Script::new(vec![
+            Command::Op(OP_DUP),
+            Command::Op(OP_HASH160),
+            Command::push(vec![0xab; 20]),
+            Command::Op(OP_EQUALVERIFY),
+            Command::Op(OP_CHECKSIG),
+        ]);
+        let asm = format!(
+            "OP_DUP HASH160 {} OP_EQUALVERIFY\n CHECKSIG",
+            "ab".repeat(20)
+        );
+        assert_eq!(Script::from_asm(&asm).unwrap(), expected);
+        assert_eq!(expected.to_string().parse::<Script>().unwrap(), expected);
+
+        let script = Script::from_asm("0 -1 16 17 -1000 0x0102 0xAB 'a b' \"\" OP_TRUE").unwrap();
+        assert_eq!(
+            hex::encode(script.raw_serialize()),
+            "004f60011102e88302010201ab036120620051"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Pushes made only of digits, a long push and opcodes that used to
+        // have no name
+        let raw = "0212340100011a7eb0ff4c4c".to_string() + &"ab".repeat(0x4c);
+        let script = Script::from_bytes(&hex::decode(&raw).unwrap()).unwrap();
+        assert_eq!(Script::from_asm(&script.to_string()).unwrap(), script);
+    }
+
+    #[test]
+    fn test_from_asm_errors() {
+        for (asm, err) in [
+            ("OP_FOO", ParseAsmError::UnknownToken("OP_FOO".to_string())),
+            ("abc", ParseAsmError::UnknownToken("abc".to_string())),
+            ("0x123", ParseAsmError::InvalidHex("0x123".to_string())),
+            (
+                "2147483648",
+                ParseAsmError::NumberOutOfRange("2147483648".to_string()),
+            ),
+            (
+                "-2147483648",
+                ParseAsmError::NumberOutOfRange("-2147483648".to_string()),
+            ),
+            ("DUP 'abc", ParseAsmError::UnterminatedString),
+            ("OP_PUSHDATA1", ParseAsmError::BarePushOpcode(OP_PUSHDATA1)),
+            ("PUSHBYTES_3", ParseAsmError::BarePushOpcode(OP_PUSHBYTES_3)),
+        ] {
+            assert_eq!(Script::from_asm(asm), Err(err));
+        }
+    }
+}
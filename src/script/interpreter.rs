@@ -0,0 +1,1680 @@
+pub mod checker;
+pub mod executor;
+#[cfg(test)]
+pub(crate) mod script_tests;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::{BitOr, BitOrAssign},
+    str::FromStr,
+};
+
+use num::BigUint;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ecc::{point::ECPoint, secp256k1_params::Secp256k1Params},
+    encode::VarInt,
+    transactions::{timelock::Sequence, tx::Locktime},
+    utils::tagged_hash::tagged_hash,
+};
+
+use super::{
+    num::ScriptNum,
+    op::{cast_to_bool, create_op_code_functions, encode_num, OpFunction, *},
+    Command, Script,
+};
+
+/// Scripts are at most 10,000 bytes, except in tapscript
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+/// Pushed data and stack elements are at most 520 bytes
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Opcodes above OP_16 executed by a script, except in tapscript
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// The stack and altstack hold at most 1,000 elements together
+pub const MAX_STACK_SIZE: usize = 1_000;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// Numeric operands are at most 4 bytes
+pub const MAX_NUM_SIZE: usize = 4;
+/// Except for the timelocks of OP_CHECKLOCKTIMEVERIFY and
+/// OP_CHECKSEQUENCEVERIFY, which can be 5 bytes
+pub const MAX_LOCKTIME_NUM_SIZE: usize = 5;
+/// Tapscript signatures use up this much of the validation weight budget
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+/// Added to the witness size to get the tapscript validation weight budget
+pub const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+const ANNEX_TAG: u8 = 0x50;
+const TAPROOT_LEAF_MASK: u8 = 0xfe;
+const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+/// A set of script verification rules. Besides the consensus rules, most
+/// are policy rules that nodes apply to the transactions they relay.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct VerifyFlags(u32);
+
+/// Defines the flags along with the names they have in Bitcoin Core
+macro_rules! verify_flags {
+    ($($(#[$doc:meta])* $name:ident = $bit:expr),* $(,)?) => {
+        impl VerifyFlags {
+            $($(#[$doc])* pub const $name: VerifyFlags = VerifyFlags(1 << $bit);)*
+
+            /// Every flag, with its name
+            pub(crate) const NAMES: &'static [(&'static str, VerifyFlags)] =
+                &[$((stringify!($name), VerifyFlags::$name)),*];
+        }
+    };
+}
+
+verify_flags! {
+    /// Evaluate P2SH redeem scripts (BIP16)
+    P2SH = 0,
+    /// Signatures must be strict DER with a defined sighash type, and
+    /// public keys compressed or uncompressed
+    STRICTENC = 1,
+    /// Signatures must be strict DER (BIP66)
+    DERSIG = 2,
+    /// Signatures must have S at most half the curve order
+    LOW_S = 3,
+    /// The extra element popped by OP_CHECKMULTISIG must be empty (BIP147)
+    NULLDUMMY = 4,
+    /// Script sigs must only push data
+    SIGPUSHONLY = 5,
+    /// Pushes and numbers must use their shortest form
+    MINIMALDATA = 6,
+    /// Fail on OP_NOP1 and OP_NOP4 to OP_NOP10, reserved for upgrades
+    DISCOURAGE_UPGRADABLE_NOPS = 7,
+    /// Exactly one element must be left on the stack
+    CLEANSTACK = 8,
+    /// Enforce OP_CHECKLOCKTIMEVERIFY (BIP65)
+    CHECKLOCKTIMEVERIFY = 9,
+    /// Enforce OP_CHECKSEQUENCEVERIFY (BIP112)
+    CHECKSEQUENCEVERIFY = 10,
+    /// Evaluate witness programs (BIP141)
+    WITNESS = 11,
+    /// Fail on witness versions reserved for upgrades
+    DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM = 12,
+    /// The argument of OP_IF and OP_NOTIF must be empty or 0x01 in segwit
+    MINIMALIF = 13,
+    /// Failed signature checks must have empty signatures
+    NULLFAIL = 14,
+    /// Public keys must be compressed in segwit v0
+    WITNESS_PUBKEYTYPE = 15,
+    /// OP_CODESEPARATOR and signatures found by FindAndDelete fail in
+    /// legacy scripts
+    CONST_SCRIPTCODE = 16,
+    /// Evaluate taproot outputs (BIP341 and BIP342)
+    TAPROOT = 17,
+    /// Fail on tapscript public keys of unknown types
+    DISCOURAGE_UPGRADABLE_PUBKEYTYPE = 18,
+    /// Fail on tapscript OP_SUCCESS opcodes
+    DISCOURAGE_OP_SUCCESS = 19,
+    /// Fail on unknown taproot leaf versions
+    DISCOURAGE_UPGRADABLE_TAPROOT_VERSION = 20,
+}
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+
+    /// The rules enforced by consensus
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+
+    /// The rules Bitcoin Core applies to transactions it relays
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::CONSENSUS.0
+            | Self::STRICTENC.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::MINIMALIF.0
+            | Self::NULLFAIL.0
+            | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if all the flags of other are set
+    pub fn contains(self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: VerifyFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: VerifyFlags) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, rhs: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, rhs: VerifyFlags) {
+        self.insert(rhs);
+    }
+}
+
+/// Writes the names of the flags separated by commas, or NONE
+impl fmt::Display for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = VerifyFlags::NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            write!(f, "NONE")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseVerifyFlagsError(pub String);
+
+impl fmt::Display for ParseVerifyFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown verify flag {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseVerifyFlagsError {}
+
+/// Parses flag names separated by commas, as in the Bitcoin Core test
+/// vectors. An empty string or NONE is no flags.
+impl FromStr for VerifyFlags {
+    type Err = ParseVerifyFlagsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = VerifyFlags::NONE;
+        for name in s.split(',').map(str::trim) {
+            if name.is_empty() || name == "NONE" {
+                continue;
+            }
+            let (_, flag) = VerifyFlags::NAMES
+                .iter()
+                .find(|(flag_name, _)| *flag_name == name)
+                .ok_or_else(|| ParseVerifyFlagsError(name.to_string()))?;
+            flags.insert(*flag);
+        }
+        Ok(flags)
+    }
+}
+
+/// Defines the errors along with their Bitcoin Core names and messages
+macro_rules! script_errors {
+    ($($(#[$doc:meta])* $variant:ident => ($code:expr, $message:expr)),* $(,)?) => {
+        /// Why a script failed
+        #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+        pub enum ScriptError {
+            $($(#[$doc])* $variant,)*
+        }
+
+        impl ScriptError {
+            /// The name of the error in the Bitcoin Core test vectors
+            pub fn code(self) -> &'static str {
+                match self {
+                    $(ScriptError::$variant => $code,)*
+                }
+            }
+        }
+
+        impl fmt::Display for ScriptError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(ScriptError::$variant => write!(f, $message),)*
+                }
+            }
+        }
+    };
+}
+
+script_errors! {
+    EvalFalse => ("EVAL_FALSE", "script evaluated without error but finished with a false/empty top stack element"),
+    OpReturn => ("OP_RETURN", "OP_RETURN was encountered"),
+    ScriptSize => ("SCRIPT_SIZE", "script is too big"),
+    PushSize => ("PUSH_SIZE", "push value size limit exceeded"),
+    OpCount => ("OP_COUNT", "operation limit exceeded"),
+    StackSize => ("STACK_SIZE", "stack size limit exceeded"),
+    SigCount => ("SIG_COUNT", "signature count negative or greater than pubkey count"),
+    PubkeyCount => ("PUBKEY_COUNT", "pubkey count negative or limit exceeded"),
+    Verify => ("VERIFY", "script failed an OP_VERIFY operation"),
+    EqualVerify => ("EQUALVERIFY", "script failed an OP_EQUALVERIFY operation"),
+    CheckMultisigVerify => ("CHECKMULTISIGVERIFY", "script failed an OP_CHECKMULTISIGVERIFY operation"),
+    CheckSigVerify => ("CHECKSIGVERIFY", "script failed an OP_CHECKSIGVERIFY operation"),
+    NumEqualVerify => ("NUMEQUALVERIFY", "script failed an OP_NUMEQUALVERIFY operation"),
+    BadOpcode => ("BAD_OPCODE", "opcode missing or not understood"),
+    DisabledOpcode => ("DISABLED_OPCODE", "attempted to use a disabled opcode"),
+    InvalidStackOperation => ("INVALID_STACK_OPERATION", "operation not valid with the current stack size"),
+    InvalidAltstackOperation => ("INVALID_ALTSTACK_OPERATION", "operation not valid with the current altstack size"),
+    UnbalancedConditional => ("UNBALANCED_CONDITIONAL", "invalid OP_IF construction"),
+    NegativeLocktime => ("NEGATIVE_LOCKTIME", "negative locktime"),
+    UnsatisfiedLocktime => ("UNSATISFIED_LOCKTIME", "locktime requirement not satisfied"),
+    SigHashType => ("SIG_HASHTYPE", "signature hash type missing or not understood"),
+    SigDer => ("SIG_DER", "non-canonical DER signature"),
+    MinimalData => ("MINIMALDATA", "data push larger than necessary"),
+    SigPushOnly => ("SIG_PUSHONLY", "only push operators allowed in signatures"),
+    SigHighS => ("SIG_HIGH_S", "non-canonical signature: S value is unnecessarily high"),
+    SigNullDummy => ("SIG_NULLDUMMY", "dummy CHECKMULTISIG argument must be zero"),
+    PubkeyType => ("PUBKEYTYPE", "public key is neither compressed or uncompressed"),
+    CleanStack => ("CLEANSTACK", "stack size must be exactly one after execution"),
+    MinimalIf => ("MINIMALIF", "OP_IF/NOTIF argument must be minimal"),
+    SigNullFail => ("NULLFAIL", "signature must be zero for failed CHECK(MULTI)SIG operation"),
+    DiscourageUpgradableNops => ("DISCOURAGE_UPGRADABLE_NOPS", "NOPx reserved for soft-fork upgrades"),
+    DiscourageUpgradableWitnessProgram => ("DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", "witness version reserved for soft-fork upgrades"),
+    DiscourageUpgradableTaprootVersion => ("DISCOURAGE_UPGRADABLE_TAPROOT_VERSION", "taproot version reserved for soft-fork upgrades"),
+    DiscourageOpSuccess => ("DISCOURAGE_OP_SUCCESS", "OP_SUCCESSx reserved for soft-fork upgrades"),
+    DiscourageUpgradablePubkeyType => ("DISCOURAGE_UPGRADABLE_PUBKEYTYPE", "public key version reserved for soft-fork upgrades"),
+    WitnessProgramWrongLength => ("WITNESS_PROGRAM_WRONG_LENGTH", "witness program has incorrect length"),
+    WitnessProgramWitnessEmpty => ("WITNESS_PROGRAM_WITNESS_EMPTY", "witness program was passed an empty witness"),
+    WitnessProgramMismatch => ("WITNESS_PROGRAM_MISMATCH", "witness program hash mismatch"),
+    WitnessMalleated => ("WITNESS_MALLEATED", "witness requires empty scriptSig"),
+    WitnessMalleatedP2sh => ("WITNESS_MALLEATED_P2SH", "witness requires only-redeemscript scriptSig"),
+    WitnessUnexpected => ("WITNESS_UNEXPECTED", "witness provided for non-witness script"),
+    WitnessPubkeyType => ("WITNESS_PUBKEYTYPE", "using non-compressed keys in segwit"),
+    SchnorrSigSize => ("SCHNORR_SIG_SIZE", "invalid Schnorr signature size"),
+    SchnorrSigHashtype => ("SCHNORR_SIG_HASHTYPE", "invalid Schnorr signature hash type"),
+    SchnorrSig => ("SCHNORR_SIG", "invalid Schnorr signature"),
+    TaprootWrongControlSize => ("TAPROOT_WRONG_CONTROL_SIZE", "invalid taproot control block size"),
+    TapscriptValidationWeight => ("TAPSCRIPT_VALIDATION_WEIGHT", "too much signature validation relative to witness weight"),
+    TapscriptCheckMultisig => ("TAPSCRIPT_CHECKMULTISIG", "OP_CHECKMULTISIG(VERIFY) is not available in tapscript"),
+    TapscriptMinimalIf => ("TAPSCRIPT_MINIMALIF", "OP_IF/NOTIF argument must be minimal in tapscript"),
+    OpCodeSeparator => ("OP_CODESEPARATOR", "using OP_CODESEPARATOR in non-witness script"),
+    SigFindAndDelete => ("SIG_FINDANDDELETE", "signature is found in scriptCode"),
+    /// A numeric operand is longer than allowed. Bitcoin Core reports this
+    /// as an unknown error.
+    NumOverflow => ("UNKNOWN_ERROR", "script number overflow"),
+    /// A numeric operand has extra zero bytes under MINIMALDATA, also an
+    /// unknown error for Bitcoin Core
+    NumNotMinimal => ("UNKNOWN_ERROR", "non-minimally encoded script number"),
+}
+
+impl std::error::Error for ScriptError {}
+
+/// The rules a script is run under, which depend on where it comes from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SigVersion {
+    /// Script sigs, script pubkeys and P2SH redeem scripts
+    Base,
+    /// P2WPKH and P2WSH scripts
+    WitnessV0,
+    /// Taproot key path spends
+    Taproot,
+    /// Taproot script path spends
+    Tapscript,
+}
+
+/// Data of a taproot spend that signatures commit to
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExecData {
+    pub tapleaf_hash: Option<[u8; 32]>,
+    /// Position of the last executed OP_CODESEPARATOR, u32::MAX if none
+    pub codesep_pos: u32,
+    pub annex: Option<Vec<u8>>,
+    pub validation_weight_left: i64,
+}
+
+impl Default for ExecData {
+    fn default() -> Self {
+        ExecData {
+            tapleaf_hash: None,
+            codesep_pos: u32::MAX,
+            annex: None,
+            validation_weight_left: 0,
+        }
+    }
+}
+
+/// Checks what scripts can't check on their own: signatures and timelocks,
+/// which depend on the spending transaction. The defaults fail every
+/// check.
+pub trait SignatureChecker {
+    /// Checks an ECDSA signature, which ends with its sighash type byte,
+    /// against the script code
+    fn check_ecdsa_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _script_code: &Script,
+        _sig_version: SigVersion,
+    ) -> bool {
+        false
+    }
+
+    /// Checks a 64 or 65 byte Schnorr signature against an x-only key
+    fn check_schnorr_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _sig_version: SigVersion,
+        _exec_data: &ExecData,
+    ) -> Result<(), ScriptError> {
+        Err(ScriptError::SchnorrSig)
+    }
+
+    /// Returns true if the transaction locktime satisfies OP_CHECKLOCKTIMEVERIFY
+    fn check_locktime(&self, _locktime: &Locktime) -> bool {
+        false
+    }
+
+    /// Returns true if the input sequence satisfies OP_CHECKSEQUENCEVERIFY
+    fn check_sequence(&self, _sequence: Sequence) -> bool {
+        false
+    }
+}
+
+/// A checker for scripts run outside a transaction
+pub struct NoChecker;
+
+impl SignatureChecker for NoChecker {}
+
+/// Script properties the interpreter relies on
+impl Script {
+    /// Returns true if the script only pushes data, OP_RESERVED included as
+    /// in Bitcoin Core. A truncated push isn't one.
+    pub fn is_push_only(&self) -> bool {
+        self.truncated_push().is_none()
+            && self.cmds().iter().all(|cmd| match cmd {
+                Command::Op(op) => *op <= OP_16,
+                Command::Push(..) => true,
+            })
+    }
+
+    /// Removes the pushes of the data in its shortest length encoding, as
+    /// legacy signature checks do with signatures. Returns the number of
+    /// pushes removed.
+    pub fn find_and_delete(&mut self, data: &[u8]) -> usize {
+        let mut pattern = vec![];
+        Command::push(data).serialize_into(&mut pattern);
+        let before = self.cmds.len();
+        self.cmds.retain(|cmd| {
+            let mut bytes = vec![];
+            cmd.serialize_into(&mut bytes);
+            bytes != pattern
+        });
+        before - self.cmds.len()
+    }
+}
+
+/// Runs the script on the stack, failing with the first rule broken
+pub fn eval_script(
+    stack: &mut Vec<Vec<u8>>,
+    script: &Script,
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    sig_version: SigVersion,
+    exec_data: &mut ExecData,
+) -> Result<(), ScriptError> {
+    if is_oversized(script.raw_serialize().len(), sig_version) {
+        return Err(ScriptError::ScriptSize);
+    }
+    let mut interpreter = Interpreter::new(
+        script,
+        flags,
+        checker,
+        sig_version,
+        std::mem::take(exec_data),
+    );
+    let result = interpreter.run(stack);
+    *exec_data = interpreter.exec_data;
+    result
+}
+
+/// Returns true if the script is over the size limit that applies to it
+fn is_oversized(script_size: usize, sig_version: SigVersion) -> bool {
+    script_size > MAX_SCRIPT_SIZE && matches!(sig_version, SigVersion::Base | SigVersion::WitnessV0)
+}
+
+/// Runs script bytes that may not parse. Like Bitcoin Core, the commands
+/// before a truncated push run before failing with BadOpcode.
+fn eval_bytes(
+    stack: &mut Vec<Vec<u8>>,
+    bytes: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    sig_version: SigVersion,
+    exec_data: &mut ExecData,
+) -> Result<(), ScriptError> {
+    let script = Script::from_bytes_lossless(bytes);
+    eval_script(stack, &script, flags, checker, sig_version, exec_data)
+}
+
+struct Interpreter<'a> {
+    flags: VerifyFlags,
+    checker: &'a dyn SignatureChecker,
+    sig_version: SigVersion,
+    exec_data: ExecData,
+    cmds: &'a [Command],
+    /// Whether a truncated push follows the commands
+    truncated: bool,
+    /// Index of the next command to run
+    pc: usize,
+    /// Whether each enclosing OP_IF or OP_NOTIF branch runs, commands only
+    /// running if all of them do
+    exec_stack: Vec<bool>,
+    altstack: Vec<Vec<u8>>,
+    op_count: usize,
+    /// Index of the first command after the last executed OP_CODESEPARATOR,
+    /// where the script code legacy and segwit v0 signatures commit to
+    /// starts
+    code_start: usize,
+    op_code_functions: HashMap<Opcode, OpFunction>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(
+        script: &'a Script,
+        flags: VerifyFlags,
+        checker: &'a dyn SignatureChecker,
+        sig_version: SigVersion,
+        exec_data: ExecData,
+    ) -> Self {
+        Interpreter {
+            flags,
+            checker,
+            sig_version,
+            exec_data,
+            cmds: script.cmds(),
+            truncated: script.truncated_push().is_some(),
+            pc: 0,
+            exec_stack: vec![],
+            altstack: vec![],
+            op_count: 0,
+            code_start: 0,
+            op_code_functions: create_op_code_functions(),
+        }
+    }
+
+    fn run(&mut self, stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+        while self.pc < self.cmds.len() {
+            self.step(stack)?;
+        }
+        self.finish()
+    }
+
+    /// Checks the state at the end of the script
+    fn finish(&self) -> Result<(), ScriptError> {
+        // Reading the truncated push fails whether or not it would run
+        if self.truncated {
+            return Err(ScriptError::BadOpcode);
+        }
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(())
+    }
+
+    /// Runs the next command. Commands in branches that don't run are
+    /// still checked against the size, count and disabled opcode rules.
+    fn step(&mut self, stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+        let position = self.pc;
+        let cmd = &self.cmds[position];
+        self.pc += 1;
+        let executing = !self.exec_stack.contains(&false);
+        match cmd {
+            Command::Push(data, _) => {
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if self.flags.contains(VerifyFlags::MINIMALDATA) && !cmd.is_minimal_push() {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    stack.push(data.clone());
+                }
+            }
+            Command::Op(op) => {
+                let op = *op;
+                if self.sig_version != SigVersion::Tapscript && op > OP_16 {
+                    self.op_count += 1;
+                    if self.op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
+                    }
+                }
+                if op.is_disabled() {
+                    return Err(ScriptError::DisabledOpcode);
+                }
+                if op == OP_CODESEPARATOR
+                    && self.sig_version == SigVersion::Base
+                    && self.flags.contains(VerifyFlags::CONST_SCRIPTCODE)
+                {
+                    return Err(ScriptError::OpCodeSeparator);
+                }
+                // Conditionals run even in branches that don't, to track
+                // where the branches end
+                if executing || (OP_IF..=OP_ENDIF).contains(&op) {
+                    self.execute(op, position as u32, stack)?;
+                }
+            }
+        }
+        if stack.len() + self.altstack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        op: Opcode,
+        position: u32,
+        stack: &mut Vec<Vec<u8>>,
+    ) -> Result<(), ScriptError> {
+        match op {
+            OP_IF | OP_NOTIF => return self.op_if(stack, op),
+            OP_ELSE => {
+                let last = self
+                    .exec_stack
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *last = !*last;
+                return Ok(());
+            }
+            OP_ENDIF => {
+                self.exec_stack
+                    .pop()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                return Ok(());
+            }
+            OP_NOP1 | OP_NOP4 | OP_NOP5 | OP_NOP6 | OP_NOP7 | OP_NOP8 | OP_NOP9 | OP_NOP10 => {
+                return self.upgradable_nop();
+            }
+            // Not discouraged without their flags, so that dropping a flag
+            // never invalidates a script
+            OP_CHECKLOCKTIMEVERIFY if !self.flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) => {
+                return Ok(());
+            }
+            OP_CHECKSEQUENCEVERIFY if !self.flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) => {
+                return Ok(());
+            }
+            OP_CODESEPARATOR => {
+                self.code_start = position as usize + 1;
+                self.exec_data.codesep_pos = position;
+                return Ok(());
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => return self.op_checksig(stack, op),
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => return self.op_checkmultisig(stack, op),
+            OP_CHECKSIGADD => return self.op_checksigadd(stack),
+            _ => {}
+        }
+        match self.op_code_functions.get(&op) {
+            Some(OpFunction::StackOp(f) | OpFunction::StackHashOp(f)) => f(stack),
+            Some(OpFunction::StackAltStackOp(f)) => f(stack, &mut self.altstack),
+            Some(OpFunction::StackNumOp(f)) => f(stack, self.require_minimal()),
+            Some(OpFunction::StackCheckerOp(f)) => f(stack, self.checker, self.require_minimal()),
+            None => Err(ScriptError::BadOpcode),
+        }
+    }
+
+    /// Pushes whether the branch runs onto the condition stack. In segwit
+    /// the argument must be empty or 0x01, always so in tapscript and under
+    /// MINIMALIF in segwit v0.
+    fn op_if(&mut self, stack: &mut Vec<Vec<u8>>, op: Opcode) -> Result<(), ScriptError> {
+        let mut value = false;
+        if !self.exec_stack.contains(&false) {
+            let element = stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+            if element.len() > 1 || (element.len() == 1 && element[0] != 1) {
+                if self.sig_version == SigVersion::Tapscript {
+                    return Err(ScriptError::TapscriptMinimalIf);
+                }
+                if self.sig_version == SigVersion::WitnessV0
+                    && self.flags.contains(VerifyFlags::MINIMALIF)
+                {
+                    return Err(ScriptError::MinimalIf);
+                }
+            }
+            value = cast_to_bool(&element) == (op == OP_IF);
+        }
+        self.exec_stack.push(value);
+        Ok(())
+    }
+
+    fn require_minimal(&self) -> bool {
+        self.flags.contains(VerifyFlags::MINIMALDATA)
+    }
+
+    fn upgradable_nop(&self) -> Result<(), ScriptError> {
+        if self.flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+            return Err(ScriptError::DiscourageUpgradableNops);
+        }
+        Ok(())
+    }
+
+    /// The script code a legacy or segwit v0 signature commits to. Legacy
+    /// signatures can't sign themselves, so they are removed from it.
+    fn signature_script_code(&self, sigs: &[&[u8]]) -> Result<Script, ScriptError> {
+        let mut script_code = Script::new(self.cmds[self.code_start..].to_vec());
+        if self.sig_version == SigVersion::Base {
+            for sig in sigs {
+                let found = script_code.find_and_delete(sig);
+                if found > 0 && self.flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                    return Err(ScriptError::SigFindAndDelete);
+                }
+            }
+        }
+        Ok(script_code)
+    }
+
+    fn check_signature_encoding(&self, sig: &[u8]) -> Result<(), ScriptError> {
+        // An empty signature is a compact way to fail a check
+        if sig.is_empty() {
+            return Ok(());
+        }
+        let strict_flags = VerifyFlags::DERSIG | VerifyFlags::LOW_S | VerifyFlags::STRICTENC;
+        if self.flags.0 & strict_flags.0 != 0 && !is_valid_signature_encoding(sig) {
+            return Err(ScriptError::SigDer);
+        }
+        if self.flags.contains(VerifyFlags::LOW_S) && !is_low_der_signature(sig) {
+            return Err(ScriptError::SigHighS);
+        }
+        if self.flags.contains(VerifyFlags::STRICTENC) && !is_defined_hashtype(sig) {
+            return Err(ScriptError::SigHashType);
+        }
+        Ok(())
+    }
+
+    fn check_pubkey_encoding(&self, pubkey: &[u8]) -> Result<(), ScriptError> {
+        if self.flags.contains(VerifyFlags::STRICTENC)
+            && !is_compressed_or_uncompressed_pubkey(pubkey)
+        {
+            return Err(ScriptError::PubkeyType);
+        }
+        if self.flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE)
+            && self.sig_version == SigVersion::WitnessV0
+            && !is_compressed_pubkey(pubkey)
+        {
+            return Err(ScriptError::WitnessPubkeyType);
+        }
+        Ok(())
+    }
+
+    /// Checks an ECDSA signature, applying the encoding rules
+    fn check_ecdsa(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &Script,
+    ) -> Result<bool, ScriptError> {
+        self.check_signature_encoding(sig)?;
+        self.check_pubkey_encoding(pubkey)?;
+        Ok(self
+            .checker
+            .check_ecdsa_signature(sig, pubkey, script_code, self.sig_version))
+    }
+
+    /// Checks a tapscript signature. An empty signature fails without
+    /// using up validation weight, and unknown key types succeed.
+    fn check_tapscript_sig(&mut self, sig: &[u8], pubkey: &[u8]) -> Result<bool, ScriptError> {
+        let success = !sig.is_empty();
+        if success {
+            self.exec_data.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+            if self.exec_data.validation_weight_left < 0 {
+                return Err(ScriptError::TapscriptValidationWeight);
+            }
+        }
+        match pubkey.len() {
+            0 => return Err(ScriptError::PubkeyType),
+            32 => {
+                if success {
+                    self.checker.check_schnorr_signature(
+                        sig,
+                        pubkey,
+                        self.sig_version,
+                        &self.exec_data,
+                    )?;
+                }
+            }
+            _ => {
+                if self
+                    .flags
+                    .contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE)
+                {
+                    return Err(ScriptError::DiscourageUpgradablePubkeyType);
+                }
+            }
+        }
+        Ok(success)
+    }
+
+    fn op_checksig(&mut self, stack: &mut Vec<Vec<u8>>, op: Opcode) -> Result<(), ScriptError> {
+        if stack.len() < 2 {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        let pubkey = stack.pop().unwrap();
+        let sig = stack.pop().unwrap();
+        let success = match self.sig_version {
+            SigVersion::Base | SigVersion::WitnessV0 => {
+                let script_code = self.signature_script_code(&[&sig])?;
+                let success = self.check_ecdsa(&sig, &pubkey, &script_code)?;
+                if !success && self.flags.contains(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+                    return Err(ScriptError::SigNullFail);
+                }
+                success
+            }
+            SigVersion::Tapscript => self.check_tapscript_sig(&sig, &pubkey)?,
+            SigVersion::Taproot => unreachable!("key path spends run no script"),
+        };
+        if op == OP_CHECKSIGVERIFY {
+            if !success {
+                return Err(ScriptError::CheckSigVerify);
+            }
+        } else {
+            stack.push(encode_num(success as i64));
+        }
+        Ok(())
+    }
+
+    fn op_checksigadd(&mut self, stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+        if self.sig_version != SigVersion::Tapscript {
+            return Err(ScriptError::BadOpcode);
+        }
+        if stack.len() < 3 {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        let pubkey = stack.pop().unwrap();
+        let num = ScriptNum::decode(&stack.pop().unwrap(), self.require_minimal(), MAX_NUM_SIZE)?;
+        let sig = stack.pop().unwrap();
+        let success = self.check_tapscript_sig(&sig, &pubkey)?;
+        stack.push((num + success.into()).encode());
+        Ok(())
+    }
+
+    /// <dummy> <sigs...> m <pubkeys...> n OP_CHECKMULTISIG, the signatures
+    /// being matched to the keys in order
+    fn op_checkmultisig(
+        &mut self,
+        stack: &mut Vec<Vec<u8>>,
+        op: Opcode,
+    ) -> Result<(), ScriptError> {
+        if self.sig_version == SigVersion::Tapscript {
+            return Err(ScriptError::TapscriptCheckMultisig);
+        }
+        let top = |stack: &Vec<Vec<u8>>, i: usize| stack[stack.len() - i].clone();
+        let require_minimal = self.require_minimal();
+        let number = |element: Vec<u8>| {
+            ScriptNum::decode(&element, require_minimal, MAX_NUM_SIZE).map(ScriptNum::value)
+        };
+
+        let mut i = 1;
+        if stack.len() < i {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        let mut keys_count = number(top(stack, i))?;
+        if keys_count < 0 || keys_count as usize > MAX_PUBKEYS_PER_MULTISIG {
+            return Err(ScriptError::PubkeyCount);
+        }
+        self.op_count += keys_count as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptError::OpCount);
+        }
+        i += 1;
+        let mut ikey = i;
+        // The key index past which failed signatures must be empty under
+        // NULLFAIL
+        let mut ikey2 = keys_count as usize + 2;
+        i += keys_count as usize;
+        if stack.len() < i {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        let mut sigs_count = number(top(stack, i))?;
+        if sigs_count < 0 || sigs_count > keys_count {
+            return Err(ScriptError::SigCount);
+        }
+        i += 1;
+        let mut isig = i;
+        i += sigs_count as usize;
+        if stack.len() < i {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+
+        let sigs = (0..sigs_count as usize)
+            .map(|k| top(stack, isig + k))
+            .collect::<Vec<_>>();
+        let script_code =
+            self.signature_script_code(&sigs.iter().map(Vec::as_slice).collect::<Vec<_>>())?;
+
+        let mut success = true;
+        while success && sigs_count > 0 {
+            let sig = top(stack, isig);
+            let pubkey = top(stack, ikey);
+            if self.check_ecdsa(&sig, &pubkey, &script_code)? {
+                isig += 1;
+                sigs_count -= 1;
+            }
+            ikey += 1;
+            keys_count -= 1;
+            // Fail early if there are more signatures left than keys
+            if sigs_count > keys_count {
+                success = false;
+            }
+        }
+
+        // Pop everything but the dummy element
+        while i > 1 {
+            i -= 1;
+            if !success
+                && self.flags.contains(VerifyFlags::NULLFAIL)
+                && ikey2 == 0
+                && !stack[stack.len() - 1].is_empty()
+            {
+                return Err(ScriptError::SigNullFail);
+            }
+            ikey2 = ikey2.saturating_sub(1);
+            stack.pop();
+        }
+        let dummy = stack.pop().ok_or(ScriptError::InvalidStackOperation)?;
+        if self.flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+            return Err(ScriptError::SigNullDummy);
+        }
+
+        if op == OP_CHECKMULTISIGVERIFY {
+            if !success {
+                return Err(ScriptError::CheckMultisigVerify);
+            }
+        } else {
+            stack.push(encode_num(success as i64));
+        }
+        Ok(())
+    }
+}
+
+/// Checks the strict DER encoding of BIP66, followed by a sighash type byte:
+/// 0x30 [total length] 0x02 [R length] [R] 0x02 [S length] [S] [sighash]
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    // R and S are positive integers without leading zero bytes, unless the
+    // next byte would make them negative
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+/// Returns true if S is at most half the curve order, for a signature with
+/// a valid encoding
+fn is_low_der_signature(sig: &[u8]) -> bool {
+    if !is_valid_signature_encoding(sig) {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    let len_s = sig[5 + len_r] as usize;
+    let s = BigUint::from_bytes_be(&sig[len_r + 6..len_r + 6 + len_s]);
+    s <= Secp256k1Params::n() / 2u32
+}
+
+/// SIGHASH_ALL, SIGHASH_NONE or SIGHASH_SINGLE, with or without
+/// SIGHASH_ANYONECANPAY
+fn is_defined_hashtype(sig: &[u8]) -> bool {
+    match sig.last() {
+        Some(hashtype) => matches!(hashtype & !0x80, 1..=3),
+        None => false,
+    }
+}
+
+fn is_compressed_or_uncompressed_pubkey(pubkey: &[u8]) -> bool {
+    match pubkey.first() {
+        Some(0x04) => pubkey.len() == 65,
+        Some(0x02 | 0x03) => pubkey.len() == 33,
+        _ => false,
+    }
+}
+
+fn is_compressed_pubkey(pubkey: &[u8]) -> bool {
+    matches!(pubkey.first(), Some(0x02 | 0x03)) && pubkey.len() == 33
+}
+
+/// Verifies that the script sig and witness satisfy the script pubkey,
+/// evaluating P2SH redeem scripts and witness programs as the flags
+/// enable
+pub fn verify_script(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> Result<(), ScriptError> {
+    verify_raw_script(
+        &script_sig.raw_serialize(),
+        &script_pubkey.raw_serialize(),
+        witness,
+        flags,
+        checker,
+    )
+}
+
+/// Same as verify_script, for scripts given as bytes that may not parse.
+/// Such scripts fail with BadOpcode once execution reaches the truncated
+/// push, as in Bitcoin Core.
+pub fn verify_raw_script(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> Result<(), ScriptError> {
+    let sig_push_only = Script::from_bytes(script_sig).is_ok_and(|script| script.is_push_only());
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !sig_push_only {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    let mut stack = vec![];
+    let base = SigVersion::Base;
+    eval_bytes(
+        &mut stack,
+        script_sig,
+        flags,
+        checker,
+        base,
+        &mut ExecData::default(),
+    )?;
+    let mut p2sh_stack = stack.clone();
+    eval_bytes(
+        &mut stack,
+        script_pubkey,
+        flags,
+        checker,
+        base,
+        &mut ExecData::default(),
+    )?;
+    if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+        return Err(ScriptError::EvalFalse);
+    }
+
+    let pubkey_script = Script::from_bytes(script_pubkey).ok();
+    let mut had_witness = false;
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let Some((version, program)) = pubkey_script
+            .as_ref()
+            .and_then(|script| script.as_witness_program())
+        {
+            had_witness = true;
+            if !script_sig.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(witness, version, program, flags, checker, false)?;
+            // The witness stack was checked for cleanstack already
+            stack.truncate(1);
+        }
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && pubkey_script.as_ref().is_some_and(Script::is_p2sh) {
+        if !sig_push_only {
+            return Err(ScriptError::SigPushOnly);
+        }
+        std::mem::swap(&mut stack, &mut p2sh_stack);
+        let serialized = stack
+            .pop()
+            .expect("the script sig pushed what the script pubkey hashed");
+        eval_bytes(
+            &mut stack,
+            &serialized,
+            flags,
+            checker,
+            base,
+            &mut ExecData::default(),
+        )?;
+        if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+            return Err(ScriptError::EvalFalse);
+        }
+
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let Ok(redeem_script) = Script::from_bytes(&serialized) {
+                if let Some((version, program)) = redeem_script.as_witness_program() {
+                    had_witness = true;
+                    let expected = Script::new(vec![Command::push(serialized.clone())]);
+                    if script_sig != expected.raw_serialize() {
+                        return Err(ScriptError::WitnessMalleatedP2sh);
+                    }
+                    verify_witness_program(witness, version, program, flags, checker, true)?;
+                    stack.truncate(1);
+                }
+            }
+        }
+    }
+
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+    Ok(())
+}
+
+fn verify_witness_program(
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    is_p2sh: bool,
+) -> Result<(), ScriptError> {
+    let mut exec_data = ExecData::default();
+    match (version, program.len()) {
+        (0, 32) => {
+            let (script_bytes, stack) = witness
+                .split_last()
+                .ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            if Sha256::digest(script_bytes).as_slice() != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(
+                stack.to_vec(),
+                script_bytes,
+                flags,
+                checker,
+                SigVersion::WitnessV0,
+                &mut exec_data,
+            )
+        }
+        (0, 20) => {
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            let script = Script::p2pkh(program.try_into().unwrap());
+            execute_witness_script(
+                witness.to_vec(),
+                &script.raw_serialize(),
+                flags,
+                checker,
+                SigVersion::WitnessV0,
+                &mut exec_data,
+            )
+        }
+        (0, _) => Err(ScriptError::WitnessProgramWrongLength),
+        (1, 32) if !is_p2sh => {
+            if !flags.contains(VerifyFlags::TAPROOT) {
+                return Ok(());
+            }
+            verify_taproot(witness, program, flags, checker, &mut exec_data)
+        }
+        _ => {
+            if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+                return Err(ScriptError::DiscourageUpgradableWitnessProgram);
+            }
+            // Unknown witness programs are left for future soft forks
+            Ok(())
+        }
+    }
+}
+
+/// Verifies a key path spend, or a script path spend whose leaf the output
+/// key commits to (BIP341)
+fn verify_taproot(
+    witness: &[Vec<u8>],
+    program: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    exec_data: &mut ExecData,
+) -> Result<(), ScriptError> {
+    if witness.is_empty() {
+        return Err(ScriptError::WitnessProgramWitnessEmpty);
+    }
+    let mut stack = witness.to_vec();
+    if stack.len() >= 2 && stack.last().unwrap().first() == Some(&ANNEX_TAG) {
+        exec_data.annex = stack.pop();
+    }
+    if stack.len() == 1 {
+        return checker.check_schnorr_signature(&stack[0], program, SigVersion::Taproot, exec_data);
+    }
+
+    let control = stack.pop().unwrap();
+    let script_bytes = stack.pop().unwrap();
+    if control.len() < TAPROOT_CONTROL_BASE_SIZE
+        || !(control.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+        || (control.len() - TAPROOT_CONTROL_BASE_SIZE) / TAPROOT_CONTROL_NODE_SIZE
+            > TAPROOT_CONTROL_MAX_NODE_COUNT
+    {
+        return Err(ScriptError::TaprootWrongControlSize);
+    }
+    let leaf_version = control[0] & TAPROOT_LEAF_MASK;
+    let mut leaf = vec![leaf_version];
+    leaf.extend(crate::encode::serialize(&script_bytes));
+    let tapleaf_hash = tagged_hash("TapLeaf", &leaf);
+    if !verify_taproot_commitment(&control, program, &tapleaf_hash) {
+        return Err(ScriptError::WitnessProgramMismatch);
+    }
+    exec_data.tapleaf_hash = Some(tapleaf_hash);
+
+    if leaf_version == TAPROOT_LEAF_TAPSCRIPT {
+        let witness_size = VarInt(witness.len() as u64).size()
+            + witness
+                .iter()
+                .map(|item| VarInt(item.len() as u64).size() + item.len())
+                .sum::<usize>();
+        exec_data.validation_weight_left = witness_size as i64 + VALIDATION_WEIGHT_OFFSET;
+        return execute_witness_script(
+            stack,
+            &script_bytes,
+            flags,
+            checker,
+            SigVersion::Tapscript,
+            exec_data,
+        );
+    }
+    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+        return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+    }
+    Ok(())
+}
+
+/// Returns true if the output key is the internal key of the control block
+/// tweaked with the merkle root of the leaf and its path
+fn verify_taproot_commitment(control: &[u8], program: &[u8], tapleaf_hash: &[u8; 32]) -> bool {
+    let internal_key = match ECPoint::from_xonly(&control[1..TAPROOT_CONTROL_BASE_SIZE]) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let mut node = *tapleaf_hash;
+    for sibling in control[TAPROOT_CONTROL_BASE_SIZE..].chunks(TAPROOT_CONTROL_NODE_SIZE) {
+        let mut msg = vec![];
+        if node.as_slice() < sibling {
+            msg.extend_from_slice(&node);
+            msg.extend_from_slice(sibling);
+        } else {
+            msg.extend_from_slice(sibling);
+            msg.extend_from_slice(&node);
+        }
+        node = tagged_hash("TapBranch", &msg);
+    }
+    match internal_key.taproot_tweak(Some(&node)) {
+        Ok(output_key) => {
+            output_key.to_xonly() == program && output_key.has_even_y() == (control[0] & 1 == 0)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Runs a witness script, which must leave exactly one true element
+fn execute_witness_script(
+    mut stack: Vec<Vec<u8>>,
+    script_bytes: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+    sig_version: SigVersion,
+    exec_data: &mut ExecData,
+) -> Result<(), ScriptError> {
+    if sig_version == SigVersion::Tapscript {
+        // OP_SUCCESS opcodes make the script succeed before it runs
+        if contains_op_success(script_bytes)? {
+            if flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+                return Err(ScriptError::DiscourageOpSuccess);
+            }
+            return Ok(());
+        }
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+    }
+    if stack
+        .iter()
+        .any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(ScriptError::PushSize);
+    }
+    eval_bytes(
+        &mut stack,
+        script_bytes,
+        flags,
+        checker,
+        sig_version,
+        exec_data,
+    )?;
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if !cast_to_bool(&stack[0]) {
+        return Err(ScriptError::EvalFalse);
+    }
+    Ok(())
+}
+
+/// Scans the script for OP_SUCCESS opcodes, failing if a push is truncated
+/// before one is found
+fn contains_op_success(script_bytes: &[u8]) -> Result<bool, ScriptError> {
+    let script = Script::from_bytes_lossless(script_bytes);
+    if script
+        .cmds()
+        .iter()
+        .any(|cmd| matches!(cmd, Command::Op(op) if op.is_success()))
+    {
+        return Ok(true);
+    }
+    match script.truncated_push() {
+        Some(_) => Err(ScriptError::BadOpcode),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts the signature 0x01 for any key, and locktimes from 2^31,
+    /// which take 5 bytes
+    struct TestChecker;
+
+    impl SignatureChecker for TestChecker {
+        fn check_ecdsa_signature(
+            &self,
+            sig: &[u8],
+            _pubkey: &[u8],
+            _script_code: &Script,
+            _sig_version: SigVersion,
+        ) -> bool {
+            sig == [0x01]
+        }
+
+        fn check_locktime(&self, locktime: &Locktime) -> bool {
+            locktime.value() >= 1 << 31
+        }
+    }
+
+    fn run(asm: &str, flags: VerifyFlags) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let mut stack = vec![];
+        let script = Script::from_asm(asm).unwrap();
+        eval_script(
+            &mut stack,
+            &script,
+            flags,
+            &TestChecker,
+            SigVersion::Base,
+            &mut ExecData::default(),
+        )?;
+        Ok(stack)
+    }
+
+    fn verify(
+        script_sig: &str,
+        script_pubkey: &str,
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        verify_script(
+            &Script::from_asm(script_sig).unwrap(),
+            &Script::from_asm(script_pubkey).unwrap(),
+            &[],
+            flags,
+            &TestChecker,
+        )
+    }
+
+    #[test]
+    fn test_flags() {
+        let flags: VerifyFlags = "P2SH, STRICTENC,WITNESS".parse().unwrap();
+        assert_eq!(
+            flags,
+            VerifyFlags::P2SH | VerifyFlags::STRICTENC | VerifyFlags::WITNESS
+        );
+        assert_eq!(flags.to_string(), "P2SH,STRICTENC,WITNESS");
+        assert_eq!("".parse(), Ok(VerifyFlags::NONE));
+        assert_eq!(VerifyFlags::NONE.to_string(), "NONE");
+        assert_eq!(
+            "P2SH,FOO".parse::<VerifyFlags>(),
+            Err(ParseVerifyFlagsError("FOO".to_string()))
+        );
+        assert!(VerifyFlags::STANDARD.contains(VerifyFlags::CONSENSUS));
+        assert!(!VerifyFlags::CONSENSUS.contains(VerifyFlags::CLEANSTACK));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run("5 3 SUB", VerifyFlags::NONE), Ok(vec![vec![2]]));
+        assert_eq!(run("2 3 LESSTHAN", VerifyFlags::NONE), Ok(vec![vec![1]]));
+        assert_eq!(run("3 2 4 WITHIN", VerifyFlags::NONE), Ok(vec![vec![1]]));
+        assert_eq!(run("4 2 4 WITHIN", VerifyFlags::NONE), Ok(vec![vec![]]));
+        assert_eq!(
+            run("7 8 9 2 PICK", VerifyFlags::NONE),
+            Ok(vec![vec![7], vec![8], vec![9], vec![7]])
+        );
+        assert_eq!(
+            run("7 8 9 2 ROLL", VerifyFlags::NONE),
+            Ok(vec![vec![8], vec![9], vec![7]])
+        );
+        assert_eq!(
+            run("7 8 2 PICK", VerifyFlags::NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+        assert_eq!(
+            run("0x0000000001 1ADD", VerifyFlags::NONE),
+            Err(ScriptError::NumOverflow)
+        );
+        // Results can be 5 bytes, but not operands
+        assert_eq!(
+            run("0xffffff7f DUP ADD", VerifyFlags::NONE),
+            Ok(vec![vec![0xfe, 0xff, 0xff, 0xff, 0]])
+        );
+        assert_eq!(
+            run("0xffffff7f DUP ADD 1ADD", VerifyFlags::NONE),
+            Err(ScriptError::NumOverflow)
+        );
+        assert_eq!(
+            run("0x0100 1ADD", VerifyFlags::MINIMALDATA),
+            Err(ScriptError::NumNotMinimal)
+        );
+        assert_eq!(run("0x0100 1ADD", VerifyFlags::NONE), Ok(vec![vec![2]]));
+        // Negative zero is false
+        assert_eq!(
+            run("0x80 VERIFY", VerifyFlags::NONE),
+            Err(ScriptError::Verify)
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let ops = "NOP ".repeat(MAX_OPS_PER_SCRIPT);
+        assert!(run(&ops, VerifyFlags::NONE).is_ok());
+        assert_eq!(
+            run(&(ops + "NOP"), VerifyFlags::NONE),
+            Err(ScriptError::OpCount)
+        );
+
+        let push = format!("0x{}", "00".repeat(MAX_SCRIPT_ELEMENT_SIZE + 1));
+        assert_eq!(run(&push, VerifyFlags::NONE), Err(ScriptError::PushSize));
+
+        let pushes = "1 ".repeat(MAX_STACK_SIZE);
+        assert!(run(&pushes, VerifyFlags::NONE).is_ok());
+        assert_eq!(
+            run(&(pushes + "TOALTSTACK 1 1"), VerifyFlags::NONE),
+            Err(ScriptError::StackSize)
+        );
+
+        let script = Script::new(vec![Command::push(vec![0; 500]); 20]);
+        let mut stack = vec![];
+        assert_eq!(
+            eval_script(
+                &mut stack,
+                &script,
+                VerifyFlags::NONE,
+                &NoChecker,
+                SigVersion::Base,
+                &mut ExecData::default()
+            ),
+            Err(ScriptError::ScriptSize)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for (asm, flags, err) in [
+            ("1 2 CAT", VerifyFlags::NONE, ScriptError::DisabledOpcode),
+            ("RESERVED", VerifyFlags::NONE, ScriptError::BadOpcode),
+            ("1 RETURN", VerifyFlags::NONE, ScriptError::OpReturn),
+            (
+                "ENDIF",
+                VerifyFlags::NONE,
+                ScriptError::UnbalancedConditional,
+            ),
+            (
+                "1 IF",
+                VerifyFlags::NONE,
+                ScriptError::UnbalancedConditional,
+            ),
+            (
+                "FROMALTSTACK",
+                VerifyFlags::NONE,
+                ScriptError::InvalidAltstackOperation,
+            ),
+            (
+                "1 2 EQUALVERIFY",
+                VerifyFlags::NONE,
+                ScriptError::EqualVerify,
+            ),
+            ("0x01", VerifyFlags::MINIMALDATA, ScriptError::MinimalData),
+            (
+                "NOP1",
+                VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS,
+                ScriptError::DiscourageUpgradableNops,
+            ),
+            (
+                "-1 CHECKLOCKTIMEVERIFY",
+                VerifyFlags::CHECKLOCKTIMEVERIFY,
+                ScriptError::NegativeLocktime,
+            ),
+            (
+                "1 CHECKLOCKTIMEVERIFY",
+                VerifyFlags::CHECKLOCKTIMEVERIFY,
+                ScriptError::UnsatisfiedLocktime,
+            ),
+            (
+                "CODESEPARATOR",
+                VerifyFlags::CONST_SCRIPTCODE,
+                ScriptError::OpCodeSeparator,
+            ),
+            ("0 0 CHECKSIGADD", VerifyFlags::NONE, ScriptError::BadOpcode),
+        ] {
+            assert_eq!(run(asm, flags), Err(err), "{}", asm);
+        }
+        // Without its flag OP_CHECKLOCKTIMEVERIFY is a NOP, not a discouraged one
+        assert!(run("-1 CHECKLOCKTIMEVERIFY", VerifyFlags::NONE).is_ok());
+        let flags = VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS;
+        assert!(run("-1 CHECKLOCKTIMEVERIFY", flags).is_ok());
+        assert!(run("-1 CHECKSEQUENCEVERIFY", flags).is_ok());
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY;
+        assert!(run("0x0000008000 CHECKLOCKTIMEVERIFY", flags).is_ok());
+        assert_eq!(
+            run("0x000000800000 CHECKLOCKTIMEVERIFY", flags),
+            Err(ScriptError::NumOverflow)
+        );
+        assert_eq!(ScriptError::EvalFalse.code(), "EVAL_FALSE");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        for (asm, expected) in [
+            ("1 IF 2 ELSE 3 ENDIF", vec![vec![2]]),
+            ("0 IF 2 ELSE 3 ENDIF", vec![vec![3]]),
+            ("0 NOTIF 2 ELSE 3 ENDIF", vec![vec![2]]),
+            ("1 0 IF 0 IF 2 ENDIF ELSE 3 ENDIF", vec![vec![1], vec![3]]),
+            // ELSE toggles every time
+            ("1 IF 2 ELSE 3 ELSE 4 ENDIF", vec![vec![2], vec![4]]),
+            // Pushed bytes aren't opcodes
+            ("0 IF 0x6367 ENDIF 0x6768", vec![vec![0x67, 0x68]]),
+        ] {
+            assert_eq!(run(asm, VerifyFlags::NONE), Ok(expected), "{}", asm);
+        }
+        for (asm, err) in [
+            ("IF 1 ENDIF", ScriptError::UnbalancedConditional),
+            ("1 IF 1", ScriptError::UnbalancedConditional),
+            ("1 ELSE", ScriptError::UnbalancedConditional),
+            ("1 IF ENDIF ENDIF", ScriptError::UnbalancedConditional),
+            // Checked in branches that don't run
+            ("0 IF 2 2 MUL ENDIF", ScriptError::DisabledOpcode),
+            ("0 IF VERIF ENDIF", ScriptError::BadOpcode),
+        ] {
+            assert_eq!(run(asm, VerifyFlags::NONE), Err(err), "{}", asm);
+        }
+        assert_eq!(
+            run("0 IF RESERVED ENDIF 1", VerifyFlags::NONE),
+            Ok(vec![vec![1]])
+        );
+
+        // MINIMALIF applies in segwit only
+        let script = Script::from_asm("IF 1 ENDIF").unwrap();
+        let flags = VerifyFlags::MINIMALIF;
+        for (sig_version, err) in [
+            (SigVersion::Base, None),
+            (SigVersion::WitnessV0, Some(ScriptError::MinimalIf)),
+            (SigVersion::Tapscript, Some(ScriptError::TapscriptMinimalIf)),
+        ] {
+            let mut stack = vec![vec![2]];
+            let result = eval_script(
+                &mut stack,
+                &script,
+                flags,
+                &NoChecker,
+                sig_version,
+                &mut ExecData::default(),
+            );
+            assert_eq!(result.err(), err);
+        }
+    }
+
+    #[test]
+    fn test_checksig() {
+        let pubkey = format!("0x02{}", "11".repeat(32));
+        let script_pubkey = format!("{} CHECKSIG", pubkey);
+        assert_eq!(verify("0x01", &script_pubkey, VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            verify("0x02", &script_pubkey, VerifyFlags::NONE),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify("0x02", &script_pubkey, VerifyFlags::NULLFAIL),
+            Err(ScriptError::SigNullFail)
+        );
+        assert_eq!(
+            verify("0x01", &script_pubkey, VerifyFlags::DERSIG),
+            Err(ScriptError::SigDer)
+        );
+
+        let multisig = format!("2 {} {} 2 CHECKMULTISIG", pubkey, pubkey);
+        assert_eq!(verify("0 0x01 0x01", &multisig, VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            verify("1 0x01 0x01", &multisig, VerifyFlags::NULLDUMMY),
+            Err(ScriptError::SigNullDummy)
+        );
+        assert_eq!(
+            verify("0 0x01 0x02", &multisig, VerifyFlags::NONE),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify("0x01 0x01", &multisig, VerifyFlags::NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+    }
+
+    #[test]
+    fn test_verify_script() {
+        assert_eq!(verify("1", "", VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            verify("0", "", VerifyFlags::NONE),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify("1 NOP", "", VerifyFlags::SIGPUSHONLY),
+            Err(ScriptError::SigPushOnly)
+        );
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS | VerifyFlags::CLEANSTACK;
+        assert_eq!(verify("1 1", "", flags), Err(ScriptError::CleanStack));
+
+        // P2SH of OP_2 OP_EQUAL, spent with 2 and with 3
+        let redeem_script = Script::from_asm("2 EQUAL").unwrap();
+        let script_pubkey = Script::p2sh(&crate::utils::hash160::hash160(
+            &redeem_script.raw_serialize(),
+        ))
+        .to_string();
+        let push = format!("0x{}", hex::encode(redeem_script.raw_serialize()));
+        assert_eq!(
+            verify(&format!("2 {}", push), &script_pubkey, flags),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&format!("3 {}", push), &script_pubkey, flags),
+            Err(ScriptError::EvalFalse)
+        );
+        // Without the P2SH flag only the hash is checked
+        assert_eq!(
+            verify(&format!("3 {}", push), &script_pubkey, VerifyFlags::NONE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_witness() {
+        let witness_script = Script::from_asm("2 EQUAL").unwrap();
+        let hash: [u8; 32] = Sha256::digest(witness_script.raw_serialize()).into();
+        let script_pubkey = Script::p2wsh(&hash);
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        let verify_witness = |script_sig: &str, witness: &[Vec<u8>]| {
+            verify_script(
+                &Script::from_asm(script_sig).unwrap(),
+                &script_pubkey,
+                witness,
+                flags,
+                &NoChecker,
+            )
+        };
+
+        let script_bytes = witness_script.raw_serialize();
+        assert_eq!(verify_witness("", &[vec![2], script_bytes.clone()]), Ok(()));
+        assert_eq!(
+            verify_witness("", &[vec![3], script_bytes.clone()]),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify_witness("", &[vec![2], vec![2], script_bytes.clone()]),
+            Err(ScriptError::CleanStack)
+        );
+        assert_eq!(
+            verify_witness("", &[vec![2], vec![0x52, 0x87, 0x87]]),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        assert_eq!(
+            verify_witness("", &[]),
+            Err(ScriptError::WitnessProgramWitnessEmpty)
+        );
+        assert_eq!(
+            verify_witness("1", &[vec![2], script_bytes]),
+            Err(ScriptError::WitnessMalleated)
+        );
+        assert_eq!(
+            verify_script(
+                &Script::from_asm("1").unwrap(),
+                &Script::new(vec![]),
+                &[vec![1]],
+                flags,
+                &NoChecker
+            ),
+            Err(ScriptError::WitnessUnexpected)
+        );
+    }
+}
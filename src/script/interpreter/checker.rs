@@ -0,0 +1,148 @@
+use num::BigUint;
+
+use crate::{
+    amount::Amount,
+    ecc::{point::ECPoint, signature::Signature, EccError},
+    script::{op::OP_CODESEPARATOR, Command, Script},
+    transactions::{
+        output::TxOutput,
+        timelock::Sequence,
+        tx::{Locktime, Tx},
+    },
+};
+
+use super::{ExecData, ScriptError, SigVersion, SignatureChecker};
+
+/// Checks signatures and timelocks against an input of a transaction
+pub struct TransactionSignatureChecker<'a> {
+    tx: &'a Tx,
+    input_index: usize,
+    /// The amount of the output spent, which segwit signatures commit to
+    amount: Amount,
+    /// The outputs spent by every input, which taproot signatures commit to
+    prevouts: Option<&'a [TxOutput]>,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    pub fn new(tx: &'a Tx, input_index: usize, amount: Amount) -> Self {
+        TransactionSignatureChecker {
+            tx,
+            input_index,
+            amount,
+            prevouts: None,
+        }
+    }
+
+    /// Sets the outputs spent by the inputs, without which taproot
+    /// signatures fail
+    pub fn prevouts(mut self, prevouts: &'a [TxOutput]) -> Self {
+        self.prevouts = Some(prevouts);
+        self
+    }
+
+    fn input_sequence(&self) -> Sequence {
+        Sequence::from(self.tx.get_inputs()[self.input_index].get_sequence())
+    }
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_ecdsa_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &Script,
+        sig_version: SigVersion,
+    ) -> bool {
+        let Some((&hashtype, der)) = sig.split_last() else {
+            return false;
+        };
+        let (Ok(key), Ok(signature)) = (parse_pubkey(pubkey), Signature::parse_lax(der)) else {
+            return false;
+        };
+        let z = match sig_version {
+            SigVersion::Base => {
+                // Legacy signature hashes leave out OP_CODESEPARATOR
+                let script_code = Script::new(
+                    script_code
+                        .cmds()
+                        .iter()
+                        .filter(|cmd| **cmd != Command::Op(OP_CODESEPARATOR))
+                        .cloned()
+                        .collect(),
+                );
+                self.tx
+                    .sig_hash(self.input_index, &script_code, hashtype as u32)
+            }
+            SigVersion::WitnessV0 => self.tx.sig_hash_segwit_v0(
+                self.input_index,
+                script_code,
+                self.amount,
+                hashtype as u32,
+            ),
+            SigVersion::Taproot | SigVersion::Tapscript => return false,
+        };
+        z.is_ok_and(|z| key.verify(&BigUint::from_bytes_be(&z), &signature))
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        _sig_version: SigVersion,
+        exec_data: &ExecData,
+    ) -> Result<(), ScriptError> {
+        // 64 byte signatures are SIGHASH_DEFAULT, which can't be explicit
+        let (sig, hashtype) = match sig.len() {
+            64 => (sig, 0),
+            65 if sig[64] != 0 => (&sig[..64], sig[64]),
+            65 => return Err(ScriptError::SchnorrSigHashtype),
+            _ => return Err(ScriptError::SchnorrSigSize),
+        };
+        let prevouts = self.prevouts.ok_or(ScriptError::SchnorrSigHashtype)?;
+        let msg = self
+            .tx
+            .sig_hash_taproot_with_exec_data(self.input_index, prevouts, hashtype as u32, exec_data)
+            .map_err(|_| ScriptError::SchnorrSigHashtype)?;
+        match ECPoint::from_xonly(pubkey) {
+            Ok(key) if key.verify_schnorr(&msg, sig) => Ok(()),
+            _ => Err(ScriptError::SchnorrSig),
+        }
+    }
+
+    fn check_locktime(&self, locktime: &Locktime) -> bool {
+        // The locktime of a transaction whose inputs are all final isn't
+        // enforced, so this one must not be
+        locktime.is_implied_by(&self.tx.get_locktime()) && !self.input_sequence().is_final()
+    }
+
+    fn check_sequence(&self, sequence: Sequence) -> bool {
+        // Relative locktimes only apply from version 2
+        if self.tx.get_version() < 2 {
+            return false;
+        }
+        match (
+            sequence.relative_locktime(),
+            self.input_sequence().relative_locktime(),
+        ) {
+            (Some(required), Some(locktime)) => required.is_implied_by(&locktime),
+            _ => false,
+        }
+    }
+}
+
+/// Parses a public key in SEC format, also accepting the hybrid keys
+/// consensus allows: uncompressed keys whose prefix 0x06 or 0x07 tells
+/// the parity of y
+fn parse_pubkey(pubkey: &[u8]) -> Result<ECPoint, EccError> {
+    match pubkey {
+        [prefix @ (0x06 | 0x07), coordinates @ ..] if coordinates.len() == 64 => {
+            if coordinates[63] & 1 != prefix & 1 {
+                return Err(EccError::InvalidSec);
+            }
+            let mut uncompressed = vec![0x04];
+            uncompressed.extend_from_slice(coordinates);
+            ECPoint::parse(&uncompressed)
+        }
+        _ => ECPoint::parse(pubkey),
+    }
+}
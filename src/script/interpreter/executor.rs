@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use super::{
+    is_oversized, ExecData, Interpreter, ScriptError, SigVersion, SignatureChecker, VerifyFlags,
+};
+use crate::script::{
+    op::{create_op_code_names, Opcode},
+    Command, Script,
+};
+
+/// Runs a script one command at a time, so that the state of the
+/// interpreter can be looked at between commands
+pub struct ScriptExecutor<'a> {
+    interpreter: Interpreter<'a>,
+    stack: Vec<Vec<u8>>,
+    names: HashMap<Opcode, &'static str>,
+    /// Index of the last command run
+    current: Option<usize>,
+    /// Set once the script has finished or failed
+    result: Option<Result<(), ScriptError>>,
+}
+
+impl<'a> ScriptExecutor<'a> {
+    /// Prepares to run the script on the stack
+    pub fn new(
+        script: &'a Script,
+        stack: Vec<Vec<u8>>,
+        flags: VerifyFlags,
+        checker: &'a dyn SignatureChecker,
+        sig_version: SigVersion,
+    ) -> Self {
+        let result = is_oversized(script.raw_serialize().len(), sig_version)
+            .then_some(Err(ScriptError::ScriptSize));
+        ScriptExecutor {
+            interpreter: Interpreter::new(script, flags, checker, sig_version, ExecData::default()),
+            stack,
+            names: create_op_code_names(),
+            current: None,
+            result,
+        }
+    }
+
+    /// Sets the taproot data signatures commit to
+    pub fn exec_data(mut self, exec_data: ExecData) -> Self {
+        self.interpreter.exec_data = exec_data;
+        self
+    }
+
+    /// Runs the next command. Returns false once the script has finished,
+    /// and the error it failed with from then on.
+    pub fn step(&mut self) -> Result<bool, ScriptError> {
+        if let Some(result) = self.result {
+            return result.map(|_| false);
+        }
+        if self.interpreter.pc == self.interpreter.cmds.len() {
+            let result = self.interpreter.finish();
+            self.result = Some(result);
+            return result.map(|_| false);
+        }
+        self.current = Some(self.interpreter.pc);
+        match self.interpreter.step(&mut self.stack) {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                self.result = Some(Err(err));
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs the rest of the script
+    pub fn run(&mut self) -> Result<(), ScriptError> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Returns true once the script has finished or failed
+    pub fn is_done(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// The outcome of the script, None while it runs
+    pub fn result(&self) -> Option<Result<(), ScriptError>> {
+        self.result
+    }
+
+    pub fn stack(&self) -> &[Vec<u8>] {
+        &self.stack
+    }
+
+    pub fn altstack(&self) -> &[Vec<u8>] {
+        &self.interpreter.altstack
+    }
+
+    /// Whether each enclosing OP_IF or OP_NOTIF branch runs, innermost last
+    pub fn condition_stack(&self) -> &[bool] {
+        &self.interpreter.exec_stack
+    }
+
+    /// Opcodes counted against the 201 opcode limit so far
+    pub fn op_count(&self) -> usize {
+        self.interpreter.op_count
+    }
+
+    /// Index of the last command run, None before the first step
+    pub fn position(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// The last command run, opcodes by name and pushes in hex
+    pub fn current_command(&self) -> Option<String> {
+        let cmd = &self.interpreter.cmds[self.current?];
+        Some(match cmd {
+            Command::Op(op) => self.names[op].to_string(),
+            Command::Push(data, _) => format!("0x{}", hex::encode(data)),
+        })
+    }
+
+    /// Runs the rest of the script, returning the state after each command
+    /// and the outcome as JSON. A failing command is recorded with its
+    /// error and the state it left.
+    pub fn trace(mut self) -> Value {
+        let mut steps = vec![];
+        loop {
+            let pending = !self.is_done() && self.interpreter.pc < self.interpreter.cmds.len();
+            let result = self.step();
+            if !pending {
+                break;
+            }
+            let mut step = self.state();
+            if let Err(err) = result {
+                step["error"] = json!(err.code());
+                steps.push(step);
+                break;
+            }
+            steps.push(step);
+        }
+        let result = self.result.expect("the script has finished");
+        json!({
+            "steps": steps,
+            "success": result.is_ok(),
+            "error": result.err().map(ScriptError::code),
+            "message": result.err().map(|err| err.to_string()),
+        })
+    }
+
+    fn state(&self) -> Value {
+        let to_hex = |stack: &[Vec<u8>]| stack.iter().map(hex::encode).collect::<Vec<_>>();
+        json!({
+            "position": self.current,
+            "command": self.current_command(),
+            "stack": to_hex(self.stack()),
+            "altstack": to_hex(self.altstack()),
+            "condition_stack": self.condition_stack(),
+            "op_count": self.op_count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::interpreter::NoChecker;
+
+    use super::*;
+
+    fn new_executor(script: &Script) -> ScriptExecutor<'_> {
+        ScriptExecutor::new(
+            script,
+            vec![],
+            VerifyFlags::NONE,
+            &NoChecker,
+            SigVersion::Base,
+        )
+    }
+
+    #[test]
+    fn test_step() {
+        let script = Script::from_asm("1 TOALTSTACK 0 IF 2 ENDIF 3 DUP").unwrap();
+        let mut executor = new_executor(&script);
+        assert_eq!(executor.current_command(), None);
+
+        assert_eq!(executor.step(), Ok(true));
+        assert_eq!(executor.current_command(), Some("OP_1".to_string()));
+        assert_eq!(executor.stack(), [vec![1]]);
+
+        assert_eq!(executor.step(), Ok(true));
+        assert_eq!(executor.stack(), Vec::<Vec<u8>>::new());
+        assert_eq!(executor.altstack(), [vec![1]]);
+        assert_eq!(executor.op_count(), 1);
+
+        executor.step().unwrap();
+        executor.step().unwrap();
+        assert_eq!(executor.current_command(), Some("OP_IF".to_string()));
+        assert_eq!(executor.condition_stack(), [false]);
+        executor.step().unwrap();
+        assert_eq!(executor.stack(), Vec::<Vec<u8>>::new());
+        executor.step().unwrap();
+        assert_eq!(executor.condition_stack(), Vec::<bool>::new());
+
+        assert_eq!(executor.run(), Ok(()));
+        assert!(executor.is_done());
+        assert_eq!(executor.position(), Some(7));
+        assert_eq!(executor.stack(), [vec![3], vec![3]]);
+        assert_eq!(executor.step(), Ok(false));
+    }
+
+    #[test]
+    fn test_step_errors() {
+        let script = Script::from_asm("1 IF 2").unwrap();
+        let mut executor = new_executor(&script);
+        assert_eq!(executor.run(), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(executor.step(), Err(ScriptError::UnbalancedConditional));
+
+        let script = Script::new(vec![Command::Op(Opcode::OP_NOP); 10_001]);
+        let mut executor = new_executor(&script);
+        assert_eq!(executor.step(), Err(ScriptError::ScriptSize));
+        assert_eq!(executor.position(), None);
+    }
+
+    #[test]
+    fn test_trace() {
+        let script = Script::from_asm("2 3 ADD 0x0101 EQUALVERIFY").unwrap();
+        let trace = new_executor(&script).trace();
+        assert_eq!(trace["success"], false);
+        assert_eq!(trace["error"], "EQUALVERIFY");
+        let steps = trace["steps"].as_array().unwrap();
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[2]["command"], "OP_ADD");
+        assert_eq!(steps[2]["stack"], json!(["05"]));
+        assert_eq!(steps[4]["command"], "OP_EQUALVERIFY");
+        assert_eq!(steps[4]["error"], "EQUALVERIFY");
+        assert_eq!(steps[3]["op_count"], 1);
+
+        let script = Script::from_asm("1").unwrap();
+        let trace = new_executor(&script).trace();
+        assert_eq!(trace["success"], true);
+        assert_eq!(trace["error"], Value::Null);
+        assert_eq!(trace["steps"][0]["stack"], json!(["01"]));
+    }
+}
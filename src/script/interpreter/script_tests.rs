@@ -0,0 +1,173 @@
+//! Runs script_tests.json, script test vectors in the format of Bitcoin
+//! Core's: each test is
+//! `[[witness..., amount]?, script_sig, script_pubkey, flags, error, comment?]`
+//! and entries of a single string are comments. The file is not the upstream
+//! one yet, see its first comment. Once it is, this doc records the Bitcoin
+//! Core commit it was taken from, and KNOWN_FAILURES lists the vectors this
+//! crate doesn't pass.
+
+use serde_json::Value;
+
+use crate::{
+    amount::Amount,
+    script::{op::OP_0, opcode::Opcode, Command, Script},
+    transactions::{
+        hashes::Txid,
+        input::{OutPoint, TxInput},
+        output::TxOutput,
+        tx::Tx,
+    },
+};
+
+use super::{checker::TransactionSignatureChecker, verify_raw_script, ScriptError, VerifyFlags};
+
+const SCRIPT_TESTS: &str = include_str!("script_tests.json");
+
+const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+
+/// Vectors expected to fail, as their JSON, with the reason. Listed vectors
+/// must still fail, so that a fix also removes its entry.
+const KNOWN_FAILURES: &[(&str, &str)] = &[];
+
+/// Parses the script notation of the Bitcoin Core test vectors into script
+/// bytes. Unlike Script::from_asm, hex is inserted as is rather than pushed,
+/// so that the vectors can write any bytes, truncated pushes included.
+/// Tokens are separated by whitespace and are one of:
+/// * a decimal number, pushed as by Command::number
+/// * 0x followed by hex, inserted as is
+/// * a string in single quotes, pushed
+/// * an opcode name, with or without the OP_ prefix
+pub(crate) fn parse_script(asm: &str) -> Vec<u8> {
+    let mut result = vec![];
+    for token in asm.split_whitespace() {
+        let digits = token.strip_prefix('-').unwrap_or(token);
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            let num: i64 = token.parse().unwrap();
+            assert!(num.abs() <= 0xffff_ffff, "number out of range: {}", token);
+            result.extend(Script::new(vec![Command::number(num)]).raw_serialize());
+        } else if let Some(hex) = token.strip_prefix("0x").filter(|hex| !hex.is_empty()) {
+            result.extend(hex::decode(hex).unwrap_or_else(|_| panic!("invalid hex {}", token)));
+        } else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+            let data = &token.as_bytes()[1..token.len() - 1];
+            result.extend(Script::new(vec![Command::push(data)]).raw_serialize());
+        } else {
+            let opcode: Opcode = token.parse().unwrap();
+            result.push(opcode as u8);
+        }
+    }
+    result
+}
+
+/// The transaction the test spends from: a coinbase-like input and a single
+/// output of the amount to the script pubkey
+pub(crate) fn crediting_tx(script_pubkey: &[u8], amount: Amount) -> Tx {
+    let input = TxInput::new(
+        OutPoint::new(Txid::from_bytes([0; 32]), u32::MAX),
+        Script::new(vec![Command::Op(OP_0), Command::Op(OP_0)]),
+        SEQUENCE_FINAL,
+    );
+    // Scripts keep their bytes even with a truncated push, so that the
+    // transaction hashes the same as in Bitcoin Core
+    let script_pubkey = Script::from_bytes_lossless(script_pubkey);
+    Tx::new(
+        1,
+        vec![input],
+        vec![TxOutput::new(amount, script_pubkey)],
+        0,
+    )
+}
+
+/// The transaction spending the output of the crediting transaction with
+/// the script sig and witness, to an empty script pubkey
+pub(crate) fn spending_tx(credit: &Tx, script_sig: &[u8], witness: Vec<Vec<u8>>) -> Tx {
+    let output = &credit.get_outputs()[0];
+    let script_sig = Script::from_bytes_lossless(script_sig);
+    let mut input = TxInput::new(OutPoint::new(credit.id(), 0), script_sig, SEQUENCE_FINAL);
+    input.set_witness(witness);
+    Tx::new(
+        1,
+        vec![input],
+        vec![TxOutput::new(output.get_amount(), Script::new(vec![]))],
+        0,
+    )
+}
+
+/// Runs a test, returning the error code it was expected to fail with and
+/// the one it failed with, OK if none
+fn run_test(test: &[Value]) -> (&str, &'static str) {
+    let (witness, amount, test) = match test[0].as_array() {
+        Some(items) => {
+            let (amount, items) = items.split_last().unwrap();
+            let witness = items
+                .iter()
+                .map(|item| hex::decode(item.as_str().unwrap()).unwrap())
+                .collect();
+            let sats = (amount.as_f64().unwrap() * 100_000_000.0).round() as u64;
+            (witness, Amount::from_sat(sats), &test[1..])
+        }
+        None => (vec![], Amount::from_sat(0), test),
+    };
+    let script_sig = parse_script(test[0].as_str().unwrap());
+    let script_pubkey = parse_script(test[1].as_str().unwrap());
+    let mut flags: VerifyFlags = test[2].as_str().unwrap().parse().unwrap();
+    // As in Bitcoin Core, CLEANSTACK is only checked along with the rules
+    // that change what is left on the stack
+    if flags.contains(VerifyFlags::CLEANSTACK) {
+        flags |= VerifyFlags::P2SH | VerifyFlags::WITNESS;
+    }
+
+    let credit = crediting_tx(&script_pubkey, amount);
+    let spend = spending_tx(&credit, &script_sig, witness.clone());
+    let prevouts = credit.get_outputs();
+    let checker = TransactionSignatureChecker::new(&spend, 0, amount).prevouts(&prevouts);
+    let result = verify_raw_script(&script_sig, &script_pubkey, &witness, flags, &checker);
+    (
+        test[3].as_str().unwrap(),
+        result.err().map_or("OK", ScriptError::code),
+    )
+}
+
+#[test]
+fn test_parse_script() {
+    assert_eq!(
+        hex::encode(parse_script(
+            "0 -1 16 17 2147483648 'ab' '' DUP OP_HASH160 NOP2"
+        )),
+        "004f6001110500000080000261620076a9b1"
+    );
+    // Hex is inserted as is
+    assert_eq!(parse_script("0x4c 0x01 0x07"), [0x4c, 0x01, 0x07]);
+    assert_eq!(parse_script("0x4c01"), [0x4c, 0x01]);
+}
+
+#[test]
+fn test_script_tests() {
+    let tests: Value = serde_json::from_str(SCRIPT_TESTS).unwrap();
+    let mut failures = vec![];
+    let mut count = 0;
+    for test in tests.as_array().unwrap() {
+        let test = test.as_array().unwrap();
+        if test.len() == 1 {
+            continue;
+        }
+        count += 1;
+        let (expected, result) = run_test(test);
+        let json = Value::from(test.clone()).to_string();
+        let known = KNOWN_FAILURES.iter().any(|(known, _)| *known == json);
+        if (result != expected) != known {
+            let mut test = json;
+            // Limit tests have scripts thousands of opcodes long
+            if test.len() > 200 {
+                test.truncate(test.floor_char_boundary(200));
+                test.push_str("...");
+            }
+            if known {
+                failures.push(format!("{} passes but is a known failure", test));
+            } else {
+                failures.push(format!("{} got {}", test, result));
+            }
+        }
+    }
+    assert!(count > 0);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,205 @@
+use std::ops::{Add, Neg, Sub};
+
+use super::interpreter::ScriptError;
+
+/// A number as scripts see it: little endian bytes whose top bit is the
+/// sign, zero being the empty element. Operands are limited in size, but
+/// results of arithmetic can be one byte longer, so the value is an i64.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct ScriptNum(i64);
+
+impl ScriptNum {
+    pub fn new(value: i64) -> Self {
+        ScriptNum(value)
+    }
+
+    pub fn value(self) -> i64 {
+        self.0
+    }
+
+    /// Returns the value clamped to the i32 range
+    pub fn to_i32(self) -> i32 {
+        self.0.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+
+    /// Decodes a number of at most max_size bytes. Under MINIMALDATA it must
+    /// also have no extra zero bytes, negative zero included.
+    pub fn decode(
+        bytes: &[u8],
+        require_minimal: bool,
+        max_size: usize,
+    ) -> Result<Self, ScriptError> {
+        if bytes.len() > max_size {
+            return Err(ScriptError::NumOverflow);
+        }
+        if require_minimal && !Self::is_minimally_encoded(bytes) {
+            return Err(ScriptError::NumNotMinimal);
+        }
+        Ok(Self::decode_unchecked(bytes))
+    }
+
+    /// Decodes a number whatever its encoding. Callers check the size, as
+    /// more than 8 bytes overflow the i64.
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        debug_assert!(bytes.len() <= 8, "script numbers are at most 8 bytes");
+        let (last, rest) = match bytes.split_last() {
+            Some(split) => split,
+            None => return ScriptNum(0),
+        };
+        let magnitude = rest
+            .iter()
+            .rev()
+            .fold((last & 0x7f) as i64, |acc, &byte| (acc << 8) | byte as i64);
+        if last & 0x80 != 0 {
+            ScriptNum(-magnitude)
+        } else {
+            ScriptNum(magnitude)
+        }
+    }
+
+    /// Returns false if the last byte only holds the sign while the byte
+    /// before it has room for it
+    pub fn is_minimally_encoded(bytes: &[u8]) -> bool {
+        match bytes {
+            [] => true,
+            [.., last] if last & 0x7f != 0 => true,
+            [_] => false,
+            [.., before, _] => before & 0x80 != 0,
+        }
+    }
+
+    /// Encodes the number in its shortest form
+    pub fn encode(self) -> Vec<u8> {
+        let mut result = vec![];
+        let mut remaining = self.0.unsigned_abs();
+        while remaining > 0 {
+            result.push((remaining & 0xff) as u8);
+            remaining >>= 8;
+        }
+        // The sign goes in the top bit of the last byte, or in an extra
+        // byte if that bit is taken
+        if let Some(&last) = result.last() {
+            if last & 0x80 != 0 {
+                result.push(if self.0 < 0 { 0x80 } else { 0 });
+            } else if self.0 < 0 {
+                *result.last_mut().unwrap() |= 0x80;
+            }
+        }
+        result
+    }
+}
+
+impl From<i64> for ScriptNum {
+    fn from(value: i64) -> Self {
+        ScriptNum(value)
+    }
+}
+
+impl From<bool> for ScriptNum {
+    fn from(value: bool) -> Self {
+        ScriptNum(value as i64)
+    }
+}
+
+impl Add for ScriptNum {
+    type Output = ScriptNum;
+
+    fn add(self, rhs: ScriptNum) -> ScriptNum {
+        ScriptNum(self.0 + rhs.0)
+    }
+}
+
+impl Sub for ScriptNum {
+    type Output = ScriptNum;
+
+    fn sub(self, rhs: ScriptNum) -> ScriptNum {
+        ScriptNum(self.0 - rhs.0)
+    }
+}
+
+impl Neg for ScriptNum {
+    type Output = ScriptNum;
+
+    fn neg(self) -> ScriptNum {
+        ScriptNum(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::op::decode_num;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        for (value, hex) in [
+            (0, ""),
+            (1, "01"),
+            (-1, "81"),
+            (127, "7f"),
+            (128, "8000"),
+            (-128, "8080"),
+            (-255, "ff80"),
+            (256, "0001"),
+            (i32::MAX as i64, "ffffff7f"),
+            (-(i32::MAX as i64), "ffffffff"),
+            (1 << 31, "0000008000"),
+            (-(1 << 32) + 2, "feffffff80"),
+        ] {
+            let bytes = hex::decode(hex).unwrap();
+            assert_eq!(ScriptNum::new(value).encode(), bytes, "{}", value);
+            assert_eq!(
+                ScriptNum::decode_unchecked(&bytes).value(),
+                value,
+                "{}",
+                hex
+            );
+        }
+        // Negative zero
+        assert_eq!(ScriptNum::decode_unchecked(&[0x80]).value(), 0);
+        assert_eq!(ScriptNum::decode_unchecked(&[0, 0x80]).value(), 0);
+    }
+
+    #[test]
+    fn test_limits() {
+        let five_bytes = [0, 0, 0, 0x80, 0];
+        assert_eq!(
+            ScriptNum::decode(&five_bytes, false, 4),
+            Err(ScriptError::NumOverflow)
+        );
+        assert_eq!(
+            ScriptNum::decode(&five_bytes, false, 5),
+            Ok(ScriptNum::new(1 << 31))
+        );
+        assert_eq!(ScriptNum::new(1 << 40).to_i32(), i32::MAX);
+        assert_eq!(ScriptNum::new(-(1 << 40)).to_i32(), i32::MIN);
+
+        assert_eq!(
+            decode_num(&[0xff, 0xff, 0xff, 0xff]),
+            Ok(-(i32::MAX as i64))
+        );
+        assert_eq!(
+            decode_num(&[0, 0, 0, 0, 0, 0, 0, 0x80, 0x80]),
+            Err(ScriptError::NumOverflow)
+        );
+    }
+
+    #[test]
+    fn test_minimal() {
+        for hex in ["", "01", "81", "8000", "0081", "ff80"] {
+            let bytes = hex::decode(hex).unwrap();
+            assert!(ScriptNum::decode(&bytes, true, 4).is_ok(), "{}", hex);
+        }
+        for hex in ["00", "80", "0100", "0080", "0180", "000000"] {
+            let bytes = hex::decode(hex).unwrap();
+            assert_eq!(
+                ScriptNum::decode(&bytes, true, 4),
+                Err(ScriptError::NumNotMinimal),
+                "{}",
+                hex
+            );
+            assert!(ScriptNum::decode(&bytes, false, 4).is_ok());
+        }
+    }
+}
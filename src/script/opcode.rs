@@ -0,0 +1,476 @@
+use std::{fmt, str::FromStr};
+
+/// Defines the opcode enum from its names and values, along with the table
+/// of all opcodes in byte order and their names
+macro_rules! opcodes {
+    ($($name:ident = $value:literal,)*) => {
+        /// A script opcode. Every byte value is one: 0x01 to 0x4b push that
+        /// many bytes, and those without a meaning are named after their
+        /// value.
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+        #[repr(u8)]
+        pub enum Opcode {
+            $($name = $value,)*
+        }
+
+        impl Opcode {
+            /// Every opcode, indexed by its byte value
+            pub const ALL: [Opcode; 256] = [$(Opcode::$name,)*];
+
+            /// Returns the name, as in "OP_DUP"
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Opcode::$name => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    OP_0 = 0x00,
+    OP_PUSHBYTES_1 = 0x01,
+    OP_PUSHBYTES_2 = 0x02,
+    OP_PUSHBYTES_3 = 0x03,
+    OP_PUSHBYTES_4 = 0x04,
+    OP_PUSHBYTES_5 = 0x05,
+    OP_PUSHBYTES_6 = 0x06,
+    OP_PUSHBYTES_7 = 0x07,
+    OP_PUSHBYTES_8 = 0x08,
+    OP_PUSHBYTES_9 = 0x09,
+    OP_PUSHBYTES_10 = 0x0a,
+    OP_PUSHBYTES_11 = 0x0b,
+    OP_PUSHBYTES_12 = 0x0c,
+    OP_PUSHBYTES_13 = 0x0d,
+    OP_PUSHBYTES_14 = 0x0e,
+    OP_PUSHBYTES_15 = 0x0f,
+    OP_PUSHBYTES_16 = 0x10,
+    OP_PUSHBYTES_17 = 0x11,
+    OP_PUSHBYTES_18 = 0x12,
+    OP_PUSHBYTES_19 = 0x13,
+    OP_PUSHBYTES_20 = 0x14,
+    OP_PUSHBYTES_21 = 0x15,
+    OP_PUSHBYTES_22 = 0x16,
+    OP_PUSHBYTES_23 = 0x17,
+    OP_PUSHBYTES_24 = 0x18,
+    OP_PUSHBYTES_25 = 0x19,
+    OP_PUSHBYTES_26 = 0x1a,
+    OP_PUSHBYTES_27 = 0x1b,
+    OP_PUSHBYTES_28 = 0x1c,
+    OP_PUSHBYTES_29 = 0x1d,
+    OP_PUSHBYTES_30 = 0x1e,
+    OP_PUSHBYTES_31 = 0x1f,
+    OP_PUSHBYTES_32 = 0x20,
+    OP_PUSHBYTES_33 = 0x21,
+    OP_PUSHBYTES_34 = 0x22,
+    OP_PUSHBYTES_35 = 0x23,
+    OP_PUSHBYTES_36 = 0x24,
+    OP_PUSHBYTES_37 = 0x25,
+    OP_PUSHBYTES_38 = 0x26,
+    OP_PUSHBYTES_39 = 0x27,
+    OP_PUSHBYTES_40 = 0x28,
+    OP_PUSHBYTES_41 = 0x29,
+    OP_PUSHBYTES_42 = 0x2a,
+    OP_PUSHBYTES_43 = 0x2b,
+    OP_PUSHBYTES_44 = 0x2c,
+    OP_PUSHBYTES_45 = 0x2d,
+    OP_PUSHBYTES_46 = 0x2e,
+    OP_PUSHBYTES_47 = 0x2f,
+    OP_PUSHBYTES_48 = 0x30,
+    OP_PUSHBYTES_49 = 0x31,
+    OP_PUSHBYTES_50 = 0x32,
+    OP_PUSHBYTES_51 = 0x33,
+    OP_PUSHBYTES_52 = 0x34,
+    OP_PUSHBYTES_53 = 0x35,
+    OP_PUSHBYTES_54 = 0x36,
+    OP_PUSHBYTES_55 = 0x37,
+    OP_PUSHBYTES_56 = 0x38,
+    OP_PUSHBYTES_57 = 0x39,
+    OP_PUSHBYTES_58 = 0x3a,
+    OP_PUSHBYTES_59 = 0x3b,
+    OP_PUSHBYTES_60 = 0x3c,
+    OP_PUSHBYTES_61 = 0x3d,
+    OP_PUSHBYTES_62 = 0x3e,
+    OP_PUSHBYTES_63 = 0x3f,
+    OP_PUSHBYTES_64 = 0x40,
+    OP_PUSHBYTES_65 = 0x41,
+    OP_PUSHBYTES_66 = 0x42,
+    OP_PUSHBYTES_67 = 0x43,
+    OP_PUSHBYTES_68 = 0x44,
+    OP_PUSHBYTES_69 = 0x45,
+    OP_PUSHBYTES_70 = 0x46,
+    OP_PUSHBYTES_71 = 0x47,
+    OP_PUSHBYTES_72 = 0x48,
+    OP_PUSHBYTES_73 = 0x49,
+    OP_PUSHBYTES_74 = 0x4a,
+    OP_PUSHBYTES_75 = 0x4b,
+    OP_PUSHDATA1 = 0x4c,
+    OP_PUSHDATA2 = 0x4d,
+    OP_PUSHDATA4 = 0x4e,
+    OP_1NEGATE = 0x4f,
+    OP_RESERVED = 0x50,
+    OP_1 = 0x51,
+    OP_2 = 0x52,
+    OP_3 = 0x53,
+    OP_4 = 0x54,
+    OP_5 = 0x55,
+    OP_6 = 0x56,
+    OP_7 = 0x57,
+    OP_8 = 0x58,
+    OP_9 = 0x59,
+    OP_10 = 0x5a,
+    OP_11 = 0x5b,
+    OP_12 = 0x5c,
+    OP_13 = 0x5d,
+    OP_14 = 0x5e,
+    OP_15 = 0x5f,
+    OP_16 = 0x60,
+    OP_NOP = 0x61,
+    OP_VER = 0x62,
+    OP_IF = 0x63,
+    OP_NOTIF = 0x64,
+    OP_VERIF = 0x65,
+    OP_VERNOTIF = 0x66,
+    OP_ELSE = 0x67,
+    OP_ENDIF = 0x68,
+    OP_VERIFY = 0x69,
+    OP_RETURN = 0x6a,
+    OP_TOALTSTACK = 0x6b,
+    OP_FROMALTSTACK = 0x6c,
+    OP_2DROP = 0x6d,
+    OP_2DUP = 0x6e,
+    OP_3DUP = 0x6f,
+    OP_2OVER = 0x70,
+    OP_2ROT = 0x71,
+    OP_2SWAP = 0x72,
+    OP_IFDUP = 0x73,
+    OP_DEPTH = 0x74,
+    OP_DROP = 0x75,
+    OP_DUP = 0x76,
+    OP_NIP = 0x77,
+    OP_OVER = 0x78,
+    OP_PICK = 0x79,
+    OP_ROLL = 0x7a,
+    OP_ROT = 0x7b,
+    OP_SWAP = 0x7c,
+    OP_TUCK = 0x7d,
+    OP_CAT = 0x7e,
+    OP_SUBSTR = 0x7f,
+    OP_LEFT = 0x80,
+    OP_RIGHT = 0x81,
+    OP_SIZE = 0x82,
+    OP_INVERT = 0x83,
+    OP_AND = 0x84,
+    OP_OR = 0x85,
+    OP_XOR = 0x86,
+    OP_EQUAL = 0x87,
+    OP_EQUALVERIFY = 0x88,
+    OP_RESERVED1 = 0x89,
+    OP_RESERVED2 = 0x8a,
+    OP_1ADD = 0x8b,
+    OP_1SUB = 0x8c,
+    OP_2MUL = 0x8d,
+    OP_2DIV = 0x8e,
+    OP_NEGATE = 0x8f,
+    OP_ABS = 0x90,
+    OP_NOT = 0x91,
+    OP_0NOTEQUAL = 0x92,
+    OP_ADD = 0x93,
+    OP_SUB = 0x94,
+    OP_MUL = 0x95,
+    OP_DIV = 0x96,
+    OP_MOD = 0x97,
+    OP_LSHIFT = 0x98,
+    OP_RSHIFT = 0x99,
+    OP_BOOLAND = 0x9a,
+    OP_BOOLOR = 0x9b,
+    OP_NUMEQUAL = 0x9c,
+    OP_NUMEQUALVERIFY = 0x9d,
+    OP_NUMNOTEQUAL = 0x9e,
+    OP_LESSTHAN = 0x9f,
+    OP_GREATERTHAN = 0xa0,
+    OP_LESSTHANOREQUAL = 0xa1,
+    OP_GREATERTHANOREQUAL = 0xa2,
+    OP_MIN = 0xa3,
+    OP_MAX = 0xa4,
+    OP_WITHIN = 0xa5,
+    OP_RIPEMD160 = 0xa6,
+    OP_SHA1 = 0xa7,
+    OP_SHA256 = 0xa8,
+    OP_HASH160 = 0xa9,
+    OP_HASH256 = 0xaa,
+    OP_CODESEPARATOR = 0xab,
+    OP_CHECKSIG = 0xac,
+    OP_CHECKSIGVERIFY = 0xad,
+    OP_CHECKMULTISIG = 0xae,
+    OP_CHECKMULTISIGVERIFY = 0xaf,
+    OP_NOP1 = 0xb0,
+    OP_CHECKLOCKTIMEVERIFY = 0xb1,
+    OP_CHECKSEQUENCEVERIFY = 0xb2,
+    OP_NOP4 = 0xb3,
+    OP_NOP5 = 0xb4,
+    OP_NOP6 = 0xb5,
+    OP_NOP7 = 0xb6,
+    OP_NOP8 = 0xb7,
+    OP_NOP9 = 0xb8,
+    OP_NOP10 = 0xb9,
+    OP_CHECKSIGADD = 0xba,
+    OP_RETURN_187 = 0xbb,
+    OP_RETURN_188 = 0xbc,
+    OP_RETURN_189 = 0xbd,
+    OP_RETURN_190 = 0xbe,
+    OP_RETURN_191 = 0xbf,
+    OP_RETURN_192 = 0xc0,
+    OP_RETURN_193 = 0xc1,
+    OP_RETURN_194 = 0xc2,
+    OP_RETURN_195 = 0xc3,
+    OP_RETURN_196 = 0xc4,
+    OP_RETURN_197 = 0xc5,
+    OP_RETURN_198 = 0xc6,
+    OP_RETURN_199 = 0xc7,
+    OP_RETURN_200 = 0xc8,
+    OP_RETURN_201 = 0xc9,
+    OP_RETURN_202 = 0xca,
+    OP_RETURN_203 = 0xcb,
+    OP_RETURN_204 = 0xcc,
+    OP_RETURN_205 = 0xcd,
+    OP_RETURN_206 = 0xce,
+    OP_RETURN_207 = 0xcf,
+    OP_RETURN_208 = 0xd0,
+    OP_RETURN_209 = 0xd1,
+    OP_RETURN_210 = 0xd2,
+    OP_RETURN_211 = 0xd3,
+    OP_RETURN_212 = 0xd4,
+    OP_RETURN_213 = 0xd5,
+    OP_RETURN_214 = 0xd6,
+    OP_RETURN_215 = 0xd7,
+    OP_RETURN_216 = 0xd8,
+    OP_RETURN_217 = 0xd9,
+    OP_RETURN_218 = 0xda,
+    OP_RETURN_219 = 0xdb,
+    OP_RETURN_220 = 0xdc,
+    OP_RETURN_221 = 0xdd,
+    OP_RETURN_222 = 0xde,
+    OP_RETURN_223 = 0xdf,
+    OP_RETURN_224 = 0xe0,
+    OP_RETURN_225 = 0xe1,
+    OP_RETURN_226 = 0xe2,
+    OP_RETURN_227 = 0xe3,
+    OP_RETURN_228 = 0xe4,
+    OP_RETURN_229 = 0xe5,
+    OP_RETURN_230 = 0xe6,
+    OP_RETURN_231 = 0xe7,
+    OP_RETURN_232 = 0xe8,
+    OP_RETURN_233 = 0xe9,
+    OP_RETURN_234 = 0xea,
+    OP_RETURN_235 = 0xeb,
+    OP_RETURN_236 = 0xec,
+    OP_RETURN_237 = 0xed,
+    OP_RETURN_238 = 0xee,
+    OP_RETURN_239 = 0xef,
+    OP_RETURN_240 = 0xf0,
+    OP_RETURN_241 = 0xf1,
+    OP_RETURN_242 = 0xf2,
+    OP_RETURN_243 = 0xf3,
+    OP_RETURN_244 = 0xf4,
+    OP_RETURN_245 = 0xf5,
+    OP_RETURN_246 = 0xf6,
+    OP_RETURN_247 = 0xf7,
+    OP_RETURN_248 = 0xf8,
+    OP_RETURN_249 = 0xf9,
+    OP_RETURN_250 = 0xfa,
+    OP_RETURN_251 = 0xfb,
+    OP_RETURN_252 = 0xfc,
+    OP_RETURN_253 = 0xfd,
+    OP_RETURN_254 = 0xfe,
+    OP_INVALIDOPCODE = 0xff,
+}
+
+/// Classification
+impl Opcode {
+    /// Returns the byte value
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns true for opcodes that only push data: OP_0, the direct
+    /// pushes, OP_PUSHDATA1/2/4, OP_1NEGATE, OP_RESERVED and OP_1 to OP_16.
+    /// Scripts made of these are push-only.
+    pub fn is_push(self) -> bool {
+        self <= Opcode::OP_16
+    }
+
+    /// Returns true for 0x01 to 0x4b, which push that many bytes
+    pub fn is_push_bytes(self) -> bool {
+        (Opcode::OP_PUSHBYTES_1..=Opcode::OP_PUSHBYTES_75).contains(&self)
+    }
+
+    /// Returns the number pushed by OP_1NEGATE and OP_0 to OP_16
+    pub fn small_int(self) -> Option<i32> {
+        match self {
+            Opcode::OP_0 => Some(0),
+            Opcode::OP_1NEGATE => Some(-1),
+            op if (Opcode::OP_1..=Opcode::OP_16).contains(&op) => {
+                Some(op as i32 - Opcode::OP_1 as i32 + 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns OP_0 to OP_16 for the numbers 0 to 16
+    pub fn from_small_int(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Opcode::OP_0),
+            1..=16 => Some(Opcode::from(Opcode::OP_1 as u8 + n - 1)),
+            _ => None,
+        }
+    }
+
+    /// Returns true for the opcodes disabled in 2010 (CVE-2010-5137),
+    /// which fail a script even in an unexecuted branch
+    pub fn is_disabled(self) -> bool {
+        matches!(
+            self,
+            Opcode::OP_CAT
+                | Opcode::OP_SUBSTR
+                | Opcode::OP_LEFT
+                | Opcode::OP_RIGHT
+                | Opcode::OP_INVERT
+                | Opcode::OP_AND
+                | Opcode::OP_OR
+                | Opcode::OP_XOR
+                | Opcode::OP_2MUL
+                | Opcode::OP_2DIV
+                | Opcode::OP_MUL
+                | Opcode::OP_DIV
+                | Opcode::OP_MOD
+                | Opcode::OP_LSHIFT
+                | Opcode::OP_RSHIFT
+        )
+    }
+
+    /// Returns true for OP_IF, OP_NOTIF, OP_ELSE and OP_ENDIF, which are
+    /// evaluated in unexecuted branches too
+    pub fn is_conditional(self) -> bool {
+        matches!(
+            self,
+            Opcode::OP_IF | Opcode::OP_NOTIF | Opcode::OP_ELSE | Opcode::OP_ENDIF
+        )
+    }
+
+    /// Returns true for OP_NOP and OP_NOP1 to OP_NOP10, including OP_NOP2
+    /// and OP_NOP3 which became OP_CHECKLOCKTIMEVERIFY and
+    /// OP_CHECKSEQUENCEVERIFY
+    pub fn is_nop(self) -> bool {
+        self == Opcode::OP_NOP || (Opcode::OP_NOP1..=Opcode::OP_NOP10).contains(&self)
+    }
+
+    /// Returns true for the OP_SUCCESSx opcodes of tapscript (BIP342),
+    /// which make a tapscript succeed unconditionally
+    pub fn is_success(self) -> bool {
+        matches!(
+            self as u8,
+            80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254
+        )
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(byte: u8) -> Self {
+        Opcode::ALL[byte as usize]
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> Self {
+        opcode as u8
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// An opcode name that isn't known
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseOpcodeError(pub String);
+
+impl fmt::Display for ParseOpcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseOpcodeError {}
+
+impl FromStr for Opcode {
+    type Err = ParseOpcodeError;
+
+    /// Parses an opcode name, with or without the OP_ prefix. OP_FALSE,
+    /// OP_TRUE, OP_NOP2 and OP_NOP3 are accepted as aliases.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix("OP_").unwrap_or(s);
+        let opcode = match name {
+            "FALSE" => Some(Opcode::OP_0),
+            "TRUE" => Some(Opcode::OP_1),
+            "NOP2" => Some(Opcode::OP_CHECKLOCKTIMEVERIFY),
+            "NOP3" => Some(Opcode::OP_CHECKSEQUENCEVERIFY),
+            _ => Opcode::ALL
+                .into_iter()
+                .find(|opcode| &opcode.name()[3..] == name),
+        };
+        opcode.ok_or_else(|| ParseOpcodeError(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all() {
+        for (byte, opcode) in Opcode::ALL.into_iter().enumerate() {
+            assert_eq!(opcode as usize, byte);
+            assert_eq!(Opcode::from(byte as u8), opcode);
+            assert_eq!(opcode.name().parse::<Opcode>(), Ok(opcode));
+        }
+        assert_eq!(Opcode::from(0x76).to_string(), "OP_DUP");
+        assert_eq!(Opcode::from(0x7e).name(), "OP_CAT");
+        assert_eq!(Opcode::from(0xbb).name(), "OP_RETURN_187");
+        assert_eq!(Opcode::from(0xff).name(), "OP_INVALIDOPCODE");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("CHECKSIG".parse(), Ok(Opcode::OP_CHECKSIG));
+        assert_eq!("OP_TRUE".parse(), Ok(Opcode::OP_1));
+        assert_eq!("NOP2".parse(), Ok(Opcode::OP_CHECKLOCKTIMEVERIFY));
+        assert_eq!(
+            "OP_FOO".parse::<Opcode>(),
+            Err(ParseOpcodeError("OP_FOO".to_string()))
+        );
+        assert!("op_dup".parse::<Opcode>().is_err());
+    }
+
+    #[test]
+    fn test_classification() {
+        let count = |f: fn(Opcode) -> bool| Opcode::ALL.into_iter().filter(|&op| f(op)).count();
+        assert_eq!(count(Opcode::is_disabled), 15);
+        assert_eq!(count(Opcode::is_nop), 11);
+        assert_eq!(count(Opcode::is_conditional), 4);
+        assert_eq!(count(Opcode::is_push), 0x61);
+        // BIP342 lists 87 OP_SUCCESSx, all unassigned or disabled
+        assert_eq!(count(Opcode::is_success), 87);
+        assert!(Opcode::OP_CAT.is_success() && Opcode::OP_CAT.is_disabled());
+        assert!(!Opcode::OP_CHECKSIGADD.is_success());
+
+        assert_eq!(Opcode::OP_16.small_int(), Some(16));
+        assert_eq!(Opcode::OP_1NEGATE.small_int(), Some(-1));
+        assert_eq!(Opcode::OP_NOP.small_int(), None);
+        assert_eq!(Opcode::from_small_int(5), Some(Opcode::OP_5));
+        assert_eq!(Opcode::from_small_int(17), None);
+    }
+}
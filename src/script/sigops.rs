@@ -0,0 +1,146 @@
+use crate::script::{
+    interpreter::MAX_PUBKEYS_PER_MULTISIG,
+    opcode::Opcode::{self, *},
+    Command, Script,
+};
+
+/// Signature operations counted in a script
+impl Script {
+    /// Returns the number of signature operations: one per OP_CHECKSIG or
+    /// OP_CHECKSIGVERIFY, and per OP_CHECKMULTISIG or OP_CHECKMULTISIGVERIFY
+    /// the number of keys pushed by the preceding OP_1 to OP_16 if accurate,
+    /// 20 otherwise. Legacy counts are inaccurate, P2SH and witness ones
+    /// accurate.
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_op = None;
+        for cmd in self.cmds() {
+            let Command::Op(op) = cmd else {
+                last_op = None;
+                continue;
+            };
+            match op {
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => count += 1,
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    count += match last_op.and_then(Opcode::small_int) {
+                        Some(keys @ 1..=16) if accurate => keys as usize,
+                        _ => MAX_PUBKEYS_PER_MULTISIG,
+                    }
+                }
+                _ => {}
+            }
+            last_op = Some(*op);
+        }
+        count
+    }
+
+    /// Returns the signature operations of the redeem script the script sig
+    /// pushes last, if this is a P2SH script pubkey, and the accurate count
+    /// of this script otherwise. Script sigs that aren't push only have none.
+    pub fn p2sh_sigop_count(&self, script_sig: &Script) -> usize {
+        if !self.is_p2sh() {
+            return self.sigop_count(true);
+        }
+        if !script_sig.is_push_only() {
+            return 0;
+        }
+        script_sig.cmds().last().map_or(0, |cmd| {
+            Script::from_bytes_lossless(cmd.push_data().unwrap_or(&[])).sigop_count(true)
+        })
+    }
+
+    /// Returns the signature operations of a witness program spent with the
+    /// witness, directly or nested in P2SH: one for P2WPKH, and the accurate
+    /// count of the witness script for P2WSH. Other versions have none yet.
+    pub fn witness_sigop_count(&self, script_sig: &Script, witness: &[Vec<u8>]) -> usize {
+        if let Some((version, program)) = self.as_witness_program() {
+            return witness_program_sigops(version, program, witness);
+        }
+        if self.is_p2sh() && script_sig.is_push_only() {
+            let redeem_script = script_sig.cmds().last().and_then(Command::push_data);
+            if let Some(redeem_script) =
+                redeem_script.and_then(|bytes| Script::from_bytes(bytes).ok())
+            {
+                if let Some((version, program)) = redeem_script.as_witness_program() {
+                    return witness_program_sigops(version, program, witness);
+                }
+            }
+        }
+        0
+    }
+}
+
+fn witness_program_sigops(version: u8, program: &[u8], witness: &[Vec<u8>]) -> usize {
+    match (version, program.len(), witness.last()) {
+        (0, 20, _) => 1,
+        (0, 32, Some(witness_script)) => {
+            Script::from_bytes_lossless(witness_script).sigop_count(true)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::hash160::hash160;
+
+    use super::*;
+
+    fn script(asm: &str) -> Script {
+        Script::from_asm(asm).unwrap()
+    }
+
+    #[test]
+    fn test_sigop_count() {
+        let multisig = script("2 0x02aa 0x02bb 3 CHECKMULTISIG");
+        assert_eq!(multisig.sigop_count(false), 20);
+        assert_eq!(multisig.sigop_count(true), 3);
+        let s = script("CHECKSIG CHECKSIGVERIFY 0 CHECKMULTISIGVERIFY 0x0103 CHECKMULTISIG");
+        assert_eq!(s.sigop_count(true), 42);
+        assert_eq!(
+            script("16 CHECKMULTISIG CHECKMULTISIG").sigop_count(true),
+            36
+        );
+        // Signature checks that never run count all the same
+        assert_eq!(script("0 IF CHECKSIG ENDIF 1").sigop_count(false), 1);
+        assert_eq!(script("CHECKSIGADD").sigop_count(true), 0);
+    }
+
+    #[test]
+    fn test_p2sh_sigop_count() {
+        let redeem_script = script("1 0x02aa 0x02bb 2 CHECKMULTISIG");
+        let p2sh = Script::p2sh(&hash160(&redeem_script.raw_serialize()));
+        let script_sig = Script::new(vec![
+            Command::Op(OP_0),
+            Command::push(redeem_script.raw_serialize()),
+        ]);
+        assert_eq!(p2sh.sigop_count(true), 0);
+        assert_eq!(p2sh.p2sh_sigop_count(&script_sig), 2);
+        assert_eq!(p2sh.p2sh_sigop_count(&script("1 DUP")), 0);
+        assert_eq!(redeem_script.p2sh_sigop_count(&script_sig), 2);
+        // Counting stops at a truncated push
+        let truncated = script("0xac0302");
+        assert_eq!(p2sh.p2sh_sigop_count(&truncated), 1);
+    }
+
+    #[test]
+    fn test_witness_sigop_count() {
+        let p2wpkh = Script::p2wpkh(&[0; 20]);
+        assert_eq!(p2wpkh.witness_sigop_count(&script(""), &[]), 1);
+        let witness_script = script("CHECKSIG 2 0x02aa 0x02bb 2 CHECKMULTISIG");
+        let witness = vec![vec![], witness_script.raw_serialize()];
+        let p2wsh = Script::p2wsh(&[0; 32]);
+        assert_eq!(p2wsh.witness_sigop_count(&script(""), &witness), 3);
+        assert_eq!(p2wsh.witness_sigop_count(&script(""), &[]), 0);
+        assert_eq!(
+            Script::p2tr(&[0; 32]).witness_sigop_count(&script(""), &witness),
+            0
+        );
+
+        let nested = Script::p2sh(&hash160(&p2wpkh.raw_serialize()));
+        let script_sig = Script::new(vec![Command::push(p2wpkh.raw_serialize())]);
+        assert_eq!(nested.witness_sigop_count(&script_sig, &[]), 1);
+        assert_eq!(nested.witness_sigop_count(&script("1 DUP"), &[]), 0);
+        assert_eq!(script("CHECKSIG").witness_sigop_count(&script(""), &[]), 0);
+    }
+}
@@ -0,0 +1,399 @@
+use std::fmt;
+
+use super::{
+    op::{
+        OP_0, OP_1, OP_CHECKMULTISIG, OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160,
+        OP_RETURN,
+    },
+    opcode::Opcode,
+    Command, PushEncoding, Script,
+};
+
+/// The most keys a multisig template can have, the threshold and key count
+/// being pushed with OP_1 to OP_16
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TemplateError {
+    /// The threshold must be from 1 to the number of keys
+    InvalidThreshold {
+        m: usize,
+        n: usize,
+    },
+    TooManyKeys(usize),
+    /// Witness versions go from 0 to 16 and programs are 2 to 40 bytes
+    InvalidWitnessProgram {
+        version: u8,
+        length: usize,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::InvalidThreshold { m, n } => {
+                write!(f, "invalid threshold {} of {} keys", m, n)
+            }
+            TemplateError::TooManyKeys(n) => write!(f, "too many keys: {}", n),
+            TemplateError::InvalidWitnessProgram { version, length } => write!(
+                f,
+                "invalid witness program: version {}, {} bytes",
+                version, length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// The standard forms of script pubkeys, with the hash, keys or data they
+/// hold
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ScriptTemplate {
+    /// <pubkey> OP_CHECKSIG
+    P2pk(Vec<u8>),
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    P2wpkh([u8; 20]),
+    P2wsh([u8; 32]),
+    /// The x-only output key
+    P2tr([u8; 32]),
+    /// A witness program of a version or length without a meaning yet
+    WitnessUnknown {
+        version: u8,
+        program: Vec<u8>,
+    },
+    /// OP_m <pubkey>... OP_n OP_CHECKMULTISIG
+    Multisig {
+        m: usize,
+        keys: Vec<Vec<u8>>,
+    },
+    /// OP_RETURN followed by pushes only, with the data pushed
+    NullData(Vec<Vec<u8>>),
+    NonStandard,
+}
+
+/// Constructors
+impl Script {
+    /// OP_DUP OP_HASH160 <pubkey hash> OP_EQUALVERIFY OP_CHECKSIG
+    pub fn p2pkh(pubkey_hash: &[u8; 20]) -> Self {
+        Script::new(vec![
+            Command::Op(OP_DUP),
+            Command::Op(OP_HASH160),
+            Command::push(pubkey_hash.to_vec()),
+            Command::Op(OP_EQUALVERIFY),
+            Command::Op(OP_CHECKSIG),
+        ])
+    }
+
+    /// OP_HASH160 <script hash> OP_EQUAL
+    pub fn p2sh(script_hash: &[u8; 20]) -> Self {
+        Script::new(vec![
+            Command::Op(OP_HASH160),
+            Command::push(script_hash.to_vec()),
+            Command::Op(OP_EQUAL),
+        ])
+    }
+
+    /// OP_0 <pubkey hash>
+    pub fn p2wpkh(pubkey_hash: &[u8; 20]) -> Self {
+        Script::new(vec![Command::Op(OP_0), Command::push(pubkey_hash.to_vec())])
+    }
+
+    /// OP_0 <sha256 of the witness script>
+    pub fn p2wsh(script_hash: &[u8; 32]) -> Self {
+        Script::new(vec![Command::Op(OP_0), Command::push(script_hash.to_vec())])
+    }
+
+    /// OP_1 <x-only output key>
+    pub fn p2tr(output_key: &[u8; 32]) -> Self {
+        Script::new(vec![Command::Op(OP_1), Command::push(output_key.to_vec())])
+    }
+
+    /// OP_n <program>, for any witness version
+    pub fn witness_program(version: u8, program: &[u8]) -> Result<Self, TemplateError> {
+        match Opcode::from_small_int(version) {
+            Some(op) if (2..=40).contains(&program.len()) => {
+                Ok(Script::new(vec![Command::Op(op), Command::push(program)]))
+            }
+            _ => Err(TemplateError::InvalidWitnessProgram {
+                version,
+                length: program.len(),
+            }),
+        }
+    }
+
+    /// OP_m <pubkey>... OP_n OP_CHECKMULTISIG, with the keys in the order
+    /// given
+    pub fn multisig(m: usize, keys: &[Vec<u8>]) -> Result<Self, TemplateError> {
+        let n = keys.len();
+        if n > MAX_MULTISIG_KEYS {
+            return Err(TemplateError::TooManyKeys(n));
+        }
+        if m == 0 || m > n {
+            return Err(TemplateError::InvalidThreshold { m, n });
+        }
+        let mut cmds = vec![Command::number(m as i64)];
+        cmds.extend(keys.iter().map(|key| Command::push(key.clone())));
+        cmds.push(Command::number(n as i64));
+        cmds.push(Command::Op(OP_CHECKMULTISIG));
+        Ok(Script::new(cmds))
+    }
+
+    /// OP_RETURN <data>, an unspendable output carrying the data
+    pub fn op_return(data: &[u8]) -> Self {
+        Script::new(vec![Command::Op(OP_RETURN), Command::push(data)])
+    }
+}
+
+/// Classification
+impl Script {
+    /// Returns the standard form of the script and what it holds. Hashes
+    /// and keys must be pushed directly, as Bitcoin Core matches the bytes.
+    pub fn classify(&self) -> ScriptTemplate {
+        if self.truncated_push().is_some() {
+            return ScriptTemplate::NonStandard;
+        }
+        if let Some((version, program)) = self.as_witness_program() {
+            return classify_witness_program(version, program);
+        }
+        match self.cmds() {
+            [Command::Push(key, PushEncoding::Direct), Command::Op(OP_CHECKSIG)]
+                if is_pubkey_size(key) =>
+            {
+                ScriptTemplate::P2pk(key.clone())
+            }
+            [Command::Op(OP_DUP), Command::Op(OP_HASH160), Command::Push(hash, PushEncoding::Direct), Command::Op(OP_EQUALVERIFY), Command::Op(OP_CHECKSIG)]
+                if hash.len() == 20 =>
+            {
+                ScriptTemplate::P2pkh(hash.as_slice().try_into().unwrap())
+            }
+            [Command::Op(OP_HASH160), Command::Push(hash, PushEncoding::Direct), Command::Op(OP_EQUAL)]
+                if hash.len() == 20 =>
+            {
+                ScriptTemplate::P2sh(hash.as_slice().try_into().unwrap())
+            }
+            [Command::Op(first), keys @ .., Command::Op(last), Command::Op(OP_CHECKMULTISIG)] => {
+                classify_multisig(*first, keys, *last)
+            }
+            [Command::Op(OP_RETURN), rest @ ..] => classify_null_data(rest),
+            _ => ScriptTemplate::NonStandard,
+        }
+    }
+
+    pub fn is_p2pk(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2pk(_))
+    }
+
+    pub fn is_p2pkh(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2pkh(_))
+    }
+
+    pub fn is_p2sh(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2sh(_))
+    }
+
+    pub fn is_p2wpkh(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2wpkh(_))
+    }
+
+    pub fn is_p2wsh(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2wsh(_))
+    }
+
+    pub fn is_p2tr(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::P2tr(_))
+    }
+
+    pub fn is_multisig(&self) -> bool {
+        matches!(self.classify(), ScriptTemplate::Multisig { .. })
+    }
+
+    /// Returns true for any script starting with OP_RETURN, which can't be
+    /// spent
+    pub fn is_op_return(&self) -> bool {
+        matches!(self.cmds().first(), Some(Command::Op(OP_RETURN)))
+    }
+
+    /// Returns the version and program of a segwit script pubkey
+    pub fn as_witness_program(&self) -> Option<(u8, &[u8])> {
+        if self.truncated_push().is_some() {
+            return None;
+        }
+        match self.cmds() {
+            [Command::Op(version), Command::Push(program, PushEncoding::Direct)]
+                if (2..=40).contains(&program.len()) =>
+            {
+                match version.small_int() {
+                    Some(version @ 0..=16) => Some((version as u8, program.as_slice())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compressed keys are 33 bytes, uncompressed and hybrid ones 65
+fn is_pubkey_size(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02 | 0x03) => key.len() == 33,
+        Some(0x04 | 0x06 | 0x07) => key.len() == 65,
+        _ => false,
+    }
+}
+
+fn classify_witness_program(version: u8, program: &[u8]) -> ScriptTemplate {
+    match (version, program.len()) {
+        (0, 20) => ScriptTemplate::P2wpkh(program.try_into().unwrap()),
+        (0, 32) => ScriptTemplate::P2wsh(program.try_into().unwrap()),
+        // Version 0 is only defined for these two lengths
+        (0, _) => ScriptTemplate::NonStandard,
+        (1, 32) => ScriptTemplate::P2tr(program.try_into().unwrap()),
+        _ => ScriptTemplate::WitnessUnknown {
+            version,
+            program: program.to_vec(),
+        },
+    }
+}
+
+fn classify_multisig(first: Opcode, keys: &[Command], last: Opcode) -> ScriptTemplate {
+    let (Some(m @ 1..=16), Some(n @ 1..=16)) = (first.small_int(), last.small_int()) else {
+        return ScriptTemplate::NonStandard;
+    };
+    let keys = keys
+        .iter()
+        .map(|cmd| match cmd {
+            Command::Push(key, PushEncoding::Direct) if is_pubkey_size(key) => Some(key.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    match keys {
+        Some(keys) if keys.len() == n as usize && m <= n => ScriptTemplate::Multisig {
+            m: m as usize,
+            keys,
+        },
+        _ => ScriptTemplate::NonStandard,
+    }
+}
+
+fn classify_null_data(cmds: &[Command]) -> ScriptTemplate {
+    let data = cmds
+        .iter()
+        .map(|cmd| match cmd {
+            Command::Push(data, _) => Some(data.clone()),
+            Command::Op(op) => op.small_int().map(|num| super::op::encode_num(num.into())),
+        })
+        .collect::<Option<Vec<_>>>();
+    match data {
+        Some(data) => ScriptTemplate::NullData(data),
+        None => ScriptTemplate::NonStandard,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates() {
+        let hash = [0x11; 20];
+        let key = [0x22; 32];
+        let cases = [
+            (
+                Script::p2pkh(&hash),
+                "76a914111111111111111111111111111111111111111188ac",
+                ScriptTemplate::P2pkh(hash),
+            ),
+            (
+                Script::p2sh(&hash),
+                "a914111111111111111111111111111111111111111187",
+                ScriptTemplate::P2sh(hash),
+            ),
+            (
+                Script::p2wpkh(&hash),
+                "00141111111111111111111111111111111111111111",
+                ScriptTemplate::P2wpkh(hash),
+            ),
+            (
+                Script::p2wsh(&key),
+                "00202222222222222222222222222222222222222222222222222222222222222222",
+                ScriptTemplate::P2wsh(key),
+            ),
+            (
+                Script::p2tr(&key),
+                "51202222222222222222222222222222222222222222222222222222222222222222",
+                ScriptTemplate::P2tr(key),
+            ),
+            (
+                Script::witness_program(2, &[0x33; 2]).unwrap(),
+                "52023333",
+                ScriptTemplate::WitnessUnknown {
+                    version: 2,
+                    program: vec![0x33; 2],
+                },
+            ),
+            (
+                Script::op_return(b"hi"),
+                "6a026869",
+                ScriptTemplate::NullData(vec![b"hi".to_vec()]),
+            ),
+        ];
+        for (script, raw, template) in cases {
+            assert_eq!(hex::encode(script.raw_serialize()), raw);
+            assert_eq!(script.classify(), template);
+        }
+        assert!(Script::p2pkh(&hash).is_p2pkh());
+        assert!(!Script::p2sh(&hash).is_p2pkh());
+        assert!(Script::p2tr(&key).is_p2tr());
+        assert_eq!(
+            Script::p2wsh(&key).as_witness_program(),
+            Some((0, key.as_slice()))
+        );
+        assert!(Script::witness_program(17, &[0; 20]).is_err());
+        assert!(Script::witness_program(1, &[0; 41]).is_err());
+    }
+
+    #[test]
+    fn test_multisig() {
+        let keys = vec![vec![0x02; 33], vec![0x03; 33], vec![0x04; 65]];
+        let script = Script::multisig(2, &keys).unwrap();
+        assert!(script.is_multisig());
+        assert_eq!(script.classify(), ScriptTemplate::Multisig { m: 2, keys });
+        assert_eq!(
+            Script::multisig(0, &[vec![0x02; 33]]),
+            Err(TemplateError::InvalidThreshold { m: 0, n: 1 })
+        );
+        assert_eq!(
+            Script::multisig(2, &[vec![0x02; 33]]),
+            Err(TemplateError::InvalidThreshold { m: 2, n: 1 })
+        );
+        assert_eq!(
+            Script::multisig(1, &vec![vec![0x02; 33]; 17]),
+            Err(TemplateError::TooManyKeys(17))
+        );
+        // Not a key
+        let script = Script::multisig(1, &[vec![0x05; 33]]).unwrap();
+        assert_eq!(script.classify(), ScriptTemplate::NonStandard);
+    }
+
+    #[test]
+    fn test_classify_nonstandard() {
+        for asm in [
+            "OP_0 0x1111",
+            "OP_DUP OP_HASH160 0x1111 OP_EQUALVERIFY OP_CHECKSIG",
+            "OP_RETURN OP_DUP",
+            "OP_3 OP_CHECKSIG",
+            "OP_2 0x020202020202020202020202020202020202020202020202020202020202020202 OP_1 OP_CHECKMULTISIG",
+        ] {
+            let script = Script::from_asm(asm).unwrap();
+            assert_eq!(script.classify(), ScriptTemplate::NonStandard, "{}", asm);
+        }
+        let script = Script::from_asm("OP_RETURN OP_1 aabb").unwrap();
+        assert_eq!(
+            script.classify(),
+            ScriptTemplate::NullData(vec![vec![1], vec![0xaa, 0xbb]])
+        );
+        assert!(script.is_op_return());
+    }
+}
@@ -1,3 +1,11 @@
+pub mod builder;
+pub mod check;
+pub mod coin_selection;
+pub mod hashes;
 pub mod input;
 pub mod output;
+pub mod sighash;
+pub mod sigops;
+pub mod timelock;
 pub mod tx;
+pub mod verify;
@@ -0,0 +1,636 @@
+use std::fmt;
+
+use crate::{
+    address::{Address, AddressType},
+    amount::Amount,
+    script::Script,
+    utils::varint::encode_varint,
+};
+
+use super::{
+    check::MAX_BLOCK_WEIGHT,
+    coin_selection::{
+        select_coins, SelectionError, SelectionParams, Utxo, DEFAULT_LONG_TERM_FEE_RATE,
+    },
+    input::TxInput,
+    output::TxOutput,
+    tx::Tx,
+};
+
+/// Nodes don't relay outputs worth less than spending them costs at this
+/// rate, in sat/vB
+const DUST_RELAY_FEE_RATE: u64 = 3;
+
+/// Weight of the outpoint, the one-byte script sig length and the sequence
+const INPUT_BASE_WEIGHT: usize = (32 + 4 + 1 + 4) * 4;
+
+/// A DER signature with its sighash byte is at most 73 bytes
+const MAX_ECDSA_SIG_SIZE: usize = 73;
+
+const COMPRESSED_KEY_SIZE: usize = 33;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BuilderError {
+    NoInputs,
+    NoRecipients,
+    /// The fee rate is negative, not a number, or so high that fees would
+    /// be over MAX_MONEY
+    InvalidFeeRate,
+    /// The recipient's amount is below the dust threshold of its script
+    DustOutput(usize),
+    /// The amounts sum to more than MAX_MONEY
+    InvalidAmount,
+    InsufficientFunds {
+        available: Amount,
+        needed: Amount,
+    },
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::NoInputs => write!(f, "no inputs"),
+            BuilderError::NoRecipients => write!(f, "no recipients"),
+            BuilderError::InvalidFeeRate => write!(f, "invalid fee rate"),
+            BuilderError::DustOutput(index) => write!(f, "output {} is dust", index),
+            BuilderError::InvalidAmount => write!(f, "invalid amount"),
+            BuilderError::InsufficientFunds { available, needed } => {
+                write!(
+                    f,
+                    "insufficient funds: {} available, {} needed",
+                    available, needed
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+impl From<SelectionError> for BuilderError {
+    fn from(err: SelectionError) -> Self {
+        match err {
+            SelectionError::InsufficientFunds { available, needed } => {
+                BuilderError::InsufficientFunds { available, needed }
+            }
+            SelectionError::InvalidAmount => BuilderError::InvalidAmount,
+        }
+    }
+}
+
+/// Returns the estimated weight of an input spending the script pubkey once
+/// signed, assuming compressed keys: P2PKH, P2WPKH and P2TR key path spends
+pub fn input_weight(script_pubkey: &Script) -> Option<usize> {
+    let address = Address::from_script_pubkey(script_pubkey, false)?;
+    match address.address_type()? {
+        // <sig> <pubkey> in the script sig, 4 weight units per byte
+        AddressType::P2pkh => {
+            Some(INPUT_BASE_WEIGHT + (1 + MAX_ECDSA_SIG_SIZE + 1 + COMPRESSED_KEY_SIZE) * 4)
+        }
+        // The witness item count, <sig> and <pubkey>
+        AddressType::P2wpkh => {
+            Some(INPUT_BASE_WEIGHT + 1 + 1 + MAX_ECDSA_SIG_SIZE + 1 + COMPRESSED_KEY_SIZE)
+        }
+        // The witness item count and a 64-byte Schnorr signature
+        AddressType::P2tr => Some(INPUT_BASE_WEIGHT + 1 + 1 + 64),
+        AddressType::P2sh | AddressType::P2wsh => None,
+    }
+}
+
+/// Returns the amount below which an output paying to the script pubkey is
+/// dust, as Bitcoin Core computes it
+pub fn dust_threshold(script_pubkey: &Script) -> Amount {
+    if script_pubkey.is_op_return() {
+        return Amount::ZERO;
+    }
+    let output_size = script_pubkey.serialize().len() + 8;
+    let is_witness = script_pubkey.as_witness_program().is_some();
+    // The size of an input spending the output, witness discounted
+    let spend_size = if is_witness {
+        32 + 4 + 1 + 107 / 4 + 4
+    } else {
+        32 + 4 + 1 + 107 + 4
+    };
+    Amount::from_sat((output_size + spend_size) as u64 * DUST_RELAY_FEE_RATE)
+}
+
+/// The unsigned transaction a TxBuilder builds, with its fee
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BuiltTx {
+    pub tx: Tx,
+    pub fee: Amount,
+    /// The estimated weight once signed
+    pub weight: usize,
+    /// The index of the change output, if there is one
+    pub change_index: Option<usize>,
+}
+
+/// Builds a transaction spending its UTXOs, and as many candidates as coin
+/// selection needs, to the recipients, paying the fee for the target fee rate
+/// and returning the rest as change
+pub struct TxBuilder {
+    utxos: Vec<Utxo>,
+    candidates: Vec<Utxo>,
+    recipients: Vec<TxOutput>,
+    change_script: Option<Script>,
+    fee_rate: f64,
+    long_term_fee_rate: f64,
+    version: u32,
+    locktime: u32,
+    sequence: u32,
+}
+
+impl Default for TxBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxBuilder {
+    /// Creates a builder for a version 2 transaction with no locktime, whose
+    /// inputs signal replaceability (BIP125)
+    pub fn new() -> Self {
+        Self {
+            utxos: vec![],
+            candidates: vec![],
+            recipients: vec![],
+            change_script: None,
+            fee_rate: 1.0,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
+            version: 2,
+            locktime: 0,
+            sequence: 0xfffffffd,
+        }
+    }
+
+    /// Adds a UTXO the transaction must spend
+    pub fn add_utxo(mut self, utxo: Utxo) -> Self {
+        self.utxos.push(utxo);
+        self
+    }
+
+    /// Adds a UTXO coin selection may spend if the UTXOs added with add_utxo
+    /// don't cover the recipients and fee
+    pub fn add_candidate(mut self, utxo: Utxo) -> Self {
+        self.candidates.push(utxo);
+        self
+    }
+
+    /// Pays the amount to the address
+    pub fn add_recipient(self, address: &Address, amount: Amount) -> Self {
+        self.add_recipient_script(address.script_pubkey(), amount)
+    }
+
+    /// Pays the amount to the script pubkey
+    pub fn add_recipient_script(mut self, script_pubkey: Script, amount: Amount) -> Self {
+        self.recipients.push(TxOutput::new(amount, script_pubkey));
+        self
+    }
+
+    /// Sends the change to the address. Without one, the change goes to the
+    /// fee.
+    pub fn change_address(mut self, address: &Address) -> Self {
+        self.change_script = Some(address.script_pubkey());
+        self
+    }
+
+    /// Sets the target fee rate in sat/vB
+    pub fn fee_rate(mut self, fee_rate: f64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Sets the fee rate in sat/vB coin selection expects to spend change
+    /// at in the long run
+    pub fn long_term_fee_rate(mut self, fee_rate: f64) -> Self {
+        self.long_term_fee_rate = fee_rate;
+        self
+    }
+
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn locktime(mut self, locktime: u32) -> Self {
+        self.locktime = locktime;
+        self
+    }
+
+    /// Sets the sequence of every input
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Builds the unsigned transaction. The change output is added last, and
+    /// only if it isn't dust once it has paid for itself.
+    pub fn build(mut self) -> Result<BuiltTx, BuilderError> {
+        if self.utxos.is_empty() && self.candidates.is_empty() {
+            return Err(BuilderError::NoInputs);
+        }
+        if self.recipients.is_empty() {
+            return Err(BuilderError::NoRecipients);
+        }
+        // Coin selection works out fees of inputs and outputs at the rate,
+        // which must fit in the money supply for any transaction in a block
+        if !self.fee_rate.is_finite()
+            || self.fee_rate < 0.0
+            || checked_fee(MAX_BLOCK_WEIGHT, self.fee_rate).is_err()
+        {
+            return Err(BuilderError::InvalidFeeRate);
+        }
+        for (index, recipient) in self.recipients.iter().enumerate() {
+            if recipient.get_amount() < dust_threshold(&recipient.get_script_pubkey()) {
+                return Err(BuilderError::DustOutput(index));
+            }
+        }
+
+        let sent = Amount::checked_sum(self.recipients.iter().map(|output| output.get_amount()))
+            .filter(|sent| sent.is_valid())
+            .ok_or(BuilderError::InvalidAmount)?;
+        if !self.candidates.is_empty() {
+            self.select_candidates(sent)?;
+        }
+
+        let input_weights = self
+            .utxos
+            .iter()
+            .map(|utxo| utxo.spend_weight)
+            .collect::<Vec<_>>();
+        // Inputs without a witness still need an empty one in a segwit
+        // transaction; anything but P2PKH is assumed to have a witness
+        let legacy_inputs = self.utxos.iter().filter(|utxo| is_p2pkh(utxo)).count();
+        let segwit = legacy_inputs < self.utxos.len();
+
+        let available = Amount::checked_sum(self.utxos.iter().map(|utxo| utxo.amount))
+            .filter(|available| available.is_valid())
+            .ok_or(BuilderError::InvalidAmount)?;
+
+        let mut outputs = self.recipients.clone();
+        let weight = estimate_weight(&input_weights, &outputs, segwit, legacy_inputs);
+        let fee = checked_fee(weight, self.fee_rate)?;
+        let needed = sent.checked_add(fee).ok_or(BuilderError::InvalidAmount)?;
+        if available < needed {
+            return Err(BuilderError::InsufficientFunds { available, needed });
+        }
+
+        let mut result = (weight, fee, None);
+        if let Some(change_script) = self.change_script {
+            outputs.push(TxOutput::new(Amount::ZERO, change_script.clone()));
+            let weight = estimate_weight(&input_weights, &outputs, segwit, legacy_inputs);
+            let fee = checked_fee(weight, self.fee_rate)?;
+            let needed = sent.checked_add(fee).ok_or(BuilderError::InvalidAmount)?;
+            let change = available.saturating_sub(needed);
+            outputs.pop();
+            if change >= dust_threshold(&change_script) && change > Amount::ZERO {
+                outputs.push(TxOutput::new(change, change_script));
+                result = (weight, fee, Some(outputs.len() - 1));
+            }
+        }
+        let (weight, fee, change_index) = result;
+        // Without change, whatever the recipients don't get is the fee
+        let fee = if change_index.is_some() {
+            fee
+        } else {
+            available - sent
+        };
+
+        let inputs = self
+            .utxos
+            .iter()
+            .map(|utxo| TxInput::new(utxo.outpoint, Script::new(vec![]), self.sequence))
+            .collect();
+        Ok(BuiltTx {
+            tx: Tx::new(self.version, inputs, outputs, self.locktime),
+            fee,
+            weight,
+            change_index,
+        })
+    }
+
+    /// Adds the candidates coin selection picks to pay what the UTXOs added
+    /// with add_utxo don't
+    fn select_candidates(&mut self, sent: Amount) -> Result<(), BuilderError> {
+        // Assume a segwit transaction, which costs at most a few weight units
+        // more
+        let base_weight = estimate_weight(&[], &self.recipients, true, 0);
+        let base_fee = checked_fee(base_weight, self.fee_rate)?.to_sat() as i64;
+        let preselected = self
+            .utxos
+            .iter()
+            .map(|utxo| utxo.effective_value(self.fee_rate).to_sat())
+            .sum::<i64>();
+        // The base fee is part of the target, since the UTXOs added with
+        // add_utxo may pay some of it
+        let target = sent.to_sat() as i64 + base_fee - preselected;
+        if target <= 0 {
+            return Ok(());
+        }
+        let mut params = match &self.change_script {
+            Some(change_script) => SelectionParams::new(self.fee_rate, 0, change_script),
+            None => SelectionParams::changeless(self.fee_rate, 0),
+        };
+        params.long_term_fee_rate = self.long_term_fee_rate;
+
+        // Legacy inputs need an empty witness in a segwit transaction
+        let candidates = self
+            .candidates
+            .iter()
+            .cloned()
+            .map(|mut utxo| {
+                if is_p2pkh(&utxo) {
+                    utxo.spend_weight += 1;
+                }
+                utxo
+            })
+            .collect::<Vec<_>>();
+        let target = Amount::from_sat(target as u64);
+        let selection = select_coins(&candidates, target, &params, &mut rand::thread_rng())?;
+        for utxo in selection.selected {
+            let index = candidates
+                .iter()
+                .position(|candidate| *candidate == utxo)
+                .unwrap();
+            self.utxos.push(self.candidates[index].clone());
+        }
+        Ok(())
+    }
+}
+
+fn is_p2pkh(utxo: &Utxo) -> bool {
+    Address::from_script_pubkey(&utxo.script_pubkey, false)
+        .and_then(|address| address.address_type())
+        == Some(AddressType::P2pkh)
+}
+
+/// The weight of a signed transaction with inputs of the given weights
+fn estimate_weight(
+    input_weights: &[usize],
+    outputs: &[TxOutput],
+    segwit: bool,
+    legacy_inputs: usize,
+) -> usize {
+    let mut base_size = 4 + 4;
+    base_size += encode_varint(input_weights.len() as u64).unwrap().len();
+    base_size += encode_varint(outputs.len() as u64).unwrap().len();
+    base_size += outputs
+        .iter()
+        .map(|output| output.serialize().len())
+        .sum::<usize>();
+    let mut weight = base_size * 4 + input_weights.iter().sum::<usize>();
+    if segwit {
+        // The marker and flag, and an empty witness for every legacy input
+        weight += 2 + legacy_inputs;
+    }
+    weight
+}
+
+/// The fee of a transaction of the weight at the fee rate, or InvalidFeeRate
+/// if it is over MAX_MONEY
+fn checked_fee(weight: usize, fee_rate: f64) -> Result<Amount, BuilderError> {
+    Some(fee_for_weight(weight, fee_rate))
+        .filter(|fee| fee.is_valid())
+        .ok_or(BuilderError::InvalidFeeRate)
+}
+
+/// The fee of a transaction of the weight at the fee rate, rounded up
+pub(super) fn fee_for_weight(weight: usize, fee_rate: f64) -> Amount {
+    let vsize = weight.div_ceil(4);
+    Amount::from_sat((vsize as f64 * fee_rate).ceil() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigUint;
+
+    use crate::{
+        ecc::private_key::PrivateKey,
+        psbt::Psbt,
+        transactions::{hashes::Txid, input::OutPoint},
+    };
+
+    use super::*;
+
+    fn address(secret: u32) -> Address {
+        Address::p2wpkh(&PrivateKey::new(&BigUint::from(secret)).point(), false)
+    }
+
+    fn utxo(amount: u64, script_pubkey: Script) -> Utxo {
+        let outpoint = OutPoint {
+            txid: Txid::from_bytes([1; 32]),
+            vout: 0,
+        };
+        Utxo::new(outpoint, Amount::from_sat(amount), script_pubkey).unwrap()
+    }
+
+    #[test]
+    fn test_build_with_change() {
+        let built = TxBuilder::new()
+            .add_utxo(utxo(100_000, address(1).script_pubkey()))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .change_address(&address(3))
+            .fee_rate(2.0)
+            .build()
+            .unwrap();
+        // 72 bytes outside the inputs, one P2WPKH input and the segwit
+        // marker and flag
+        assert_eq!(built.weight, 72 * 4 + 273 + 2);
+        assert_eq!(built.fee, Amount::from_sat(282));
+        assert_eq!(built.change_index, Some(1));
+        let outputs = built.tx.get_outputs();
+        assert_eq!(outputs[1].get_amount().to_sat(), 100_000 - 50_000 - 282);
+        assert_eq!(outputs[1].get_script_pubkey(), address(3).script_pubkey());
+    }
+
+    #[test]
+    fn test_build_drops_dust_change() {
+        let built = TxBuilder::new()
+            .add_utxo(utxo(50_400, address(1).script_pubkey()))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .change_address(&address(3))
+            .build()
+            .unwrap();
+        assert_eq!(built.change_index, None);
+        assert_eq!(built.tx.get_outputs().len(), 1);
+        assert_eq!(built.fee, Amount::from_sat(400));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let p2wpkh = address(1).script_pubkey();
+        let err = TxBuilder::new()
+            .add_utxo(utxo(50_000, p2wpkh.clone()))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .build();
+        assert_eq!(
+            err.unwrap_err(),
+            BuilderError::InsufficientFunds {
+                available: Amount::from_sat(50_000),
+                needed: Amount::from_sat(50_110)
+            }
+        );
+
+        let err = TxBuilder::new()
+            .add_utxo(utxo(50_000, p2wpkh.clone()))
+            .add_recipient(&address(2), Amount::from_sat(293))
+            .build();
+        assert_eq!(err.unwrap_err(), BuilderError::DustOutput(0));
+
+        let builder = || TxBuilder::new().add_recipient(&address(2), Amount::from_sat(10_000));
+        assert_eq!(builder().build(), Err(BuilderError::NoInputs));
+        assert_eq!(
+            builder()
+                .add_utxo(utxo(50_000, p2wpkh))
+                .fee_rate(-1.0)
+                .build(),
+            Err(BuilderError::InvalidFeeRate)
+        );
+    }
+
+    #[test]
+    fn test_build_extreme_fee_rate() {
+        let spk = address(1).script_pubkey();
+        let utxo = |vout| {
+            let outpoint = OutPoint {
+                txid: Txid::from_bytes([2; 32]),
+                vout,
+            };
+            Utxo::new(outpoint, Amount::MAX_MONEY, spk.clone()).unwrap()
+        };
+        let builder = || TxBuilder::new().add_recipient(&address(2), Amount::from_sat(10_000));
+        for fee_rate in [1e30, f64::MAX, 1e10] {
+            let with_utxo = builder().add_utxo(utxo(0)).fee_rate(fee_rate).build();
+            assert_eq!(with_utxo, Err(BuilderError::InvalidFeeRate));
+            let with_candidate = builder().add_candidate(utxo(0)).fee_rate(fee_rate).build();
+            assert_eq!(with_candidate, Err(BuilderError::InvalidFeeRate));
+        }
+        // High but payable
+        let built = builder().add_utxo(utxo(0)).fee_rate(1e9).build().unwrap();
+        assert_eq!(built.fee, Amount::MAX_MONEY - Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn test_build_selects_candidates() {
+        let spk = address(1).script_pubkey();
+        let candidate = |vout, amount| {
+            let outpoint = OutPoint {
+                txid: Txid::from_bytes([2; 32]),
+                vout,
+            };
+            Utxo::new(outpoint, Amount::from_sat(amount), spk.clone()).unwrap()
+        };
+        // 20k and 30k pay 49k and the fee without change at the long term
+        // fee rate
+        let built = TxBuilder::new()
+            .add_candidate(candidate(0, 100_000))
+            .add_candidate(candidate(1, 20_000))
+            .add_candidate(candidate(2, 30_000))
+            .add_recipient(&address(2), Amount::from_sat(50_000 - 690 * 2 - 430))
+            .change_address(&address(3))
+            .fee_rate(10.0)
+            .build()
+            .unwrap();
+        let mut vouts = built
+            .tx
+            .get_inputs()
+            .iter()
+            .map(|input| input.get_prev_index())
+            .collect::<Vec<_>>();
+        vouts.sort();
+        assert_eq!(vouts, [1, 2]);
+        assert_eq!(built.change_index, None);
+
+        // A UTXO added with add_utxo is always spent
+        let built = TxBuilder::new()
+            .add_utxo(utxo(60_000, spk.clone()))
+            .add_candidate(candidate(0, 100_000))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .change_address(&address(3))
+            .build()
+            .unwrap();
+        assert_eq!(built.tx.get_inputs().len(), 1);
+
+        let err = TxBuilder::new()
+            .add_candidate(candidate(0, 10_000))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .build();
+        assert!(matches!(err, Err(BuilderError::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn test_build_with_spend_weight() {
+        // P2SH-P2WPKH has no estimate from the script pubkey alone
+        let p2sh = Address::p2sh(&address(1).script_pubkey(), false).script_pubkey();
+        let outpoint = OutPoint {
+            txid: Txid::from_bytes([1; 32]),
+            vout: 0,
+        };
+        assert!(Utxo::new(outpoint, Amount::from_sat(50_000), p2sh.clone()).is_none());
+        let built = TxBuilder::new()
+            .add_utxo(Utxo {
+                outpoint,
+                amount: Amount::from_sat(50_000),
+                script_pubkey: p2sh,
+                spend_weight: 364,
+            })
+            .add_recipient(&address(2), Amount::from_sat(40_000))
+            .build()
+            .unwrap();
+        assert_eq!(built.weight, 41 * 4 + 364 + 2);
+    }
+
+    #[test]
+    fn test_build_settings() {
+        let built = TxBuilder::new()
+            .add_utxo(utxo(100_000, address(1).script_pubkey()))
+            .add_recipient_script(address(2).script_pubkey(), Amount::from_sat(50_000))
+            .version(1)
+            .locktime(800_000)
+            .sequence(0xfffffffe)
+            .build()
+            .unwrap();
+        assert_eq!(built.tx.get_version(), 1);
+        assert_eq!(built.tx.get_locktime().value(), 800_000);
+        assert_eq!(built.tx.get_inputs()[0].get_sequence(), 0xfffffffe);
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        let key = PrivateKey::new(&BigUint::from(1u32)).point();
+        assert_eq!(
+            dust_threshold(&Address::p2pkh(&key, false).script_pubkey()),
+            Amount::from_sat(546)
+        );
+        assert_eq!(
+            dust_threshold(&Address::p2wpkh(&key, false).script_pubkey()),
+            Amount::from_sat(294)
+        );
+        assert_eq!(
+            dust_threshold(&Address::p2tr(&key, None, false).script_pubkey()),
+            Amount::from_sat(330)
+        );
+        assert_eq!(dust_threshold(&Script::op_return(&[])), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_weight_estimate_covers_signed_tx() {
+        let key = PrivateKey::new(&BigUint::from(1u32));
+        let spk = address(1).script_pubkey();
+        let built = TxBuilder::new()
+            .add_utxo(utxo(100_000, spk.clone()))
+            .add_recipient(&address(2), Amount::from_sat(50_000))
+            .change_address(&address(3))
+            .build()
+            .unwrap();
+
+        let mut psbt = Psbt::from_unsigned_tx(built.tx).unwrap();
+        psbt.add_witness_utxo(0, TxOutput::new(Amount::from_sat(100_000), spk))
+            .unwrap();
+        psbt.sign(&key).unwrap();
+        psbt.finalize().unwrap();
+        let weight = psbt.extract_tx().unwrap().weight();
+        assert!(weight <= built.weight);
+        assert!(built.weight - weight <= 2);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashSet, fmt};
+
+use crate::amount::Amount;
+
+use super::{sigops::WITNESS_SCALE_FACTOR, tx::Tx};
+
+/// The weight limit of blocks (BIP141)
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+/// Why a transaction is invalid regardless of the chain it is in
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CheckError {
+    NoInputs,
+    NoOutputs,
+    /// The transaction without witnesses wouldn't fit in a block
+    Oversize(usize),
+    /// The value of the output is over MAX_MONEY
+    OutputTooLarge(usize),
+    /// The values of the outputs sum to more than MAX_MONEY
+    OutputTotalTooLarge,
+    DuplicateInput(usize),
+    /// The coinbase script sig isn't 2 to 100 bytes long
+    CoinbaseLength(usize),
+    /// The input of a transaction other than a coinbase spends no output
+    NullPrevout(usize),
+}
+
+impl CheckError {
+    /// The reason Bitcoin Core gives when rejecting the transaction
+    pub fn reason(&self) -> &'static str {
+        match self {
+            CheckError::NoInputs => "bad-txns-vin-empty",
+            CheckError::NoOutputs => "bad-txns-vout-empty",
+            CheckError::Oversize(_) => "bad-txns-oversize",
+            CheckError::OutputTooLarge(_) => "bad-txns-vout-toolarge",
+            CheckError::OutputTotalTooLarge => "bad-txns-txouttotal-toolarge",
+            CheckError::DuplicateInput(_) => "bad-txns-inputs-duplicate",
+            CheckError::CoinbaseLength(_) => "bad-cb-length",
+            CheckError::NullPrevout(_) => "bad-txns-prevout-null",
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::NoInputs => write!(f, "no inputs"),
+            CheckError::NoOutputs => write!(f, "no outputs"),
+            CheckError::Oversize(size) => write!(f, "base size {} is too large", size),
+            CheckError::OutputTooLarge(index) => {
+                write!(f, "value of output {} is over the money supply", index)
+            }
+            CheckError::OutputTotalTooLarge => write!(f, "outputs total over the money supply"),
+            CheckError::DuplicateInput(index) => write!(f, "input {} is a duplicate", index),
+            CheckError::CoinbaseLength(length) => {
+                write!(f, "coinbase script sig length {} is out of range", length)
+            }
+            CheckError::NullPrevout(index) => write!(f, "input {} spends a null outpoint", index),
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Consensus checks
+impl Tx {
+    /// Checks the consensus rules that don't depend on the outputs spent or
+    /// the chain, as Bitcoin Core's CheckTransaction. Transactions failing
+    /// them can never be mined.
+    pub fn check(&self) -> Result<(), CheckError> {
+        let inputs = self.get_inputs();
+        let outputs = self.get_outputs();
+        if inputs.is_empty() {
+            return Err(CheckError::NoInputs);
+        }
+        if outputs.is_empty() {
+            return Err(CheckError::NoOutputs);
+        }
+        let base_size = self.serialize_legacy().len();
+        if base_size * WITNESS_SCALE_FACTOR > MAX_BLOCK_WEIGHT {
+            return Err(CheckError::Oversize(base_size));
+        }
+
+        let mut total = Amount::ZERO;
+        for (index, output) in outputs.iter().enumerate() {
+            let amount = output.get_amount();
+            if !amount.is_valid() {
+                return Err(CheckError::OutputTooLarge(index));
+            }
+            total = total
+                .checked_add(amount)
+                .filter(|total| total.is_valid())
+                .ok_or(CheckError::OutputTotalTooLarge)?;
+        }
+
+        let mut outpoints = HashSet::new();
+        for (index, input) in inputs.iter().enumerate() {
+            if !outpoints.insert(input.outpoint()) {
+                return Err(CheckError::DuplicateInput(index));
+            }
+        }
+
+        if self.is_coinbase() {
+            let length = inputs[0].get_script_sig().raw_serialize().len();
+            if !(2..=100).contains(&length) {
+                return Err(CheckError::CoinbaseLength(length));
+            }
+        } else if let Some(index) = inputs.iter().position(|input| input.outpoint().is_null()) {
+            return Err(CheckError::NullPrevout(index));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        script::{Command, Script},
+        transactions::{
+            hashes::Txid,
+            input::{OutPoint, TxInput},
+            output::TxOutput,
+        },
+    };
+
+    use super::*;
+
+    fn input(vout: u32) -> TxInput {
+        TxInput::new(
+            OutPoint::new(Txid::from_bytes([1; 32]), vout),
+            Script::new(vec![]),
+            0,
+        )
+    }
+
+    fn output(amount: Amount) -> TxOutput {
+        TxOutput::new(amount, Script::p2wpkh(&[0; 20]))
+    }
+
+    #[test]
+    fn test_check() {
+        let tx = Tx::new(
+            2,
+            vec![input(0), input(1)],
+            vec![output(Amount::ONE_BTC)],
+            0,
+        );
+        assert_eq!(tx.check(), Ok(()));
+
+        let tx = Tx::new(2, vec![], vec![output(Amount::ONE_BTC)], 0);
+        assert_eq!(tx.check(), Err(CheckError::NoInputs));
+        let tx = Tx::new(2, vec![input(0)], vec![], 0);
+        assert_eq!(tx.check(), Err(CheckError::NoOutputs));
+        let tx = Tx::new(
+            2,
+            vec![input(0), input(1), input(0)],
+            vec![output(Amount::ONE_BTC)],
+            0,
+        );
+        assert_eq!(tx.check(), Err(CheckError::DuplicateInput(2)));
+        let tx = Tx::new(
+            2,
+            vec![
+                input(0),
+                TxInput::new(OutPoint::null(), Script::new(vec![]), 0),
+            ],
+            vec![output(Amount::ONE_BTC)],
+            0,
+        );
+        assert_eq!(tx.check(), Err(CheckError::NullPrevout(1)));
+
+        let mut big = input(0);
+        big.set_script_sig(Script::new(vec![Command::push(vec![0; 520]); 2_000]));
+        let tx = Tx::new(2, vec![big], vec![output(Amount::ONE_BTC)], 0);
+        let err = tx.check().unwrap_err();
+        assert_eq!(err, CheckError::Oversize(tx.serialize_legacy().len()));
+        assert_eq!(err.reason(), "bad-txns-oversize");
+    }
+
+    #[test]
+    fn test_check_amounts() {
+        let max = output(Amount::MAX_MONEY);
+        let tx = Tx::new(2, vec![input(0)], vec![max.clone()], 0);
+        assert_eq!(tx.check(), Ok(()));
+        let too_large = output(Amount::from_sat(Amount::MAX_MONEY.to_sat() + 1));
+        let tx = Tx::new(2, vec![input(0)], vec![max.clone(), too_large], 0);
+        assert_eq!(tx.check(), Err(CheckError::OutputTooLarge(1)));
+        let halves = vec![output(Amount::from_sat(Amount::MAX_MONEY.to_sat() / 2)); 2];
+        let tx = Tx::new(2, vec![input(0)], halves, 0);
+        assert_eq!(tx.check(), Ok(()));
+        let tx = Tx::new(2, vec![input(0)], vec![max, output(Amount::ONE_SAT)], 0);
+        assert_eq!(tx.check(), Err(CheckError::OutputTotalTooLarge));
+        let tx = Tx::new(
+            2,
+            vec![input(0)],
+            vec![output(Amount::from_sat(u64::MAX)); 2],
+            0,
+        );
+        assert_eq!(tx.check(), Err(CheckError::OutputTooLarge(0)));
+    }
+
+    #[test]
+    fn test_check_coinbase() {
+        let coinbase = |data: usize| {
+            let script_sig = Script::new(vec![Command::push(vec![1; data])]);
+            let input = TxInput::new(OutPoint::null(), script_sig, u32::MAX);
+            Tx::new(1, vec![input], vec![output(Amount::from_sat(5000))], 0)
+        };
+        assert_eq!(coinbase(1).check(), Ok(()));
+        // OP_PUSHDATA1, the length and 98 bytes
+        assert_eq!(coinbase(98).check(), Ok(()));
+        assert_eq!(coinbase(0).check(), Err(CheckError::CoinbaseLength(1)));
+        assert_eq!(coinbase(99).check(), Err(CheckError::CoinbaseLength(101)));
+    }
+}
@@ -0,0 +1,720 @@
+use std::{cmp::Reverse, fmt};
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    amount::{Amount, SignedAmount},
+    script::Script,
+};
+
+use super::{
+    builder::{dust_threshold, fee_for_weight, input_weight},
+    input::OutPoint,
+};
+
+/// Branch and bound gives up after visiting this many nodes
+const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// The number of random subsets the knapsack solver tries
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+/// The fee rate, in sat/vB, a wallet expects to consolidate its coins at
+pub const DEFAULT_LONG_TERM_FEE_RATE: f64 = 10.0;
+
+/// An output the wallet can spend
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub script_pubkey: Script,
+    /// The weight of the input spending the output once signed
+    pub spend_weight: usize,
+}
+
+impl Utxo {
+    /// Creates a UTXO whose spend weight is estimated from the script
+    /// pubkey. Returns None for script types without an estimate.
+    pub fn new(outpoint: OutPoint, amount: Amount, script_pubkey: Script) -> Option<Self> {
+        let spend_weight = input_weight(&script_pubkey)?;
+        Some(Utxo {
+            outpoint,
+            amount,
+            script_pubkey,
+            spend_weight,
+        })
+    }
+
+    /// Returns the amount minus the fee to spend the output at the fee rate
+    pub fn effective_value(&self, fee_rate: f64) -> SignedAmount {
+        SignedAmount::from_sat(self.sats(fee_rate))
+    }
+
+    /// The effective value in satoshis
+    fn sats(&self, fee_rate: f64) -> i64 {
+        sats(self.amount) - sats(fee_for_weight(self.spend_weight, fee_rate))
+    }
+
+    /// Returns the fee to spend the output now minus the fee to spend it at
+    /// the long term fee rate
+    fn waste(&self, params: &SelectionParams) -> i64 {
+        sats(fee_for_weight(self.spend_weight, params.fee_rate))
+            - sats(fee_for_weight(self.spend_weight, params.long_term_fee_rate))
+    }
+}
+
+/// The fee rates and transaction shape coin selection works with
+#[derive(Debug, PartialEq, Clone)]
+pub struct SelectionParams {
+    /// The fee rate of the transaction, in sat/vB
+    pub fee_rate: f64,
+    /// The fee rate expected in the long run, in sat/vB. Above it, selection
+    /// prefers fewer inputs; below it, more.
+    pub long_term_fee_rate: f64,
+    /// The weight of the transaction without inputs or change
+    pub base_weight: usize,
+    /// The weight of the change output
+    pub change_weight: usize,
+    /// The weight of the input spending the change later
+    pub change_spend_weight: usize,
+    /// Change below this amount goes to the fee instead
+    pub min_change: Amount,
+}
+
+impl SelectionParams {
+    /// Creates parameters for a transaction of the base weight that sends
+    /// its change to the script pubkey
+    pub fn new(fee_rate: f64, base_weight: usize, change_script: &Script) -> Self {
+        SelectionParams {
+            fee_rate,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
+            base_weight,
+            change_weight: (change_script.serialize().len() + 8) * 4,
+            // Unknown change types only count the output
+            change_spend_weight: input_weight(change_script).unwrap_or(0),
+            min_change: dust_threshold(change_script),
+        }
+    }
+
+    /// Creates parameters for a transaction that never has change
+    pub fn changeless(fee_rate: f64, base_weight: usize) -> Self {
+        SelectionParams {
+            fee_rate,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
+            base_weight,
+            change_weight: 0,
+            change_spend_weight: 0,
+            min_change: Amount::MAX_MONEY,
+        }
+    }
+
+    /// The fee of the transaction without inputs or change
+    fn base_fee(&self) -> i64 {
+        sats(fee_for_weight(self.base_weight, self.fee_rate))
+    }
+
+    /// The fee of the change output
+    fn change_fee(&self) -> i64 {
+        sats(fee_for_weight(self.change_weight, self.fee_rate))
+    }
+
+    /// The fee of creating the change output now and spending it later
+    fn cost_of_change(&self) -> i64 {
+        self.change_fee()
+            + sats(fee_for_weight(
+                self.change_spend_weight,
+                self.long_term_fee_rate,
+            ))
+    }
+
+    fn min_change(&self) -> i64 {
+        sats(self.min_change)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Algorithm {
+    BranchAndBound,
+    SingleRandomDraw,
+    Knapsack,
+    LargestFirst,
+}
+
+/// The UTXOs an algorithm selected for a payment
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Selection {
+    pub selected: Vec<Utxo>,
+    pub algorithm: Algorithm,
+    /// The fee, including any excess left to it when there is no change
+    pub fee: Amount,
+    /// The change amount, zero without change
+    pub change: Amount,
+    /// The cost of the selection compared to spending the same inputs at the
+    /// long term fee rate, plus the cost of change or the excess
+    pub waste: i64,
+}
+
+impl Selection {
+    /// Computes the change, fee and waste of a selection covering the target.
+    /// Returns None if the selected amounts sum to more than MAX_MONEY.
+    fn new(
+        selected: Vec<Utxo>,
+        target: Amount,
+        params: &SelectionParams,
+        algorithm: Algorithm,
+    ) -> Option<Self> {
+        let effective_value = selected
+            .iter()
+            .map(|utxo| utxo.sats(params.fee_rate))
+            .sum::<i64>();
+        let excess = effective_value - sats(target) - params.base_fee();
+        let change = excess - params.change_fee();
+        let change = if algorithm != Algorithm::BranchAndBound && change >= params.min_change() {
+            Amount::from_sat(change as u64)
+        } else {
+            Amount::ZERO
+        };
+        let inputs_waste = selected.iter().map(|utxo| utxo.waste(params)).sum::<i64>();
+        let waste = if change > Amount::ZERO {
+            inputs_waste + params.cost_of_change()
+        } else {
+            inputs_waste + excess
+        };
+        let amount = Amount::checked_sum(selected.iter().map(|utxo| utxo.amount))
+            .filter(|amount| amount.is_valid())?;
+        Some(Selection {
+            fee: amount.checked_sub(target)?.checked_sub(change)?,
+            selected,
+            algorithm,
+            change,
+            waste,
+        })
+    }
+
+    /// Returns the total amount of the selected UTXOs
+    pub fn amount(&self) -> Amount {
+        self.selected.iter().map(|utxo| utxo.amount).sum()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SelectionError {
+    /// The UTXOs are worth less than the target and fees, counting only what
+    /// each is worth once its own fee is paid
+    InsufficientFunds { available: Amount, needed: Amount },
+    /// The UTXOs sum to more than MAX_MONEY, or the target is over it
+    InvalidAmount,
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionError::InsufficientFunds { available, needed } => {
+                write!(
+                    f,
+                    "insufficient funds: {} available, {} needed",
+                    available, needed
+                )
+            }
+            SelectionError::InvalidAmount => write!(f, "invalid amount"),
+        }
+    }
+}
+
+impl std::error::Error for SelectionError {}
+
+/// Amounts are at most MAX_MONEY in valid transactions, so satoshis fit in an
+/// i64 with room for the effective value arithmetic
+fn sats(amount: Amount) -> i64 {
+    amount.to_sat().min(i64::MAX as u64 / 2) as i64
+}
+
+/// Returns the UTXOs worth spending at the fee rate, with their effective
+/// values, largest first. Returns None if the UTXOs sum to more than
+/// MAX_MONEY, which no valid outputs can.
+fn positive_utxos(utxos: &[Utxo], fee_rate: f64) -> Option<Vec<(i64, &Utxo)>> {
+    Amount::checked_sum(utxos.iter().map(|utxo| utxo.amount)).filter(|total| total.is_valid())?;
+    let mut pool = utxos
+        .iter()
+        .map(|utxo| (utxo.sats(fee_rate), utxo))
+        .filter(|(value, _)| *value > 0)
+        .collect::<Vec<_>>();
+    pool.sort_by_key(|(value, _)| Reverse(*value));
+    Some(pool)
+}
+
+/// Searches for the set of UTXOs that pays exactly the target and fees,
+/// give or take the cost of change, with the least waste, so the transaction
+/// needs no change output
+pub fn select_bnb(utxos: &[Utxo], target: Amount, params: &SelectionParams) -> Option<Selection> {
+    let pool = positive_utxos(utxos, params.fee_rate)?;
+    let wastes = pool
+        .iter()
+        .map(|(_, utxo)| utxo.waste(params))
+        .collect::<Vec<_>>();
+    let selection_target = sats(target) + params.base_fee();
+    let cost_of_change = params.cost_of_change();
+    let is_fee_rate_high = params.fee_rate > params.long_term_fee_rate;
+
+    let mut available = pool.iter().map(|(value, _)| value).sum::<i64>();
+    if available < selection_target {
+        return None;
+    }
+    let mut current = Vec::<usize>::new();
+    let mut current_value = 0;
+    let mut current_waste = 0;
+    let mut best: Option<Vec<usize>> = None;
+    let mut best_waste = i64::MAX;
+
+    // Depth first, trying to include each UTXO before excluding it
+    let mut index = 0;
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        if current_value + available < selection_target
+            || current_value > selection_target + cost_of_change
+            || (current_waste > best_waste && is_fee_rate_high)
+        {
+            // Can't reach the target, overshoots it, or only gets worse
+            backtrack = true;
+        } else if current_value >= selection_target {
+            let waste = current_waste + current_value - selection_target;
+            if waste <= best_waste {
+                best = Some(current.clone());
+                best_waste = waste;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = current.last() else {
+                break;
+            };
+            // Put the UTXOs after the last included one back, then try
+            // excluding it
+            index -= 1;
+            while index > last {
+                available += pool[index].0;
+                index -= 1;
+            }
+            current_value -= pool[last].0;
+            current_waste -= wastes[last];
+            current.pop();
+        } else {
+            available -= pool[index].0;
+            // Excluding a UTXO and including the next one of the same value
+            // gives the same result as including it
+            let skip = index > 0
+                && current.last() != Some(&(index - 1))
+                && pool[index].0 == pool[index - 1].0
+                && wastes[index] == wastes[index - 1];
+            if !skip {
+                current.push(index);
+                current_value += pool[index].0;
+                current_waste += wastes[index];
+            }
+        }
+        index += 1;
+    }
+
+    let selected = best?
+        .into_iter()
+        .map(|index| pool[index].1.clone())
+        .collect();
+    Selection::new(selected, target, params, Algorithm::BranchAndBound)
+}
+
+/// Picks UTXOs at random until they pay the target, fees and a change
+/// output worth keeping
+pub fn select_srd(
+    utxos: &[Utxo],
+    target: Amount,
+    params: &SelectionParams,
+    rng: &mut impl Rng,
+) -> Option<Selection> {
+    let mut pool = positive_utxos(utxos, params.fee_rate)?;
+    pool.shuffle(rng);
+    let needed = (sats(target) + params.base_fee() + params.change_fee())
+        .saturating_add(params.min_change());
+    let mut selected = vec![];
+    let mut value = 0;
+    for (effective_value, utxo) in pool {
+        selected.push(utxo.clone());
+        value += effective_value;
+        if value >= needed {
+            return Selection::new(selected, target, params, Algorithm::SingleRandomDraw);
+        }
+    }
+    None
+}
+
+/// Bitcoin Core's original selection: an exact match, all the smaller UTXOs,
+/// the best random subset of them or the smallest larger UTXO, whichever
+/// comes closest to the target while leaving change worth keeping
+pub fn select_knapsack(
+    utxos: &[Utxo],
+    target: Amount,
+    params: &SelectionParams,
+    rng: &mut impl Rng,
+) -> Option<Selection> {
+    let mut pool = positive_utxos(utxos, params.fee_rate)?;
+    pool.shuffle(rng);
+    let selection_target = sats(target) + params.base_fee() + params.change_fee();
+    let target_with_change = selection_target.saturating_add(params.min_change());
+    let select = |selected: Vec<&Utxo>| {
+        let selected = selected.into_iter().cloned().collect();
+        Selection::new(selected, target, params, Algorithm::Knapsack)
+    };
+
+    let mut lowest_larger: Option<(i64, &Utxo)> = None;
+    let mut applicable = vec![];
+    let mut total_lower = 0;
+    for (value, utxo) in pool {
+        if value == selection_target {
+            return select(vec![utxo]);
+        } else if value < target_with_change {
+            applicable.push((value, utxo));
+            total_lower += value;
+        } else if lowest_larger.is_none_or(|(lowest, _)| value < lowest) {
+            lowest_larger = Some((value, utxo));
+        }
+    }
+
+    if total_lower == selection_target {
+        return select(applicable.into_iter().map(|(_, utxo)| utxo).collect());
+    }
+    if total_lower < selection_target {
+        return select(vec![lowest_larger?.1]);
+    }
+
+    applicable.sort_by_key(|(value, _)| Reverse(*value));
+    let values = applicable
+        .iter()
+        .map(|(value, _)| *value)
+        .collect::<Vec<_>>();
+    let (mut best, mut best_value) = approximate_best_subset(&values, selection_target, rng);
+    if best_value != selection_target && total_lower >= target_with_change {
+        (best, best_value) = approximate_best_subset(&values, target_with_change, rng);
+    }
+
+    // Prefer the larger UTXO if the subset leaves dust change, or if it is
+    // no larger than the subset
+    if let Some((value, utxo)) = lowest_larger {
+        if (best_value != selection_target && best_value < target_with_change)
+            || value <= best_value
+        {
+            return select(vec![utxo]);
+        }
+    }
+    select(
+        applicable
+            .into_iter()
+            .zip(best)
+            .filter(|(_, included)| *included)
+            .map(|((_, utxo), _)| utxo)
+            .collect(),
+    )
+}
+
+/// Looks for the subset of values whose sum is the smallest at or above the
+/// target, including each at random on the first pass and filling up on the
+/// second
+fn approximate_best_subset(values: &[i64], target: i64, rng: &mut impl Rng) -> (Vec<bool>, i64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = values.iter().sum::<i64>();
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for i in 0..values.len() {
+                let include = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[i]
+                };
+                if !include {
+                    continue;
+                }
+                total += values[i];
+                included[i] = true;
+                if total >= target {
+                    reached_target = true;
+                    if total < best_value {
+                        best_value = total;
+                        best = included.clone();
+                    }
+                    // Try the subsets without this value
+                    total -= values[i];
+                    included[i] = false;
+                }
+            }
+        }
+    }
+    (best, best_value)
+}
+
+/// Spends the largest UTXOs first until they pay the target and fees
+pub fn select_largest_first(
+    utxos: &[Utxo],
+    target: Amount,
+    params: &SelectionParams,
+) -> Option<Selection> {
+    let selection_target = sats(target) + params.base_fee();
+    let mut selected = vec![];
+    let mut value = 0;
+    for (effective_value, utxo) in positive_utxos(utxos, params.fee_rate)? {
+        selected.push(utxo.clone());
+        value += effective_value;
+        if value >= selection_target {
+            return Selection::new(selected, target, params, Algorithm::LargestFirst);
+        }
+    }
+    None
+}
+
+/// Selects UTXOs to pay the target as Bitcoin Core does: runs branch and
+/// bound, knapsack and single random draw, and keeps the result with the
+/// least waste, preferring more inputs on ties
+pub fn select_coins(
+    utxos: &[Utxo],
+    target: Amount,
+    params: &SelectionParams,
+    rng: &mut impl Rng,
+) -> Result<Selection, SelectionError> {
+    let total = Amount::checked_sum(utxos.iter().map(|utxo| utxo.amount));
+    if !total.is_some_and(|total| total.is_valid()) || !target.is_valid() {
+        return Err(SelectionError::InvalidAmount);
+    }
+    let results = [
+        select_bnb(utxos, target, params),
+        select_knapsack(utxos, target, params, rng),
+        select_srd(utxos, target, params, rng),
+    ];
+    results
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| {
+            a.waste
+                .cmp(&b.waste)
+                .then(b.selected.len().cmp(&a.selected.len()))
+        })
+        .ok_or_else(|| SelectionError::InsufficientFunds {
+            available: positive_utxos(utxos, params.fee_rate)
+                .unwrap_or_default()
+                .iter()
+                .map(|(value, _)| Amount::from_sat(*value as u64))
+                .sum(),
+            needed: target + Amount::from_sat(params.base_fee() as u64),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigUint;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{address::Address, ecc::private_key::PrivateKey, transactions::hashes::Txid};
+
+    use super::*;
+
+    fn script_pubkey() -> Script {
+        Address::p2wpkh(&PrivateKey::new(&BigUint::from(1u32)).point(), false).script_pubkey()
+    }
+
+    fn utxos(amounts: &[u64]) -> Vec<Utxo> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(vout, &amount)| {
+                let outpoint = OutPoint {
+                    txid: Txid::from_bytes([1; 32]),
+                    vout: vout as u32,
+                };
+                Utxo::new(outpoint, Amount::from_sat(amount), script_pubkey()).unwrap()
+            })
+            .collect()
+    }
+
+    fn amounts(selection: &Selection) -> Vec<u64> {
+        let mut amounts = selection
+            .selected
+            .iter()
+            .map(|utxo| utxo.amount.to_sat())
+            .collect::<Vec<_>>();
+        amounts.sort();
+        amounts
+    }
+
+    /// Free spending, so effective values are the amounts
+    fn free_params() -> SelectionParams {
+        SelectionParams {
+            fee_rate: 0.0,
+            long_term_fee_rate: 0.0,
+            base_weight: 0,
+            change_weight: 0,
+            change_spend_weight: 0,
+            min_change: Amount::from_sat(1000),
+        }
+    }
+
+    #[test]
+    fn test_bnb_exact_match() {
+        let pool = utxos(&[1000, 2000, 3000, 4000, 5500]);
+        let selection = select_bnb(&pool, Amount::from_sat(7000), &free_params()).unwrap();
+        assert_eq!(selection.amount(), Amount::from_sat(7000));
+        assert_eq!(selection.change, Amount::ZERO);
+        assert_eq!(selection.fee, Amount::ZERO);
+        assert_eq!(selection.waste, 0);
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+
+        // Nothing within the cost of change of the target
+        assert!(select_bnb(&pool, Amount::from_sat(15_600), &free_params()).is_none());
+        assert!(select_bnb(&utxos(&[4000]), Amount::from_sat(1000), &free_params()).is_none());
+    }
+
+    #[test]
+    fn test_bnb_within_cost_of_change() {
+        let params = SelectionParams::new(10.0, 0, &script_pubkey());
+        let pool = utxos(&[10_000, 20_000]);
+        let effective_value = pool[0].effective_value(10.0).to_sat() as u64;
+        // The excess goes to the fee and counts as waste
+        let selection =
+            select_bnb(&pool, Amount::from_sat(effective_value - 100), &params).unwrap();
+        assert_eq!(amounts(&selection), [10_000]);
+        assert_eq!(
+            selection.fee,
+            Amount::from_sat(10_000 - effective_value + 100)
+        );
+        assert_eq!(selection.waste, 100);
+    }
+
+    #[test]
+    fn test_bnb_fewer_inputs_at_high_fee_rate() {
+        let mut params = SelectionParams::new(20.0, 0, &script_pubkey());
+        params.long_term_fee_rate = 5.0;
+        // The single UTXO is worth 10 more than the pair
+        let pool = utxos(&[10_000, 10_000, 20_000 - 1370]);
+        let target = Amount::from_sat(pool[0].effective_value(20.0).to_sat() as u64 * 2);
+        let selection = select_bnb(&pool, target, &params).unwrap();
+        assert_eq!(amounts(&selection), [18_630]);
+        assert_eq!(selection.waste, 1035 + 10);
+
+        // At a low fee rate, consolidating now is cheaper
+        params.fee_rate = 1.0;
+        let target = Amount::from_sat(pool[0].effective_value(1.0).to_sat() as u64 * 2);
+        let selection = select_bnb(&pool, target, &params).unwrap();
+        assert_eq!(amounts(&selection), [10_000, 10_000]);
+        assert!(selection.waste < 0);
+    }
+
+    #[test]
+    fn test_srd() {
+        let params = free_params();
+        let pool = utxos(&[1000, 2000, 3000, 4000, 5000]);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let selection = select_srd(&pool, Amount::from_sat(6000), &params, &mut rng).unwrap();
+            assert!(selection.amount() >= Amount::from_sat(7000));
+            assert_eq!(
+                selection.change,
+                selection.amount() - Amount::from_sat(6000)
+            );
+            assert_eq!(selection.algorithm, Algorithm::SingleRandomDraw);
+        }
+        assert!(select_srd(&pool, Amount::from_sat(14_500), &params, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_knapsack() {
+        let params = free_params();
+        let mut rng = StdRng::seed_from_u64(0);
+        let pool = utxos(&[1000, 2000, 5000, 20_000]);
+        // An exact match wins
+        let selection = select_knapsack(&pool, Amount::from_sat(5000), &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), [5000]);
+        // The smaller UTXOs cover the target with change to keep
+        let selection = select_knapsack(&pool, Amount::from_sat(6500), &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), [1000, 2000, 5000]);
+        assert_eq!(selection.change, Amount::from_sat(1500));
+        assert_eq!(selection.fee, Amount::ZERO);
+        // Rather than dust change, the larger UTXO
+        let selection = select_knapsack(&pool, Amount::from_sat(7500), &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), [20_000]);
+        // Without it, the excess goes to the fee
+        let selection =
+            select_knapsack(&pool[..3], Amount::from_sat(7500), &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), [1000, 2000, 5000]);
+        assert_eq!(selection.change, Amount::ZERO);
+        assert_eq!(selection.fee, Amount::from_sat(500));
+        // Only the larger UTXO is enough
+        let selection = select_knapsack(&pool, Amount::from_sat(9000), &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), [20_000]);
+        assert!(select_knapsack(&pool, Amount::from_sat(30_000), &params, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let params = SelectionParams::new(2.0, 400, &script_pubkey());
+        let pool = utxos(&[1000, 50_000, 30_000, 8000]);
+        let selection = select_largest_first(&pool, Amount::from_sat(60_000), &params).unwrap();
+        assert_eq!(amounts(&selection), [30_000, 50_000]);
+        let effective_value =
+            (pool[1].effective_value(2.0) + pool[2].effective_value(2.0)).to_sat() as u64;
+        let change = Amount::from_sat(effective_value - 60_000 - 200 - 62);
+        assert_eq!(selection.change, change);
+        assert_eq!(selection.fee, Amount::from_sat(80_000 - 60_000) - change);
+        assert!(select_largest_first(&pool, Amount::from_sat(89_000), &params).is_none());
+    }
+
+    #[test]
+    fn test_select_coins() {
+        let params = SelectionParams::new(10.0, 400, &script_pubkey());
+        let pool = utxos(&[20_000, 30_000, 55_000, 100_000]);
+        let mut rng = StdRng::seed_from_u64(0);
+        // 20k and 30k pay the target and fees with no change
+        let target = Amount::from_sat(50_000 - 1000 - 2 * 690);
+        let selection = select_coins(&pool, target, &params, &mut rng).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(amounts(&selection), [20_000, 30_000]);
+        assert_eq!(selection.change, Amount::ZERO);
+
+        let selection = select_coins(&pool, Amount::from_sat(120_000), &params, &mut rng).unwrap();
+        assert!(selection.amount() >= Amount::from_sat(120_000) + selection.fee);
+        assert_eq!(
+            selection.amount(),
+            Amount::from_sat(120_000) + selection.fee + selection.change
+        );
+
+        assert_eq!(
+            select_coins(&pool, Amount::from_sat(300_000), &params, &mut rng),
+            Err(SelectionError::InsufficientFunds {
+                available: Amount::from_sat(205_000 - 4 * 690),
+                needed: Amount::from_sat(301_000)
+            })
+        );
+    }
+
+    #[test]
+    fn test_select_invalid_amounts() {
+        let params = free_params();
+        let mut rng = StdRng::seed_from_u64(0);
+        let max = Amount::MAX_MONEY.to_sat();
+        let pool = utxos(&[max, max, u64::MAX]);
+        assert_eq!(
+            select_coins(&pool, Amount::ONE_BTC, &params, &mut rng),
+            Err(SelectionError::InvalidAmount)
+        );
+        assert_eq!(select_bnb(&pool, Amount::ONE_BTC, &params), None);
+        assert_eq!(select_largest_first(&pool, Amount::ONE_BTC, &params), None);
+        let target = Amount::from_sat(max + 1);
+        assert_eq!(
+            select_coins(&utxos(&[1000]), target, &params, &mut rng),
+            Err(SelectionError::InvalidAmount)
+        );
+    }
+}
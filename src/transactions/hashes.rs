@@ -0,0 +1,159 @@
+use std::{
+    fmt, io,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use crate::{
+    encode::{Decodable, Encodable},
+    utils::hash256::hash256,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseHashError {
+    InvalidHex,
+    /// A hash is 32 bytes, 64 hex characters
+    InvalidLength(usize),
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidHex => write!(f, "invalid hex"),
+            ParseHashError::InvalidLength(len) => {
+                write!(f, "invalid hash length: {} bytes", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseHashError {}
+
+/// Defines a double SHA256 hash type. The bytes are stored in serialization
+/// order and displayed reversed, as block explorers and RPCs show them.
+macro_rules! hash256_type {
+    ($name:ident, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            /// Wraps a hash given in serialization order
+            pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+                $name(bytes)
+            }
+
+            /// Returns the hash in serialization order
+            pub fn to_bytes(&self) -> [u8; 32] {
+                self.0
+            }
+
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            /// Returns the hash256 of the data
+            pub fn hash(data: &[u8]) -> Self {
+                $name(hash256(data))
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                $name(bytes)
+            }
+        }
+
+        impl Encodable for $name {
+            fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                self.0.consensus_encode(writer)
+            }
+        }
+
+        impl Decodable for $name {
+            fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+                Ok($name(<[u8; 32]>::consensus_decode(reader)?))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut bytes = self.0;
+                bytes.reverse();
+                write!(f, "{}", hex::encode(bytes))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseHashError;
+
+            /// Parses the reversed hex form
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = hex::decode(s).map_err(|_| ParseHashError::InvalidHex)?;
+                let len = bytes.len();
+                let mut bytes: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|_| ParseHashError::InvalidLength(len))?;
+                bytes.reverse();
+                Ok($name(bytes))
+            }
+        }
+    };
+}
+
+hash256_type!(
+    Txid,
+    "The id of a transaction: the hash of its serialization without witness data"
+);
+hash256_type!(
+    Wtxid,
+    "The hash of a transaction's serialization with witness data (BIP141)"
+);
+hash256_type!(BlockHash, "The hash of a block header");
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn test_txid_display_roundtrip() {
+        let hex = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81";
+        let txid: Txid = hex.parse().unwrap();
+        assert_eq!(txid.to_string(), hex);
+        assert_eq!(txid.as_bytes()[0], 0x81);
+        assert_eq!(txid.as_bytes()[31], 0xd1);
+        assert_eq!(format!("{:?}", txid), format!("Txid({})", hex));
+
+        assert_eq!("zz".parse::<Txid>(), Err(ParseHashError::InvalidHex));
+        assert_eq!(
+            "00ff".parse::<Txid>(),
+            Err(ParseHashError::InvalidLength(2))
+        );
+    }
+
+    #[test]
+    fn test_block_hash() {
+        // The genesis block
+        let header = hex::decode("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c").unwrap();
+        assert_eq!(
+            BlockHash::hash(&header).to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+
+    #[test]
+    fn test_ord() {
+        let a = Txid::from_bytes([1; 32]);
+        let b = Txid::from_bytes([2; 32]);
+        let set = BTreeSet::from([b, a, a]);
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [a, b]);
+    }
+}
@@ -1,68 +1,163 @@
 use std::{
-    fmt,
-    io::{Cursor, Error, Read},
+    fmt, io,
+    io::{Cursor, Read, Write},
+    str::FromStr,
 };
 
-use crate::script::Script;
+use crate::{
+    encode::{self, impl_vec_encodable, Decodable, DecodeError, Encodable},
+    script::Script,
+};
+
+use super::hashes::{ParseHashError, Txid};
+
+/// A reference to an output of a transaction, displayed as txid:vout
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl OutPoint {
+    pub fn new(txid: Txid, vout: u32) -> Self {
+        OutPoint { txid, vout }
+    }
+
+    /// The outpoint of coinbase inputs, which spend no output
+    pub fn null() -> Self {
+        OutPoint::new(Txid::default(), u32::MAX)
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+
+    /// Returns the transaction hash in serialization order followed by the
+    /// output index in little endian
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+}
+
+impl Encodable for OutPoint {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let len = self.txid.consensus_encode(writer)?;
+        Ok(len + self.vout.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for OutPoint {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        Ok(OutPoint {
+            txid: Txid::consensus_decode(reader)?,
+            vout: u32::consensus_decode(reader)?,
+        })
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseOutPointError {
+    MissingVout,
+    InvalidTxid(ParseHashError),
+    InvalidVout,
+}
+
+impl fmt::Display for ParseOutPointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseOutPointError::MissingVout => write!(f, "missing output index"),
+            ParseOutPointError::InvalidTxid(err) => write!(f, "invalid txid: {}", err),
+            ParseOutPointError::InvalidVout => write!(f, "invalid output index"),
+        }
+    }
+}
+
+impl std::error::Error for ParseOutPointError {}
+
+impl FromStr for OutPoint {
+    type Err = ParseOutPointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, vout) = s.split_once(':').ok_or(ParseOutPointError::MissingVout)?;
+        let txid = txid.parse().map_err(ParseOutPointError::InvalidTxid)?;
+        // Leading zeros and signs are not part of the canonical form
+        if vout.is_empty()
+            || !vout.bytes().all(|c| c.is_ascii_digit())
+            || (vout.len() > 1 && vout.starts_with('0'))
+        {
+            return Err(ParseOutPointError::InvalidVout);
+        }
+        let vout = vout.parse().map_err(|_| ParseOutPointError::InvalidVout)?;
+        Ok(OutPoint { txid, vout })
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct TxInput {
-    prev_tx: Vec<u8>,
-    prev_index: Vec<u8>,
+    previous_output: OutPoint,
     script_sig: Script,
-    sequence: Vec<u8>,
+    sequence: u32,
+    witness: Vec<Vec<u8>>,
 }
 
 impl TxInput {
-    /// Parses a transaction input from a byte stream
-    pub fn parse(cursor: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let mut prev_tx = vec![0; 32];
-        cursor.read_exact(&mut prev_tx)?;
-        let mut prev_index = vec![0; 4];
-        cursor.read_exact(&mut prev_index)?;
-        let script_sig = Script::parse(cursor)?;
-        let mut sequence = vec![0; 4];
-        cursor.read_exact(&mut sequence)?;
-
-        Ok(TxInput {
-            prev_tx,
-            prev_index,
+    /// Creates an input spending the previous output
+    pub fn new(previous_output: OutPoint, script_sig: Script, sequence: u32) -> Self {
+        TxInput {
+            previous_output,
             script_sig,
             sequence,
-        })
+            witness: vec![],
+        }
     }
 
-    /// Returns the byte serialization of the transaction input
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::new();
+    /// Parses a transaction input from a byte stream
+    pub fn parse(cursor: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError> {
+        encode::decode_cursor(cursor)
+    }
 
-        // Serialize prev_tx (no need to reverse)
-        result.extend(&self.prev_tx);
+    /// Parses the witness stack of the input, which segwit transactions
+    /// serialize after the outputs
+    pub fn parse_witness(&mut self, cursor: &mut Cursor<Vec<u8>>) -> Result<(), DecodeError> {
+        self.witness = encode::decode_cursor(cursor)?;
+        Ok(())
+    }
 
-        // Serialize prev_index in little endian
-        let prev_index_le = self.prev_index.clone();
-        result.extend(prev_index_le);
+    /// Returns the byte serialization of the transaction input
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
 
-        // Serialize script_sig
-        result.extend(self.script_sig.serialize());
+    /// Returns the byte serialization of the witness stack
+    pub fn serialize_witness(&self) -> Vec<u8> {
+        encode::serialize(&self.witness)
+    }
 
-        // Serialize sequence in little endian
-        let sequence = self.sequence.clone();
-        result.extend(sequence);
+    /// Returns the outpoint being spent: the previous transaction hash in
+    /// serialization order followed by the output index in little endian
+    pub fn serialize_outpoint(&self) -> Vec<u8> {
+        self.previous_output.serialize()
+    }
 
-        result
+    /// Returns the id of the previous transaction
+    pub fn get_prev_tx(&self) -> Txid {
+        self.previous_output.txid
     }
 
-    /// Returns the previous transaction hash
-    pub fn get_prev_tx(&self) -> String {
-        let mut value = self.prev_tx.clone();
-        value.reverse();
-        hex::encode(value)
+    /// Returns the output the input spends
+    pub fn outpoint(&self) -> OutPoint {
+        self.previous_output
     }
 
     /// Returns the previous transaction index
     pub fn get_prev_index(&self) -> u32 {
-        u32::from_le_bytes(self.prev_index.as_slice().try_into().unwrap())
+        self.previous_output.vout
     }
 
     /// Returns the script signature
@@ -72,10 +167,54 @@ impl TxInput {
 
     /// Returns the sequence number
     pub fn get_sequence(&self) -> u32 {
-        u32::from_le_bytes(self.sequence.as_slice().try_into().unwrap())
+        self.sequence
+    }
+
+    /// Returns the witness stack, empty for non-segwit inputs
+    pub fn get_witness(&self) -> &[Vec<u8>] {
+        &self.witness
+    }
+
+    /// Replaces the sequence number
+    pub fn set_sequence(&mut self, sequence: u32) {
+        self.sequence = sequence;
+    }
+
+    /// Replaces the script signature
+    pub fn set_script_sig(&mut self, script_sig: Script) {
+        self.script_sig = script_sig;
+    }
+
+    /// Replaces the witness stack
+    pub fn set_witness(&mut self, witness: Vec<Vec<u8>>) {
+        self.witness = witness;
+    }
+}
+
+/// The serialization without the witness, which segwit transactions
+/// carry separately
+impl Encodable for TxInput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = self.previous_output.consensus_encode(writer)?;
+        len += self.script_sig.consensus_encode(writer)?;
+        len += self.sequence.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for TxInput {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        Ok(TxInput {
+            previous_output: OutPoint::consensus_decode(reader)?,
+            script_sig: Script::consensus_decode(reader)?,
+            sequence: u32::consensus_decode(reader)?,
+            witness: vec![],
+        })
     }
 }
 
+impl_vec_encodable!(TxInput);
+
 impl fmt::Display for TxInput {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -88,3 +227,36 @@ impl fmt::Display for TxInput {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outpoint_display_roundtrip() {
+        let s = "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81:13";
+        let outpoint: OutPoint = s.parse().unwrap();
+        assert_eq!(outpoint.vout, 13);
+        assert_eq!(outpoint.to_string(), s);
+        assert_eq!(outpoint.serialize()[0], 0x81);
+        assert_eq!(outpoint.serialize()[32..], 13u32.to_le_bytes());
+
+        let txid = &s[..64];
+        assert_eq!(
+            txid.parse::<OutPoint>(),
+            Err(ParseOutPointError::MissingVout)
+        );
+        for vout in ["", "-1", "+1", "01", "4294967296"] {
+            assert_eq!(
+                format!("{}:{}", txid, vout).parse::<OutPoint>(),
+                Err(ParseOutPointError::InvalidVout)
+            );
+        }
+        assert_eq!(
+            "00:0".parse::<OutPoint>(),
+            Err(ParseOutPointError::InvalidTxid(
+                ParseHashError::InvalidLength(1)
+            ))
+        );
+    }
+}
@@ -1,43 +1,41 @@
 use std::{
-    fmt,
-    io::{Cursor, Error, Read},
+    fmt, io,
+    io::{Cursor, Read, Write},
 };
 
-use crate::script::Script;
+use crate::{
+    amount::Amount,
+    encode::{self, impl_vec_encodable, Decodable, DecodeError, Encodable},
+    script::Script,
+};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct TxOutput {
-    amount: u64,
+    amount: Amount,
     script_pubkey: Script,
 }
 
 impl TxOutput {
-    /// Parses a transaction output from a byte vector
-    pub fn parse(cursor: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let mut value = [0; 8];
-        cursor.read_exact(&mut value)?;
-        let script_pubkey = Script::parse(cursor)?;
-        Ok(TxOutput {
-            amount: u64::from_le_bytes(value),
+    /// Creates an output paying the amount to the script pubkey
+    pub fn new(amount: Amount, script_pubkey: Script) -> Self {
+        TxOutput {
+            amount,
             script_pubkey,
-        })
+        }
+    }
+
+    /// Parses a transaction output from a byte vector
+    pub fn parse(cursor: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError> {
+        encode::decode_cursor(cursor)
     }
 
     /// Serializes the transaction output into a byte vector
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::new();
-
-        // Serialize amount, 8 bytes, little endian
-        result.extend_from_slice(&self.amount.to_le_bytes());
-
-        // Serialize the script_pubkey
-        result.extend(self.script_pubkey.serialize());
-
-        result
+        encode::serialize(self)
     }
 
     /// Returns the amount of the transaction output
-    pub fn get_amount(&self) -> u64 {
+    pub fn get_amount(&self) -> Amount {
         self.amount
     }
 
@@ -47,6 +45,24 @@ impl TxOutput {
     }
 }
 
+impl Encodable for TxOutput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let len = self.amount.consensus_encode(writer)?;
+        Ok(len + self.script_pubkey.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for TxOutput {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        Ok(TxOutput {
+            amount: Amount::consensus_decode(reader)?,
+            script_pubkey: Script::consensus_decode(reader)?,
+        })
+    }
+}
+
+impl_vec_encodable!(TxOutput);
+
 impl fmt::Display for TxOutput {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
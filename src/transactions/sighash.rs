@@ -0,0 +1,385 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    amount::Amount,
+    script::{interpreter::ExecData, Script},
+    utils::{hash256::hash256, tagged_hash::tagged_hash, varint::encode_varint},
+};
+
+use super::{output::TxOutput, tx::Tx};
+
+/// Taproot only: sign everything, encoded as a 64-byte signature
+pub const SIGHASH_DEFAULT: u32 = 0x00;
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// The flags of a sighash type, shared by all signature hash algorithms
+struct SighashFlags {
+    anyone_can_pay: bool,
+    none: bool,
+    single: bool,
+}
+
+impl SighashFlags {
+    fn new(sighash_type: u32) -> Self {
+        let base = sighash_type & 0x1f;
+        SighashFlags {
+            anyone_can_pay: sighash_type & SIGHASH_ANYONECANPAY != 0,
+            none: base == SIGHASH_NONE,
+            single: base == SIGHASH_SINGLE,
+        }
+    }
+}
+
+/// Signature hashes
+impl Tx {
+    /// Returns the legacy signature hash of an input, the script code being
+    /// the script pubkey or redeem script being spent
+    pub fn sig_hash(
+        &self,
+        input_index: usize,
+        script_code: &Script,
+        sighash_type: u32,
+    ) -> Result<[u8; 32], String> {
+        let inputs = self.get_inputs();
+        let outputs = self.get_outputs();
+        if input_index >= inputs.len() {
+            return Err(format!("Input {} is out of range", input_index));
+        }
+        let flags = SighashFlags::new(sighash_type);
+
+        // SIGHASH_SINGLE without a matching output signs the number one
+        if flags.single && input_index >= outputs.len() {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return Ok(one);
+        }
+
+        let mut result = self.get_version().to_le_bytes().to_vec();
+
+        let signed_inputs: Vec<usize> = if flags.anyone_can_pay {
+            vec![input_index]
+        } else {
+            (0..inputs.len()).collect()
+        };
+        result.extend(encode_varint(signed_inputs.len() as u64).unwrap());
+        for i in signed_inputs {
+            let input = &inputs[i];
+            result.extend(input.serialize_outpoint());
+            if i == input_index {
+                result.extend(script_code.serialize());
+            } else {
+                result.push(0);
+            }
+            // Other inputs may be replaced when the outputs aren't all signed
+            let sequence = if i != input_index && (flags.none || flags.single) {
+                0
+            } else {
+                input.get_sequence()
+            };
+            result.extend(sequence.to_le_bytes());
+        }
+
+        if flags.none {
+            result.push(0);
+        } else if flags.single {
+            result.extend(encode_varint(input_index as u64 + 1).unwrap());
+            for _ in 0..input_index {
+                result.extend(u64::MAX.to_le_bytes());
+                result.push(0);
+            }
+            result.extend(outputs[input_index].serialize());
+        } else {
+            result.extend(encode_varint(outputs.len() as u64).unwrap());
+            for output in &outputs {
+                result.extend(output.serialize());
+            }
+        }
+
+        result.extend(self.get_locktime().value().to_le_bytes());
+        result.extend(sighash_type.to_le_bytes());
+        Ok(hash256(&result))
+    }
+
+    /// Returns the BIP143 signature hash of a segwit v0 input spending the
+    /// amount. The script code of P2WPKH is the P2PKH script of the key
+    /// hash, that of P2WSH the witness script.
+    pub fn sig_hash_segwit_v0(
+        &self,
+        input_index: usize,
+        script_code: &Script,
+        amount: Amount,
+        sighash_type: u32,
+    ) -> Result<[u8; 32], String> {
+        let inputs = self.get_inputs();
+        let outputs = self.get_outputs();
+        let input = inputs
+            .get(input_index)
+            .ok_or_else(|| format!("Input {} is out of range", input_index))?;
+        let flags = SighashFlags::new(sighash_type);
+
+        let hash_prevouts = if flags.anyone_can_pay {
+            [0u8; 32]
+        } else {
+            hash256(
+                &inputs
+                    .iter()
+                    .flat_map(|input| input.serialize_outpoint())
+                    .collect::<Vec<u8>>(),
+            )
+        };
+        let hash_sequence = if flags.anyone_can_pay || flags.none || flags.single {
+            [0u8; 32]
+        } else {
+            hash256(
+                &inputs
+                    .iter()
+                    .flat_map(|input| input.get_sequence().to_le_bytes())
+                    .collect::<Vec<u8>>(),
+            )
+        };
+        let hash_outputs = if !flags.none && !flags.single {
+            hash256(
+                &outputs
+                    .iter()
+                    .flat_map(|output| output.serialize())
+                    .collect::<Vec<u8>>(),
+            )
+        } else if flags.single && input_index < outputs.len() {
+            hash256(&outputs[input_index].serialize())
+        } else {
+            [0u8; 32]
+        };
+
+        let mut result = self.get_version().to_le_bytes().to_vec();
+        result.extend(hash_prevouts);
+        result.extend(hash_sequence);
+        result.extend(input.serialize_outpoint());
+        result.extend(script_code.serialize());
+        result.extend(amount.to_sat().to_le_bytes());
+        result.extend(input.get_sequence().to_le_bytes());
+        result.extend(hash_outputs);
+        result.extend(self.get_locktime().value().to_le_bytes());
+        result.extend(sighash_type.to_le_bytes());
+        Ok(hash256(&result))
+    }
+
+    /// Returns the BIP341 signature hash of a taproot input. prevouts are
+    /// the outputs spent by every input of the transaction, and leaf_hash is
+    /// the tapleaf hash for script path spends (BIP342).
+    pub fn sig_hash_taproot(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOutput],
+        sighash_type: u32,
+        leaf_hash: Option<&[u8; 32]>,
+    ) -> Result<[u8; 32], String> {
+        let exec_data = ExecData {
+            tapleaf_hash: leaf_hash.copied(),
+            ..ExecData::default()
+        };
+        self.sig_hash_taproot_with_exec_data(input_index, prevouts, sighash_type, &exec_data)
+    }
+
+    /// Same as sig_hash_taproot, also committing to the annex of the input
+    /// and the position of the last OP_CODESEPARATOR a tapscript executed,
+    /// as the interpreter records them
+    pub fn sig_hash_taproot_with_exec_data(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOutput],
+        sighash_type: u32,
+        exec_data: &ExecData,
+    ) -> Result<[u8; 32], String> {
+        let inputs = self.get_inputs();
+        let outputs = self.get_outputs();
+        if input_index >= inputs.len() {
+            return Err(format!("Input {} is out of range", input_index));
+        }
+        if prevouts.len() != inputs.len() {
+            return Err("Taproot signature hashes need every spent output".to_string());
+        }
+        if !matches!(sighash_type, 0x00..=0x03 | 0x81..=0x83) {
+            return Err(format!("Invalid taproot sighash type {:#x}", sighash_type));
+        }
+        let flags = SighashFlags::new(sighash_type);
+        if flags.single && input_index >= outputs.len() {
+            return Err("SIGHASH_SINGLE without a matching output".to_string());
+        }
+
+        let sha256 = |bytes: Vec<u8>| Sha256::digest(bytes).to_vec();
+
+        // The epoch, then SigMsg
+        let mut msg = vec![0x00, sighash_type as u8];
+        msg.extend(self.get_version().to_le_bytes());
+        msg.extend(self.get_locktime().value().to_le_bytes());
+        if !flags.anyone_can_pay {
+            msg.extend(sha256(
+                inputs
+                    .iter()
+                    .flat_map(|input| input.serialize_outpoint())
+                    .collect(),
+            ));
+            msg.extend(sha256(
+                prevouts
+                    .iter()
+                    .flat_map(|prevout| prevout.get_amount().to_sat().to_le_bytes())
+                    .collect(),
+            ));
+            msg.extend(sha256(
+                prevouts
+                    .iter()
+                    .flat_map(|prevout| prevout.get_script_pubkey().serialize())
+                    .collect(),
+            ));
+            msg.extend(sha256(
+                inputs
+                    .iter()
+                    .flat_map(|input| input.get_sequence().to_le_bytes())
+                    .collect(),
+            ));
+        }
+        if !flags.none && !flags.single {
+            msg.extend(sha256(
+                outputs
+                    .iter()
+                    .flat_map(|output| output.serialize())
+                    .collect(),
+            ));
+        }
+
+        let ext_flag = u8::from(exec_data.tapleaf_hash.is_some());
+        msg.push(ext_flag * 2 + u8::from(exec_data.annex.is_some()));
+        if flags.anyone_can_pay {
+            let input = &inputs[input_index];
+            let prevout = &prevouts[input_index];
+            msg.extend(input.serialize_outpoint());
+            msg.extend(prevout.get_amount().to_sat().to_le_bytes());
+            msg.extend(prevout.get_script_pubkey().serialize());
+            msg.extend(input.get_sequence().to_le_bytes());
+        } else {
+            msg.extend((input_index as u32).to_le_bytes());
+        }
+        if let Some(annex) = &exec_data.annex {
+            msg.extend(sha256(crate::encode::serialize(annex)));
+        }
+        if flags.single {
+            msg.extend(sha256(outputs[input_index].serialize()));
+        }
+
+        if let Some(leaf_hash) = &exec_data.tapleaf_hash {
+            msg.extend_from_slice(leaf_hash);
+            // key_version 0
+            msg.push(0x00);
+            msg.extend(exec_data.codesep_pos.to_le_bytes());
+        }
+
+        Ok(tagged_hash("TapSighash", &msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use num::BigUint;
+
+    use crate::{
+        ecc::{point::ECPoint, private_key::PrivateKey, signature::Signature},
+        script::{op::OP_1, Command, Script},
+        transactions::tx::Tx,
+    };
+
+    use super::*;
+
+    fn parse_script(hex_script: &str) -> Script {
+        let mut bytes = encode_varint(hex_script.len() as u64 / 2).unwrap();
+        bytes.extend(hex::decode(hex_script).unwrap());
+        Script::parse(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_sig_hash() {
+        let raw_tx = hex::decode("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw_tx)).unwrap();
+        let script_pubkey = parse_script("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac");
+        let z = tx.sig_hash(0, &script_pubkey, SIGHASH_ALL).unwrap();
+        assert_eq!(
+            hex::encode(z),
+            "27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6"
+        );
+
+        // The signature in the script sig signs that hash
+        let script_sig = tx.get_inputs()[0].get_script_sig();
+        let [Command::Push(sig, _), Command::Push(sec, _)] = script_sig.cmds() else {
+            panic!("unexpected script sig");
+        };
+        let sig = Signature::parse(&sig[..sig.len() - 1]).unwrap();
+        let point = ECPoint::parse(sec).unwrap();
+        assert!(point.verify(&BigUint::from_bytes_be(&z), &sig));
+    }
+
+    #[test]
+    fn test_sig_hash_segwit_v0() {
+        // The BIP143 native P2WPKH example
+        let raw_tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw_tx)).unwrap();
+        let script_code = parse_script("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac");
+        let z = tx
+            .sig_hash_segwit_v0(1, &script_code, Amount::from_sat(600_000_000), SIGHASH_ALL)
+            .unwrap();
+        assert_eq!(
+            hex::encode(z),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+    }
+
+    #[test]
+    fn test_sig_hash_taproot() {
+        let raw_tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw_tx)).unwrap();
+        let key = PrivateKey::new(&BigUint::from(3u32));
+        let script_pubkey = Script::new(vec![
+            Command::Op(OP_1),
+            Command::push(key.point().to_xonly().to_vec()),
+        ]);
+        let prevouts = vec![
+            TxOutput::new(Amount::from_sat(625_000_000), script_pubkey.clone()),
+            TxOutput::new(Amount::from_sat(600_000_000), script_pubkey),
+        ];
+
+        let default = tx
+            .sig_hash_taproot(1, &prevouts, SIGHASH_DEFAULT, None)
+            .unwrap();
+        let all = tx
+            .sig_hash_taproot(1, &prevouts, SIGHASH_ALL, None)
+            .unwrap();
+        let script_path = tx
+            .sig_hash_taproot(1, &prevouts, SIGHASH_DEFAULT, Some(&[0u8; 32]))
+            .unwrap();
+        assert_ne!(default, all);
+        assert_ne!(default, script_path);
+
+        // Signing for the output key of the first input doesn't commit to
+        // the amount of the second one with ANYONECANPAY
+        let mut changed = prevouts.clone();
+        changed[1] = TxOutput::new(Amount::from_sat(1), changed[1].get_script_pubkey());
+        let acp = SIGHASH_ALL | SIGHASH_ANYONECANPAY;
+        assert_eq!(
+            tx.sig_hash_taproot(0, &prevouts, acp, None).unwrap(),
+            tx.sig_hash_taproot(0, &changed, acp, None).unwrap()
+        );
+        assert_ne!(
+            tx.sig_hash_taproot(0, &prevouts, SIGHASH_ALL, None)
+                .unwrap(),
+            tx.sig_hash_taproot(0, &changed, SIGHASH_ALL, None).unwrap()
+        );
+
+        assert!(tx.sig_hash_taproot(1, &prevouts, 0x04, None).is_err());
+        assert!(tx
+            .sig_hash_taproot(1, &prevouts[..1], SIGHASH_ALL, None)
+            .is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use super::{output::TxOutput, tx::Tx};
+
+/// Legacy signature operations cost as much as four witness ones, as
+/// legacy bytes weigh four times witness bytes
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+/// Signature operations
+impl Tx {
+    /// Returns the signature operations of the script sigs and script
+    /// pubkeys, counted inaccurately as before P2SH
+    pub fn legacy_sigop_count(&self) -> usize {
+        let inputs = self.get_inputs();
+        let outputs = self.get_outputs();
+        let script_sigs = inputs.iter().map(|input| input.get_script_sig());
+        let script_pubkeys = outputs.iter().map(|output| output.get_script_pubkey());
+        script_sigs
+            .chain(script_pubkeys)
+            .map(|script| script.sigop_count(false))
+            .sum()
+    }
+
+    /// Returns the signature operations of the P2SH redeem scripts spent.
+    /// prevouts are the outputs spent by the inputs, in order. Returns None
+    /// if there isn't one per input, rather than leave some uncounted.
+    pub fn p2sh_sigop_count(&self, prevouts: &[TxOutput]) -> Option<usize> {
+        if self.is_coinbase() {
+            return Some(0);
+        }
+        let inputs = self.get_inputs();
+        if prevouts.len() != inputs.len() {
+            return None;
+        }
+        let count = inputs
+            .iter()
+            .zip(prevouts)
+            .map(|(input, prevout)| {
+                let script_pubkey = prevout.get_script_pubkey();
+                if script_pubkey.is_p2sh() {
+                    script_pubkey.p2sh_sigop_count(&input.get_script_sig())
+                } else {
+                    0
+                }
+            })
+            .sum();
+        Some(count)
+    }
+
+    /// Returns the signature operation cost the block limit of 80,000
+    /// applies to: legacy and P2SH signature operations count four times,
+    /// witness ones once. prevouts are the outputs spent by the inputs, in
+    /// order, and can be empty for coinbase transactions. Returns None if
+    /// there isn't one per input otherwise.
+    pub fn sigop_cost(&self, prevouts: &[TxOutput]) -> Option<usize> {
+        let legacy = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coinbase() {
+            return Some(legacy);
+        }
+        let p2sh = self.p2sh_sigop_count(prevouts)?;
+        let witness: usize = self
+            .get_inputs()
+            .iter()
+            .zip(prevouts)
+            .map(|(input, prevout)| {
+                prevout
+                    .get_script_pubkey()
+                    .witness_sigop_count(&input.get_script_sig(), input.get_witness())
+            })
+            .sum();
+        Some(legacy + p2sh * WITNESS_SCALE_FACTOR + witness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        amount::Amount,
+        script::{opcode::Opcode, Command, Script},
+        transactions::{
+            hashes::Txid,
+            input::{OutPoint, TxInput},
+        },
+        utils::hash160::hash160,
+    };
+
+    use super::*;
+
+    fn input(vout: u32, script_sig: Script, witness: Vec<Vec<u8>>) -> TxInput {
+        let mut input = TxInput::new(
+            OutPoint::new(Txid::from_bytes([1; 32]), vout),
+            script_sig,
+            0,
+        );
+        input.set_witness(witness);
+        input
+    }
+
+    #[test]
+    fn test_sigop_cost() {
+        let key = vec![2; 33];
+        let redeem_script = Script::multisig(2, &[key.clone(), key.clone(), key.clone()]).unwrap();
+        let witness_script = Script::from_asm("CHECKSIGVERIFY CHECKSIG").unwrap();
+        let prevouts = vec![
+            TxOutput::new(
+                Amount::from_sat(1000),
+                Script::p2sh(&hash160(&redeem_script.raw_serialize())),
+            ),
+            TxOutput::new(Amount::from_sat(1000), Script::p2wpkh(&[0; 20])),
+            TxOutput::new(Amount::from_sat(1000), Script::p2wsh(&[0; 32])),
+            TxOutput::new(Amount::from_sat(1000), Script::p2pkh(&[0; 20])),
+        ];
+        let inputs = vec![
+            input(
+                0,
+                Script::new(vec![
+                    Command::Op(Opcode::OP_0),
+                    Command::push(redeem_script.raw_serialize()),
+                ]),
+                vec![],
+            ),
+            input(1, Script::new(vec![]), vec![vec![], key.clone()]),
+            input(2, Script::new(vec![]), vec![witness_script.raw_serialize()]),
+            input(
+                3,
+                Script::new(vec![Command::push(vec![0x30]), Command::push(key)]),
+                vec![],
+            ),
+        ];
+        let outputs = vec![
+            TxOutput::new(Amount::from_sat(500), Script::p2pkh(&[0; 20])),
+            TxOutput::new(Amount::from_sat(500), redeem_script),
+        ];
+        let tx = Tx::new(2, inputs, outputs, 0);
+
+        // The P2PKH output counts one and the bare multisig output 20
+        assert_eq!(tx.legacy_sigop_count(), 21);
+        // The 2-of-3 redeem script counts 3
+        assert_eq!(tx.p2sh_sigop_count(&prevouts), Some(3));
+        // P2WPKH counts 1 and the witness script 2
+        assert_eq!(tx.sigop_cost(&prevouts), Some((21 + 3) * 4 + 1 + 2));
+
+        // Missing prevouts would leave inputs uncounted
+        assert_eq!(tx.p2sh_sigop_count(&prevouts[..3]), None);
+        assert_eq!(tx.sigop_cost(&prevouts[..3]), None);
+        assert_eq!(tx.sigop_cost(&[]), None);
+    }
+
+    #[test]
+    fn test_coinbase_sigop_cost() {
+        let coinbase = TxInput::new(
+            OutPoint::null(),
+            Script::new(vec![Command::Op(Opcode::OP_CHECKSIG)]),
+            u32::MAX,
+        );
+        let output = TxOutput::new(Amount::from_sat(5000), Script::p2pkh(&[0; 20]));
+        let tx = Tx::new(1, vec![coinbase], vec![output], 0);
+        assert!(tx.is_coinbase());
+        assert_eq!(tx.p2sh_sigop_count(&[]), Some(0));
+        assert_eq!(tx.sigop_cost(&[]), Some(8));
+    }
+}
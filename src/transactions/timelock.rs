@@ -0,0 +1,251 @@
+use super::tx::Tx;
+
+/// The sequence number of an input, which encodes its relative locktime
+/// (BIP68) and whether it signals replaceability (BIP125)
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct Sequence(u32);
+
+impl Sequence {
+    /// Final inputs disable the locktime of the transaction, if all are
+    pub const FINAL: Sequence = Sequence(0xffff_ffff);
+    /// The highest sequence that enables the locktime without signaling
+    /// replaceability
+    pub const ENABLE_LOCKTIME_NO_RBF: Sequence = Sequence(0xffff_fffe);
+    pub const ENABLE_RBF_NO_LOCKTIME: Sequence = Sequence(0xffff_fffd);
+
+    /// Sequences with this bit set have no relative locktime
+    pub const LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+    /// Relative locktimes with this bit set are in units of 512 seconds
+    pub const LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+    pub const LOCKTIME_MASK: u32 = 0xffff;
+
+    /// Relative locktimes in 512 second units are shifted by this to get
+    /// seconds
+    pub const LOCKTIME_GRANULARITY: u32 = 9;
+
+    /// A relative locktime of blocks
+    pub fn from_height(blocks: u16) -> Self {
+        Sequence(blocks as u32)
+    }
+
+    /// A relative locktime of 512 second intervals
+    pub fn from_512_second_intervals(intervals: u16) -> Self {
+        Sequence(Self::LOCKTIME_TYPE_FLAG | intervals as u32)
+    }
+
+    /// Returns the raw nSequence value
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_final(&self) -> bool {
+        *self == Sequence::FINAL
+    }
+
+    /// Returns true if the input signals that the transaction can be
+    /// replaced by one paying more (BIP125)
+    pub fn signals_rbf(&self) -> bool {
+        *self < Sequence::ENABLE_LOCKTIME_NO_RBF
+    }
+
+    pub fn is_relative_locktime_enabled(&self) -> bool {
+        self.0 & Self::LOCKTIME_DISABLE_FLAG == 0
+    }
+
+    /// Returns the relative locktime, None if its disable flag is set. Bits
+    /// other than the flags and the 16 bit value have no meaning yet.
+    pub fn relative_locktime(&self) -> Option<RelativeLocktime> {
+        if !self.is_relative_locktime_enabled() {
+            return None;
+        }
+        let value = (self.0 & Self::LOCKTIME_MASK) as u16;
+        if self.0 & Self::LOCKTIME_TYPE_FLAG != 0 {
+            Some(RelativeLocktime::Time(value))
+        } else {
+            Some(RelativeLocktime::Blocks(value))
+        }
+    }
+}
+
+impl From<u32> for Sequence {
+    fn from(value: u32) -> Self {
+        Sequence(value)
+    }
+}
+
+impl From<Sequence> for u32 {
+    fn from(sequence: Sequence) -> Self {
+        sequence.0
+    }
+}
+
+/// How long after the output it spends was mined an input can be mined
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RelativeLocktime {
+    Blocks(u16),
+    /// In units of 512 seconds, counted from the median time past of the
+    /// block before the output's
+    Time(u16),
+}
+
+impl RelativeLocktime {
+    /// Returns true if an input of the other relative locktime can only be
+    /// mined once this one has passed, as OP_CHECKSEQUENCEVERIFY requires:
+    /// both are in the same unit, and the other is at least this one
+    pub fn is_implied_by(&self, other: &RelativeLocktime) -> bool {
+        match (self, other) {
+            (RelativeLocktime::Blocks(required), RelativeLocktime::Blocks(locktime))
+            | (RelativeLocktime::Time(required), RelativeLocktime::Time(locktime)) => {
+                required <= locktime
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Timelocks
+impl Tx {
+    /// Returns true if the locktime of the transaction lets it be mined in a
+    /// block of the height whose previous block has the median time past,
+    /// as Bitcoin Core's IsFinalTx. Final inputs disable the locktime.
+    pub fn is_final(&self, height: u32, median_time_past: u32) -> bool {
+        self.get_locktime()
+            .is_satisfied_by(height, median_time_past)
+            || self
+                .get_inputs()
+                .iter()
+                .all(|input| Sequence::from(input.get_sequence()).is_final())
+    }
+
+    /// Returns true if the relative locktimes of the inputs let the
+    /// transaction be mined in a block of the height whose previous block
+    /// has the median time past (BIP68). prev_heights are the heights of
+    /// the blocks of the outputs spent, and prev_mtps the median times past
+    /// of the blocks before them, one per input. Returns false if either
+    /// doesn't have one per input, rather than skip the locks of some.
+    pub fn check_sequence_locks(
+        &self,
+        prev_heights: &[u32],
+        prev_mtps: &[u32],
+        height: u32,
+        median_time_past: u32,
+    ) -> bool {
+        let inputs = self.get_inputs();
+        if prev_heights.len() != inputs.len() || prev_mtps.len() != inputs.len() {
+            return false;
+        }
+        // Relative locktimes only apply from version 2
+        if self.get_version() < 2 {
+            return true;
+        }
+        // The last height and time at which the transaction can't be mined
+        // yet, -1 if there is none
+        let mut min_height = -1i64;
+        let mut min_time = -1i64;
+        for ((input, prev_height), prev_mtp) in inputs.iter().zip(prev_heights).zip(prev_mtps) {
+            match Sequence::from(input.get_sequence()).relative_locktime() {
+                Some(RelativeLocktime::Blocks(blocks)) => {
+                    min_height = min_height.max(*prev_height as i64 + blocks as i64 - 1);
+                }
+                Some(RelativeLocktime::Time(intervals)) => {
+                    let seconds = (intervals as i64) << Sequence::LOCKTIME_GRANULARITY;
+                    min_time = min_time.max(*prev_mtp as i64 + seconds - 1);
+                }
+                None => {}
+            }
+        }
+        min_height < height as i64 && min_time < median_time_past as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        amount::Amount,
+        script::Script,
+        transactions::{
+            hashes::Txid,
+            input::{OutPoint, TxInput},
+            output::TxOutput,
+        },
+    };
+
+    use super::*;
+
+    fn tx(version: u32, sequences: &[u32], locktime: u32) -> Tx {
+        let inputs = sequences
+            .iter()
+            .enumerate()
+            .map(|(vout, sequence)| {
+                TxInput::new(
+                    OutPoint::new(Txid::from_bytes([1; 32]), vout as u32),
+                    Script::new(vec![]),
+                    *sequence,
+                )
+            })
+            .collect();
+        let output = TxOutput::new(Amount::from_sat(1000), Script::p2wpkh(&[0; 20]));
+        Tx::new(version, inputs, vec![output], locktime)
+    }
+
+    #[test]
+    fn test_sequence() {
+        assert_eq!(
+            Sequence::from_height(144).relative_locktime(),
+            Some(RelativeLocktime::Blocks(144))
+        );
+        let time = Sequence::from_512_second_intervals(10);
+        assert_eq!(time.value(), 0x0040_000a);
+        assert_eq!(time.relative_locktime(), Some(RelativeLocktime::Time(10)));
+        // Bits outside the flags and value are ignored
+        assert_eq!(
+            Sequence::from(0x0001_0005).relative_locktime(),
+            Some(RelativeLocktime::Blocks(5))
+        );
+        assert_eq!(Sequence::FINAL.relative_locktime(), None);
+        assert!(Sequence::FINAL.is_final());
+        assert!(!Sequence::ENABLE_LOCKTIME_NO_RBF.signals_rbf());
+        assert!(Sequence::ENABLE_RBF_NO_LOCKTIME.signals_rbf());
+        assert!(Sequence::from_height(0).signals_rbf());
+
+        let blocks = RelativeLocktime::Blocks(10);
+        assert!(blocks.is_implied_by(&RelativeLocktime::Blocks(10)));
+        assert!(!blocks.is_implied_by(&RelativeLocktime::Blocks(9)));
+        assert!(!blocks.is_implied_by(&RelativeLocktime::Time(10)));
+    }
+
+    #[test]
+    fn test_is_final() {
+        assert!(tx(2, &[0], 0).is_final(0, 0));
+        let height_locked = tx(2, &[0], 100);
+        assert!(!height_locked.is_final(100, u32::MAX));
+        assert!(height_locked.is_final(101, 0));
+        let time_locked = tx(2, &[0, 0], 600_000_000);
+        assert!(!time_locked.is_final(u32::MAX, 600_000_000));
+        assert!(time_locked.is_final(0, 600_000_001));
+        // Final inputs disable the locktime
+        assert!(tx(2, &[u32::MAX], 100).is_final(1, 0));
+        assert!(!tx(2, &[u32::MAX, 0], 100).is_final(1, 0));
+    }
+
+    #[test]
+    fn test_check_sequence_locks() {
+        let blocks = tx(2, &[10, u32::MAX], 0);
+        assert!(!blocks.check_sequence_locks(&[100, 200], &[0, 0], 109, 0));
+        assert!(blocks.check_sequence_locks(&[100, 200], &[0, 0], 110, 0));
+        // Version 1 transactions have no relative locktimes
+        assert!(tx(1, &[10], 0).check_sequence_locks(&[100], &[0], 101, 0));
+
+        let time = Sequence::from_512_second_intervals(2).value();
+        let both = tx(2, &[time, 5], 0);
+        let (prev_heights, prev_mtps) = ([100, 100], [1_000_000, 0]);
+        assert!(!both.check_sequence_locks(&prev_heights, &prev_mtps, 105, 1_001_023));
+        assert!(!both.check_sequence_locks(&prev_heights, &prev_mtps, 104, 1_001_024));
+        assert!(both.check_sequence_locks(&prev_heights, &prev_mtps, 105, 1_001_024));
+
+        // Missing heights or times would leave inputs unchecked
+        assert!(!both.check_sequence_locks(&[100], &prev_mtps, 105, 1_001_024));
+        assert!(!both.check_sequence_locks(&prev_heights, &[1_000_000], 105, 1_001_024));
+        assert!(!tx(1, &[10], 0).check_sequence_locks(&[], &[], 101, 0));
+    }
+}
@@ -1,14 +1,15 @@
 use std::{
     fmt,
-    io::{Cursor, Error, ErrorKind, Read},
+    io::{Cursor, Error, Read, Write},
 };
 
-use crate::utils::{
-    hash256::hash256,
-    varint::{encode_varint, read_varint},
-};
+use crate::encode::{self, Decodable, DecodeError, DecodeErrorKind, Encodable};
 
-use super::{input::TxInput, output::TxOutput};
+use super::{
+    hashes::{Txid, Wtxid},
+    input::TxInput,
+    output::TxOutput,
+};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Tx {
@@ -18,107 +19,152 @@ pub struct Tx {
     locktime: Locktime,
 }
 
+/// Locktimes below this are block heights, the rest unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Locktime {
     BlockHeight(u32),
     UnixTimestamp(u32),
 }
 
-impl Tx {
-    /// Parses a transaction from a byte stream
-    pub fn parse(stream: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let mut version = vec![0; 4];
-
-        stream
-            .read_exact(&mut version)
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Invalid version: {}", e)))?;
-
-        let version = u32::from_le_bytes(
-            version
-                .try_into()
-                .map_err(|_| Error::new(ErrorKind::Other, "Invalid version"))?,
-        );
-
-        let mut inputs = vec![];
-        if let Ok(num_inputs) = read_varint(stream) {
-            for _ in 0..num_inputs {
-                let input = TxInput::parse(stream)
-                    .map_err(|_| Error::new(ErrorKind::Other, "Invalid input"))?;
-                inputs.push(input);
-            }
+impl Locktime {
+    /// Returns the raw nLockTime value
+    pub fn value(&self) -> u32 {
+        match self {
+            Locktime::BlockHeight(value) | Locktime::UnixTimestamp(value) => *value,
         }
+    }
 
-        let mut outputs = vec![];
-        if let Ok(num_outputs) = read_varint(stream) {
-            for _ in 0..num_outputs {
-                let output = TxOutput::parse(stream)
-                    .map_err(|_| Error::new(ErrorKind::Other, "Invalid output"))?;
+    /// Returns true if the locktime has passed in a block of the height
+    /// whose previous block has the median time past (BIP113). A zero
+    /// locktime always has.
+    pub fn is_satisfied_by(&self, height: u32, median_time_past: u32) -> bool {
+        match self {
+            Locktime::BlockHeight(0) => true,
+            Locktime::BlockHeight(locktime) => *locktime < height,
+            Locktime::UnixTimestamp(locktime) => *locktime < median_time_past,
+        }
+    }
 
-                outputs.push(output);
+    /// Returns true if a transaction of the other locktime can only be mined
+    /// once this one has passed, as OP_CHECKLOCKTIMEVERIFY requires: both
+    /// are heights or both timestamps, and the other is at least this one
+    pub fn is_implied_by(&self, other: &Locktime) -> bool {
+        match (self, other) {
+            (Locktime::BlockHeight(required), Locktime::BlockHeight(locktime))
+            | (Locktime::UnixTimestamp(required), Locktime::UnixTimestamp(locktime)) => {
+                required <= locktime
             }
+            _ => false,
         }
+    }
+}
 
-        let mut locktime_bytes = vec![0; 4];
-        stream.read_exact(&mut locktime_bytes).unwrap();
-        let locktime_value = u32::from_le_bytes(locktime_bytes.try_into().unwrap());
-
-        let locktime = if locktime_value >= 500_000_000 {
-            Locktime::UnixTimestamp(locktime_value)
+impl From<u32> for Locktime {
+    /// Values below 500,000,000 are block heights, the rest unix timestamps
+    fn from(value: u32) -> Self {
+        if value >= LOCKTIME_THRESHOLD {
+            Locktime::UnixTimestamp(value)
         } else {
-            Locktime::BlockHeight(locktime_value)
-        };
+            Locktime::BlockHeight(value)
+        }
+    }
+}
 
-        Ok(Tx {
+impl Tx {
+    /// Creates a transaction from its parts
+    pub fn new(version: u32, inputs: Vec<TxInput>, outputs: Vec<TxOutput>, locktime: u32) -> Self {
+        Tx {
             version,
             inputs,
             outputs,
-            locktime,
-        })
+            locktime: Locktime::from(locktime),
+        }
     }
 
-    /// Returns the byte serialization of the transaction
+    /// Parses a transaction from a byte stream, in either the legacy or the
+    /// segwit (BIP144) serialization
+    pub fn parse(stream: &mut Cursor<Vec<u8>>) -> Result<Self, DecodeError> {
+        encode::decode_cursor(stream)
+    }
+
+    /// Returns the byte serialization of the transaction, in the segwit
+    /// serialization if any input has a witness
     pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    /// Returns the byte serialization of the transaction without witness
+    /// data, which is what the transaction id commits to
+    pub fn serialize_legacy(&self) -> Vec<u8> {
         let mut result = Vec::new();
+        self.encode_with_witness(&mut result, false)
+            .expect("writing to a vector does not fail");
+        result
+    }
 
-        // Serialize version in little endian
-        let version_le = self.version.to_le_bytes().to_vec();
-        result.extend(version_le);
+    /// Writes the transaction, with the segwit marker, flag and witnesses
+    /// when `witness` is set
+    pub fn encode_with_witness<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        witness: bool,
+    ) -> Result<usize, Error> {
+        let mut len = self.version.consensus_encode(writer)?;
+
+        // Segwit marker and flag
+        if witness {
+            len += [0x00, 0x01].consensus_encode(writer)?;
+        }
 
-        // Serialize inputs
-        let inputs = self.inputs.clone();
-        result.extend_from_slice(&encode_varint(inputs.len() as u64).unwrap());
+        len += self.inputs.consensus_encode(writer)?;
+        len += self.outputs.consensus_encode(writer)?;
 
-        for input in inputs {
-            result.extend(input.serialize());
+        if witness {
+            for input in &self.inputs {
+                len += input.get_witness().consensus_encode(writer)?;
+            }
         }
 
-        // Serialize outputs
-        let outputs = self.outputs.clone();
-        result.extend_from_slice(&encode_varint(outputs.len() as u64).unwrap());
-        for output in outputs {
-            result.extend(output.serialize());
-        }
+        len += self.locktime.value().consensus_encode(writer)?;
+        Ok(len)
+    }
+
+    /// Returns true if any input has witness data
+    pub fn has_witness(&self) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| !input.get_witness().is_empty())
+    }
 
-        // Serialize locktime in little endian
-        let locktime_le = match self.locktime {
-            Locktime::BlockHeight(value) => value.to_le_bytes().to_vec(),
-            Locktime::UnixTimestamp(value) => value.to_le_bytes().to_vec(),
-        };
-        result.extend(locktime_le);
+    /// Returns the weight: four units per byte of the legacy serialization
+    /// and one per byte of witness data (BIP141)
+    pub fn weight(&self) -> usize {
+        let base_size = self.serialize_legacy().len();
+        base_size * 3 + self.serialize().len()
+    }
 
-        result
+    /// Returns the virtual size, the weight divided by four and rounded up
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
+
+    /// Returns true for coinbase transactions, whose single input spends
+    /// no output
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].outpoint().is_null()
     }
 
     /// Returns the transaction id
-    pub fn id(&self) -> String {
-        hex::encode(self.hash())
+    pub fn id(&self) -> Txid {
+        Txid::hash(&self.serialize_legacy())
     }
 
-    fn hash(&self) -> Vec<u8> {
-        let bytes = self.serialize();
-        let mut hash = hash256(&bytes);
-        hash.reverse();
-        hash.to_vec()
+    /// Returns the witness transaction id, the same hash as the id when no
+    /// input has a witness
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::hash(&self.serialize())
     }
 
     pub fn get_version(&self) -> u32 {
@@ -136,6 +182,56 @@ impl Tx {
     pub fn get_locktime(&self) -> Locktime {
         self.locktime.clone()
     }
+
+    /// Returns a mutable reference to the inputs, to attach signatures
+    pub fn inputs_mut(&mut self) -> &mut [TxInput] {
+        &mut self.inputs
+    }
+}
+
+impl Encodable for Tx {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.encode_with_witness(writer, self.has_witness())
+    }
+}
+
+impl Decodable for Tx {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let version = u32::consensus_decode(reader)?;
+
+        // A zero input count is the segwit marker, followed by the flag
+        let mut inputs = Vec::<TxInput>::consensus_decode(reader)?;
+        let segwit = inputs.is_empty();
+        if segwit {
+            let flag = u8::consensus_decode(reader)?;
+            if flag != 0x01 {
+                return Err(DecodeErrorKind::InvalidSegwitFlag(flag).into());
+            }
+            inputs = Vec::consensus_decode(reader)?;
+        }
+
+        let outputs = Vec::<TxOutput>::consensus_decode(reader)?;
+
+        if segwit {
+            for input in inputs.iter_mut() {
+                input.set_witness(Vec::consensus_decode(reader)?);
+            }
+            // Without witnesses it would serialize back in the legacy
+            // format, with another wtxid
+            if inputs.iter().all(|input| input.get_witness().is_empty()) {
+                return Err(DecodeErrorKind::SuperfluousWitness.into());
+            }
+        }
+
+        let locktime = u32::consensus_decode(reader)?;
+
+        Ok(Tx {
+            version,
+            inputs,
+            outputs,
+            locktime: Locktime::from(locktime),
+        })
+    }
 }
 
 impl fmt::Display for Tx {
@@ -156,7 +252,9 @@ impl fmt::Display for Tx {
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::io::{BufRead, BufReader, Cursor};
+
+    use crate::amount::Amount;
 
     use super::*;
 
@@ -175,9 +273,9 @@ mod tests {
         let tx = Tx::parse(&mut stream).unwrap();
         assert_eq!(tx.get_inputs().len(), 1);
 
-        let input = tx.get_inputs().get(0).unwrap().clone();
+        let input = tx.get_inputs().first().unwrap().clone();
         let want = String::from("d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81");
-        assert_eq!(input.get_prev_tx(), want);
+        assert_eq!(input.get_prev_tx().to_string(), want);
         assert_eq!(input.get_prev_index(), 0);
 
         let want = hex::decode("6b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a").unwrap();
@@ -192,13 +290,13 @@ mod tests {
         let tx = Tx::parse(&mut stream).unwrap();
         assert_eq!(tx.get_outputs().len(), 2);
 
-        let output = tx.get_outputs().get(0).unwrap().clone();
-        assert_eq!(output.get_amount(), 32454049);
+        let output = tx.get_outputs().first().unwrap().clone();
+        assert_eq!(output.get_amount(), Amount::from_sat(32454049));
         let want = hex::decode("1976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
         assert_eq!(output.get_script_pubkey().serialize(), want);
 
         let output = tx.get_outputs().get(1).unwrap().clone();
-        assert_eq!(output.get_amount(), 10011545);
+        assert_eq!(output.get_amount(), Amount::from_sat(10011545));
         let want = hex::decode("1976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac").unwrap();
         assert_eq!(output.get_script_pubkey().serialize(), want);
     }
@@ -210,4 +308,126 @@ mod tests {
         let tx = Tx::parse(&mut stream).unwrap();
         assert_eq!(tx.get_locktime(), Locktime::BlockHeight(410393));
     }
+
+    #[test]
+    fn test_parse_truncated_push() {
+        // A coinbase whose script sig pushes past its end, valid on chain
+        let raw_tx = hex::decode(format!(
+            "0100000001{}ffffffff020301ffffffff0100f2052a01000000015100000000",
+            "00".repeat(32)
+        ))
+        .unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw_tx.clone())).unwrap();
+        let script_sig = tx.get_inputs()[0].get_script_sig();
+        assert_eq!(script_sig.truncated_push(), Some([0x03, 0x01].as_slice()));
+        assert_eq!(tx.serialize(), raw_tx);
+    }
+
+    #[test]
+    fn test_locktime() {
+        let height = Locktime::from(100);
+        assert!(!height.is_satisfied_by(100, u32::MAX));
+        assert!(height.is_satisfied_by(101, 0));
+        assert!(Locktime::from(0).is_satisfied_by(0, 0));
+        let time = Locktime::from(LOCKTIME_THRESHOLD);
+        assert_eq!(time, Locktime::UnixTimestamp(500_000_000));
+        assert!(!time.is_satisfied_by(u32::MAX, 500_000_000));
+        assert!(time.is_satisfied_by(0, 500_000_001));
+
+        assert!(height.is_implied_by(&Locktime::from(100)));
+        assert!(!height.is_implied_by(&Locktime::from(99)));
+        assert!(!height.is_implied_by(&time));
+    }
+
+    #[test]
+    fn test_parse_segwit() {
+        // The BIP143 native P2WPKH example, signed
+        let raw_tx = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+        let mut stream = Cursor::new(hex::decode(raw_tx).unwrap());
+        let tx = Tx::parse(&mut stream).unwrap();
+        assert!(tx.has_witness());
+        assert_eq!(tx.get_inputs()[0].get_witness().len(), 0);
+        assert_eq!(tx.get_inputs()[1].get_witness().len(), 2);
+        assert_eq!(tx.get_locktime(), Locktime::BlockHeight(17));
+        assert_eq!(hex::encode(tx.serialize()), raw_tx);
+        assert_eq!(
+            tx.id().to_string(),
+            "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609"
+        );
+        assert_ne!(tx.wtxid().to_bytes(), tx.id().to_bytes());
+    }
+
+    #[test]
+    fn test_decode_stream() {
+        let legacy = hex::decode("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+        let segwit = hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap();
+
+        // Back to back in a buffered stream, as in a block file
+        let stream = [legacy.clone(), segwit.clone()].concat();
+        let mut reader = BufReader::new(stream.as_slice());
+        let first = Tx::consensus_decode(&mut reader).unwrap();
+        let second = Tx::consensus_decode(&mut reader).unwrap();
+        assert!(reader.fill_buf().unwrap().is_empty());
+        assert_eq!(encode::serialize(&first), legacy);
+        assert_eq!(encode::serialize(&second), segwit);
+
+        let mut written = vec![];
+        let len = second.consensus_encode(&mut written).unwrap();
+        assert_eq!(len, segwit.len());
+        assert_eq!(encode::deserialize::<Tx>(&written).unwrap(), second);
+        assert_eq!(
+            encode::deserialize::<Tx>(&legacy[..legacy.len() - 1]),
+            Err(DecodeError::new(
+                legacy.len() as u64 - 1,
+                DecodeErrorKind::UnexpectedEof
+            ))
+        );
+        // The flag after a segwit marker
+        let mut bad_flag = segwit.clone();
+        bad_flag[5] = 0x02;
+        assert_eq!(
+            Tx::parse(&mut Cursor::new(bad_flag)),
+            Err(DecodeError::new(6, DecodeErrorKind::InvalidSegwitFlag(2)))
+        );
+
+        // The legacy transaction with the marker, the flag and an empty
+        // witness, which has no encoding to round trip to
+        let mut empty_witness = legacy.clone();
+        empty_witness.splice(4..4, [0x00, 0x01]);
+        empty_witness.splice(empty_witness.len() - 4.., [0x00]);
+        empty_witness.extend(&legacy[legacy.len() - 4..]);
+        assert_eq!(
+            encode::deserialize::<Tx>(&empty_witness),
+            Err(DecodeError::new(
+                empty_witness.len() as u64 - 4,
+                DecodeErrorKind::SuperfluousWitness
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new() {
+        let raw_tx = hex::decode("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw_tx.clone())).unwrap();
+        let inputs = tx
+            .get_inputs()
+            .iter()
+            .map(|input| {
+                TxInput::new(
+                    input.outpoint(),
+                    input.get_script_sig(),
+                    input.get_sequence(),
+                )
+            })
+            .collect();
+        let outputs = tx
+            .get_outputs()
+            .iter()
+            .map(|output| TxOutput::new(output.get_amount(), output.get_script_pubkey()))
+            .collect();
+        let rebuilt = Tx::new(1, inputs, outputs, 410393);
+        assert_eq!(rebuilt, tx);
+        assert_eq!(rebuilt.serialize(), raw_tx);
+        assert_eq!(rebuilt.wtxid().to_bytes(), rebuilt.id().to_bytes());
+    }
 }
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tx_tests;
+
+use std::fmt;
+
+use crate::script::interpreter::{
+    checker::TransactionSignatureChecker, verify_raw_script, ScriptError, VerifyFlags,
+};
+
+use super::{output::TxOutput, tx::Tx};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VerifyError {
+    /// There must be one spent output per input
+    PrevoutCount {
+        inputs: usize,
+        prevouts: usize,
+    },
+    InputOutOfRange(usize),
+    /// The scripts of the input failed
+    Script {
+        input: usize,
+        error: ScriptError,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::PrevoutCount { inputs, prevouts } => {
+                write!(f, "{} inputs but {} spent outputs", inputs, prevouts)
+            }
+            VerifyError::InputOutOfRange(index) => write!(f, "input {} is out of range", index),
+            VerifyError::Script { input, error } => write!(f, "input {}: {}", input, error),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Script verification
+impl Tx {
+    /// Verifies the scripts of an input against the output it spends.
+    /// prevouts are the outputs spent by every input, in order, which
+    /// taproot signatures commit to.
+    pub fn verify_input(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOutput],
+        flags: VerifyFlags,
+    ) -> Result<(), VerifyError> {
+        let inputs = self.get_inputs();
+        if prevouts.len() != inputs.len() {
+            return Err(VerifyError::PrevoutCount {
+                inputs: inputs.len(),
+                prevouts: prevouts.len(),
+            });
+        }
+        let input = inputs
+            .get(input_index)
+            .ok_or(VerifyError::InputOutOfRange(input_index))?;
+        let prevout = &prevouts[input_index];
+        let checker = TransactionSignatureChecker::new(self, input_index, prevout.get_amount())
+            .prevouts(prevouts);
+        verify_raw_script(
+            &input.get_script_sig().raw_serialize(),
+            &prevout.get_script_pubkey().raw_serialize(),
+            input.get_witness(),
+            flags,
+            &checker,
+        )
+        .map_err(|error| VerifyError::Script {
+            input: input_index,
+            error,
+        })
+    }
+
+    /// Verifies the scripts of every input, stopping at the first failure
+    pub fn verify(&self, prevouts: &[TxOutput], flags: VerifyFlags) -> Result<(), VerifyError> {
+        (0..self.get_inputs().len()).try_for_each(|index| self.verify_input(index, prevouts, flags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        amount::Amount,
+        script::{op::OP_1, Command, Script},
+        transactions::{
+            hashes::Txid,
+            input::{OutPoint, TxInput},
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let prevouts = vec![TxOutput::new(
+            Amount::from_sat(1000),
+            Script::new(vec![Command::Op(OP_1)]),
+        )];
+        let input = TxInput::new(
+            OutPoint::new(Txid::from_bytes([1; 32]), 0),
+            Script::new(vec![]),
+            0xffff_ffff,
+        );
+        let mut tx = Tx::new(1, vec![input], vec![], 0);
+        assert_eq!(tx.verify(&prevouts, VerifyFlags::STANDARD), Ok(()));
+        assert_eq!(
+            tx.verify(&[], VerifyFlags::NONE),
+            Err(VerifyError::PrevoutCount {
+                inputs: 1,
+                prevouts: 0
+            })
+        );
+        assert_eq!(
+            tx.verify_input(1, &prevouts, VerifyFlags::NONE),
+            Err(VerifyError::InputOutOfRange(1))
+        );
+
+        tx.inputs_mut()[0].set_witness(vec![vec![1]]);
+        assert_eq!(tx.verify(&prevouts, VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            tx.verify(&prevouts, VerifyFlags::P2SH | VerifyFlags::WITNESS),
+            Err(VerifyError::Script {
+                input: 0,
+                error: ScriptError::WitnessUnexpected
+            })
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Runs tx_valid.json and tx_invalid.json, transaction test vectors in the
+//! format of Bitcoin Core's: each test is
+//! `[[[prevout_txid, prevout_index, prevout_script_pubkey, amount?]...], tx, flags]`
+//! and entries of a single string are comments. Valid transactions must
+//! pass Tx::check and every flag but the ones listed, invalid ones must
+//! fail with the flags listed, or fail Tx::check if they include BADTX.
+//! The flags must be the fewest each test needs, see check_flags; that
+//! re-verifies every test once per flag, so it runs in ignored tests.
+//! The files are not the upstream ones yet, see their first comment; once
+//! they are, KNOWN_FAILURES lists the vectors this crate doesn't pass.
+
+use std::{collections::HashMap, io::Cursor};
+
+use serde_json::Value;
+
+use crate::{
+    amount::Amount,
+    script::{
+        interpreter::{script_tests::parse_script, VerifyFlags},
+        Script,
+    },
+    transactions::{input::OutPoint, output::TxOutput, tx::Tx},
+};
+
+const TX_VALID: &str = include_str!("tx_valid.json");
+const TX_INVALID: &str = include_str!("tx_invalid.json");
+
+/// Vectors expected to fail, as their JSON, with the reason. Listed vectors
+/// must still fail the full run with check_flags, so that a fix also
+/// removes its entry.
+const KNOWN_FAILURES: &[(&str, &str)] = &[];
+
+/// Adds the flags the flags set depend on, as Bitcoin Core's tests do
+fn fill_flags(mut flags: VerifyFlags) -> VerifyFlags {
+    if flags.contains(VerifyFlags::CLEANSTACK) {
+        flags |= VerifyFlags::WITNESS;
+    }
+    if flags.contains(VerifyFlags::WITNESS) {
+        flags |= VerifyFlags::P2SH;
+    }
+    flags
+}
+
+/// Removes the flags that depend on flags not set
+fn trim_flags(mut flags: VerifyFlags) -> VerifyFlags {
+    if !flags.contains(VerifyFlags::P2SH) {
+        flags.remove(VerifyFlags::WITNESS);
+    }
+    if !flags.contains(VerifyFlags::WITNESS) {
+        flags.remove(VerifyFlags::CLEANSTACK);
+    }
+    flags
+}
+
+/// Parses the transaction of a test and the outputs its inputs spend
+fn parse_test(test: &[Value]) -> (Tx, Vec<TxOutput>) {
+    let mut spent = HashMap::new();
+    for prevout in test[0].as_array().unwrap() {
+        let prevout = prevout.as_array().unwrap();
+        let txid = prevout[0].as_str().unwrap().parse().unwrap();
+        // -1 stands for the index of null prevouts
+        let vout = prevout[1].as_i64().unwrap() as u32;
+        let script_pubkey =
+            Script::from_bytes_lossless(&parse_script(prevout[2].as_str().unwrap()));
+        let amount = prevout.get(3).map_or(0, |amount| amount.as_u64().unwrap());
+        spent.insert(
+            OutPoint::new(txid, vout),
+            TxOutput::new(Amount::from_sat(amount), script_pubkey),
+        );
+    }
+    let raw = hex::decode(test[1].as_str().unwrap()).unwrap();
+    let tx = Tx::parse(&mut Cursor::new(raw)).unwrap();
+    let prevouts = tx
+        .get_inputs()
+        .iter()
+        .map(|input| spent[&input.outpoint()].clone())
+        .collect();
+    (tx, prevouts)
+}
+
+/// Checks that the flags of a test are the fewest it needs, as Bitcoin
+/// Core's tests do, passes telling whether the transaction passes with the
+/// flags given. A valid transaction must still pass with any flag removed,
+/// and fail if any flag it excludes is added back. An invalid one must pass
+/// if any flag listed is removed. Returns what went wrong.
+fn check_flags(
+    listed: VerifyFlags,
+    valid: bool,
+    all_flags: VerifyFlags,
+    passes: impl Fn(VerifyFlags) -> bool,
+) -> Option<String> {
+    let flags = if valid {
+        let mut flags = all_flags;
+        flags.remove(listed);
+        if fill_flags(flags) != flags {
+            return Some(format!("excluded flags {} leave flags they need", listed));
+        }
+        flags
+    } else {
+        fill_flags(listed)
+    };
+    for &(name, flag) in VerifyFlags::NAMES {
+        let mut fewer = flags;
+        fewer.remove(flag);
+        if flags.contains(flag) && !passes(trim_flags(fewer)) {
+            return Some(if valid {
+                format!("fails without {}", name)
+            } else {
+                format!("fails without {}, which needn't be listed", name)
+            });
+        }
+        if valid && listed.contains(flag) && passes(fill_flags(flags | flag)) {
+            return Some(format!("passes with {}, which needn't be excluded", name));
+        }
+    }
+    None
+}
+
+/// Runs the tests of a file, returning the ones whose outcome differs from
+/// the expected one along with it. Flags are only checked to be the fewest
+/// needed with `minimal_flags`.
+fn run_tests(tests: &str, valid: bool, minimal_flags: bool) -> Vec<String> {
+    let tests: Value = serde_json::from_str(tests).unwrap();
+    let all_flags = VerifyFlags::STANDARD | VerifyFlags::SIGPUSHONLY;
+    let mut failures = vec![];
+    let mut count = 0;
+    for test in tests.as_array().unwrap() {
+        let test = test.as_array().unwrap();
+        if test.len() == 1 {
+            continue;
+        }
+        count += 1;
+        let (tx, prevouts) = parse_test(test);
+        let flags = test[2].as_str().unwrap();
+        let bad_tx = flags.split(',').any(|flag| flag == "BADTX");
+        let listed: VerifyFlags = flags
+            .split(',')
+            .filter(|flag| *flag != "BADTX")
+            .collect::<Vec<_>>()
+            .join(",")
+            .parse()
+            .unwrap();
+        let checked = tx.check().map_err(|err| err.to_string());
+        let result = if bad_tx {
+            checked
+        } else if valid {
+            let mut flags = all_flags;
+            flags.remove(listed);
+            checked.and_then(|()| {
+                tx.verify(&prevouts, trim_flags(flags))
+                    .map_err(|err| err.to_string())
+            })
+        } else {
+            tx.verify(&prevouts, fill_flags(listed))
+                .map_err(|err| err.to_string())
+        };
+        let outcome = match result {
+            Ok(()) if !valid => Some("valid".to_string()),
+            Err(err) if valid => Some(err),
+            _ if bad_tx || !minimal_flags => None,
+            _ => check_flags(listed, valid, all_flags, |flags| {
+                tx.verify(&prevouts, flags).is_ok()
+            }),
+        };
+        let json = Value::from(test.clone()).to_string();
+        let known = KNOWN_FAILURES.iter().any(|(known, _)| *known == json);
+        match outcome {
+            Some(outcome) if !known => failures.push(format!("{} got {}", json, outcome)),
+            None if known && minimal_flags => {
+                failures.push(format!("{} passes but is a known failure", json))
+            }
+            _ => {}
+        }
+    }
+    assert!(count > 0);
+    failures
+}
+
+#[test]
+fn test_tx_valid() {
+    let failures = run_tests(TX_VALID, true, false);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_tx_invalid() {
+    let failures = run_tests(TX_INVALID, false, false);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "re-verifies every vector once per flag, run with --ignored"]
+fn test_tx_valid_minimal_flags() {
+    let failures = run_tests(TX_VALID, true, true);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "re-verifies every vector once per flag, run with --ignored"]
+fn test_tx_invalid_minimal_flags() {
+    let failures = run_tests(TX_INVALID, false, true);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
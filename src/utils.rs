@@ -1,7 +1,11 @@
+pub mod base64;
+pub mod bech32;
 pub mod biguint_primality_checker;
 pub mod calculate_fee;
 pub mod encode_base58;
 pub mod hash160;
 pub mod hash256;
+pub mod hmac_sha512;
+pub mod tagged_hash;
 pub mod tx_fetcher;
 pub mod varint;
@@ -0,0 +1,263 @@
+/// Bech32 (BIP173) and Bech32m (BIP350) encoding, used by segwit addresses.
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+/// Which checksum constant the string uses
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+impl Variant {
+    fn constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => BECH32_CONST,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for &value in values {
+        let top = chk >> 25;
+        chk = (chk & 0x1ffffff) << 5 ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    result.push(0);
+    result.extend(hrp.bytes().map(|b| b & 31));
+    result
+}
+
+fn create_checksum(hrp: &str, data: &[u8], variant: Variant) -> Vec<u8> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; 6]);
+    let polymod = polymod(&values) ^ variant.constant();
+    (0..6)
+        .map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8)
+        .collect()
+}
+
+/// Encodes 5-bit values with the human readable part
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let mut combined = data.to_vec();
+    combined.extend(create_checksum(hrp, data, variant));
+    let mut result = format!("{}1", hrp);
+    for value in combined {
+        result.push(CHARSET.as_bytes()[value as usize] as char);
+    }
+    result
+}
+
+/// Decodes a bech32 or bech32m string into its human readable part and
+/// 5-bit values
+pub fn decode(s: &str) -> Result<(String, Vec<u8>, Variant), String> {
+    if s.bytes().any(|b| !(33..=126).contains(&b)) {
+        return Err("Invalid character in bech32 string".to_string());
+    }
+    if s.to_lowercase() != s && s.to_uppercase() != s {
+        return Err("Mixed case bech32 string".to_string());
+    }
+    let s = s.to_lowercase();
+    let pos = s
+        .rfind('1')
+        .ok_or_else(|| "Missing bech32 separator".to_string())?;
+    if pos < 1 || pos + 7 > s.len() || s.len() > 90 {
+        return Err("Invalid bech32 length".to_string());
+    }
+
+    let hrp = &s[..pos];
+    let mut data = Vec::with_capacity(s.len() - pos - 1);
+    for c in s[pos + 1..].chars() {
+        let value = CHARSET
+            .find(c)
+            .ok_or_else(|| format!("Invalid bech32 character: {}", c))?;
+        data.push(value as u8);
+    }
+
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    let variant = match polymod(&values) {
+        BECH32_CONST => Variant::Bech32,
+        BECH32M_CONST => Variant::Bech32m,
+        _ => return Err("Invalid bech32 checksum".to_string()),
+    };
+
+    data.truncate(data.len() - 6);
+    Ok((hrp.to_string(), data, variant))
+}
+
+/// Regroups bits, e.g. from 8-bit bytes to 5-bit values and back
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, String> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value = (1 << to) - 1;
+    let mut result = Vec::new();
+    for &value in data {
+        if (value as u32) >> from != 0 {
+            return Err("Invalid value for bit conversion".to_string());
+        }
+        acc = (acc << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max_value) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max_value) != 0 {
+        return Err("Invalid padding in bit conversion".to_string());
+    }
+    Ok(result)
+}
+
+/// Encodes a segwit address. Version 0 uses bech32, later versions bech32m.
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    let variant = if version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).expect("bytes always fit in 8 bits"));
+    encode(hrp, &data, variant)
+}
+
+/// Decodes a segwit address with the expected human readable part into its
+/// witness version and program
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), String> {
+    let (found_hrp, data, variant) = decode(address)?;
+    if found_hrp != hrp {
+        return Err(format!("Unexpected human readable part: {}", found_hrp));
+    }
+    if data.is_empty() || data[0] > 16 {
+        return Err("Invalid witness version".to_string());
+    }
+    let version = data[0];
+    let program = convert_bits(&data[1..], 5, 8, false)?;
+    if program.len() < 2 || program.len() > 40 {
+        return Err("Invalid witness program length".to_string());
+    }
+    if version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err("Invalid witness v0 program length".to_string());
+    }
+    if (version == 0 && variant != Variant::Bech32) || (version != 0 && variant != Variant::Bech32m)
+    {
+        return Err("Invalid checksum variant for witness version".to_string());
+    }
+    Ok((version, program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_checksums() {
+        let values = vec![
+            ("A12UEL5L", Variant::Bech32),
+            (
+                "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+                Variant::Bech32,
+            ),
+            ("?1ezyfcl", Variant::Bech32),
+            ("A1LQFN3A", Variant::Bech32m),
+            (
+                "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+                Variant::Bech32m,
+            ),
+            ("?1v759aa", Variant::Bech32m),
+        ];
+        for (s, variant) in values {
+            let (hrp, data, found) = decode(s).unwrap();
+            assert_eq!(found, variant);
+            assert_eq!(encode(&hrp, &data, variant), s.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_invalid_checksums() {
+        for s in ["A12UEL5l", "x1b4n0q5v", "li1dgmt3", "1qzzfhee", "A1G7SGD8"] {
+            assert!(decode(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_segwit_addresses() {
+        let values = vec![
+            (
+                "bc",
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                0,
+                "751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "tb",
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                0,
+                "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc",
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+                1,
+                "751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "bc",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                1,
+                "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+        ];
+        for (hrp, address, version, program) in values {
+            let (found_version, found_program) = decode_segwit_address(hrp, address).unwrap();
+            assert_eq!(found_version, version);
+            assert_eq!(hex::encode(&found_program), program);
+            assert_eq!(
+                encode_segwit_address(hrp, version, &found_program),
+                address.to_lowercase()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_segwit_addresses() {
+        let values = vec![
+            // Wrong human readable part
+            (
+                "bc",
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+            ),
+            // Version 1 with a bech32 checksum
+            (
+                "bc",
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            ),
+            // Version 0 with a bech32m checksum
+            ("bc", "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"),
+            // Invalid program length for version 0
+            ("bc", "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P"),
+        ];
+        for (hrp, address) in values {
+            assert!(decode_segwit_address(hrp, address).is_err(), "{}", address);
+        }
+    }
+}
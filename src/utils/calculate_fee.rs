@@ -1,42 +1,82 @@
-use crate::transactions::{input::TxInput, tx::Tx};
-
-use super::tx_fetcher::TxFetcher;
+use std::fmt;
+
+use crate::{
+    amount::Amount,
+    transactions::{
+        input::{OutPoint, TxInput},
+        tx::Tx,
+    },
+};
+
+use super::tx_fetcher::{FetchError, TxFetcher};
+
+#[derive(Debug)]
+pub enum FeeError {
+    Fetch(FetchError),
+    /// The previous transaction has no such output
+    MissingOutput(OutPoint),
+    /// The input or output values sum to more than MAX_MONEY
+    Overflow,
+    /// The outputs spend more than the inputs
+    NegativeFee,
+}
 
-/// Calculates the fee of a transaction
-pub fn calculate_fee(tx: &Tx, fetcher: &TxFetcher) -> Result<u64, Box<dyn std::error::Error>> {
-    let mut input_sum = 0;
-    for input in tx.get_inputs() {
-        input_sum += get_input_value(&input, fetcher)?;
+impl fmt::Display for FeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeError::Fetch(err) => write!(f, "{}", err),
+            FeeError::MissingOutput(outpoint) => {
+                write!(f, "previous output {} not found", outpoint)
+            }
+            FeeError::Overflow => write!(f, "value sum is over the money supply"),
+            FeeError::NegativeFee => write!(f, "output sum is greater than input sum"),
+        }
     }
+}
 
-    let mut output_sum = 0;
-    for output in tx.get_outputs() {
-        output_sum += output.get_amount();
+impl std::error::Error for FeeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FeeError::Fetch(err) => Some(err),
+            _ => None,
+        }
     }
+}
 
-    if input_sum < output_sum {
-        return Err("Invalid transaction: output sum is greater than input sum".into());
+impl From<FetchError> for FeeError {
+    fn from(err: FetchError) -> Self {
+        FeeError::Fetch(err)
     }
-
-    Ok(input_sum - output_sum)
 }
 
-/// Returns the value of the input
-fn get_input_value(
-    input: &TxInput,
-    fetcher: &TxFetcher,
-) -> Result<u64, Box<dyn std::error::Error>> {
-    let prev_tx_hex = input.get_prev_tx();
+/// Calculates the fee of a transaction
+pub fn calculate_fee(tx: &Tx, fetcher: &TxFetcher) -> Result<Amount, FeeError> {
+    let mut input_sum = Amount::ZERO;
+    for input in tx.get_inputs() {
+        input_sum = input_sum
+            .checked_add(get_input_value(&input, fetcher)?)
+            .filter(|sum| sum.is_valid())
+            .ok_or(FeeError::Overflow)?;
+    }
 
-    let prev_tx = fetcher.fetch(&prev_tx_hex, false)?;
+    let output_sum = Amount::checked_sum(tx.get_outputs().iter().map(|output| output.get_amount()))
+        .filter(|sum| sum.is_valid())
+        .ok_or(FeeError::Overflow)?;
 
-    let prev_outputs = prev_tx.get_outputs();
+    input_sum
+        .checked_sub(output_sum)
+        .ok_or(FeeError::NegativeFee)
+}
 
-    let prev_index = input.get_prev_index();
+/// Returns the value of the input
+fn get_input_value(input: &TxInput, fetcher: &TxFetcher) -> Result<Amount, FeeError> {
+    let prev_tx = fetcher.fetch(&input.get_prev_tx(), false)?;
 
-    let prev_output = prev_outputs
-        .get(prev_index as usize)
-        .ok_or("Previous output not found")?;
+    let prev_output = prev_tx
+        .get_outputs()
+        .get(input.get_prev_index() as usize)
+        .cloned()
+        .ok_or(FeeError::MissingOutput(input.outpoint()))?;
 
     Ok(prev_output.get_amount())
 }
@@ -83,7 +123,7 @@ mod tests {
             let tx = Tx::parse(&mut stream).unwrap();
 
             let value = calculate_fee(&tx, &fetcher).unwrap();
-            assert_eq!(fee, value);
+            assert_eq!(Amount::from_sat(fee), value);
         }
     }
 }
@@ -42,6 +42,43 @@ pub fn encode_base58_checksum(bytes: &[u8]) -> String {
     encode_base58(&result)
 }
 
+/// Decodes a base58 string into bytes
+pub fn decode_base58(s: &str) -> Result<Vec<u8>, String> {
+    let mut num = BigUint::zero();
+    let mut leading_zeros = 0;
+    let mut counting_zeros = true;
+    for c in s.chars() {
+        let digit = BASE58_ALPHABET
+            .find(c)
+            .ok_or_else(|| format!("Invalid base58 character: {}", c))?;
+        // Each leading '1' stands for a 0 byte that the number can't represent.
+        if counting_zeros && digit == 0 {
+            leading_zeros += 1;
+        } else {
+            counting_zeros = false;
+        }
+        num = num * 58u8 + digit;
+    }
+    let mut result = vec![0u8; leading_zeros];
+    if !num.is_zero() {
+        result.extend(num.to_bytes_be());
+    }
+    Ok(result)
+}
+
+/// Decodes a base58 string and verifies its 4-byte checksum
+pub fn decode_base58_checksum(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = decode_base58(s)?;
+    if bytes.len() < 4 {
+        return Err("Base58 string too short".to_string());
+    }
+    let checksum = bytes.split_off(bytes.len() - 4);
+    if hash256(&bytes)[0..4] != checksum[..] {
+        return Err("Invalid base58 checksum".to_string());
+    }
+    Ok(bytes)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -81,4 +118,31 @@ mod tests {
             "wdA2ffYs5cudrdkhFm5Ym94AuLvavacapuDBL2CAcvqYPkcvi"
         );
     }
+
+    #[test]
+    fn test_decode_base58() {
+        for value in [
+            "7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d",
+            "00eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c",
+            "0000c7207fee",
+        ] {
+            let value: Vec<u8> = hex::decode(value).unwrap();
+            assert_eq!(decode_base58(&encode_base58(&value)).unwrap(), value);
+        }
+        assert!(decode_base58("0OIl").is_err());
+    }
+
+    #[test]
+    fn test_decode_base58_checksum() {
+        let value = "7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d";
+        let value: Vec<u8> = hex::decode(value).unwrap();
+
+        assert_eq!(
+            decode_base58_checksum("wdA2ffYs5cudrdkhFm5Ym94AuLvavacapuDBL2CAcvqYPkcvi").unwrap(),
+            value
+        );
+        assert!(
+            decode_base58_checksum("wdA2ffYs5cudrdkhFm5Ym94AuLvavacapuDBL2CAcvqYPkcvj").is_err()
+        );
+    }
 }
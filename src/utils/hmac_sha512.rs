@@ -0,0 +1,28 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+
+/// HMAC-SHA512 of the data with the given key
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    // HMAC accepts keys of any length, so this can never fail
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    let mut result: [u8; 64] = [0; 64];
+    result.copy_from_slice(&mac.finalize().into_bytes());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha512() {
+        // RFC 4231, test case 2
+        let result = hmac_sha512(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex::encode(result),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+            9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+}
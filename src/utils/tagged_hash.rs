@@ -0,0 +1,30 @@
+use sha2::{Digest, Sha256};
+
+/// BIP340 tagged hash: sha256(sha256(tag) || sha256(tag) || msg)
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(msg);
+    let mut result: [u8; 32] = [0; 32];
+    result.copy_from_slice(&hasher.finalize());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_hash() {
+        let tag_hash = Sha256::digest(b"TapLeaf");
+        let mut preimage = tag_hash.to_vec();
+        preimage.extend(tag_hash);
+        preimage.extend(b"abc");
+        assert_eq!(
+            tagged_hash("TapLeaf", b"abc").to_vec(),
+            Sha256::digest(&preimage).to_vec()
+        );
+    }
+}
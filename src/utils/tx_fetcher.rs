@@ -1,15 +1,106 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
+    fmt,
     fs::{File, OpenOptions},
-    io::{Cursor, Read, Write},
+    io::{self, Cursor, Read, Write},
 };
 
-use crate::transactions::tx::Tx;
+use crate::{
+    encode::DecodeError,
+    transactions::{
+        hashes::{ParseHashError, Txid},
+        tx::Tx,
+    },
+};
+
+#[derive(Debug)]
+pub enum FetchError {
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+    InvalidHex(hex::FromHexError),
+    InvalidTxid(ParseHashError),
+    Decode(DecodeError),
+    /// The transaction returned hashes to a different id
+    IdMismatch {
+        expected: Txid,
+        got: Txid,
+    },
+    /// The response lacks a field
+    MissingField(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Http(err) => write!(f, "request failed: {}", err),
+            FetchError::Json(err) => write!(f, "invalid JSON: {}", err),
+            FetchError::Io(err) => write!(f, "{}", err),
+            FetchError::InvalidHex(err) => write!(f, "invalid hex: {}", err),
+            FetchError::InvalidTxid(err) => write!(f, "invalid txid: {}", err),
+            FetchError::Decode(err) => write!(f, "invalid transaction: {}", err),
+            FetchError::IdMismatch { expected, got } => {
+                write!(f, "not the same id: {} vs {}", got, expected)
+            }
+            FetchError::MissingField(field) => write!(f, "missing {}", field),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Http(err) => Some(err),
+            FetchError::Json(err) => Some(err),
+            FetchError::Io(err) => Some(err),
+            FetchError::InvalidHex(err) => Some(err),
+            FetchError::InvalidTxid(err) => Some(err),
+            FetchError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        FetchError::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(err: serde_json::Error) -> Self {
+        FetchError::Json(err)
+    }
+}
+
+impl From<io::Error> for FetchError {
+    fn from(err: io::Error) -> Self {
+        FetchError::Io(err)
+    }
+}
+
+impl From<hex::FromHexError> for FetchError {
+    fn from(err: hex::FromHexError) -> Self {
+        FetchError::InvalidHex(err)
+    }
+}
+
+impl From<ParseHashError> for FetchError {
+    fn from(err: ParseHashError) -> Self {
+        FetchError::InvalidTxid(err)
+    }
+}
+
+impl From<DecodeError> for FetchError {
+    fn from(err: DecodeError) -> Self {
+        FetchError::Decode(err)
+    }
+}
 
 /// Fetches transactions from the network
 pub struct TxFetcher {
-    cache: RefCell<HashMap<String, Tx>>,
+    cache: RefCell<HashMap<Txid, Tx>>,
     api_url: String,
 }
 
@@ -20,7 +111,7 @@ impl TxFetcher {
     }
 
     /// Fetches a transaction from the network
-    pub fn fetch(&self, tx_id: &str, fresh: bool) -> Result<Tx, Box<dyn std::error::Error>> {
+    pub fn fetch(&self, tx_id: &Txid, fresh: bool) -> Result<Tx, FetchError> {
         let mut cache = self.cache.borrow_mut();
         if fresh || !cache.contains_key(tx_id) {
             let url = format!("{}/tx/{}/hex", self.api_url, tx_id);
@@ -29,17 +120,36 @@ impl TxFetcher {
             let mut cursor = Cursor::new(raw);
             let tx = Tx::parse(&mut cursor)?;
 
-            if tx.id() != tx_id {
-                return Err(format!("not the same id: {} vs {}", tx.id(), tx_id).into());
+            if tx.id() != *tx_id {
+                return Err(FetchError::IdMismatch {
+                    expected: *tx_id,
+                    got: tx.id(),
+                });
             }
-            cache.insert(tx_id.to_string(), tx);
+            cache.insert(*tx_id, tx);
         }
 
         Ok(cache.get(tx_id).unwrap().clone())
     }
 
+    /// Returns the number of transactions, confirmed or in the mempool, that
+    /// involve the address
+    pub fn fetch_address_tx_count(&self, address: &str) -> Result<u64, FetchError> {
+        let url = format!("{}/address/{}", self.api_url, address);
+        let response = reqwest::blocking::get(url)?.text()?;
+        let stats: serde_json::Value = serde_json::from_str(&response)?;
+
+        let mut count = 0;
+        for key in ["chain_stats", "mempool_stats"] {
+            count += stats[key]["tx_count"].as_u64().ok_or_else(|| {
+                FetchError::MissingField(format!("{}.tx_count for {}", key, address))
+            })?;
+        }
+        Ok(count)
+    }
+
     /// Loads the cache from a file
-    pub fn load_cache(&mut self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn load_cache(&mut self, filename: &str) -> Result<(), FetchError> {
         let mut file = File::open(filename)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
@@ -49,14 +159,14 @@ impl TxFetcher {
             let raw = hex::decode(raw_hex)?;
             let mut cursor = Cursor::new(raw);
             let tx = Tx::parse(&mut cursor)?;
-            self.cache.borrow_mut().insert(k, tx);
+            self.cache.borrow_mut().insert(k.parse()?, tx);
         }
 
         Ok(())
     }
 
     /// Dumps the cache to a file
-    pub fn dump_cache(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn dump_cache(&self, filename: &str) -> Result<(), FetchError> {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
@@ -67,7 +177,7 @@ impl TxFetcher {
             .cache
             .borrow()
             .iter()
-            .map(|(k, tx)| (k.clone(), hex::encode(tx.serialize())))
+            .map(|(k, tx)| (k.to_string(), hex::encode(tx.serialize())))
             .collect();
 
         let serialized = serde_json::to_string_pretty(&to_dump)?;
@@ -116,3 +226,29 @@ impl TxFetcherBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let raw_tx = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
+        let tx = Tx::parse(&mut Cursor::new(hex::decode(raw_tx).unwrap())).unwrap();
+        let path = std::env::temp_dir().join(format!("tx_cache_{}.json", std::process::id()));
+        let filename = path.to_str().unwrap();
+        let cache = HashMap::from([(tx.id().to_string(), raw_tx)]);
+        std::fs::write(&path, serde_json::to_string(&cache).unwrap()).unwrap();
+
+        // Cached transactions are returned without a request
+        let mut fetcher = TxFetcher::builder().api_url("http://invalid").build();
+        fetcher.load_cache(filename).unwrap();
+        assert_eq!(fetcher.fetch(&tx.id(), false).unwrap(), tx);
+
+        fetcher.dump_cache(filename).unwrap();
+        let mut reloaded = TxFetcher::builder().api_url("http://invalid").build();
+        reloaded.load_cache(filename).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.fetch(&tx.id(), false).unwrap(), tx);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod account;
+pub mod bip32;
+pub mod bip39;
+pub mod derivation_path;
@@ -0,0 +1,438 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    address::{Address, AddressType},
+    ecc::point::ECPoint,
+    utils::tx_fetcher::{FetchError, TxFetcher},
+};
+
+use super::{
+    bip32::{Bip32Error, ExtendedPrivateKey, ExtendedPublicKey},
+    derivation_path::{DerivationPath, HARDENED},
+};
+
+/// Number of consecutive unused addresses after which a chain is assumed
+/// to have no more used addresses (BIP44)
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+#[derive(Debug)]
+pub enum AccountError {
+    /// The account index is HARDENED or above; it is hardened in the path,
+    /// so it must be below the HARDENED bit
+    InvalidIndex(u32),
+    /// Deriving a key failed
+    Bip32(Bip32Error),
+    /// The transaction source couldn't tell whether an address is used
+    Source(Box<dyn Error + Send + Sync>),
+    /// Every account index below HARDENED is used
+    AccountsExhausted,
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::InvalidIndex(index) => write!(f, "invalid account index {}", index),
+            AccountError::Bip32(err) => write!(f, "{}", err),
+            AccountError::Source(err) => write!(f, "transaction source failed: {}", err),
+            AccountError::AccountsExhausted => write!(f, "every account index is used"),
+        }
+    }
+}
+
+impl Error for AccountError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AccountError::Bip32(err) => Some(err),
+            AccountError::Source(err) => Some(err.as_ref()),
+            AccountError::InvalidIndex(_) | AccountError::AccountsExhausted => None,
+        }
+    }
+}
+
+impl From<Bip32Error> for AccountError {
+    fn from(err: Bip32Error) -> Self {
+        AccountError::Bip32(err)
+    }
+}
+
+impl From<FetchError> for AccountError {
+    fn from(err: FetchError) -> Self {
+        AccountError::Source(Box::new(err))
+    }
+}
+
+/// The BIP43 purposes we derive accounts for, each with its address type
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Purpose {
+    /// BIP44, P2PKH addresses
+    Bip44,
+    /// BIP49, P2SH-P2WPKH addresses
+    Bip49,
+    /// BIP84, P2WPKH addresses
+    Bip84,
+    /// BIP86, P2TR key-path addresses
+    Bip86,
+}
+
+impl Purpose {
+    /// The purpose index, the first level of the derivation path
+    pub fn index(self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+        }
+    }
+
+    pub fn address_type(self) -> AddressType {
+        match self {
+            Purpose::Bip44 => AddressType::P2pkh,
+            Purpose::Bip49 => AddressType::P2sh,
+            Purpose::Bip84 => AddressType::P2wpkh,
+            Purpose::Bip86 => AddressType::P2tr,
+        }
+    }
+
+    /// Returns the address this purpose uses for the public key
+    pub fn address(self, pubkey: &ECPoint, testnet: bool) -> Address {
+        match self {
+            Purpose::Bip44 => Address::p2pkh(pubkey, testnet),
+            Purpose::Bip49 => Address::p2sh_p2wpkh(pubkey, testnet),
+            Purpose::Bip84 => Address::p2wpkh(pubkey, testnet),
+            Purpose::Bip86 => Address::p2tr(pubkey, None, testnet),
+        }
+    }
+}
+
+/// The two address chains of an account
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Chain {
+    /// External chain, addresses handed out to receive payments
+    Receive,
+    /// Internal chain, addresses used for change
+    Change,
+}
+
+impl Chain {
+    pub fn index(self) -> u32 {
+        match self {
+            Chain::Receive => 0,
+            Chain::Change => 1,
+        }
+    }
+}
+
+/// Anything that can tell whether an address has been used
+pub trait TransactionSource {
+    /// Returns true if any transaction pays to the address
+    fn has_transactions(&self, address: &Address) -> Result<bool, AccountError>;
+}
+
+impl TransactionSource for TxFetcher {
+    fn has_transactions(&self, address: &Address) -> Result<bool, AccountError> {
+        Ok(self.fetch_address_tx_count(&address.to_string())? > 0)
+    }
+}
+
+/// A BIP44-style account: m / purpose' / coin_type' / account'
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Account {
+    purpose: Purpose,
+    index: u32,
+    xpub: ExtendedPublicKey,
+}
+
+/// Used addresses found on one chain of an account
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChainScan {
+    /// (address index, address) of every used address
+    pub used: Vec<(u32, Address)>,
+    /// The first index after the last used address
+    pub next_index: u32,
+}
+
+/// Result of scanning both chains of an account
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AccountScan {
+    pub account: Account,
+    pub receive: ChainScan,
+    pub change: ChainScan,
+}
+
+impl AccountScan {
+    /// An account is used if its receive chain has any history
+    pub fn is_used(&self) -> bool {
+        !self.receive.used.is_empty()
+    }
+}
+
+impl Account {
+    /// Derives the account from the master key. The coin type is 0 for
+    /// mainnet keys and 1 for testnet keys.
+    pub fn from_master(
+        master: &ExtendedPrivateKey,
+        purpose: Purpose,
+        index: u32,
+    ) -> Result<Self, AccountError> {
+        check_index(index)?;
+        let path = Self::path(purpose, master.is_testnet(), index);
+        let xpub = master.derive_path(&path)?.to_extended_public_key();
+        Ok(Self {
+            purpose,
+            index,
+            xpub,
+        })
+    }
+
+    /// Wraps an account-level extended public key, for watch-only restores
+    pub fn from_xpub(
+        purpose: Purpose,
+        index: u32,
+        xpub: ExtendedPublicKey,
+    ) -> Result<Self, AccountError> {
+        check_index(index)?;
+        Ok(Self {
+            purpose,
+            index,
+            xpub,
+        })
+    }
+
+    fn path(purpose: Purpose, testnet: bool, index: u32) -> DerivationPath {
+        let coin_type = if testnet { 1 } else { 0 };
+        DerivationPath::master()
+            .hardened_child(purpose.index())
+            .hardened_child(coin_type)
+            .hardened_child(index)
+    }
+
+    /// Returns the derivation path of the account from the master key
+    pub fn derivation_path(&self) -> DerivationPath {
+        Self::path(self.purpose, self.xpub.is_testnet(), self.index)
+    }
+
+    pub fn purpose(&self) -> Purpose {
+        self.purpose
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn xpub(&self) -> &ExtendedPublicKey {
+        &self.xpub
+    }
+
+    /// Returns the address at the index of the chain
+    pub fn address(&self, chain: Chain, index: u32) -> Result<Address, Bip32Error> {
+        let key = self.xpub.derive_child(chain.index())?.derive_child(index)?;
+        Ok(self
+            .purpose
+            .address(key.public_key(), self.xpub.is_testnet()))
+    }
+
+    /// Scans both chains until `gap_limit` consecutive unused addresses
+    pub fn scan(
+        &self,
+        source: &dyn TransactionSource,
+        gap_limit: u32,
+    ) -> Result<AccountScan, AccountError> {
+        Ok(AccountScan {
+            account: self.clone(),
+            receive: self.scan_chain(Chain::Receive, source, gap_limit)?,
+            change: self.scan_chain(Chain::Change, source, gap_limit)?,
+        })
+    }
+
+    fn scan_chain(
+        &self,
+        chain: Chain,
+        source: &dyn TransactionSource,
+        gap_limit: u32,
+    ) -> Result<ChainScan, AccountError> {
+        let chain_key = self.xpub.derive_child(chain.index())?;
+        let mut used = vec![];
+        let mut gap = 0;
+        let mut index = 0;
+        while gap < gap_limit {
+            let key = chain_key.derive_child(index)?;
+            let address = self
+                .purpose
+                .address(key.public_key(), self.xpub.is_testnet());
+            if source.has_transactions(&address)? {
+                used.push((index, address));
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+            index += 1;
+        }
+        let next_index = used.last().map_or(0, |(index, _)| index + 1);
+        Ok(ChainScan { used, next_index })
+    }
+}
+
+/// hardened_child would fold an index with the HARDENED bit into another
+/// account
+fn check_index(index: u32) -> Result<(), AccountError> {
+    if index >= HARDENED {
+        return Err(AccountError::InvalidIndex(index));
+    }
+    Ok(())
+}
+
+/// Discovers the used accounts of a purpose as described in BIP44: accounts
+/// are scanned in order, stopping at the first one without receive history.
+pub fn discover_accounts(
+    master: &ExtendedPrivateKey,
+    purpose: Purpose,
+    source: &dyn TransactionSource,
+    gap_limit: u32,
+) -> Result<Vec<AccountScan>, AccountError> {
+    let mut accounts = vec![];
+    // Account indexes are hardened, so the index can't reach the HARDENED bit
+    for index in 0..HARDENED {
+        let scan = Account::from_master(master, purpose, index)?.scan(source, gap_limit)?;
+        if !scan.is_used() {
+            return Ok(accounts);
+        }
+        accounts.push(scan);
+    }
+    Err(AccountError::AccountsExhausted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::wallet::bip39::Mnemonic;
+
+    use super::*;
+
+    fn master() -> ExtendedPrivateKey {
+        Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap()
+        .to_master_key("", false)
+        .unwrap()
+    }
+
+    struct UsedAddresses(HashSet<String>);
+
+    impl TransactionSource for UsedAddresses {
+        fn has_transactions(&self, address: &Address) -> Result<bool, AccountError> {
+            Ok(self.0.contains(&address.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_addresses_per_purpose() {
+        let master = master();
+        let values = vec![
+            (Purpose::Bip44, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"),
+            (Purpose::Bip84, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"),
+            (
+                Purpose::Bip86,
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            ),
+        ];
+        for (purpose, address) in values {
+            let account = Account::from_master(&master, purpose, 0).unwrap();
+            assert_eq!(
+                account.address(Chain::Receive, 0).unwrap().to_string(),
+                address
+            );
+            assert_eq!(
+                account.address(Chain::Receive, 0).unwrap().address_type(),
+                Some(purpose.address_type())
+            );
+        }
+
+        // The BIP49 test vector is on testnet
+        let master = Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap()
+        .to_master_key("", true)
+        .unwrap();
+        let account = Account::from_master(&master, Purpose::Bip49, 0).unwrap();
+        assert_eq!(account.derivation_path().to_string(), "m/49'/1'/0'");
+        assert_eq!(
+            account.address(Chain::Receive, 0).unwrap().to_string(),
+            "2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        let master = master();
+        assert!(Account::from_master(&master, Purpose::Bip84, HARDENED - 1).is_ok());
+        for index in [HARDENED, u32::MAX] {
+            assert!(matches!(
+                Account::from_master(&master, Purpose::Bip84, index),
+                Err(AccountError::InvalidIndex(i)) if i == index
+            ));
+        }
+        let xpub = master.to_extended_public_key();
+        assert!(matches!(
+            Account::from_xpub(Purpose::Bip84, HARDENED, xpub),
+            Err(AccountError::InvalidIndex(HARDENED))
+        ));
+    }
+
+    #[test]
+    fn test_discover_accounts() {
+        let master = master();
+        let account = Account::from_master(&master, Purpose::Bip84, 0).unwrap();
+        let used = [
+            account.address(Chain::Receive, 0).unwrap(),
+            account.address(Chain::Receive, 2).unwrap(),
+            account.address(Chain::Change, 0).unwrap(),
+        ];
+        let source = UsedAddresses(used.iter().map(|a| a.to_string()).collect());
+
+        let accounts = discover_accounts(&master, Purpose::Bip84, &source, 2).unwrap();
+        assert_eq!(accounts.len(), 1);
+        let scan = &accounts[0];
+        assert_eq!(scan.account, account);
+        assert_eq!(
+            scan.receive.used,
+            vec![(0, used[0].clone()), (2, used[1].clone())]
+        );
+        assert_eq!(scan.receive.next_index, 3);
+        assert_eq!(scan.change.used, vec![(0, used[2].clone())]);
+        assert_eq!(scan.change.next_index, 1);
+    }
+
+    struct Offline;
+
+    impl TransactionSource for Offline {
+        fn has_transactions(&self, _: &Address) -> Result<bool, AccountError> {
+            Err(AccountError::Source("offline".into()))
+        }
+    }
+
+    #[test]
+    fn test_source_error() {
+        let err = discover_accounts(&master(), Purpose::Bip84, &Offline, 2).unwrap_err();
+        assert!(matches!(err, AccountError::Source(_)));
+        assert_eq!(err.to_string(), "transaction source failed: offline");
+    }
+
+    #[test]
+    fn test_gap_limit_stops_scanning() {
+        let master = master();
+        let account = Account::from_master(&master, Purpose::Bip44, 0).unwrap();
+        // Index 3 is beyond a gap of 2 unused addresses, so it isn't found
+        let source = UsedAddresses(
+            [account.address(Chain::Receive, 3).unwrap().to_string()]
+                .into_iter()
+                .collect(),
+        );
+        let scan = account.scan(&source, 2).unwrap();
+        assert!(!scan.is_used());
+        assert_eq!(scan.receive.next_index, 0);
+    }
+}
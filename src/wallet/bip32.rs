@@ -0,0 +1,565 @@
+use std::{fmt, str::FromStr};
+
+use num::{BigUint, Zero};
+
+use crate::{
+    ecc::{point::ECPoint, private_key::PrivateKey, secp256k1_params::Secp256k1Params},
+    utils::{
+        encode_base58::{decode_base58_checksum, encode_base58_checksum},
+        hash160::hash160,
+        hmac_sha512::hmac_sha512,
+    },
+};
+
+use super::derivation_path::{format_child_number, DerivationPath, HARDENED};
+
+// Version bytes of the serialized extended keys
+const MAINNET_PRIVATE: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const MAINNET_PUBLIC: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TESTNET_PRIVATE: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+const TESTNET_PUBLIC: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+/// Length of a serialized extended key, without the checksum
+const SERIALIZED_LENGTH: usize = 78;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Bip32Error {
+    /// Seeds must be between 128 and 512 bits
+    InvalidSeedLength(usize),
+    /// The derived key is zero or not below the curve order
+    InvalidKey,
+    /// The base58 payload is not valid
+    Base58(String),
+    /// A serialized extended key is 78 bytes
+    InvalidLength(usize),
+    /// The version bytes don't match the expected key type
+    UnknownVersion([u8; 4]),
+    /// Hardened children can only be derived from private keys
+    HardenedFromPublicKey(u32),
+    /// Keys can't be deeper than 255 levels
+    MaxDepthExceeded,
+}
+
+impl fmt::Display for Bip32Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bip32Error::InvalidSeedLength(len) => write!(f, "invalid seed length: {} bytes", len),
+            Bip32Error::InvalidKey => write!(f, "invalid extended key"),
+            Bip32Error::Base58(err) => write!(f, "{}", err),
+            Bip32Error::InvalidLength(len) => {
+                write!(f, "invalid extended key length: {} bytes", len)
+            }
+            Bip32Error::UnknownVersion(version) => {
+                write!(f, "unknown version bytes: {}", hex::encode(version))
+            }
+            Bip32Error::HardenedFromPublicKey(index) => write!(
+                f,
+                "cannot derive hardened child {} from a public key",
+                format_child_number(*index)
+            ),
+            Bip32Error::MaxDepthExceeded => write!(f, "maximum derivation depth exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for Bip32Error {}
+
+/// An extended private key: a private key plus the chain code used to
+/// derive its children
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExtendedPrivateKey {
+    testnet: bool,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    private_key: PrivateKey,
+}
+
+/// An extended public key: a public key plus the chain code used to
+/// derive its non-hardened children
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExtendedPublicKey {
+    testnet: bool,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    public_key: ECPoint,
+}
+
+impl ExtendedPrivateKey {
+    /// Creates the master key from a seed, as HMAC-SHA512("Bitcoin seed", seed)
+    pub fn new_master(seed: &[u8], testnet: bool) -> Result<Self, Bip32Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Bip32Error::InvalidSeedLength(seed.len()));
+        }
+
+        let i = hmac_sha512(b"Bitcoin seed", seed);
+
+        // The left half is the master secret, the right half the chain code
+        let secret = BigUint::from_bytes_be(&i[..32]);
+        if secret.is_zero() || secret >= Secp256k1Params::n() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+
+        Ok(Self {
+            testnet,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+            chain_code,
+            private_key: PrivateKey::new(&secret),
+        })
+    }
+
+    /// Derives the child key at the given index (CKDpriv). Indexes with the
+    /// `HARDENED` bit set derive hardened children.
+    pub fn derive_child(&self, index: u32) -> Result<Self, Bip32Error> {
+        let point = self.private_key.point();
+
+        // Hardened children commit to the private key, normal children to the
+        // public key so they can also be derived from the extended public key.
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend(self.private_key.to_bytes());
+        } else {
+            data.extend(point.to_compressed_sec());
+        }
+        data.extend(index.to_be_bytes());
+        let i = hmac_sha512(&self.chain_code, &data);
+
+        let n = Secp256k1Params::n();
+        let tweak = BigUint::from_bytes_be(&i[..32]);
+        if tweak >= n {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let secret = (tweak + self.private_key.secret()) % &n;
+        if secret.is_zero() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+
+        Ok(Self {
+            testnet: self.testnet,
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Bip32Error::MaxDepthExceeded)?,
+            parent_fingerprint: fingerprint(&point),
+            child_number: index,
+            chain_code,
+            private_key: PrivateKey::new(&secret),
+        })
+    }
+
+    /// Derives the key at the path relative to this key
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Bip32Error> {
+        path.as_slice()
+            .iter()
+            .try_fold(self.clone(), |key, &index| key.derive_child(index))
+    }
+
+    /// Returns the extended public key with the same chain code
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            testnet: self.testnet,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.private_key.point(),
+        }
+    }
+
+    /// Returns the first 4 bytes of the hash160 of the public key
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.private_key.point())
+    }
+
+    /// Serializes the key into the 78-byte BIP32 format
+    pub fn serialize(&self) -> Vec<u8> {
+        let version = if self.testnet {
+            TESTNET_PRIVATE
+        } else {
+            MAINNET_PRIVATE
+        };
+        // Private keys are prefixed with 0x00 to be 33 bytes long
+        let mut key = vec![0u8];
+        key.extend(self.private_key.to_bytes());
+        serialize(
+            version,
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            &self.chain_code,
+            &key,
+        )
+    }
+
+    /// Parses a key from the 78-byte BIP32 format
+    pub fn parse(bytes: &[u8]) -> Result<Self, Bip32Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) =
+            deserialize(bytes)?;
+        let testnet = match version {
+            MAINNET_PRIVATE => false,
+            TESTNET_PRIVATE => true,
+            _ => return Err(Bip32Error::UnknownVersion(version)),
+        };
+        if key[0] != 0 {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let secret = BigUint::from_bytes_be(&key[1..]);
+        if secret.is_zero() || secret >= Secp256k1Params::n() {
+            return Err(Bip32Error::InvalidKey);
+        }
+
+        Ok(Self {
+            testnet,
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+            private_key: PrivateKey::new(&secret),
+        })
+    }
+
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.private_key
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn parent_fingerprint(&self) -> [u8; 4] {
+        self.parent_fingerprint
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    pub fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
+
+impl ExtendedPublicKey {
+    /// Derives the non-hardened child key at the given index (CKDpub)
+    pub fn derive_child(&self, index: u32) -> Result<Self, Bip32Error> {
+        if index >= HARDENED {
+            return Err(Bip32Error::HardenedFromPublicKey(index));
+        }
+
+        let mut data = self.public_key.to_compressed_sec();
+        data.extend(index.to_be_bytes());
+        let i = hmac_sha512(&self.chain_code, &data);
+
+        let tweak = BigUint::from_bytes_be(&i[..32]);
+        if tweak >= Secp256k1Params::n() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let public_key = Secp256k1Params::g() * tweak + self.public_key.clone();
+        if public_key.is_infinity() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+
+        Ok(Self {
+            testnet: self.testnet,
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Bip32Error::MaxDepthExceeded)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code,
+            public_key,
+        })
+    }
+
+    /// Derives the key at the path relative to this key. The path can't
+    /// contain hardened children.
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Bip32Error> {
+        path.as_slice()
+            .iter()
+            .try_fold(self.clone(), |key, &index| key.derive_child(index))
+    }
+
+    /// Returns the first 4 bytes of the hash160 of the public key
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.public_key)
+    }
+
+    /// Serializes the key into the 78-byte BIP32 format
+    pub fn serialize(&self) -> Vec<u8> {
+        let version = if self.testnet {
+            TESTNET_PUBLIC
+        } else {
+            MAINNET_PUBLIC
+        };
+        serialize(
+            version,
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            &self.chain_code,
+            &self.public_key.to_compressed_sec(),
+        )
+    }
+
+    /// Parses a key from the 78-byte BIP32 format
+    pub fn parse(bytes: &[u8]) -> Result<Self, Bip32Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) =
+            deserialize(bytes)?;
+        let testnet = match version {
+            MAINNET_PUBLIC => false,
+            TESTNET_PUBLIC => true,
+            _ => return Err(Bip32Error::UnknownVersion(version)),
+        };
+        if key[0] != 2 && key[0] != 3 {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let public_key = ECPoint::parse(&key).map_err(|_| Bip32Error::InvalidKey)?;
+
+        Ok(Self {
+            testnet,
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+            public_key,
+        })
+    }
+
+    pub fn public_key(&self) -> &ECPoint {
+        &self.public_key
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn parent_fingerprint(&self) -> [u8; 4] {
+        self.parent_fingerprint
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    pub fn is_testnet(&self) -> bool {
+        self.testnet
+    }
+}
+
+impl fmt::Display for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode_base58_checksum(&self.serialize()))
+    }
+}
+
+impl fmt::Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode_base58_checksum(&self.serialize()))
+    }
+}
+
+impl FromStr for ExtendedPrivateKey {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(&decode_base58_checksum(s).map_err(Bip32Error::Base58)?)
+    }
+}
+
+impl FromStr for ExtendedPublicKey {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(&decode_base58_checksum(s).map_err(Bip32Error::Base58)?)
+    }
+}
+
+fn fingerprint(point: &ECPoint) -> [u8; 4] {
+    let hash = hash160(&point.to_compressed_sec());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// version (4) || depth (1) || parent fingerprint (4) || child number (4) ||
+/// chain code (32) || key (33)
+fn serialize(
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: &[u8; 32],
+    key: &[u8],
+) -> Vec<u8> {
+    let mut result = Vec::with_capacity(SERIALIZED_LENGTH);
+    result.extend_from_slice(&version);
+    result.push(depth);
+    result.extend_from_slice(&parent_fingerprint);
+    result.extend_from_slice(&child_number.to_be_bytes());
+    result.extend_from_slice(chain_code);
+    result.extend_from_slice(key);
+    result
+}
+
+type Fields = ([u8; 4], u8, [u8; 4], u32, [u8; 32], [u8; 33]);
+
+fn deserialize(bytes: &[u8]) -> Result<Fields, Bip32Error> {
+    if bytes.len() != SERIALIZED_LENGTH {
+        return Err(Bip32Error::InvalidLength(bytes.len()));
+    }
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&bytes[0..4]);
+    let depth = bytes[4];
+    let mut parent_fingerprint = [0u8; 4];
+    parent_fingerprint.copy_from_slice(&bytes[5..9]);
+    let child_number = u32::from_be_bytes([bytes[9], bytes[10], bytes[11], bytes[12]]);
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&bytes[13..45]);
+    let mut key = [0u8; 33];
+    key.copy_from_slice(&bytes[45..78]);
+
+    // A master key has no parent
+    if depth == 0 && (parent_fingerprint != [0; 4] || child_number != 0) {
+        return Err(Bip32Error::InvalidKey);
+    }
+
+    Ok((
+        version,
+        depth,
+        parent_fingerprint,
+        child_number,
+        chain_code,
+        key,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_key_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, false).unwrap();
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"
+        );
+        assert_eq!(
+            master.to_extended_public_key().to_string(),
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"
+        );
+        assert_eq!(hex::encode(master.fingerprint()), "3442193e");
+    }
+
+    #[test]
+    fn test_master_key_vector_3() {
+        // Retention of leading zeros
+        let seed = hex::decode(
+            "4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4ac\
+            ba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be",
+        )
+        .unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, false).unwrap();
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6"
+        );
+        assert_eq!(
+            master.to_extended_public_key().to_string(),
+            "xpub661MyMwAqRbcEZVB4dScxMAdx6d4nFc9nvyvH3v4gJL378CSRZiYmhRoP7mBy6gSPSCYk6SzXPTf3ND1cZAceL7SfJ1Z3GC8vBgp2epUt13"
+        );
+    }
+
+    #[test]
+    fn test_derive_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, false).unwrap();
+
+        let values = vec![
+            (
+                "m/0'",
+                "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+                "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            ),
+            (
+                "m/0'/1/2'/2/1000000000",
+                "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+                "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+            ),
+        ];
+
+        for (path, xprv, xpub) in values {
+            let path: DerivationPath = path.parse().unwrap();
+            let child = master.derive_path(&path).unwrap();
+            assert_eq!(child.to_string(), xprv);
+            assert_eq!(child.to_extended_public_key().to_string(), xpub);
+        }
+    }
+
+    #[test]
+    fn test_public_derivation() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let account = ExtendedPrivateKey::new_master(&seed, false)
+            .unwrap()
+            .derive_child(HARDENED)
+            .unwrap();
+        let path: DerivationPath = "1/2".parse().unwrap();
+
+        // Public derivation of normal children matches private derivation
+        assert_eq!(
+            account.to_extended_public_key().derive_path(&path).unwrap(),
+            account.derive_path(&path).unwrap().to_extended_public_key()
+        );
+
+        assert_eq!(
+            account.to_extended_public_key().derive_child(HARDENED + 2),
+            Err(Bip32Error::HardenedFromPublicKey(HARDENED + 2))
+        );
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, true).unwrap();
+        let xprv = master.to_string();
+        assert!(xprv.starts_with("tprv"));
+        assert_eq!(xprv.parse::<ExtendedPrivateKey>().unwrap(), master);
+
+        let xpub = master.to_extended_public_key();
+        assert_eq!(xpub.to_string().parse::<ExtendedPublicKey>().unwrap(), xpub);
+
+        // A public key can't be parsed as a private one
+        assert_eq!(
+            xpub.to_string().parse::<ExtendedPrivateKey>(),
+            Err(Bip32Error::UnknownVersion(TESTNET_PUBLIC))
+        );
+    }
+
+    #[test]
+    fn test_invalid_seed_length() {
+        assert_eq!(
+            ExtendedPrivateKey::new_master(&[0u8; 8], false),
+            Err(Bip32Error::InvalidSeedLength(8))
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use unicode_normalization::UnicodeNormalization;
+
+use super::bip32::{Bip32Error, ExtendedPrivateKey};
+
+/// The BIP39 English wordlist, one word per line
+const ENGLISH_WORDLIST: &str = include_str!("bip39_english.txt");
+
+/// Number of PBKDF2 rounds used to stretch the mnemonic into a seed
+const PBKDF2_ROUNDS: u32 = 2048;
+
+fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| ENGLISH_WORDLIST.lines().collect())
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Bip39Error {
+    /// Entropy must be 128 to 256 bits, in steps of 32 bits
+    InvalidEntropyLength(usize),
+    /// A mnemonic has 12, 15, 18, 21 or 24 words
+    InvalidWordCount(usize),
+    /// The word is not in the wordlist
+    UnknownWord(String),
+    /// The checksum bits don't match the entropy
+    InvalidChecksum,
+}
+
+impl fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bip39Error::InvalidEntropyLength(len) => {
+                write!(f, "invalid entropy length: {} bytes", len)
+            }
+            Bip39Error::InvalidWordCount(count) => write!(f, "invalid word count: {}", count),
+            Bip39Error::UnknownWord(word) => write!(f, "unknown word: {}", word),
+            Bip39Error::InvalidChecksum => write!(f, "invalid mnemonic checksum"),
+        }
+    }
+}
+
+impl std::error::Error for Bip39Error {}
+
+/// A BIP39 mnemonic sentence.
+///
+/// The mnemonic is stored as its entropy; the words are derived from it
+/// together with the checksum.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Mnemonic {
+    entropy: Vec<u8>,
+}
+
+impl Mnemonic {
+    /// Creates a mnemonic from 16, 20, 24, 28 or 32 bytes of entropy
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Bip39Error> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            return Err(Bip39Error::InvalidEntropyLength(entropy.len()));
+        }
+        Ok(Self {
+            entropy: entropy.to_vec(),
+        })
+    }
+
+    /// Generates a new random mnemonic with the given number of words
+    pub fn generate(word_count: usize) -> Result<Self, Bip39Error> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(Bip39Error::InvalidWordCount(word_count));
+        }
+        // Every 3 words encode 32 bits of entropy and 1 bit of checksum
+        let mut entropy = vec![0u8; word_count / 3 * 4];
+        rand::thread_rng().fill_bytes(&mut entropy);
+        Self::from_entropy(&entropy)
+    }
+
+    /// Parses a mnemonic sentence and validates its checksum
+    pub fn parse(phrase: &str) -> Result<Self, Bip39Error> {
+        let phrase: String = phrase.nfkd().collect();
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+        if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+            return Err(Bip39Error::InvalidWordCount(words.len()));
+        }
+
+        // Each word is an 11-bit index into the wordlist
+        let mut bits = Vec::with_capacity(words.len() * 11);
+        for word in &words {
+            let index = wordlist()
+                .iter()
+                .position(|w| w == word)
+                .ok_or_else(|| Bip39Error::UnknownWord(word.to_string()))?;
+            for i in (0..11).rev() {
+                bits.push((index >> i) & 1 == 1);
+            }
+        }
+
+        // The first ENT bits are the entropy, the remaining ENT/32 the checksum
+        let checksum_len = bits.len() / 33;
+        let entropy_len = bits.len() - checksum_len;
+        let entropy: Vec<u8> = bits[..entropy_len]
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+            .collect();
+
+        let mnemonic = Self::from_entropy(&entropy)?;
+        if mnemonic.checksum_bits() != bits[entropy_len..] {
+            return Err(Bip39Error::InvalidChecksum);
+        }
+        Ok(mnemonic)
+    }
+
+    /// Returns the entropy the mnemonic encodes
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    /// Returns the words of the mnemonic sentence
+    pub fn words(&self) -> Vec<&'static str> {
+        let mut bits = Vec::with_capacity(self.entropy.len() * 8 + self.entropy.len() / 4);
+        for byte in &self.entropy {
+            for i in (0..8).rev() {
+                bits.push((byte >> i) & 1 == 1);
+            }
+        }
+        bits.extend(self.checksum_bits());
+
+        bits.chunks(11)
+            .map(|chunk| {
+                let index = chunk
+                    .iter()
+                    .fold(0usize, |acc, &bit| (acc << 1) | bit as usize);
+                wordlist()[index]
+            })
+            .collect()
+    }
+
+    /// Derives the 64-byte seed with PBKDF2-HMAC-SHA512, using
+    /// "mnemonic" + passphrase as salt
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let password: String = self.to_string().nfkd().collect();
+        let salt: String = format!("mnemonic{}", passphrase).nfkd().collect();
+        let mut seed = [0u8; 64];
+        pbkdf2_hmac::<Sha512>(
+            password.as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            &mut seed,
+        );
+        seed
+    }
+
+    /// Derives the BIP32 master key for this mnemonic and passphrase
+    pub fn to_master_key(
+        &self,
+        passphrase: &str,
+        testnet: bool,
+    ) -> Result<ExtendedPrivateKey, Bip32Error> {
+        ExtendedPrivateKey::new_master(&self.to_seed(passphrase), testnet)
+    }
+
+    /// The first ENT/32 bits of SHA256(entropy)
+    fn checksum_bits(&self) -> Vec<bool> {
+        let hash = Sha256::digest(&self.entropy);
+        let checksum_len = self.entropy.len() / 4;
+        (0..checksum_len)
+            .map(|i| (hash[i / 8] >> (7 - i % 8)) & 1 == 1)
+            .collect()
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Bip39Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.words().join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wordlist() {
+        assert_eq!(wordlist().len(), 2048);
+        assert_eq!(wordlist()[0], "abandon");
+        assert_eq!(wordlist()[2047], "zoo");
+    }
+
+    #[test]
+    fn test_vectors() {
+        // (entropy, mnemonic, seed with passphrase "TREZOR")
+        let values = vec![
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e5349553\
+                1f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6f\
+                a457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+                "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e16\
+                13912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+            ),
+        ];
+
+        for (entropy, phrase, seed) in values {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(mnemonic.to_string(), phrase);
+            assert_eq!(Mnemonic::parse(phrase).unwrap(), mnemonic);
+            assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), seed);
+        }
+    }
+
+    #[test]
+    fn test_master_key() {
+        let mnemonic = Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        assert_eq!(
+            mnemonic.to_master_key("TREZOR", false).unwrap().to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Mnemonic::parse("abandon abandon abandon"),
+            Err(Bip39Error::InvalidWordCount(3))
+        );
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+            ),
+            Err(Bip39Error::InvalidChecksum)
+        );
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon qwerty"
+            ),
+            Err(Bip39Error::UnknownWord("qwerty".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let mnemonic = Mnemonic::generate(24).unwrap();
+        assert_eq!(mnemonic.words().len(), 24);
+        assert_eq!(Mnemonic::parse(&mnemonic.to_string()).unwrap(), mnemonic);
+        assert_eq!(
+            Mnemonic::generate(13),
+            Err(Bip39Error::InvalidWordCount(13))
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fmt, str::FromStr};
+
+/// Child numbers at or above this index are hardened
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// A BIP32 derivation path such as m/84'/0'/0'/0/5
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default)]
+pub struct DerivationPath {
+    path: Vec<u32>,
+}
+
+impl DerivationPath {
+    /// Creates a path from child numbers, hardened ones including the
+    /// `HARDENED` bit
+    pub fn new(path: Vec<u32>) -> Self {
+        Self { path }
+    }
+
+    /// The empty path, m
+    pub fn master() -> Self {
+        Self::default()
+    }
+
+    /// Returns a new path with a non-hardened child appended
+    pub fn child(&self, index: u32) -> Self {
+        let mut path = self.path.clone();
+        path.push(index);
+        Self { path }
+    }
+
+    /// Returns a new path with a hardened child appended
+    pub fn hardened_child(&self, index: u32) -> Self {
+        self.child(index | HARDENED)
+    }
+
+    /// Returns a new path with the other path appended
+    pub fn extend(&self, other: &DerivationPath) -> Self {
+        let mut path = self.path.clone();
+        path.extend_from_slice(&other.path);
+        Self { path }
+    }
+
+    /// Returns the child numbers
+    pub fn as_slice(&self) -> &[u32] {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+}
+
+/// Parses a single child number: 5, 5' or 5h
+pub fn parse_child_number(s: &str) -> Result<u32, String> {
+    let (index, hardened) = match s.strip_suffix(['\'', 'h', 'H']) {
+        Some(index) => (index, true),
+        None => (s, false),
+    };
+    // Reject signs and whitespace that u32::from_str would let through
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("Invalid child number: {}", s));
+    }
+    let index: u32 = index
+        .parse()
+        .map_err(|_| format!("Invalid child number: {}", s))?;
+    if index >= HARDENED {
+        return Err(format!("Child number out of range: {}", s));
+    }
+    Ok(if hardened { index | HARDENED } else { index })
+}
+
+/// Formats a child number with the ' notation for hardened children
+pub fn format_child_number(index: u32) -> String {
+    if index >= HARDENED {
+        format!("{}'", index - HARDENED)
+    } else {
+        index.to_string()
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    /// Parses "m/84'/0'/0'/0/5", accepting both ' and h for hardened
+    /// children. The leading "m" is optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/').peekable();
+        if parts.peek() == Some(&"m") {
+            parts.next();
+        }
+        let path = parts
+            .map(parse_child_number)
+            .collect::<Result<Vec<u32>, String>>()?;
+        Ok(Self { path })
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.path {
+            write!(f, "/{}", format_child_number(*index))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<u32>> for DerivationPath {
+    fn from(path: Vec<u32>) -> Self {
+        Self::new(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let path: DerivationPath = "m/84'/0'/0'/0/5".parse().unwrap();
+        assert_eq!(path.as_slice(), &[84 | HARDENED, HARDENED, HARDENED, 0, 5]);
+        assert_eq!(path, "m/84h/0h/0H/0/5".parse().unwrap());
+        assert_eq!(path, "84'/0'/0'/0/5".parse().unwrap());
+        assert_eq!(path.to_string(), "m/84'/0'/0'/0/5");
+
+        let master: DerivationPath = "m".parse().unwrap();
+        assert!(master.is_empty());
+        assert_eq!(master.to_string(), "m");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for s in [
+            "m/",
+            "m/a",
+            "m/1//2",
+            "m/+1",
+            "m/2147483648",
+            "m/1''",
+            "n/1",
+        ] {
+            assert!(s.parse::<DerivationPath>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_child() {
+        let path = DerivationPath::master().hardened_child(44).child(1);
+        assert_eq!(path.to_string(), "m/44'/1");
+        let path = path.extend(&"0/7".parse().unwrap());
+        assert_eq!(path.to_string(), "m/44'/1/0/7");
+    }
+}